@@ -9,6 +9,36 @@
 // See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
 // of this distribution.
 
+//! A small library for computing diffs.
+//!
+//! The main entry point is [`diff`], which compares two slices (typically the lines of two files)
+//! and returns a list of [`LineDiff`]s saying which elements were kept, deleted, or inserted. It
+//! uses the patience diff algorithm (specifically, the non-recursive variant described
+//! [here](https://bramcohen.livejournal.com/73318.html)), which tends to produce diffs that line
+//! up with the structure of the text.
+//!
+//! ```
+//! use ojo_diff::LineDiff::*;
+//!
+//! let a = ["fn main() {", "}"];
+//! let b = ["fn main() {", "    println!(\"hi\");", "}"];
+//! assert_eq!(ojo_diff::diff(&a, &b), vec![Keep(0, 0), New(1), Keep(1, 2)]);
+//! ```
+//!
+//! [`diff_with_options`] allows choosing a different algorithm, and [`diff_text`] splits strings
+//! into tokens (lines, words or characters) before diffing them. Finally, [`hunks`] groups a diff
+//! into the chunks of changes (with some surrounding context) that make up a unified diff:
+//!
+//! ```
+//! let a = [1, 2, 3, 4, 5, 6, 7, 8];
+//! let b = [1, 2, 3, 4, 0, 5, 6, 7, 8];
+//! let d = ojo_diff::diff(&a, &b);
+//! let hunks = ojo_diff::hunks(&d, 2);
+//! assert_eq!(hunks.len(), 1);
+//! assert_eq!((hunks[0].a_start, hunks[0].a_len), (2, 4));
+//! assert_eq!((hunks[0].b_start, hunks[0].b_len), (2, 5));
+//! ```
+
 #[cfg(test)]
 #[macro_use]
 extern crate proptest;
@@ -19,6 +49,11 @@ use std::hash::{Hash, Hasher};
 
 mod lis;
 
+/// A single entry in a diff.
+///
+/// A diff is a list of these, and it says how to turn the first file into the second one: the
+/// line numbers of the first file (in `Keep` and `Delete`) and of the second file (in `Keep` and
+/// `New`) each appear exactly once, and in increasing order.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum LineDiff {
     /// This line was introduced in the second file, and the `usize` is the line number in the
@@ -31,6 +66,153 @@ pub enum LineDiff {
     Keep(usize, usize),
 }
 
+/// Options for controlling the behavior of [`diff_with_options`] and [`diff_text`].
+///
+/// The default options give the same result as [`diff`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DiffOptions {
+    /// Which algorithm to use for computing the diff.
+    pub algorithm: Algorithm,
+    /// How [`diff_text`] splits its inputs into tokens. This is ignored by [`diff_with_options`],
+    /// which is given its tokens directly.
+    pub tokenizer: Tokenizer,
+    /// If this is `Some(n)`, [`Algorithm::Myers`] gives up once it is clear that the diff needs
+    /// more than `n` insertions and deletions. In that case, everything between the common prefix
+    /// and the common suffix of the two inputs is reported as changed. This bounds the running
+    /// time on inputs that are very different.
+    ///
+    /// The patience algorithm is always fast, so it ignores this.
+    pub max_cost: Option<usize>,
+}
+
+/// The algorithms that [`diff_with_options`] knows about.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Algorithm {
+    /// The patience diff algorithm, which is what [`diff`] uses. It matches up lines that are
+    /// unique in both files, and so it does a good job of aligning things like function
+    /// signatures. It doesn't always find the smallest possible diff, though.
+    #[default]
+    Patience,
+    /// Myers' algorithm, which always finds a diff with as few insertions and deletions as
+    /// possible. It can be slow (and use lots of memory) on files that are very different; see
+    /// [`DiffOptions::max_cost`].
+    Myers,
+    /// A fast but coarse diff for very large inputs, which compares blocks of lines instead of
+    /// single lines.
+    ///
+    /// The inputs are cut into blocks of about `block_len` lines (the cuts depend on the lines'
+    /// contents, so that the blocks of two similar inputs mostly line up even if some lines were
+    /// inserted or deleted), and then the blocks are diffed using the patience algorithm. A block
+    /// with even one changed line is reported as entirely deleted and re-inserted, so the changes
+    /// tend to be much bigger than they need to be.
+    Blocks {
+        /// The average number of lines in a block.
+        block_len: usize,
+    },
+    /// A precise diff for large inputs with few changes.
+    ///
+    /// This starts like [`Algorithm::Blocks`], but then every region of blocks that changed is
+    /// diffed line by line using the patience algorithm. The result is usually the same as the
+    /// patience diff, but the work done by the line-by-line diff only depends on the size of the
+    /// changed regions.
+    Chunked {
+        /// The average number of lines in a block.
+        block_len: usize,
+    },
+}
+
+/// The ways that [`diff_text`] can split text into tokens.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Tokenizer {
+    /// Each line (including its terminating `'\n'`, if there is one) is a token.
+    #[default]
+    Lines,
+    /// Each word (that is, each maximal run of alphanumeric characters and underscores) is a
+    /// token, as is each maximal run of whitespace and each other character.
+    Words,
+    /// Each `char` is a token.
+    Chars,
+}
+
+impl Tokenizer {
+    /// Splits `text` into tokens. Concatenating the tokens gives back `text`.
+    ///
+    /// ```
+    /// use ojo_diff::Tokenizer;
+    ///
+    /// assert_eq!(Tokenizer::Lines.tokenize("a b\nc"), vec!["a b\n", "c"]);
+    /// assert_eq!(Tokenizer::Words.tokenize("a_1 (b)"), vec!["a_1", " ", "(", "b", ")"]);
+    /// ```
+    pub fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        match *self {
+            Tokenizer::Lines => text.split_inclusive('\n').collect(),
+            Tokenizer::Chars => text
+                .char_indices()
+                .map(|(i, c)| &text[i..(i + c.len_utf8())])
+                .collect(),
+            Tokenizer::Words => {
+                // 0 for word characters, 1 for whitespace and 2 for anything else.
+                fn class(c: char) -> u8 {
+                    if c.is_alphanumeric() || c == '_' {
+                        0
+                    } else if c.is_whitespace() {
+                        1
+                    } else {
+                        2
+                    }
+                }
+
+                let mut ret = Vec::new();
+                let mut start = 0;
+                let mut prev_class = None;
+                for (i, c) in text.char_indices() {
+                    let cl = class(c);
+                    if i > start && (prev_class != Some(cl) || cl == 2) {
+                        ret.push(&text[start..i]);
+                        start = i;
+                    }
+                    prev_class = Some(cl);
+                }
+                if start < text.len() {
+                    ret.push(&text[start..]);
+                }
+                ret
+            }
+        }
+    }
+}
+
+/// The result of [`diff_text`]: the tokens of the two inputs, and the diff between them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextDiff<'a> {
+    /// The tokens of the first input.
+    pub a: Vec<&'a str>,
+    /// The tokens of the second input.
+    pub b: Vec<&'a str>,
+    /// The diff between `a` and `b`. The indices in here refer to tokens, not to bytes.
+    pub diff: Vec<LineDiff>,
+}
+
+/// A group of nearby changes in a diff, together with some unchanged lines around them.
+///
+/// See [`hunks`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hunk {
+    /// The line number (in the first file) where this hunk starts. If the hunk doesn't contain any
+    /// lines from the first file, this is the number of lines in the first file that come before
+    /// the hunk.
+    pub a_start: usize,
+    /// The number of lines from the first file that are in this hunk (either kept or deleted).
+    pub a_len: usize,
+    /// The line number (in the second file) where this hunk starts. As for `a_start`, this is the
+    /// number of lines in the second file that come before the hunk.
+    pub b_start: usize,
+    /// The number of lines from the second file that are in this hunk (either kept, new or moved).
+    pub b_len: usize,
+    /// The part of the diff that belongs to this hunk.
+    pub lines: Vec<LineDiff>,
+}
+
 // This is a little trick for associating an element with its line number in a file. The point is
 // that our implementation of Hash and Eq will ignore the index, so we can put `WithIndex` in
 // hash maps and the index will just be transparently carried along.
@@ -120,6 +302,9 @@ fn diff_ends<T: Eq>(a: &[T], a_offset: usize, b: &[T], b_offset: usize, diff: &m
     }
 }
 
+/// Computes a diff between `a` and `b`, using the patience diff algorithm.
+///
+/// The returned diff lists the elements of both inputs in order, as described in [`LineDiff`].
 pub fn diff<T: Hash + Eq>(a: &[T], b: &[T]) -> Vec<LineDiff> {
     let (pref_len, a_mid, b_mid, suff_len) = match_ends(a, b);
     let a_line_counts = line_counts(a_mid);
@@ -192,6 +377,284 @@ pub fn diff<T: Hash + Eq>(a: &[T], b: &[T]) -> Vec<LineDiff> {
     ret
 }
 
+/// Like [`diff`], but with some extra options (see [`DiffOptions`]).
+pub fn diff_with_options<T: Hash + Eq>(a: &[T], b: &[T], options: &DiffOptions) -> Vec<LineDiff> {
+    match options.algorithm {
+        Algorithm::Patience => diff(a, b),
+        Algorithm::Myers => myers_diff(a, b, options.max_cost),
+        Algorithm::Blocks { block_len } => block_diff(a, b, block_len.max(1)),
+        Algorithm::Chunked { block_len } => chunked_diff(a, b, block_len.max(1)),
+    }
+}
+
+/// Splits `a` and `b` into tokens (according to `options.tokenizer`) and diffs them.
+///
+/// ```
+/// use ojo_diff::{DiffOptions, LineDiff::*, Tokenizer};
+///
+/// let options = DiffOptions {
+///     tokenizer: Tokenizer::Words,
+///     ..DiffOptions::default()
+/// };
+/// let d = ojo_diff::diff_text("let x = 1;", "let y = 1;", &options);
+/// assert_eq!(d.a[2], "x");
+/// assert_eq!(d.b[2], "y");
+/// assert!(d.diff.contains(&Delete(2)));
+/// assert!(d.diff.contains(&New(2)));
+/// ```
+pub fn diff_text<'a>(a: &'a str, b: &'a str, options: &DiffOptions) -> TextDiff<'a> {
+    let a = options.tokenizer.tokenize(a);
+    let b = options.tokenizer.tokenize(b);
+    let diff = diff_with_options(&a, &b, options);
+    TextDiff { a, b, diff }
+}
+
+/// Groups the changes in `diff` into hunks.
+///
+/// Each hunk contains one or more changes (that is, anything except a [`LineDiff::Keep`]),
+/// together with up to `context` unchanged lines before and after them. Changes that are close
+/// enough for their context to touch or overlap end up in the same hunk. Unchanged lines that
+/// aren't close to any change don't appear in any hunk.
+pub fn hunks(diff: &[LineDiff], context: usize) -> Vec<Hunk> {
+    // The line numbers in the two files where each entry of the diff starts.
+    let mut positions = Vec::with_capacity(diff.len());
+    let (mut a_pos, mut b_pos) = (0, 0);
+    for d in diff {
+        positions.push((a_pos, b_pos));
+        let (a_len, b_len) = entry_lens(d);
+        a_pos += a_len;
+        b_pos += b_len;
+    }
+
+    let changes = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| !matches!(d, LineDiff::Keep(..)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] - 1 <= 2 * context {
+            j += 1;
+        }
+
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(diff.len());
+        let lines = diff[start..end].to_vec();
+        let (a_len, b_len) = lines
+            .iter()
+            .map(entry_lens)
+            .fold((0, 0), |(x, y), (a, b)| (x + a, y + b));
+        ret.push(Hunk {
+            a_start: positions[start].0,
+            a_len,
+            b_start: positions[start].1,
+            b_len,
+            lines,
+        });
+        i = j + 1;
+    }
+    ret
+}
+
+// The number of lines in the first and second files that are covered by a diff entry.
+fn entry_lens(d: &LineDiff) -> (usize, usize) {
+    match *d {
+        LineDiff::Keep(..) => (1, 1),
+        LineDiff::Delete(_) => (1, 0),
+        LineDiff::New(_) => (0, 1),
+    }
+}
+
+// Computes a minimal diff using Myers' algorithm (from "An O(ND) Difference Algorithm and Its
+// Variations"). If `max_cost` is set and the diff needs more edits than that, we give up and fall
+// back to `diff_ends`.
+fn myers_diff<T: Eq>(a: &[T], b: &[T], max_cost: Option<usize>) -> Vec<LineDiff> {
+    let (pref_len, a_mid, b_mid, suff_len) = match_ends(a, b);
+    let mut ret = Vec::with_capacity(a.len().max(b.len()));
+    for i in 0..pref_len {
+        ret.push(LineDiff::Keep(i, i));
+    }
+    myers_middle(a_mid, b_mid, pref_len, max_cost, &mut ret);
+    for i in 0..suff_len {
+        ret.push(LineDiff::Keep(
+            a.len() - suff_len + i,
+            b.len() - suff_len + i,
+        ));
+    }
+    ret
+}
+
+fn myers_middle<T: Eq>(
+    a: &[T],
+    b: &[T],
+    offset: usize,
+    max_cost: Option<usize>,
+    diff: &mut Vec<LineDiff>,
+) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let limit = max_cost.map_or(max, |c| (c as isize).min(max));
+
+    // `v[idx(k)]` is the furthest x coordinate that we have reached on diagonal `k` (that is, the
+    // diagonal where x - y = k). We keep a copy of `v` for each cost, for backtracking.
+    let idx = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = Vec::new();
+    for d in 0..=limit {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+
+            if x >= n && y >= m {
+                // We found the end, so walk back along the path that got us here.
+                let mut rev = Vec::new();
+                let (mut x, mut y) = (n, m);
+                for d in (0..=d).rev() {
+                    let v = &trace[d as usize];
+                    let k = x - y;
+                    let prev_k = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                        k + 1
+                    } else {
+                        k - 1
+                    };
+                    let prev_x = v[idx(prev_k)];
+                    let prev_y = prev_x - prev_k;
+                    while x > prev_x && y > prev_y {
+                        x -= 1;
+                        y -= 1;
+                        rev.push(LineDiff::Keep(offset + x as usize, offset + y as usize));
+                    }
+                    if d > 0 {
+                        if x == prev_x {
+                            rev.push(LineDiff::New(offset + prev_y as usize));
+                        } else {
+                            rev.push(LineDiff::Delete(offset + prev_x as usize));
+                        }
+                        x = prev_x;
+                        y = prev_y;
+                    }
+                }
+                diff.extend(rev.into_iter().rev());
+                return;
+            }
+        }
+    }
+    diff_ends(a, offset, b, offset, diff);
+}
+
+// Cuts `lines` into blocks, returning the index where each block starts. A block ends after any
+// line whose hash is divisible by `block_len` (or once it gets much longer than `block_len`), so
+// on average the blocks have `block_len` lines, and a change to one line only changes the
+// boundaries of the blocks around it.
+fn block_starts<T: Hash>(lines: &[T], block_len: usize) -> Vec<usize> {
+    use std::collections::hash_map::DefaultHasher;
+
+    let mut ret = vec![0];
+    let mut start = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        let end = i + 1;
+        if end < lines.len()
+            && (hasher.finish().is_multiple_of(block_len as u64) || end - start >= 4 * block_len)
+        {
+            ret.push(end);
+            start = end;
+        }
+    }
+    ret
+}
+
+fn block_diff<T: Hash + Eq>(a: &[T], b: &[T], block_len: usize) -> Vec<LineDiff> {
+    let (pref_len, a_mid, b_mid, suff_len) = match_ends(a, b);
+    let blocks = |lines: &[T]| -> Vec<std::ops::Range<usize>> {
+        if lines.is_empty() {
+            return Vec::new();
+        }
+        let starts = block_starts(lines, block_len);
+        let ends = starts.iter().skip(1).cloned().chain(Some(lines.len()));
+        starts.iter().zip(ends).map(|(&s, e)| s..e).collect()
+    };
+    let a_blocks = blocks(a_mid);
+    let b_blocks = blocks(b_mid);
+    let a_slices = a_blocks
+        .iter()
+        .map(|r| &a_mid[r.clone()])
+        .collect::<Vec<_>>();
+    let b_slices = b_blocks
+        .iter()
+        .map(|r| &b_mid[r.clone()])
+        .collect::<Vec<_>>();
+
+    let mut ret = Vec::with_capacity(a.len().max(b.len()));
+    ret.extend((0..pref_len).map(|i| LineDiff::Keep(i, i)));
+    for d in diff(&a_slices, &b_slices) {
+        match d {
+            LineDiff::Keep(i, j) => {
+                let (a_range, b_range) = (a_blocks[i].clone(), b_blocks[j].clone());
+                ret.extend(
+                    a_range
+                        .zip(b_range)
+                        .map(|(x, y)| LineDiff::Keep(pref_len + x, pref_len + y)),
+                );
+            }
+            LineDiff::Delete(i) => {
+                ret.extend(a_blocks[i].clone().map(|x| LineDiff::Delete(pref_len + x)));
+            }
+            LineDiff::New(j) => {
+                ret.extend(b_blocks[j].clone().map(|y| LineDiff::New(pref_len + y)));
+            }
+        }
+    }
+    ret.extend(
+        (0..suff_len).map(|i| LineDiff::Keep(a.len() - suff_len + i, b.len() - suff_len + i)),
+    );
+    ret
+}
+
+fn chunked_diff<T: Hash + Eq>(a: &[T], b: &[T], block_len: usize) -> Vec<LineDiff> {
+    let blocks = block_diff(a, b, block_len);
+    let mut ret = Vec::with_capacity(blocks.len());
+    // Diffs the lines between the end of the last kept line and the given positions, which make up
+    // a region of changed blocks.
+    let mut start = (0, 0);
+    let refine = |ret: &mut Vec<LineDiff>, start: (usize, usize), end: (usize, usize)| {
+        let (a_start, b_start) = start;
+        ret.extend(
+            diff(&a[a_start..end.0], &b[b_start..end.1])
+                .into_iter()
+                .map(|d| match d {
+                    LineDiff::Keep(i, j) => LineDiff::Keep(a_start + i, b_start + j),
+                    LineDiff::Delete(i) => LineDiff::Delete(a_start + i),
+                    LineDiff::New(j) => LineDiff::New(b_start + j),
+                }),
+        );
+    };
+    for d in blocks {
+        if let LineDiff::Keep(i, j) = d {
+            refine(&mut ret, start, (i, j));
+            ret.push(d);
+            start = (i + 1, j + 1);
+        }
+    }
+    refine(&mut ret, start, (a.len(), b.len()));
+    ret
+}
+
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
@@ -265,6 +728,158 @@ mod tests {
         }
     }
 
+    fn myers() -> DiffOptions {
+        DiffOptions {
+            algorithm: Algorithm::Myers,
+            ..DiffOptions::default()
+        }
+    }
+
+    #[test]
+    fn myers_is_minimal() {
+        // Patience diff anchors on the unique line 9, which means deleting and re-inserting all
+        // the 1s; Myers just moves the 9.
+        let a = [9, 1, 1, 1];
+        let b = [1, 1, 1, 9];
+        let d = diff_with_options(&a, &b, &myers());
+        assert_valid(&a, &b, &d);
+        assert_eq!(d.iter().filter(|x| !matches!(x, Keep(..))).count(), 2);
+    }
+
+    #[test]
+    fn myers_max_cost() {
+        let a = [0, 1, 2, 3, 4, 5];
+        let b = [0, 2, 1, 4, 3, 5];
+        let options = DiffOptions {
+            max_cost: Some(2),
+            ..myers()
+        };
+        let d = diff_with_options(&a, &b, &options);
+        assert_valid(&a, &b, &d);
+        assert_eq!(
+            d,
+            vec![
+                Keep(0, 0),
+                Delete(1),
+                Delete(2),
+                Delete(3),
+                Delete(4),
+                New(1),
+                New(2),
+                New(3),
+                New(4),
+                Keep(5, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize() {
+        assert_eq!(Tokenizer::Lines.tokenize(""), Vec::<&str>::new());
+        assert_eq!(
+            Tokenizer::Lines.tokenize("a\n\nb\n"),
+            vec!["a\n", "\n", "b\n"]
+        );
+        assert_eq!(
+            Tokenizer::Words.tokenize("foo(bar,  baz)\n"),
+            vec!["foo", "(", "bar", ",", "  ", "baz", ")", "\n"]
+        );
+        assert_eq!(Tokenizer::Chars.tokenize("añb"), vec!["a", "ñ", "b"]);
+    }
+
+    #[test]
+    fn text_diff() {
+        let d = diff_text("a\nb\nc\n", "a\nc\n", &DiffOptions::default());
+        assert_eq!(d.a, vec!["a\n", "b\n", "c\n"]);
+        assert_eq!(d.b, vec!["a\n", "c\n"]);
+        assert_eq!(d.diff, vec![Keep(0, 0), Delete(1), Keep(2, 1)]);
+    }
+
+    #[test]
+    fn hunk_grouping() {
+        let a = (0..20).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b[2] = 100;
+        b[6] = 101;
+        b.remove(15);
+        let d = diff(&a, &b);
+
+        // With a context of 1, the first two changes are separate.
+        let h = hunks(&d, 1);
+        assert_eq!(h.len(), 3);
+        assert_eq!(
+            (h[0].a_start, h[0].a_len, h[0].b_start, h[0].b_len),
+            (1, 3, 1, 3)
+        );
+        assert_eq!(h[0].lines, vec![Keep(1, 1), Delete(2), New(2), Keep(3, 3)]);
+        assert_eq!(
+            (h[2].a_start, h[2].a_len, h[2].b_start, h[2].b_len),
+            (14, 3, 14, 2)
+        );
+
+        // With a context of 2, their contexts touch.
+        let h = hunks(&d, 2);
+        assert_eq!(h.len(), 2);
+        assert_eq!(
+            (h[0].a_start, h[0].a_len, h[0].b_start, h[0].b_len),
+            (0, 9, 0, 9)
+        );
+
+        assert_eq!(hunks(&d, 100).len(), 1);
+        assert!(hunks(&diff(&a, &a), 3).is_empty());
+    }
+
+    #[test]
+    fn block_diff_is_coarse() {
+        let a = (0..1000).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b[500] = 5000;
+        b.insert(200, 2000);
+        let options = DiffOptions {
+            algorithm: Algorithm::Blocks { block_len: 16 },
+            ..DiffOptions::default()
+        };
+        let d = diff_with_options(&a, &b, &options);
+        assert_valid(&a, &b, &d);
+
+        // Both changes are there, but they take more than one line each.
+        let deleted = d.iter().filter(|x| matches!(x, Delete(_))).count();
+        let new = d.iter().filter(|x| matches!(x, New(_))).count();
+        assert!(deleted > 1 && deleted < 200);
+        assert_eq!(new, deleted + 1);
+        assert!(d.contains(&Keep(0, 0)));
+        assert!(d.contains(&Keep(999, 1000)));
+
+        assert_eq!(diff_with_options(&a, &a, &options), diff(&a, &a));
+    }
+
+    #[test]
+    fn chunked_diff_is_precise() {
+        let a = (0..1000).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b[500] = 5000;
+        b.insert(200, 2000);
+        let options = DiffOptions {
+            algorithm: Algorithm::Chunked { block_len: 16 },
+            ..DiffOptions::default()
+        };
+        let d = diff_with_options(&a, &b, &options);
+        assert_eq!(d, diff(&a, &b));
+        let changes = d.iter().filter(|x| !matches!(x, Keep(..))).count();
+        assert_eq!(changes, 3);
+    }
+
+    #[test]
+    fn hunk_at_end() {
+        let d = diff(&[1, 2, 3], &[1, 2, 3, 4]);
+        let h = hunks(&d, 1);
+        assert_eq!(h.len(), 1);
+        assert_eq!(
+            (h[0].a_start, h[0].a_len, h[0].b_start, h[0].b_len),
+            (2, 1, 2, 2)
+        );
+    }
+
     // We generate files by mostly generating common numbers (up to 10), and occasionally
     // rare numbers (up to 1000). The common numbers are to make diff's job harder, and the rare
     // numbers are to ensure that there are some unique lines.
@@ -316,5 +931,58 @@ mod tests {
             let d = diff(&f, &g);
             assert_valid(&f, &g, &d);
         }
+
+        #[test]
+        fn test_valid_myers_diff((f, g) in two_files(), max_cost in prop::option::of(0..20usize)) {
+            let options = DiffOptions { max_cost, ..myers() };
+            let d = diff_with_options(&f, &g, &options);
+            assert_valid(&f, &g, &d);
+
+            // The unbounded Myers diff is never bigger than the patience diff.
+            if max_cost.is_none() {
+                let cost = |d: &[LineDiff]| d.iter().filter(|x| !matches!(x, Keep(..))).count();
+                assert!(cost(&d) <= cost(&diff(&f, &g)));
+            }
+        }
+
+        #[test]
+        fn test_valid_block_diff((f, g) in two_files(), block_len in 1..8usize) {
+            let options = DiffOptions {
+                algorithm: Algorithm::Blocks { block_len },
+                ..DiffOptions::default()
+            };
+            let d = diff_with_options(&f, &g, &options);
+            assert_valid(&f, &g, &d);
+        }
+
+        #[test]
+        fn test_valid_chunked_diff((f, g) in two_files(), block_len in 1..8usize) {
+            let options = DiffOptions {
+                algorithm: Algorithm::Chunked { block_len },
+                ..DiffOptions::default()
+            };
+            let d = diff_with_options(&f, &g, &options);
+            assert_valid(&f, &g, &d);
+        }
+
+        #[test]
+        fn test_hunks_cover_changes((f, g) in two_files(), context in 0..4usize) {
+            let d = diff(&f, &g);
+            let h = hunks(&d, context);
+            let changes = d.iter().filter(|x| !matches!(x, Keep(..))).count();
+            let hunk_changes = h
+                .iter()
+                .flat_map(|h| h.lines.iter())
+                .filter(|x| !matches!(x, Keep(..)))
+                .count();
+            assert_eq!(changes, hunk_changes);
+            for h in &h {
+                let a_lines = h.lines.iter().filter_map(|x| match *x {
+                    Keep(i, _) | Delete(i) => Some(i),
+                    _ => None,
+                });
+                assert!(a_lines.eq(h.a_start..(h.a_start + h.a_len)));
+            }
+        }
     }
 }
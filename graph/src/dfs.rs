@@ -32,34 +32,39 @@ pub enum Visit<N> {
 //
 // (There is also a simpler non-recursive way to write DFS (described, e.g. on wikipedia), but that
 // one loses information about which edges we're traversing.)
+//
+// The types of the neighbor iterators can't be named, so we box them. This means one allocation
+// per visited node.
 struct StackFrame<'a, G: Graph + ?Sized> {
     u: G::Node,
     neighbors: Box<dyn Iterator<Item = G::Edge> + 'a>,
 }
 
-impl<'a, G: Graph + ?Sized> StackFrame<'a, G> {
-    fn new(g: &'a G, u: G::Node) -> StackFrame<'a, G> {
-        StackFrame {
-            neighbors: g.out_edges(&u),
-            u: u,
-        }
-    }
-}
-
 pub struct Dfs<'a, G: Graph + ?Sized> {
     g: &'a G,
     visited: HashSet<G::Node>,
     stack: Vec<StackFrame<'a, G>>,
     roots: Box<dyn Iterator<Item = G::Node> + 'a>,
+    // Nodes at this depth (where roots have depth zero) are visited, but their neighbors aren't.
+    max_depth: Option<usize>,
+    // If set, this is used to sort the out-edges of each node before visiting them.
+    sort: Option<fn(&mut Vec<G::Edge>)>,
 }
 
 impl<'a, G: Graph + ?Sized> Dfs<'a, G> {
     pub(crate) fn new(g: &'a G) -> Dfs<'a, G> {
+        Dfs::with_capacity(g, 0)
+    }
+
+    /// Creates a new DFS, with space for visiting `capacity` nodes without reallocating.
+    pub(crate) fn with_capacity(g: &'a G, capacity: usize) -> Dfs<'a, G> {
         Dfs {
             g: g,
-            visited: HashSet::new(),
+            visited: HashSet::with_capacity(capacity),
             stack: Vec::new(),
-            roots: g.nodes(),
+            roots: Box::new(g.nodes()),
+            max_depth: None,
+            sort: None,
         }
     }
 
@@ -69,9 +74,45 @@ impl<'a, G: Graph + ?Sized> Dfs<'a, G> {
             visited: HashSet::new(),
             stack: Vec::new(),
             roots: Box::new(Some(*root).into_iter()),
+            max_depth: None,
+            sort: None,
         }
     }
 
+    /// Limits the depth of the search: nodes that are `depth` edges away from their root will be
+    /// visited, but their out-edges won't be explored.
+    pub fn with_max_depth(mut self, depth: usize) -> Dfs<'a, G> {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Stops exploring the out-edges of the current node (i.e. the node at the top of the DFS
+    /// stack), so that the next visit will be a retreat from it.
+    ///
+    /// Right after a [`Visit::Root`] or a [`Status::New`] edge, the current node is the one that
+    /// was just discovered, so this skips everything below it.
+    pub fn prune(&mut self) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.neighbors = Box::new(std::iter::empty());
+        }
+    }
+
+    // Marks `u` as visited and pushes it onto the stack.
+    fn push(&mut self, u: G::Node) {
+        let neighbors: Box<dyn Iterator<Item = G::Edge> + 'a> =
+            if self.max_depth.is_some_and(|d| self.stack.len() >= d) {
+                Box::new(std::iter::empty())
+            } else if let Some(sort) = self.sort {
+                let mut edges = self.g.out_edges(&u).collect::<Vec<_>>();
+                sort(&mut edges);
+                Box::new(edges.into_iter())
+            } else {
+                Box::new(self.g.out_edges(&u))
+            };
+        self.visited.insert(u);
+        self.stack.push(StackFrame { u, neighbors });
+    }
+
     fn next_root(&mut self) -> Option<G::Node> {
         while let Some(root) = self.roots.next() {
             if !self.visited.contains(&root) {
@@ -86,6 +127,21 @@ impl<'a, G: Graph + ?Sized> Dfs<'a, G> {
     }
 }
 
+impl<'a, G: Graph + ?Sized> Dfs<'a, G>
+where
+    G::Node: Ord,
+{
+    /// Makes the order of the search deterministic, by visiting roots and neighbors in increasing
+    /// order.
+    pub fn sorted(mut self) -> Dfs<'a, G> {
+        let mut roots = self.roots.collect::<Vec<_>>();
+        roots.sort();
+        self.roots = Box::new(roots.into_iter());
+        self.sort = Some(|edges| edges.sort_by_key(|e| e.target()));
+        self
+    }
+}
+
 impl<'a, G: Graph + ?Sized> Iterator for Dfs<'a, G> {
     type Item = Visit<G::Node>;
 
@@ -97,8 +153,7 @@ impl<'a, G: Graph + ?Sized> Iterator for Dfs<'a, G> {
                 let status = if self.visited.contains(&next) {
                     Status::Repeated
                 } else {
-                    self.stack.push(StackFrame::new(self.g, next));
-                    self.visited.insert(next);
+                    self.push(next);
                     Status::New
                 };
                 Some(Visit::Edge {
@@ -114,8 +169,7 @@ impl<'a, G: Graph + ?Sized> Iterator for Dfs<'a, G> {
                 })
             }
         } else if let Some(next_root) = self.next_root() {
-            self.stack.push(StackFrame::new(self.g, next_root));
-            self.visited.insert(next_root);
+            self.push(next_root);
             Some(Visit::Root(next_root))
         } else {
             None
@@ -208,4 +262,58 @@ mod tests {
             Retreat { u: 0, parent: None },
         ]
     );
+
+    #[test]
+    fn prune() {
+        let g = graph("0-1, 1-2, 0-3, 3-4");
+        let mut dfs = g.dfs_from(&0);
+        let mut visited = Vec::new();
+        while let Some(visit) = dfs.next() {
+            if let Edge { dst, status, .. } = visit {
+                visited.push(dst);
+                if status == New && dst == 1 {
+                    dfs.prune();
+                }
+            }
+        }
+        assert_eq!(visited, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn max_depth() {
+        let g = graph("0-1, 1-2, 2-3, 0-4");
+        let visited: Vec<_> = g
+            .dfs_from(&0)
+            .with_max_depth(2)
+            .filter_map(|v| match v {
+                Edge { dst, .. } => Some(dst),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(visited, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn sorted() {
+        let g = graph("3-1, 3-0, 1-2, 0-2");
+        let roots: Vec<_> = g
+            .dfs()
+            .sorted()
+            .filter_map(|v| match v {
+                Root(u) => Some(u),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(roots, vec![0, 1, 3]);
+
+        let visited: Vec<_> = g
+            .dfs_from(&3)
+            .sorted()
+            .filter_map(|v| match v {
+                Edge { dst, status, .. } => Some((dst, status)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(visited, vec![(0, New), (2, New), (1, New), (2, Repeated)]);
+    }
 }
@@ -13,8 +13,8 @@
 #[macro_use]
 extern crate proptest;
 
-use itertools::Itertools;
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::hash::Hash;
 
 pub mod dfs;
@@ -33,31 +33,38 @@ impl<N: Copy> Edge<N> for N {
     }
 }
 
+/// An edge that carries some extra data (for example, a weight or a kind) besides its target.
+///
+/// Algorithms that only care about the extra data can be written generically using
+/// [`Graph::label_filtered`] and [`Graph::shortest_paths`], without knowing about the concrete
+/// edge type.
+pub trait LabeledEdge<N>: Edge<N> {
+    type Label;
+
+    fn label(&self) -> Self::Label;
+}
+
 pub trait Graph {
     type Node: Copy + Eq + Hash;
     type Edge: Copy + Eq + Edge<Self::Node>;
 
-    // Once impl iterator is available in traits, unbox these.
-    fn nodes<'a>(&'a self) -> Box<dyn Iterator<Item = Self::Node> + 'a>;
-    fn out_edges<'a>(&'a self, u: &Self::Node) -> Box<dyn Iterator<Item = Self::Edge> + 'a>;
-    fn in_edges<'a>(&'a self, u: &Self::Node) -> Box<dyn Iterator<Item = Self::Edge> + 'a>;
+    // The iterators returned by these methods only borrow `self`, and not `u`.
+    fn nodes<'a>(&'a self) -> impl Iterator<Item = Self::Node> + use<'a, Self>;
+    fn out_edges<'a>(&'a self, u: &Self::Node) -> impl Iterator<Item = Self::Edge> + use<'a, Self>;
+    fn in_edges<'a>(&'a self, u: &Self::Node) -> impl Iterator<Item = Self::Edge> + use<'a, Self>;
 
     fn out_neighbors<'a>(
         &'a self,
         u: &Self::Node,
-    ) -> std::iter::Map<Box<dyn Iterator<Item = Self::Edge> + 'a>, fn(Self::Edge) -> Self::Node>
-    {
-        self.out_edges(u)
-            .map((|e| e.target()) as fn(Self::Edge) -> Self::Node)
+    ) -> impl Iterator<Item = Self::Node> + use<'a, Self> {
+        self.out_edges(u).map(|e| e.target())
     }
 
     fn in_neighbors<'a>(
         &'a self,
         u: &Self::Node,
-    ) -> std::iter::Map<Box<dyn Iterator<Item = Self::Edge> + 'a>, fn(Self::Edge) -> Self::Node>
-    {
-        self.in_edges(u)
-            .map((|e| e.target()) as fn(Self::Edge) -> Self::Node)
+    ) -> impl Iterator<Item = Self::Node> + use<'a, Self> {
+        self.in_edges(u).map(|e| e.target())
     }
 
     fn dfs<'a>(&'a self) -> dfs::Dfs<'a, Self> {
@@ -145,6 +152,50 @@ pub trait Graph {
         }
     }
 
+    /// Returns the subgraph of this graph containing all the edges whose labels satisfy the
+    /// predicate.
+    fn label_filtered<'a, F>(&'a self, predicate: F) -> LabelFiltered<'a, Self, F>
+    where
+        Self::Edge: LabeledEdge<Self::Node>,
+        F: Fn(&<Self::Edge as LabeledEdge<Self::Node>>::Label) -> bool,
+    {
+        LabelFiltered {
+            predicate,
+            graph: self,
+        }
+    }
+
+    /// Computes the lengths of the shortest paths from `root` to every node that is reachable from
+    /// it, where the length of an edge is given by `weight`.
+    ///
+    /// The returned map contains `root` itself (at distance zero), but no unreachable nodes.
+    fn shortest_paths<W>(&self, root: &Self::Node, weight: W) -> HashMap<Self::Node, u64>
+    where
+        W: Fn(&Self::Node, &Self::Edge) -> u64,
+    {
+        let mut dist = HashMap::new();
+        // Nodes aren't required to be `Ord`, so the heap refers to them by their index in
+        // `queued`.
+        let mut heap = BinaryHeap::new();
+        let mut queued = vec![*root];
+        heap.push(Reverse((0, 0)));
+        while let Some(Reverse((d, idx))) = heap.pop() {
+            let u = queued[idx];
+            if dist.contains_key(&u) {
+                continue;
+            }
+            dist.insert(u, d);
+            for e in self.out_edges(&u) {
+                let v = e.target();
+                if !dist.contains_key(&v) {
+                    heap.push(Reverse((d + weight(&u, &e), queued.len())));
+                    queued.push(v);
+                }
+            }
+        }
+        dist
+    }
+
     /// If this graph is acyclic, returns a topological sort of the vertices. Otherwise, returns
     /// `None`.
     fn top_sort<'a>(&'a self) -> Option<Vec<Self::Node>> {
@@ -184,22 +235,102 @@ pub trait Graph {
         Some(top_sort)
     }
 
-    fn linear_order<'a>(&'a self) -> Option<Vec<Self::Node>> {
-        if let Some(top) = self.top_sort() {
-            // A graph has a linear order if and only if it has a unique topological sort. A
-            // topological sort is unique if and only if every node in it has an edge pointing to
-            // the subsequent node.
-            for (u, v) in top.iter().tuple_windows() {
-                if self.out_neighbors(u).position(|x| x == *v).is_none() {
+    /// If this graph has a unique topological sort, returns it. Otherwise, returns `None`.
+    ///
+    /// A graph has a unique topological sort if and only if it is acyclic and every node in the
+    /// topological sort has an edge pointing to the subsequent node.
+    fn linear_order(&self) -> Option<Vec<Self::Node>> {
+        // We run Kahn's algorithm, repeatedly taking a node that has no remaining in-edges. The
+        // topological sort is unique if and only if there is never more than one node to choose
+        // from, so we can bail out as soon as there is. This visits every edge twice, regardless
+        // of the degrees of the nodes.
+        let nodes = self.nodes().collect::<Vec<_>>();
+        let mut in_degrees = HashMap::with_capacity(nodes.len());
+        for u in &nodes {
+            for v in self.out_neighbors(u) {
+                *in_degrees.entry(v).or_insert(0usize) += 1;
+            }
+        }
+
+        let mut next = None;
+        for u in &nodes {
+            if !in_degrees.contains_key(u) && next.replace(*u).is_some() {
+                return None;
+            }
+        }
+
+        let mut ret = Vec::with_capacity(nodes.len());
+        while let Some(u) = next.take() {
+            ret.push(u);
+            for v in self.out_neighbors(&u) {
+                let deg = in_degrees.get_mut(&v)?;
+                *deg -= 1;
+                if *deg == 0 && next.replace(v).is_some() {
                     return None;
                 }
             }
-            Some(top)
+        }
+
+        // If we didn't get to every node, the rest of them are stuck on a cycle.
+        if ret.len() == nodes.len() {
+            Some(ret)
         } else {
             None
         }
     }
 
+    /// If this graph is acyclic, divides its nodes into layers so that every edge goes from an
+    /// earlier layer to a later one. Otherwise, returns `None`.
+    ///
+    /// Each node goes in the layer given by the length of the longest path ending at it, so the
+    /// first layer contains the nodes without in-edges. Since every path goes strictly forwards
+    /// through the layers, the nodes in each layer are unordered relative to one another (that is,
+    /// they form an antichain). Within a layer, the nodes are in the order that `nodes` returns
+    /// them.
+    fn antichain_layers(&self) -> Option<Vec<Vec<Self::Node>>> {
+        // This is Kahn's algorithm again, except that we keep track of the longest path to each
+        // node as we go.
+        let nodes = self.nodes().collect::<Vec<_>>();
+        let index = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, u)| (*u, i))
+            .collect::<HashMap<_, _>>();
+        let mut in_degrees = vec![0usize; nodes.len()];
+        for u in &nodes {
+            for v in self.out_neighbors(u) {
+                in_degrees[index[&v]] += 1;
+            }
+        }
+
+        let mut layers = vec![0usize; nodes.len()];
+        let mut ready = (0..nodes.len())
+            .filter(|&i| in_degrees[i] == 0)
+            .collect::<Vec<_>>();
+        let mut visited = 0;
+        while let Some(i) = ready.pop() {
+            visited += 1;
+            for v in self.out_neighbors(&nodes[i]) {
+                let j = index[&v];
+                layers[j] = layers[j].max(layers[i] + 1);
+                in_degrees[j] -= 1;
+                if in_degrees[j] == 0 {
+                    ready.push(j);
+                }
+            }
+        }
+        // If we didn't get to every node, the rest of them are stuck on a cycle.
+        if visited < nodes.len() {
+            return None;
+        }
+
+        let mut ret = vec![Vec::new(); layers.iter().max().map_or(0, |l| l + 1)];
+        for (u, layer) in nodes.into_iter().zip(layers) {
+            ret[layer].push(u);
+        }
+        Some(ret)
+    }
+
     /// Returns the set of all nodes that are adjacent (either an in-neighbor or an out-neighbor)
     /// to something in `set`.
     fn neighbor_set<'a, I: Iterator<Item = &'a Self::Node>>(&self, set: I) -> HashSet<Self::Node>
@@ -233,24 +364,23 @@ where
     type Node = G::Node;
     type Edge = G::Edge;
 
-    fn nodes<'b>(&'b self) -> Box<dyn Iterator<Item = G::Node> + 'b> {
-        Box::new(self.graph.nodes().filter(move |n| (self.predicate)(n)))
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = G::Node> + use<'a, 'b, G, F> {
+        self.graph.nodes().filter(move |n| (self.predicate)(n))
     }
 
-    fn out_edges<'b>(&'b self, u: &Self::Node) -> Box<dyn Iterator<Item = G::Edge> + 'b> {
-        Box::new(
-            self.graph
-                .out_edges(u)
-                .filter(move |e| (self.predicate)(&e.target())),
-        )
+    fn out_edges<'b>(
+        &'b self,
+        u: &Self::Node,
+    ) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G, F> {
+        self.graph
+            .out_edges(u)
+            .filter(move |e| (self.predicate)(&e.target()))
     }
 
-    fn in_edges<'b>(&'b self, u: &Self::Node) -> Box<dyn Iterator<Item = G::Edge> + 'b> {
-        Box::new(
-            self.graph
-                .in_edges(u)
-                .filter(move |e| (self.predicate)(&e.target())),
-        )
+    fn in_edges<'b>(&'b self, u: &Self::Node) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G, F> {
+        self.graph
+            .in_edges(u)
+            .filter(move |e| (self.predicate)(&e.target()))
     }
 }
 
@@ -272,26 +402,65 @@ where
     type Node = G::Node;
     type Edge = G::Edge;
 
-    fn nodes<'b>(&'b self) -> Box<dyn Iterator<Item = G::Node> + 'b> {
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = G::Node> + use<'a, 'b, G, F> {
         self.graph.nodes()
     }
 
-    fn out_edges<'b>(&'b self, u: &Self::Node) -> Box<dyn Iterator<Item = G::Edge> + 'b> {
+    fn out_edges<'b>(
+        &'b self,
+        u: &Self::Node,
+    ) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G, F> {
         let u = *u;
-        Box::new(
-            self.graph
-                .out_edges(&u)
-                .filter(move |e| (self.predicate)(&u, e)),
-        )
+        self.graph
+            .out_edges(&u)
+            .filter(move |e| (self.predicate)(&u, e))
     }
 
-    fn in_edges<'b>(&'b self, u: &Self::Node) -> Box<dyn Iterator<Item = G::Edge> + 'b> {
+    fn in_edges<'b>(&'b self, u: &Self::Node) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G, F> {
         let u = *u;
-        Box::new(
-            self.graph
-                .in_edges(&u)
-                .filter(move |e| (self.predicate)(&u, e)),
-        )
+        self.graph
+            .in_edges(&u)
+            .filter(move |e| (self.predicate)(&u, e))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LabelFiltered<'a, G, F>
+where
+    G: Graph + ?Sized,
+    G::Edge: LabeledEdge<G::Node>,
+    F: Fn(&<G::Edge as LabeledEdge<G::Node>>::Label) -> bool + 'a,
+{
+    predicate: F,
+    graph: &'a G,
+}
+
+impl<'a, G, F> Graph for LabelFiltered<'a, G, F>
+where
+    G: Graph + ?Sized,
+    G::Edge: LabeledEdge<G::Node>,
+    F: Fn(&<G::Edge as LabeledEdge<G::Node>>::Label) -> bool + 'a,
+{
+    type Node = G::Node;
+    type Edge = G::Edge;
+
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = G::Node> + use<'a, 'b, G, F> {
+        self.graph.nodes()
+    }
+
+    fn out_edges<'b>(
+        &'b self,
+        u: &Self::Node,
+    ) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G, F> {
+        self.graph
+            .out_edges(u)
+            .filter(move |e| (self.predicate)(&e.label()))
+    }
+
+    fn in_edges<'b>(&'b self, u: &Self::Node) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G, F> {
+        self.graph
+            .in_edges(u)
+            .filter(move |e| (self.predicate)(&e.label()))
     }
 }
 
@@ -307,15 +476,15 @@ where
     type Node = G::Node;
     type Edge = G::Edge;
 
-    fn nodes<'b>(&'b self) -> Box<dyn Iterator<Item = G::Node> + 'b> {
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = G::Node> + use<'a, 'b, G> {
         self.graph.nodes()
     }
 
-    fn out_edges<'b>(&'b self, u: &Self::Node) -> Box<dyn Iterator<Item = G::Edge> + 'b> {
-        Box::new(self.graph.out_edges(u).chain(self.graph.in_edges(u)))
+    fn out_edges<'b>(&'b self, u: &Self::Node) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G> {
+        self.graph.out_edges(u).chain(self.graph.in_edges(u))
     }
 
-    fn in_edges<'b>(&'b self, u: &Self::Node) -> Box<dyn Iterator<Item = G::Edge> + 'b> {
+    fn in_edges<'b>(&'b self, u: &Self::Node) -> impl Iterator<Item = G::Edge> + use<'a, 'b, G> {
         self.out_edges(u)
     }
 }
@@ -323,9 +492,9 @@ where
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
 
-    use super::Graph;
+    use super::{Edge, Graph, LabeledEdge};
 
     #[derive(Clone, Debug)]
     pub struct Node {
@@ -349,16 +518,16 @@ mod tests {
         type Node = u32;
         type Edge = u32;
 
-        fn nodes<'a>(&'a self) -> Box<dyn Iterator<Item = u32> + 'a> {
-            Box::new(self.ids.iter().cloned())
+        fn nodes<'a>(&'a self) -> impl Iterator<Item = u32> + use<'a> {
+            self.ids.iter().cloned()
         }
 
-        fn out_edges<'a>(&'a self, u: &u32) -> Box<dyn Iterator<Item = u32> + 'a> {
-            Box::new(self.nodes[*u as usize].next.iter().cloned())
+        fn out_edges<'a>(&'a self, u: &u32) -> impl Iterator<Item = u32> + use<'a> {
+            self.nodes[*u as usize].next.iter().cloned()
         }
 
-        fn in_edges<'a>(&'a self, u: &u32) -> Box<dyn Iterator<Item = u32> + 'a> {
-            Box::new(self.nodes[*u as usize].prev.iter().cloned())
+        fn in_edges<'a>(&'a self, u: &u32) -> impl Iterator<Item = u32> + use<'a> {
+            self.nodes[*u as usize].prev.iter().cloned()
         }
     }
 
@@ -433,6 +602,96 @@ mod tests {
     linear_order_test!(linear_order_tree, "0-2, 2-3, 1-3", None);
     linear_order_test!(linear_order_diamond, "0-1, 0-2, 1-3, 2-3", None);
 
+    macro_rules! antichain_layers_test {
+        ($name:ident, $graph:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let g = graph($graph);
+                let layers = g.antichain_layers();
+                assert_eq!(layers, $expected);
+            }
+        };
+    }
+
+    antichain_layers_test!(
+        antichain_layers_chain,
+        "0-1, 1-3, 3-2",
+        Some(vec![vec![0], vec![1], vec![3], vec![2]])
+    );
+    antichain_layers_test!(antichain_layers_cycle, "0-1, 1-2, 2-3, 3-1", None);
+    antichain_layers_test!(
+        antichain_layers_diamond,
+        "0-1, 0-2, 0-3, 1-4, 2-4, 3-4",
+        Some(vec![vec![0], vec![1, 2, 3], vec![4]])
+    );
+    // Node 2 goes in the layer after node 1, even though it also has an edge straight from node 0.
+    antichain_layers_test!(
+        antichain_layers_longest_path,
+        "0-1, 1-2, 0-2",
+        Some(vec![vec![0], vec![1], vec![2]])
+    );
+    antichain_layers_test!(
+        antichain_layers_uneven,
+        "0-1, 1-2, 2-4, 0-3, 3-4",
+        Some(vec![vec![0], vec![1, 3], vec![2], vec![4]])
+    );
+
+    // An edge whose weight is the index of its target.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct WeightedEdge(u32);
+
+    impl Edge<u32> for WeightedEdge {
+        fn target(&self) -> u32 {
+            self.0
+        }
+    }
+
+    impl LabeledEdge<u32> for WeightedEdge {
+        type Label = u64;
+
+        fn label(&self) -> u64 {
+            u64::from(self.0)
+        }
+    }
+
+    struct Weighted(GraphData);
+
+    impl Graph for Weighted {
+        type Node = u32;
+        type Edge = WeightedEdge;
+
+        fn nodes<'a>(&'a self) -> impl Iterator<Item = u32> + use<'a> {
+            self.0.nodes()
+        }
+
+        fn out_edges<'a>(&'a self, u: &u32) -> impl Iterator<Item = WeightedEdge> + use<'a> {
+            self.0.out_edges(u).map(WeightedEdge)
+        }
+
+        fn in_edges<'a>(&'a self, u: &u32) -> impl Iterator<Item = WeightedEdge> + use<'a> {
+            self.0.in_edges(u).map(WeightedEdge)
+        }
+    }
+
+    #[test]
+    fn labeled_edges() {
+        let g = Weighted(graph("0-1, 1-3, 0-3, 3-2, 4-0"));
+        let dist = g.shortest_paths(&0, |_, e| e.label());
+        let expected: HashMap<u32, u64> =
+            vec![(0, 0), (1, 1), (3, 3), (2, 5)].into_iter().collect();
+        assert_eq!(dist, expected);
+
+        let filtered = g.label_filtered(|w| *w < 3);
+        assert_eq!(filtered.out_neighbors(&0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            filtered.in_neighbors(&0).collect::<Vec<_>>(),
+            Vec::<u32>::new()
+        );
+        let dist = filtered.shortest_paths(&0, |_, e| e.label());
+        let expected: HashMap<u32, u64> = vec![(0, 0), (1, 1)].into_iter().collect();
+        assert_eq!(dist, expected);
+    }
+
     // A strategy for generating arbitrary graphs (with up to 20 nodes and up to 40 edges).
     prop_compose! {
         [pub(crate)] fn arb_graph()
@@ -492,6 +751,48 @@ mod tests {
             }
         }
 
+        #[test]
+        fn linear_order_proptest(ref g in arb_graph()) {
+            // Compare against the definition: a topological sort in which every node has an edge
+            // to the next one.
+            let expected = g.top_sort().filter(|sort| {
+                sort.windows(2).all(|w| g.has_edge(w[0], w[1]))
+            });
+            assert_eq!(g.linear_order(), expected);
+        }
+
+        #[test]
+        fn linear_order_dag_proptest(ref g in arb_dag()) {
+            let expected = g.top_sort().filter(|sort| {
+                sort.windows(2).all(|w| g.has_edge(w[0], w[1]))
+            });
+            assert_eq!(g.linear_order(), expected);
+        }
+
+        #[test]
+        fn antichain_layers_proptest(ref g in arb_graph()) {
+            let layers = g.antichain_layers();
+            assert_eq!(layers.is_some(), g.top_sort().is_some());
+            if let Some(layers) = layers {
+                let layer_of = layers
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, layer)| layer.iter().map(move |u| (*u, i)))
+                    .collect::<HashMap<_, _>>();
+                assert_eq!(layer_of.len(), g.nodes().count());
+                for u in g.nodes() {
+                    // Every edge goes forwards, so no layer contains two ordered nodes.
+                    for v in g.out_neighbors(&u) {
+                        assert!(layer_of[&u] < layer_of[&v]);
+                    }
+                    // The layers are as early as possible.
+                    if layer_of[&u] > 0 {
+                        assert!(g.in_neighbors(&u).any(|v| layer_of[&v] + 1 == layer_of[&u]));
+                    }
+                }
+            }
+        }
+
         #[test]
         fn doubled_proptest(ref g in arb_graph()) {
             let d = g.doubled();
@@ -83,15 +83,15 @@ impl<G: Graph + ?Sized> Graph for Partition<G> {
     type Node = usize;
     type Edge = usize;
 
-    fn nodes<'a>(&'a self) -> Box<dyn Iterator<Item = usize>> {
-        Box::new(0..self.num_components())
+    fn nodes<'a>(&'a self) -> impl Iterator<Item = usize> + use<'a, G> {
+        0..self.num_components()
     }
 
-    fn out_edges<'a>(&'a self, u: &usize) -> Box<dyn Iterator<Item = usize> + 'a> {
-        Box::new(self.edges[&*u].iter().cloned())
+    fn out_edges<'a>(&'a self, u: &usize) -> impl Iterator<Item = usize> + use<'a, G> {
+        self.edges[u].iter().cloned()
     }
 
-    fn in_edges<'a>(&'a self, u: &usize) -> Box<dyn Iterator<Item = usize> + 'a> {
-        Box::new(self.back_edges[&*u].iter().cloned())
+    fn in_edges<'a>(&'a self, u: &usize) -> impl Iterator<Item = usize> + use<'a, G> {
+        self.back_edges[u].iter().cloned()
     }
 }
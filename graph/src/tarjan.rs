@@ -39,13 +39,19 @@ pub(crate) struct Tarjan<'a, G: Graph + ?Sized> {
     next_index: usize,
 }
 
+// This doesn't recurse: the DFS maintains its own stack, and we just follow along with its
+// visits. That means that it can handle huge strongly connected components (for example, a cycle
+// with many thousands of nodes) without overflowing the stack.
 impl<'a, G: Graph + ?Sized> Tarjan<'a, G> {
     pub fn from_graph(g: &'a G) -> Self {
+        // The graph doesn't necessarily know how many nodes it has, but if it does then we can
+        // avoid growing our maps.
+        let size_hint = g.nodes().size_hint().0;
         Tarjan {
             g,
-            dfs: g.dfs(),
-            stack: Vec::new(),
-            node_states: HashMap::new(),
+            dfs: Dfs::with_capacity(g, size_hint),
+            stack: Vec::with_capacity(size_hint),
+            node_states: HashMap::with_capacity(size_hint),
             next_index: 0,
         }
     }
@@ -116,6 +122,8 @@ impl<'a, G: Graph + ?Sized> Tarjan<'a, G> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeSet;
+
     use crate::tests::{arb_dag, arb_graph, graph};
     use crate::Graph;
 
@@ -142,6 +150,30 @@ mod tests {
     );
     tarjan_test!(diamond, "0-1, 0-2, 1-3, 2-3", [[0], [2], [1], [3]]);
 
+    #[test]
+    fn long_cycle() {
+        let size = 100_000;
+        let edges = (0..size)
+            .map(|i| format!("{}-{}", i, (i + 1) % size))
+            .collect::<Vec<_>>()
+            .join(",");
+        let sccs = graph(&edges).tarjan();
+        assert_eq!(sccs.num_components(), 1);
+        assert_eq!(sccs.part(0).len(), size);
+    }
+
+    // A slow but obviously correct way to find the strongly connected components: u and v are in
+    // the same component if and only if they are equal or there are paths in both directions.
+    fn reference_sccs<G: Graph<Node = u32>>(g: &G) -> HashSet<BTreeSet<u32>> {
+        g.nodes()
+            .map(|u| {
+                g.nodes()
+                    .filter(|&v| u == v || (g.has_path(&u, &v) && g.has_path(&v, &u)))
+                    .collect()
+            })
+            .collect()
+    }
+
     proptest! {
         #[test]
         fn tarjan_dag_proptest(ref g in arb_dag()) {
@@ -173,6 +205,17 @@ mod tests {
             }
         }
 
+        #[test]
+        fn tarjan_reference_proptest(ref g in arb_graph()) {
+            let sccs = g
+                .tarjan()
+                .into_parts()
+                .into_iter()
+                .map(|s| s.into_iter().collect::<BTreeSet<_>>())
+                .collect::<HashSet<_>>();
+            assert_eq!(sccs, reference_sccs(g));
+        }
+
         #[test]
         fn tarjan_scc_dag(ref g in arb_graph()) {
             let sccs = g.tarjan();
@@ -0,0 +1,106 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+//! Benchmarks for some realistic workloads. The fixtures are the same ones that are created by
+//! `ojo synthesize --preset`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use libojo::{PatchId, Repo, SynthPreset};
+
+// Synthesizes a history, and returns the contents of all of its patches (in the order that they
+// were applied).
+fn patch_data(preset: SynthPreset, size: usize) -> Vec<Vec<u8>> {
+    let mut repo = Repo::init_tmp();
+    repo.synthesize("master", preset, size)
+        .unwrap()
+        .iter()
+        .map(|p| repo.open_patch_data(p).unwrap().to_owned())
+        .collect()
+}
+
+// Creates a repository that knows about all the given patches, but has only applied the first
+// `applied` of them.
+fn repo_with_patches(data: &[Vec<u8>], applied: usize) -> (Repo, Vec<PatchId>) {
+    let mut repo = Repo::init_tmp();
+    let ids = data
+        .iter()
+        .map(|d| repo.register_patch(d).unwrap())
+        .collect::<Vec<_>>();
+    for id in &ids[..applied] {
+        repo.apply_patch("master", id).unwrap();
+    }
+    (repo, ids)
+}
+
+// The contents of a synthetic file with `n` lines, in which every `every`th line is changed.
+fn file(n: usize, every: usize) -> Vec<u8> {
+    (0..n)
+        .flat_map(|i| {
+            if i % every == 0 {
+                format!("Changed line {}\n", i)
+            } else {
+                format!("Line {}\n", i)
+            }
+            .into_bytes()
+        })
+        .collect()
+}
+
+fn apply_patches(c: &mut Criterion) {
+    // Every patch depends on the previous one, so applying the last one applies all of them.
+    let data = patch_data(SynthPreset::Appends, 1000);
+    c.bench_function("apply 1k patches", |b| {
+        b.iter_batched(
+            || repo_with_patches(&data, 0),
+            |(mut repo, ids)| repo.apply_patch("master", ids.last().unwrap()).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn render(c: &mut Criterion) {
+    let mut repo = Repo::init_tmp();
+    repo.synthesize("master", SynthPreset::Linear, 100_000)
+        .unwrap();
+    c.bench_function("render 100k lines", |b| {
+        b.iter(|| repo.file("master").unwrap())
+    });
+}
+
+fn resolve_pseudo_edges(c: &mut Criterion) {
+    // Applying the patch that deletes everything has to bridge the deleted lines with
+    // pseudo-edges.
+    let data = patch_data(SynthPreset::Tombstones, 10_000);
+    c.bench_function("delete 10k lines", |b| {
+        b.iter_batched(
+            || repo_with_patches(&data, 1),
+            |(mut repo, ids)| repo.apply_patch("master", &ids[1]).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn diff(c: &mut Criterion) {
+    let mut repo = Repo::init_tmp();
+    repo.synthesize("master", SynthPreset::Linear, 50_000)
+        .unwrap();
+    let new = file(50_000, 100);
+    c.bench_function("diff 50k lines", |b| {
+        b.iter(|| repo.diff("master", &new).unwrap())
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = apply_patches, render, resolve_pseudo_edges, diff
+}
+criterion_main!(benches);
@@ -0,0 +1,258 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::collections::HashSet;
+
+use crate::preview::closure;
+use crate::{Error, PatchId, Repo};
+
+/// The name of the temporary branch that holds the patches being tested during a bisection (see
+/// [`Repo::bisect_start`]).
+pub const BISECT_BRANCH: &str = "bisect";
+
+/// A search for the patch that introduced a regression, as started by [`Repo::bisect_start`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Bisect {
+    /// The branch that is being searched.
+    pub branch: String,
+    /// The patches that might have introduced the regression, in the order that they were applied
+    /// to `branch`.
+    pub candidates: Vec<PatchId>,
+    /// The patch that is currently being tested: [`BISECT_BRANCH`] contains this patch and its
+    /// dependencies.
+    pub testing: PatchId,
+}
+
+/// What to do next during a bisection, as returned by [`Repo::bisect_start`] and
+/// [`Repo::bisect_mark`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BisectStep {
+    /// The branch [`BISECT_BRANCH`] is ready to be tested. It contains `patch`, its dependencies
+    /// and any baselines (see [`Repo::create_baseline`]).
+    Test {
+        /// The patch being tested.
+        patch: PatchId,
+        /// How many patches might still have introduced the regression.
+        remaining: usize,
+    },
+    /// The search is over, and this is the patch that introduced the regression. The branch
+    /// [`BISECT_BRANCH`] has been deleted.
+    Found(PatchId),
+}
+
+impl Repo {
+    /// Starts searching `branch` for the patch that introduced a regression.
+    ///
+    /// The search assumes that `branch` is bad, that an empty branch is good, and that a single
+    /// patch is to blame: any set of patches is bad if and only if it contains that patch. At
+    /// each step, the branch [`BISECT_BRANCH`] is created with some of the patches from `branch`;
+    /// after testing it, report the result with [`Repo::bisect_mark`]. Each step roughly halves
+    /// the number of patches that could be to blame.
+    ///
+    /// Returns [`Error::BisectInProgress`] if there is already a bisection, and
+    /// [`Error::BranchExists`] if there's already a branch called [`BISECT_BRANCH`].
+    pub fn bisect_start(&mut self, branch: &str) -> Result<BisectStep, Error> {
+        if self.storage.bisect.is_some() {
+            return Err(Error::BisectInProgress);
+        }
+        self.inode(branch)?;
+        if self.storage.inode(BISECT_BRANCH).is_some() {
+            return Err(Error::BranchExists(BISECT_BRANCH.to_owned()));
+        }
+        // Baselines can't be unapplied, so they're in every branch that we test.
+        let candidates = self
+            .storage
+            .branch_patches_in_order(branch)
+            .iter()
+            .filter(|p| !self.is_baseline(p))
+            .cloned()
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(Error::NothingToBisect(branch.to_owned()));
+        }
+        self.transaction(|repo| repo.bisect_step(branch.to_owned(), candidates))
+    }
+
+    /// Records whether the branch [`BISECT_BRANCH`] is good or bad, and moves on to the next
+    /// step of the bisection.
+    pub fn bisect_mark(&mut self, good: bool) -> Result<BisectStep, Error> {
+        let bisect = self.storage.bisect.clone().ok_or(Error::NoBisect)?;
+        let tested = closure(&[bisect.testing], &self.storage.patch_deps, |_| false)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        // If the tested patches were good, the culprit is one of the others; if they were bad,
+        // it's one of them.
+        let candidates = bisect
+            .candidates
+            .into_iter()
+            .filter(|p| tested.contains(p) != good)
+            .collect::<Vec<_>>();
+        let branch = bisect.branch;
+        self.transaction(|repo| {
+            repo.delete_branch(BISECT_BRANCH)?;
+            repo.bisect_step(branch, candidates)
+        })
+    }
+
+    /// Returns the bisection that is currently in progress, if there is one.
+    pub fn bisect(&self) -> Option<&Bisect> {
+        self.storage.bisect.as_ref()
+    }
+
+    /// Abandons the bisection that is currently in progress, deleting the branch
+    /// [`BISECT_BRANCH`].
+    pub fn bisect_reset(&mut self) -> Result<(), Error> {
+        if self.storage.bisect.is_none() {
+            return Err(Error::NoBisect);
+        }
+        if self.storage.inode(BISECT_BRANCH).is_some() {
+            self.delete_branch(BISECT_BRANCH)?;
+        }
+        self.storage.bisect = None;
+        Ok(())
+    }
+
+    // Picks the next patch to test, out of the (non-empty) list of candidates, and creates a
+    // branch for testing it.
+    fn bisect_step(
+        &mut self,
+        branch: String,
+        candidates: Vec<PatchId>,
+    ) -> Result<BisectStep, Error> {
+        if candidates.len() == 1 {
+            self.storage.bisect = None;
+            return Ok(BisectStep::Found(candidates[0]));
+        }
+
+        // Testing a patch tells us whether the culprit is among the candidates that it depends
+        // on, so we want a patch that depends on about half of the candidates. (There's always
+        // one that depends on some but not all of them, because the first candidate to be
+        // applied doesn't depend on any of the others.)
+        let candidate_set = candidates.iter().collect::<HashSet<_>>();
+        let split = |p: &PatchId| {
+            let below = closure(&[*p], &self.storage.patch_deps, |_| false)
+                .iter()
+                .filter(|q| candidate_set.contains(q))
+                .count();
+            below.min(candidates.len() - below)
+        };
+        // The unwrap is ok because there are at least two candidates.
+        let testing = *candidates.iter().rev().max_by_key(|p| split(p)).unwrap();
+
+        let mut keep = closure(&[testing], &self.storage.patch_deps, |_| false);
+        keep.extend(self.patches(&branch).filter(|p| self.is_baseline(p)));
+        self.fork_branch_at(&branch, BISECT_BRANCH, &keep)?;
+        let remaining = candidates.len();
+        self.storage.bisect = Some(Bisect {
+            branch,
+            candidates,
+            testing,
+        });
+        Ok(BisectStep::Test {
+            patch: testing,
+            remaining,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Changes;
+
+    // Bisects `branch`, assuming that `culprit` is the patch to blame. Returns the patch that was
+    // found, and the number of steps that it took.
+    fn find(repo: &mut Repo, branch: &str, culprit: &PatchId) -> (PatchId, usize) {
+        let mut step = repo.bisect_start(branch).unwrap();
+        let mut steps = 0;
+        loop {
+            match step {
+                BisectStep::Test { .. } => {
+                    let good = !repo.is_applied(BISECT_BRANCH, culprit);
+                    step = repo.bisect_mark(good).unwrap();
+                    steps += 1;
+                }
+                BisectStep::Found(p) => {
+                    assert!(repo.bisect().is_none());
+                    assert!(repo.branches().all(|b| b != BISECT_BRANCH));
+                    return (p, steps);
+                }
+            }
+        }
+    }
+
+    fn patch(repo: &mut Repo, contents: &[u8]) -> PatchId {
+        let diff = repo.diff("master", contents).unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        repo.create_patch("Author", "Msg", changes).unwrap()
+    }
+
+    #[test]
+    fn linear() {
+        let mut repo = Repo::init_tmp();
+        let mut contents = Vec::new();
+        let mut patches = Vec::new();
+        for i in 0..7 {
+            contents.extend_from_slice(format!("line {}\n", i).as_bytes());
+            let p = patch(&mut repo, &contents);
+            repo.apply_patch("master", &p).unwrap();
+            patches.push(p);
+        }
+        for p in &patches {
+            let (found, steps) = find(&mut repo, "master", p);
+            assert_eq!(found, *p);
+            assert!(steps <= 3);
+        }
+    }
+
+    #[test]
+    fn independent() {
+        let mut repo = Repo::init_tmp();
+        let base = patch(&mut repo, b"a\nb\n");
+        repo.apply_patch("master", &base).unwrap();
+        // These all insert a line between "a" and "b", so they only depend on `base`.
+        let mut patches = vec![base];
+        for i in 0..5 {
+            patches.push(patch(&mut repo, format!("a\n{}\nb\n", i).as_bytes()));
+        }
+        repo.apply_patches("master", &patches).unwrap();
+        for p in &patches {
+            assert_eq!(find(&mut repo, "master", p).0, *p);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut repo = Repo::init_tmp();
+        assert!(matches!(
+            repo.bisect_start("master"),
+            Err(Error::NothingToBisect(_))
+        ));
+        assert!(matches!(repo.bisect_mark(true), Err(Error::NoBisect)));
+        assert!(matches!(repo.bisect_reset(), Err(Error::NoBisect)));
+
+        for contents in &[&b"a\n"[..], b"a\nb\n"] {
+            let p = patch(&mut repo, contents);
+            repo.apply_patch("master", &p).unwrap();
+        }
+        assert!(matches!(
+            repo.bisect_start("master"),
+            Ok(BisectStep::Test { remaining: 2, .. })
+        ));
+        assert!(matches!(
+            repo.bisect_start("master"),
+            Err(Error::BisectInProgress)
+        ));
+        repo.bisect_reset().unwrap();
+        assert!(repo.bisect().is_none());
+        assert!(repo.branches().all(|b| b != BISECT_BRANCH));
+    }
+}
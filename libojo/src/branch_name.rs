@@ -0,0 +1,140 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::Error;
+
+/// The name of a branch, which is guaranteed to be valid.
+///
+/// Branch names can be namespaced, like `user/feature`. Each `/`-separated component must be
+/// non-empty, must not be `.` or `..`, and must not start with `-` (so that it can't be confused
+/// with a command-line flag). Branch names must not contain whitespace, control characters, or
+/// the characters `*`, `?`, `[` and `\` (which are reserved for patterns; see
+/// [`BranchName::matches`]).
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BranchName(String);
+
+impl BranchName {
+    /// Returns the name as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Checks whether this name matches a glob-like pattern.
+    ///
+    /// In the pattern, `?` matches any single character and `*` matches any sequence of
+    /// characters, but neither of them matches `/`. For example, `user/*` matches `user/feature`
+    /// but not `user/feature/old`.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.chars().collect::<Vec<_>>();
+        let name = self.0.chars().collect::<Vec<_>>();
+        glob_matches(&pattern, &name)
+    }
+}
+
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => {
+            // Try to match the star against every prefix of the name that doesn't contain '/'.
+            let max = name.iter().position(|&c| c == '/').unwrap_or(name.len());
+            (0..=max).any(|i| glob_matches(rest, &name[i..]))
+        }
+        Some((&p, rest)) => match name.split_first() {
+            Some((&c, name_rest)) if p == c || (p == '?' && c != '/') => {
+                glob_matches(rest, name_rest)
+            }
+            _ => false,
+        },
+    }
+}
+
+fn check_component(component: &str) -> Result<(), &'static str> {
+    if component.is_empty() {
+        Err("it has an empty component")
+    } else if component == "." || component == ".." {
+        Err("it has a component that is \".\" or \"..\"")
+    } else if component.starts_with('-') {
+        Err("it has a component that starts with '-'")
+    } else if component
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control())
+    {
+        Err("it contains whitespace or control characters")
+    } else if component.contains(&['*', '?', '[', '\\'][..]) {
+        Err("it contains one of the reserved characters '*', '?', '[' or '\\'")
+    } else {
+        Ok(())
+    }
+}
+
+impl<'a> TryFrom<&'a str> for BranchName {
+    type Error = Error;
+
+    fn try_from(name: &'a str) -> Result<BranchName, Error> {
+        for component in name.split('/') {
+            check_component(component)
+                .map_err(|reason| Error::InvalidBranchName(name.to_owned(), reason))?;
+        }
+        Ok(BranchName(name.to_owned()))
+    }
+}
+
+impl AsRef<str> for BranchName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for BranchName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation() {
+        for name in &["master", "user/feature", "fix-123", "ünïcode", "a.b/c_d"] {
+            assert_eq!(BranchName::try_from(*name).unwrap().as_str(), *name);
+        }
+        for name in &[
+            "", "/", "user/", "/user", "a//b", ".", "a/../b", "-f", "a/-b", "a b", "a\nb", "a\tb",
+            "a*", "a?", "a[b]", "a\\b",
+        ] {
+            assert!(BranchName::try_from(*name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        let name = BranchName::try_from("user/feature").unwrap();
+        for pattern in &[
+            "user/feature",
+            "user/*",
+            "*/feature",
+            "*/*",
+            "user/feat?re",
+            "u*/f*e",
+        ] {
+            assert!(name.matches(pattern), "{:?}", pattern);
+        }
+        for pattern in &["user", "*", "user/feat", "?", "user?feature", "*/*/*"] {
+            assert!(!name.matches(pattern), "{:?}", pattern);
+        }
+        assert!(BranchName::try_from("ünïcode").unwrap().matches("?n?code"));
+    }
+}
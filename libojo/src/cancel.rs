@@ -0,0 +1,43 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle for cancelling long-running operations on a [`Repo`](crate::Repo), as returned by
+/// [`Repo::cancel_token`](crate::Repo::cancel_token).
+///
+/// Tokens can be cloned and sent to other threads; all the clones refer to the same repository.
+/// Cancellation is cooperative: the operation notices it the next time that it checks, and then
+/// fails with [`Error::Cancelled`](crate::Error::Cancelled).
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Asks the operation that is currently running to stop.
+    ///
+    /// If no cancellable operation is running, this does nothing: the flag is reset whenever a
+    /// cancellable operation starts.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Has cancellation been requested (since the last cancellable operation started)?
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+}
@@ -27,11 +27,25 @@ use crate::NodeId;
 /// This struct represents the same graph as a graggle, except that every chain has been "collapsed"
 /// into a single node. That is, you can think of a `ChainGraggle` as a graph in which every node
 /// represents a chain (possibly of length 1) in the original graph.
+///
+/// Chains are identified by their index, which is a `usize` between `0` and
+/// [`num_chains`](ChainGraggle::num_chains) (exclusive). These indices are the nodes of the
+/// [`Graph`] implementation, and they can be turned back into sequences of `NodeId`s using
+/// [`chain`](ChainGraggle::chain). Going the other way, [`chain_of`](ChainGraggle::chain_of)
+/// finds the chain that a `NodeId` belongs to.
+///
+/// Nodes that belong to a cycle in the original graph are never part of a (non-trivial) chain:
+/// each of them gets a length-1 chain of its own, and the chains that came from the same strongly
+/// connected component are grouped together in a "cluster" (see
+/// [`clusters`](ChainGraggle::clusters)).
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ChainGraggle {
     // TODO: allow retrieving liveness of NodeIds and type of edges.
     chains: Vec<Vec<NodeId>>,
+    // A map from each node of the original graph to the index of the chain containing it.
+    node_chains: BTreeMap<NodeId, usize>,
     edges: MMap<usize, usize>,
+    back_edges: MMap<usize, usize>,
     clusters: Vec<HashSet<usize>>,
 }
 
@@ -84,10 +98,28 @@ impl ChainGraggle {
     }
 
     /// Returns the sequence of `NodeId`s making up the chain at index `i`.
+    ///
+    /// The nodes are returned in order, so that (in the original graph) there is an edge from
+    /// each node in the chain to the next one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not smaller than [`num_chains`](ChainGraggle::num_chains).
     pub fn chain(&self, i: usize) -> &[NodeId] {
         &self.chains[i]
     }
 
+    /// Returns an iterator over all the chains, in order of their indices.
+    pub fn chains(&self) -> impl Iterator<Item = &[NodeId]> {
+        self.chains.iter().map(|c| &c[..])
+    }
+
+    /// Returns the index of the chain containing `node`, or `None` if `node` was not part of the
+    /// original graph.
+    pub fn chain_of(&self, node: &NodeId) -> Option<usize> {
+        self.node_chains.get(node).cloned()
+    }
+
     /// Returns an iterator over strongly connected components of the original graph.
     pub fn clusters(&self) -> impl Iterator<Item = &HashSet<usize>> {
         self.clusters.iter()
@@ -136,6 +168,7 @@ impl ChainGraggle {
         }
 
         let mut edges = MMap::new();
+        let mut back_edges = MMap::new();
 
         for u in g.nodes() {
             for v in g.out_neighbors(&u) {
@@ -146,6 +179,7 @@ impl ChainGraggle {
                 // edge between them.
                 if u_idx != v_idx {
                     edges.insert(u_idx, v_idx);
+                    back_edges.insert(v_idx, u_idx);
                 }
             }
         }
@@ -158,7 +192,9 @@ impl ChainGraggle {
 
         ChainGraggle {
             chains,
+            node_chains: node_part,
             edges,
+            back_edges,
             clusters,
         }
     }
@@ -171,18 +207,16 @@ impl Graph for ChainGraggle {
     type Node = usize;
     type Edge = usize;
 
-    fn nodes(&'_ self) -> Box<dyn Iterator<Item = usize> + '_> {
-        Box::new(0..self.chains.len())
+    fn nodes<'a>(&'a self) -> impl Iterator<Item = usize> + use<'a> {
+        0..self.chains.len()
     }
 
-    fn out_edges(&'_ self, u: &usize) -> Box<dyn Iterator<Item = usize> + '_> {
-        Box::new(self.edges.get(u).cloned())
+    fn out_edges<'a>(&'a self, u: &usize) -> impl Iterator<Item = usize> + use<'a> {
+        self.edges.get(u).cloned()
     }
 
-    // TODO: consider removing in_edges from the Graph trait and making it part of a different
-    // trait.
-    fn in_edges(&'_ self, _u: &usize) -> Box<dyn Iterator<Item = usize> + '_> {
-        panic!("in-edges not implemented for this graph");
+    fn in_edges<'a>(&'a self, u: &usize) -> impl Iterator<Item = usize> + use<'a> {
+        self.back_edges.get(u).cloned()
     }
 }
 
@@ -206,6 +240,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn chain_lookup() {
+        let graggle = graggle!(
+            live: 0, 1, 2, 3, 4
+            edges: 0-1, 1-2, 0-3, 3-4, 2-4
+        );
+        let decomp = ChainGraggle::from_graph(graggle.as_graggle().as_live_graph());
+        assert_eq!(decomp.num_chains(), 4);
+
+        let idx_1 = decomp.chain_of(&NodeId::cur(1)).unwrap();
+        assert_eq!(decomp.chain_of(&NodeId::cur(2)), Some(idx_1));
+        assert_eq!(decomp.chain(idx_1), &[NodeId::cur(1), NodeId::cur(2)]);
+        assert_eq!(decomp.chain_of(&NodeId::cur(5)), None);
+
+        let idx_0 = decomp.chain_of(&NodeId::cur(0)).unwrap();
+        let idx_4 = decomp.chain_of(&NodeId::cur(4)).unwrap();
+        assert!(decomp.out_neighbors(&idx_0).any(|i| i == idx_1));
+        assert!(decomp.in_neighbors(&idx_1).any(|i| i == idx_0));
+        assert_eq!(decomp.in_neighbors(&idx_4).count(), 2);
+    }
+
     proptest! {
         // Checks that the chains of the decomposition form a partition of the original node set.
         #[test]
@@ -219,6 +274,13 @@ mod tests {
 
             // Check that we got all the nodes once.
             assert_eq!(decomp_nodes.len(), d.as_graggle().nodes().count());
+
+            // Check that every node maps back to the chain containing it.
+            for (i, chain) in decomp.chains().enumerate() {
+                for u in chain {
+                    assert_eq!(decomp.chain_of(u), Some(i));
+                }
+            }
         }
     }
 }
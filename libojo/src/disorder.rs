@@ -0,0 +1,102 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use ojo_graph::Graph;
+use std::fmt;
+
+use crate::{NodeId, PatchId};
+
+/// The maximum number of nodes recorded in [`Disorder::nodes`].
+pub const MAX_DISORDER_NODES: usize = 5;
+
+/// A summary of why a graggle doesn't represent a totally ordered file (see
+/// [`Error::NotOrdered`](crate::Error::NotOrdered)).
+///
+/// This gives a rough idea of how much work it will take to resolve the graggle: every cycle needs
+/// to be broken, every unordered region needs an order to be chosen, and every line that was
+/// edited concurrently needs its contents to be chosen.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Disorder {
+    /// The number of cycles, i.e. of groups of nodes that all come before one another.
+    pub cycles: usize,
+    /// The number of places where some nodes (or cycles) are not ordered relative to one another.
+    pub unordered: usize,
+    /// The number of lines that were edited concurrently (see
+    /// [`Repo::edit_conflicts`](crate::Repo::edit_conflicts)).
+    pub edits: usize,
+    /// Some of the nodes involved in the cycles or the unordered regions, in the order that they
+    /// (roughly) appear in the graggle, followed by some of the lines that were edited
+    /// concurrently. At most [`MAX_DISORDER_NODES`] are recorded.
+    pub nodes: Vec<NodeId>,
+}
+
+impl Disorder {
+    /// Summarizes the problems that stop `graph` from being totally ordered.
+    pub(crate) fn of<G: Graph<Node = NodeId>>(graph: &G) -> Disorder {
+        let components = graph.tarjan();
+        // The graph of components is acyclic, so this can't fail.
+        let order = components.top_sort().unwrap_or_default();
+        // Represent each component by its smallest node, so that the result doesn't depend on the
+        // order of hash sets.
+        let rep = |i: usize| components.part(i).iter().min().cloned();
+
+        let mut ret = Disorder::default();
+        for (pos, &i) in order.iter().enumerate() {
+            if components.part(i).len() > 1 {
+                ret.cycles += 1;
+                ret.note(rep(i));
+            }
+            if let Some(&j) = order.get(pos + 1) {
+                if !components.out_neighbors(&i).any(|k| k == j) {
+                    ret.unordered += 1;
+                    ret.note(rep(i));
+                    ret.note(rep(j));
+                }
+            }
+        }
+        ret
+    }
+
+    /// Adds the lines that were edited concurrently, as returned by
+    /// [`Repo::edit_conflicts`](crate::Repo::edit_conflicts).
+    pub(crate) fn with_edits(mut self, edits: &[(NodeId, Vec<PatchId>)]) -> Disorder {
+        self.edits += edits.len();
+        for (u, _) in edits {
+            self.note(Some(*u));
+        }
+        self
+    }
+
+    fn note(&mut self, u: Option<NodeId>) {
+        if let Some(u) = u {
+            if self.nodes.len() < MAX_DISORDER_NODES && !self.nodes.contains(&u) {
+                self.nodes.push(u);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Disorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cycle(s) and {} unordered region(s)",
+            self.cycles, self.unordered
+        )?;
+        if self.edits > 0 {
+            write!(f, ", and {} line(s) with conflicting edits", self.edits)?;
+        }
+        if let Some(u) = self.nodes.first() {
+            write!(f, ", starting near node {}/{}", u.patch, u.node)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+// Rendering graggles in graphviz's `.dot` format.
+
+use ojo_graph::Graph;
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::{ChainGraggle, Error, Graggle, NodeId, PatchId, Repo};
+
+/// Options controlling which parts of a graggle get written by
+/// [`Repo::write_graph_dot`](crate::Repo::write_graph_dot).
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    /// If set, only nodes that were introduced by this patch are written.
+    pub patch: Option<PatchId>,
+    /// If set, only nodes in this set are written.
+    pub nodes: Option<HashSet<NodeId>>,
+    /// If true, deleted nodes are omitted.
+    pub live_only: bool,
+    /// If true, live nodes are omitted.
+    pub deleted_only: bool,
+    /// If true, long runs of nodes that have no conflicts between them are written as a single
+    /// node showing only the first and last lines of the run (and the number of lines in
+    /// between).
+    pub condense_chains: bool,
+}
+
+// With `condense_chains`, chains with more nodes than this get condensed.
+const MAX_CHAIN_LEN: usize = 3;
+
+pub(crate) fn write_dot<W: Write>(
+    repo: &Repo,
+    graggle: Graggle<'_>,
+    out: &mut W,
+    opts: &DotOptions,
+) -> Result<(), Error> {
+    let keep = |u: &NodeId| {
+        opts.patch.is_none_or(|p| u.patch == p)
+            && opts.nodes.as_ref().is_none_or(|a| a.contains(u))
+            && (!opts.live_only || graggle.is_live(u))
+            && (!opts.deleted_only || !graggle.is_live(u))
+    };
+    let full = graggle.as_full_graph();
+    let decomp = ChainGraggle::from_graph(full.node_filtered(keep));
+
+    writeln!(out, "digraph {{")?;
+    for idx in decomp.nodes() {
+        let chain = decomp.chain(idx);
+        let lines = if opts.condense_chains && chain.len() > MAX_CHAIN_LEN {
+            vec![
+                node_label(repo, graggle, &chain[0]),
+                format!(
+                    "<font color=\"gray\"><i>... {} more lines ...</i></font>",
+                    chain.len() - 2
+                ),
+                node_label(repo, graggle, &chain[chain.len() - 1]),
+            ]
+        } else {
+            chain
+                .iter()
+                .map(|id| node_label(repo, graggle, id))
+                .collect::<Vec<String>>()
+        };
+        let mut label = lines.join("<br align=\"left\"/>");
+        if lines.len() > 1 {
+            // Graphviz defaults to centering the text. To left-align it all, we put
+            // <br align="left"/> at the end of every line (including the last one).
+            label.push_str("<br align=\"left\"/>");
+        }
+        writeln!(
+            out,
+            "\"{}\" [shape=box, style=rounded, label=<{}>]",
+            idx, label
+        )?;
+
+        for nbr_idx in decomp.out_neighbors(&idx) {
+            writeln!(out, "\"{}\" -> \"{}\";", idx, nbr_idx)?;
+        }
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn short_node_id(n: &NodeId) -> String {
+    format!("{}/{:04}", escape(&n.patch.to_string()[0..4]), n.node)
+}
+
+fn node_label(repo: &Repo, graggle: Graggle<'_>, id: &NodeId) -> String {
+    let contents = String::from_utf8_lossy(repo.contents_in(graggle, id));
+    let label = format!(
+        "<font color=\"gray\">{}:</font> {}",
+        short_node_id(id),
+        escape(contents.trim_end())
+    );
+    if graggle.is_live(id) {
+        label
+    } else {
+        format!("<s>{}</s>", label)
+    }
+}
+
+// Escapes the characters that have a special meaning in graphviz's HTML-like labels.
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '&' => ret.push_str("&amp;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#x27;"),
+            '/' => ret.push_str("&#x2f;"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
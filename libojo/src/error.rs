@@ -18,12 +18,14 @@ use std::ffi::OsString;
 use std::path::PathBuf;
 use std::{self, fmt, io};
 
-use crate::{NodeId, PatchId};
+use crate::{Disorder, HookKind, Limit, NodeId, PatchId};
 
 #[derive(Debug)]
 pub enum PatchIdError {
     Base64Decode(base64::DecodeError),
     InvalidLength(usize),
+    MissingPrefix,
+    NonCanonical,
     Collision(crate::PatchId),
 }
 
@@ -40,11 +42,9 @@ impl fmt::Display for PatchIdError {
         match self {
             Base64Decode(e) => e.fmt(f),
             InvalidLength(n) => write!(f, "Found the wrong number of bytes: {}", n),
-            Collision(p) => write!(
-                f,
-                "Encountered a collision between patch hashes: {}",
-                p.to_base64()
-            ),
+            MissingPrefix => write!(f, "Patch ids must start with 'P'"),
+            NonCanonical => write!(f, "This is not the standard form of a patch id"),
+            Collision(p) => write!(f, "Encountered a collision between patch hashes: {}", p),
         }
     }
 }
@@ -62,48 +62,134 @@ impl std::error::Error for PatchIdError {
 
 #[derive(Debug)]
 pub enum Error {
+    AliasExists(String),
+    AmbiguousPatchPrefix(String),
+    Baseline(PatchId),
+    BisectInProgress,
     BranchExists(String),
+    Cancelled,
+    CrossFileEdge(NodeId, NodeId),
     CurrentBranch(String),
     DbCorruption,
+    DuplicateNode(NodeId),
     Encoding(std::string::FromUtf8Error),
+    ForeignPatch(PatchId, String),
+    HookFailed(HookKind, String),
     IdMismatch(PatchId, PatchId),
+    Inconsistent(String, String),
+    InTransaction,
+    InvalidBranchName(String, &'static str),
     Io(io::Error, String),
+    JournalCorruption(String),
+    LimitExceeded(PatchId, Limit, usize, usize),
     MissingDep(PatchId),
+    NeededBy(PatchId, PatchId),
     NoFilename(PathBuf),
+    NoBisect,
     NoParent(PathBuf),
+    NodeIndexTooLarge(NodeId),
     NonUtfFilename(OsString),
-    NotOrdered,
+    NotApplied(PatchId, String),
+    NotOrdered(Disorder),
+    NothingToBisect(String),
     PatchId(PatchIdError),
+    ProtectedBranch(String),
     RepoExists(PathBuf),
     RepoNotFound(PathBuf),
     Serde(serde_yaml::Error),
+    StillApplied(PatchId, String),
     UnknownBranch(String),
+    UnknownChange(String),
     UnknownNode(NodeId),
     UnknownPatch(PatchId),
+    UnknownPatchPrefix(String),
+    UnknownStash(usize),
+    UnsupportedChangesVersion(u32),
+    UnsupportedPackVersion(u32),
+    UnsupportedPath(PathBuf),
+    UnsupportedRepoVersion(u32),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Error::AliasExists(a) => write!(f, "The alias \"{}\" already exists", a),
+            Error::AmbiguousPatchPrefix(p) => {
+                write!(
+                    f,
+                    "There is more than one patch whose hash starts with {:?}",
+                    p
+                )
+            }
+            Error::Baseline(p) => write!(
+                f,
+                "Cannot unapply {}: it is a baseline, and the history before it is not available",
+                p
+            ),
+            Error::BisectInProgress => write!(f, "There is already a bisection in progress"),
             Error::BranchExists(b) => write!(f, "The branch \"{}\" already exists", b),
+            Error::Cancelled => write!(f, "The operation was cancelled"),
+            Error::CrossFileEdge(src, dest) => write!(
+                f,
+                "The nodes {:?} and {:?} are in different files, so they can't be joined by an edge",
+                src, dest
+            ),
             Error::CurrentBranch(b) => write!(f, "\"{}\" is the current branch", b),
             Error::DbCorruption => write!(f, "Found corruption in the database"),
+            Error::DuplicateNode(n) => write!(f, "The node {:?} was added more than once", n),
             Error::Encoding(e) => e.fmt(f),
-            Error::IdMismatch(actual, expected) => write!(
+            Error::ForeignPatch(p, id) => write!(
                 f,
-                "Expected {}, found {}",
-                expected.to_base64(),
-                actual.to_base64()
+                "The patch {} was created in a different repository ({})",
+                p.to_base64(),
+                id
             ),
+            Error::HookFailed(kind, msg) => write!(f, "The {} hook failed: {}", kind, msg),
+            Error::IdMismatch(actual, expected) => {
+                write!(f, "Expected {}, found {}", expected, actual)
+            }
+            Error::Inconsistent(b, problem) => {
+                write!(f, "The branch \"{}\" is inconsistent: {}", b, problem)
+            }
+            Error::InTransaction => write!(
+                f,
+                "The repository can't be written while a transaction is running"
+            ),
+            Error::InvalidBranchName(b, reason) => {
+                write!(f, "{:?} is not a valid branch name: {}", b, reason)
+            }
             Error::Io(e, msg) => write!(f, "I/O error: {}. Details: {}", msg, e),
-            Error::MissingDep(id) => write!(f, "Missing a dependency: {}", id.to_base64()),
+            Error::JournalCorruption(line) => {
+                write!(f, "Found a corrupted line in the journal: {:?}", line)
+            }
+            Error::LimitExceeded(p, limit, value, max) => write!(
+                f,
+                "The patch {} is too large: it has {} {}, but the limit is {}",
+                p,
+                value,
+                limit.noun(),
+                max
+            ),
+            Error::MissingDep(id) => write!(f, "Missing a dependency: {}", id),
+            Error::NeededBy(p, q) => write!(f, "The patch {} is needed by the patch {}", p, q),
             Error::NoFilename(p) => write!(f, "This path didn't end in a filename: {:?}", p),
+            Error::NoBisect => write!(f, "There is no bisection in progress"),
             Error::NoParent(p) => write!(f, "I could not find the parent directory of: {:?}", p),
+            Error::NodeIndexTooLarge(n) => write!(f, "The node id {:?} is too large", n),
             Error::NonUtfFilename(p) => {
                 write!(f, "This filename couldn't be converted to UTF-8: {:?}", p)
             }
-            Error::NotOrdered => write!(f, "The data does not represent a totally ordered file"),
+            Error::NotApplied(p, b) => {
+                write!(f, "The patch {} is not applied to the branch \"{}\"", p, b)
+            }
+            Error::NotOrdered(d) => write!(
+                f,
+                "The data does not represent a totally ordered file: it has {}",
+                d
+            ),
+            Error::NothingToBisect(b) => write!(f, "The branch \"{}\" has no patches to search", b),
             Error::PatchId(e) => write!(f, "Found a broken PatchId\n\tcaused by: {}", e),
+            Error::ProtectedBranch(b) => write!(f, "The branch \"{}\" is protected", b),
             Error::RepoExists(p) => write!(f, "There is already a repository in {:?}", p),
             Error::RepoNotFound(p) => write!(
                 f,
@@ -111,9 +197,42 @@ impl fmt::Display for Error {
                 p
             ),
             Error::Serde(e) => e.fmt(f),
+            Error::StillApplied(p, b) => write!(
+                f,
+                "The patch {} is still applied to the branch \"{}\"",
+                p, b
+            ),
             Error::UnknownBranch(b) => write!(f, "There is no branch named {:?}", b),
+            Error::UnknownChange(c) => write!(
+                f,
+                "The patch contains a change of an unknown kind ({:?}): it was probably created by \
+                 a newer version",
+                c
+            ),
             Error::UnknownNode(n) => write!(f, "There is no node with id {:?}", n),
-            Error::UnknownPatch(p) => write!(f, "There is no patch with hash {:?}", p.to_base64()),
+            Error::UnknownPatch(p) => write!(f, "There is no patch with hash {:?}", p.to_string()),
+            Error::UnknownPatchPrefix(p) => {
+                write!(f, "There is no patch whose hash starts with {:?}", p)
+            }
+            Error::UnknownStash(i) => write!(f, "There is no stash with index {}", i),
+            Error::UnsupportedChangesVersion(v) => write!(
+                f,
+                "Unsupported changes format version {}: the patch was probably created by a newer \
+                 version",
+                v
+            ),
+            Error::UnsupportedPackVersion(v) => write!(f, "Unsupported pack format version {}", v),
+            Error::UnsupportedPath(p) => write!(
+                f,
+                "The path {:?} is relative to a drive or to a root, which is not supported",
+                p
+            ),
+            Error::UnsupportedRepoVersion(v) => write!(
+                f,
+                "Unsupported repository format version {}: the repository was probably written by \
+                 a newer version",
+                v
+            ),
         }
     }
 }
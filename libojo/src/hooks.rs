@@ -0,0 +1,140 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::fmt;
+
+use crate::{Changes, Error, PatchId};
+
+/// The points at which a [`Repo`](crate::Repo) runs hooks.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HookKind {
+    /// Runs before a patch is created. If the hook fails, the patch isn't created.
+    PreCreatePatch,
+    /// Runs after some patches were applied to a branch.
+    PostApply,
+    /// Runs after the repository was written to disk.
+    PostWrite,
+    /// Runs repeatedly while the pseudo-edges of a branch are being resolved, to report progress.
+    CacheProgress,
+}
+
+impl HookKind {
+    /// All the different kinds of hooks.
+    pub const ALL: [HookKind; 4] = [
+        HookKind::PreCreatePatch,
+        HookKind::PostApply,
+        HookKind::PostWrite,
+        HookKind::CacheProgress,
+    ];
+
+    /// The name of this kind of hook, in kebab-case (for example, `"pre-create-patch"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            HookKind::PreCreatePatch => "pre-create-patch",
+            HookKind::PostApply => "post-apply",
+            HookKind::PostWrite => "post-write",
+            HookKind::CacheProgress => "cache-progress",
+        }
+    }
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The information that is passed to a hook when it runs.
+#[derive(Debug)]
+pub enum HookEvent<'a> {
+    /// A patch is about to be created.
+    PreCreatePatch {
+        /// The author of the new patch.
+        author: &'a str,
+        /// The description of the new patch.
+        description: &'a str,
+        /// The changes in the new patch.
+        changes: &'a Changes,
+    },
+    /// Some patches were applied to a branch.
+    PostApply {
+        /// The branch that the patches were applied to.
+        branch: &'a str,
+        /// The patches that were applied, in the order that they were applied. This includes the
+        /// patch that was requested and any of its dependencies that weren't already applied.
+        patches: &'a [PatchId],
+    },
+    /// The repository was written to disk.
+    PostWrite,
+    /// Some of the pseudo-edges of a branch were resolved.
+    CacheProgress {
+        /// The branch whose pseudo-edges are being resolved.
+        branch: &'a str,
+        /// How many components of deleted nodes have been handled so far.
+        done: usize,
+        /// How many components of deleted nodes need to be handled in total.
+        total: usize,
+    },
+}
+
+impl<'a> HookEvent<'a> {
+    /// The kind of hook that this event triggers.
+    pub fn kind(&self) -> HookKind {
+        match self {
+            HookEvent::PreCreatePatch { .. } => HookKind::PreCreatePatch,
+            HookEvent::PostApply { .. } => HookKind::PostApply,
+            HookEvent::PostWrite => HookKind::PostWrite,
+            HookEvent::CacheProgress { .. } => HookKind::CacheProgress,
+        }
+    }
+}
+
+/// A hook, as registered with [`Repo::register_hook`](crate::Repo::register_hook).
+///
+/// If the hook fails, it should return an error message.
+pub type Hook = Box<dyn Fn(&HookEvent<'_>) -> Result<(), String>>;
+
+// The hooks that are registered with a repository.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<(HookKind, Hook)>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.hooks.iter().map(|(kind, _)| kind))
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub fn register(&mut self, kind: HookKind, hook: Hook) {
+        self.hooks.push((kind, hook));
+    }
+
+    // Runs all the hooks that are interested in `event`, stopping at the first one that fails.
+    pub fn run(&self, event: &HookEvent<'_>) -> Result<(), Error> {
+        let kind = event.kind();
+        for (_, hook) in self.hooks.iter().filter(|(k, _)| *k == kind) {
+            hook(event).map_err(|msg| Error::HookFailed(kind, msg))?;
+        }
+        Ok(())
+    }
+
+    // Runs all the hooks that are interested in `event`. Since the event has already happened,
+    // failures are only logged.
+    pub fn run_post(&self, event: &HookEvent<'_>) {
+        if let Err(e) = self.run(event) {
+            error!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+//! Named iterator types that are returned by [`Repo`](crate::Repo) methods.
+//!
+//! Unlike `impl Iterator`, these can be named, and so they can be stored in structs.
+
+use std::collections::{btree_map, btree_set, hash_map};
+use std::convert::TryFrom;
+
+use crate::storage::INode;
+use crate::{BranchName, PatchId};
+
+/// An iterator over all the patches in a repository, in no particular order.
+///
+/// This is returned by [`Repo::all_patches`](crate::Repo::all_patches).
+#[derive(Clone, Debug)]
+pub struct PatchIter<'a> {
+    pub(crate) inner: hash_map::Keys<'a, PatchId, String>,
+}
+
+impl<'a> Iterator for PatchIter<'a> {
+    type Item = &'a PatchId;
+
+    fn next(&mut self) -> Option<&'a PatchId> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for PatchIter<'a> {}
+
+/// An iterator over the patches that are applied to a branch, in the order that they were
+/// applied.
+///
+/// This is returned by [`Repo::patches`](crate::Repo::patches).
+#[derive(Clone, Debug)]
+pub struct BranchPatchIter<'a> {
+    pub(crate) inner: std::slice::Iter<'a, PatchId>,
+}
+
+impl<'a> Iterator for BranchPatchIter<'a> {
+    type Item = &'a PatchId;
+
+    fn next(&mut self) -> Option<&'a PatchId> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for BranchPatchIter<'a> {
+    fn next_back(&mut self) -> Option<&'a PatchId> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for BranchPatchIter<'a> {}
+
+/// An iterator over the direct dependencies (or the direct dependents) of a patch.
+///
+/// This is returned by [`Repo::patch_deps`](crate::Repo::patch_deps) and
+/// [`Repo::patch_rev_deps`](crate::Repo::patch_rev_deps).
+#[derive(Clone, Debug)]
+pub struct DepIter<'a> {
+    pub(crate) inner: btree_set::Iter<'a, PatchId>,
+}
+
+impl<'a> Iterator for DepIter<'a> {
+    type Item = &'a PatchId;
+
+    fn next(&mut self) -> Option<&'a PatchId> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for DepIter<'a> {
+    fn next_back(&mut self) -> Option<&'a PatchId> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for DepIter<'a> {}
+
+/// An iterator over the aliases of a patch, in alphabetical order.
+///
+/// This is returned by [`Repo::patch_aliases`](crate::Repo::patch_aliases).
+#[derive(Clone, Debug)]
+pub struct AliasIter<'a> {
+    pub(crate) inner: btree_map::Iter<'a, String, PatchId>,
+    pub(crate) id: &'a PatchId,
+}
+
+impl<'a> Iterator for AliasIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let id = self.id;
+        self.inner
+            .find(|(_, p)| *p == id)
+            .map(|(alias, _)| alias.as_str())
+    }
+}
+
+/// An iterator over the names of branches, in alphabetical order.
+///
+/// This is returned by [`Repo::branches`](crate::Repo::branches).
+#[derive(Clone, Debug)]
+pub struct BranchIter<'a> {
+    pub(crate) inner: btree_map::Keys<'a, String, INode>,
+}
+
+impl<'a> Iterator for BranchIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.inner.next().map(|s| s.as_str())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for BranchIter<'a> {
+    fn next_back(&mut self) -> Option<&'a str> {
+        self.inner.next_back().map(|s| s.as_str())
+    }
+}
+
+impl<'a> ExactSizeIterator for BranchIter<'a> {}
+
+/// An iterator over the names of branches that match a pattern, in alphabetical order.
+///
+/// This is returned by [`Repo::branches_matching`](crate::Repo::branches_matching).
+#[derive(Clone, Debug)]
+pub struct MatchingBranchIter<'a> {
+    pub(crate) inner: BranchIter<'a>,
+    pub(crate) pattern: &'a str,
+}
+
+impl<'a> Iterator for MatchingBranchIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let pattern = self.pattern;
+        self.inner.find(|b| {
+            // Branches from before names were validated might not be valid names, but we can
+            // still match them against the pattern.
+            match BranchName::try_from(*b) {
+                Ok(name) => name.matches(pattern),
+                Err(_) => *b == pattern,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BranchIter, BranchPatchIter, Changes, Repo};
+
+    // The point of these types is that they can be stored, for example by a GUI that wants to
+    // hold onto them across frames.
+    struct Holder<'a> {
+        branches: BranchIter<'a>,
+        patches: BranchPatchIter<'a>,
+    }
+
+    #[test]
+    fn store_iterators() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        repo.create_branch("other").unwrap();
+
+        let mut holder = Holder {
+            branches: repo.branches(),
+            patches: repo.patches("master"),
+        };
+        assert_eq!(holder.branches.len(), 2);
+        assert_eq!(holder.branches.next_back(), Some("other"));
+        assert_eq!(holder.patches.next(), Some(&id));
+        assert_eq!(holder.patches.next(), None);
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use chrono::{DateTime, Utc};
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::{Error, PatchId, Repo};
+
+/// A high-level event that changed a repository.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum JournalEvent {
+    /// A new patch was created.
+    PatchCreated {
+        /// The new patch.
+        patch: PatchId,
+    },
+    /// A patch was applied to a branch.
+    PatchApplied {
+        /// The branch that the patch was applied to.
+        branch: String,
+        /// The patch that was applied.
+        patch: PatchId,
+    },
+    /// A patch was unapplied from a branch.
+    PatchUnapplied {
+        /// The branch that the patch was unapplied from.
+        branch: String,
+        /// The patch that was unapplied.
+        patch: PatchId,
+    },
+    /// A new branch was created.
+    BranchCreated {
+        /// The name of the new branch.
+        branch: String,
+    },
+    /// A branch was deleted.
+    BranchDeleted {
+        /// The name of the deleted branch.
+        branch: String,
+    },
+    /// The current branch changed.
+    BranchSwitched {
+        /// The name of the new current branch.
+        branch: String,
+    },
+}
+
+/// An entry in a repository's journal (see [`Repo::journal`]).
+///
+/// In the journal file, each entry is a single line consisting of whitespace-separated fields: the
+/// time (in RFC 3339 format), the kind of event (for example, `patch-applied`), and then the
+/// branch name and patch id, if the event has them. For example,
+///
+/// ```text
+/// 2019-01-01T12:00:00+00:00 patch-applied master PkaBNbP5iR6e-kc6fMWgNV5juRNZGMsCbJ35GUj7dVSw
+/// ```
+///
+/// Since branch names can contain whitespace, they are percent-encoded: every `%`, whitespace or
+/// control character is replaced by a `%` followed by two hex digits for each of its UTF-8 bytes.
+/// For example, the branch `my branch` appears as `my%20branch`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JournalEntry {
+    /// When the event happened.
+    pub time: DateTime<Utc>,
+    /// What happened.
+    pub event: JournalEvent,
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::JournalEvent::*;
+
+        write!(f, "{} ", self.time.to_rfc3339())?;
+        match &self.event {
+            PatchCreated { patch } => write!(f, "patch-created {}", patch),
+            PatchApplied { branch, patch } => {
+                write!(f, "patch-applied {} {}", escape(branch), patch)
+            }
+            PatchUnapplied { branch, patch } => {
+                write!(f, "patch-unapplied {} {}", escape(branch), patch)
+            }
+            BranchCreated { branch } => write!(f, "branch-created {}", escape(branch)),
+            BranchDeleted { branch } => write!(f, "branch-deleted {}", escape(branch)),
+            BranchSwitched { branch } => write!(f, "branch-switched {}", escape(branch)),
+        }
+    }
+}
+
+impl FromStr for JournalEntry {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<JournalEntry, Error> {
+        use self::JournalEvent::*;
+
+        let corrupt = || Error::JournalCorruption(line.to_owned());
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let time = fields
+            .first()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .ok_or_else(corrupt)?
+            .with_timezone(&Utc);
+        let patch = |s: &str| s.parse::<PatchId>().map_err(|_| corrupt());
+        let branch = |s: &str| unescape(s).ok_or_else(corrupt);
+        let event = match fields[1..] {
+            ["patch-created", p] => PatchCreated { patch: patch(p)? },
+            ["patch-applied", b, p] => PatchApplied {
+                branch: branch(b)?,
+                patch: patch(p)?,
+            },
+            ["patch-unapplied", b, p] => PatchUnapplied {
+                branch: branch(b)?,
+                patch: patch(p)?,
+            },
+            ["branch-created", b] => BranchCreated { branch: branch(b)? },
+            ["branch-deleted", b] => BranchDeleted { branch: branch(b)? },
+            ["branch-switched", b] => BranchSwitched { branch: branch(b)? },
+            _ => return Err(corrupt()),
+        };
+        Ok(JournalEntry { time, event })
+    }
+}
+
+// Percent-encodes the characters of a branch name that would break up a field of the journal.
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '%' || c.is_whitespace() || c.is_control() {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                ret.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+// Undoes `escape`, returning `None` if `s` isn't validly encoded.
+fn unescape(s: &str) -> Option<String> {
+    let mut ret = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            ret.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            ret.push(b);
+        }
+    }
+    String::from_utf8(ret).ok()
+}
+
+// The journal entries that were recorded since the repository was opened. The ones after the
+// first `written` were recorded since the last time the repository was written to disk.
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    entries: Vec<JournalEntry>,
+    written: Cell<usize>,
+}
+
+impl Journal {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Forgets the entries after the first `len`, which must not have been written yet.
+    pub fn truncate(&mut self, len: usize) {
+        debug_assert!(len >= self.written.get());
+        self.entries.truncate(len);
+    }
+}
+
+impl Repo {
+    /// The path of the file containing the journal.
+    pub fn journal_path(&self) -> PathBuf {
+        self.repo_dir.join("journal")
+    }
+
+    /// Records an event in the journal.
+    ///
+    /// The [`Repo`] methods that create patches, apply or unapply them, and create, delete or
+    /// switch branches all record their own events, so this is only needed for events that
+    /// happen outside of this library. Like everything else, new entries are only written to disk
+    /// by [`Repo::write`].
+    pub fn journal_append(&mut self, event: JournalEvent) {
+        self.journal.entries.push(JournalEntry {
+            time: Utc::now(),
+            event,
+        });
+    }
+
+    /// Reads all of the entries in the journal, oldest first.
+    ///
+    /// The journal is an append-only file that records every high-level change to the
+    /// repository, so that other tools can cheaply check whether anything happened since they last
+    /// looked. It only contains the changes that were written to disk, so this doesn't return
+    /// anything that happened since the last call to [`Repo::write`].
+    pub fn journal(&self) -> Result<Vec<JournalEntry>, Error> {
+        let data = match fs::read_to_string(self.journal_path()) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::Io(e, "failed to read the journal".to_owned())),
+        };
+        data.lines().map(str::parse).collect()
+    }
+
+    // Appends all the entries that haven't been written yet to the journal file.
+    pub(crate) fn write_journal(&self) -> Result<(), Error> {
+        let written = self.journal.written.get();
+        if written == self.journal.entries.len() {
+            return Ok(());
+        }
+
+        let mut out = String::new();
+        for entry in &self.journal.entries[written..] {
+            out.push_str(&entry.to_string());
+            out.push('\n');
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.journal_path())?;
+        file.write_all(out.as_bytes())?;
+        self.journal.written.set(self.journal.entries.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Changes;
+
+    #[test]
+    fn entry_round_trip() {
+        let time = DateTime::parse_from_rfc3339("2019-01-01T12:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        let patch = PatchId::cur();
+        for event in [
+            JournalEvent::PatchCreated { patch },
+            JournalEvent::PatchApplied {
+                branch: "user/feature".to_owned(),
+                patch,
+            },
+            JournalEvent::BranchSwitched {
+                branch: "master".to_owned(),
+            },
+            JournalEvent::BranchCreated {
+                branch: "my branch\t100%".to_owned(),
+            },
+        ] {
+            let entry = JournalEntry { time, event };
+            assert_eq!(entry.to_string().parse::<JournalEntry>().unwrap(), entry);
+        }
+        let entry = JournalEntry {
+            time,
+            event: JournalEvent::BranchDeleted {
+                branch: "my branch".to_owned(),
+            },
+        };
+        assert_eq!(
+            entry.to_string(),
+            "2019-01-01T12:00:00+00:00 branch-deleted my%20branch"
+        );
+        assert!(matches!(
+            "2019-01-01T12:00:00+00:00 patch-applied master".parse::<JournalEntry>(),
+            Err(Error::JournalCorruption(_))
+        ));
+        assert!(matches!(
+            "2019-01-01T12:00:00+00:00 branch-created my%2".parse::<JournalEntry>(),
+            Err(Error::JournalCorruption(_))
+        ));
+    }
+
+    #[test]
+    fn journal() {
+        let dir = std::env::temp_dir().join(format!("ojo-journal-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut repo = Repo::init(&dir).unwrap();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        assert!(repo.journal().unwrap().is_empty());
+        repo.write().unwrap();
+
+        repo.create_branch("other").unwrap();
+        repo.switch_branch("other").unwrap();
+        repo.write().unwrap();
+        // Writing again doesn't duplicate anything.
+        repo.write().unwrap();
+
+        let repo = Repo::open(&dir).unwrap();
+        let events = repo
+            .journal()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.event)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                JournalEvent::PatchCreated { patch: id },
+                JournalEvent::PatchApplied {
+                    branch: "master".to_owned(),
+                    patch: id
+                },
+                JournalEvent::BranchCreated {
+                    branch: "other".to_owned()
+                },
+                JournalEvent::BranchSwitched {
+                    branch: "other".to_owned()
+                },
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -33,27 +33,92 @@ extern crate proptest;
 extern crate pretty_assertions;
 
 use ojo_graph::Graph;
-use std::collections::HashSet;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::resolver::Resolution;
 
 // This module needs to go first, because it supplies some macros (for testing) that the other
 // modules use.
 #[macro_use]
 mod storage;
 
+mod bisect;
+mod branch_name;
+mod cancel;
 mod chain_graggle;
+mod disorder;
+mod dot;
 mod error;
+mod hooks;
+mod iter;
+mod journal;
+mod limits;
+mod lineage;
+mod lock;
+mod pack;
 mod patch;
+mod preview;
+#[cfg(test)]
+mod proptests;
+mod read_only;
 pub mod resolver;
-
+mod shallow;
+mod snapshot;
+mod stash;
+mod synth;
+mod transaction;
+
+pub use crate::bisect::{Bisect, BisectStep, BISECT_BRANCH};
+pub use crate::branch_name::BranchName;
+pub use crate::cancel::CancelToken;
 pub use crate::chain_graggle::ChainGraggle;
+pub use crate::disorder::{Disorder, MAX_DISORDER_NODES};
+pub use crate::dot::DotOptions;
 pub use crate::error::{Error, PatchIdError};
-pub use crate::patch::{Change, Changes, Patch, PatchId, UnidentifiedPatch};
+pub use crate::hooks::{Hook, HookEvent, HookKind};
+pub use crate::iter::{
+    AliasIter, BranchIter, BranchPatchIter, DepIter, MatchingBranchIter, PatchIter,
+};
+pub use crate::journal::{JournalEntry, JournalEvent};
+pub use crate::limits::{Limit, Limits};
+pub use crate::lineage::LineagePolicy;
+pub use crate::pack::{Pack, PACK_FORMAT_VERSION};
+pub use crate::patch::{
+    Change, Changes, ChangesBuilder, Patch, PatchHeader, PatchId, PatchStats, UnidentifiedPatch,
+    CHANGES_FORMAT_VERSION,
+};
+pub use crate::preview::ClearSummary;
+pub use crate::read_only::ReadOnlyRepo;
+pub use crate::snapshot::RepoSnapshot;
+pub use crate::stash::Stash;
 pub use crate::storage::graggle::{Edge, EdgeKind};
 pub use crate::storage::{File, FullGraph, Graggle, LiveGraph};
+pub use crate::synth::SynthPreset;
 pub use ojo_diff::LineDiff;
 
+/// The minimum length of the abbreviated patch ids returned by [`Repo::abbreviate_patch_id`].
+pub const MIN_PATCH_PREFIX_LEN: usize = 8;
+
+/// The version of the repository format, which is recorded in the repository's database and in
+/// the metadata of every patch that a [`Repo`] creates.
+///
+/// Repositories that were written before the version was recorded are migrated when they are
+/// opened.
+pub const REPO_FORMAT_VERSION: u32 = 1;
+
+/// The default for [`Repo::coarse_diff_lines`].
+pub const DEFAULT_COARSE_DIFF_LINES: usize = 200_000;
+
+// The average number of lines in a block, for the diffs computed by `Repo::diff_incremental` (see
+// `ojo_diff::Algorithm::Blocks`).
+const DIFF_BLOCK_LEN: usize = 32;
+
 /// A globally unique ID for identifying a node.
 #[derive(Clone, Copy, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct NodeId {
@@ -68,7 +133,7 @@ pub struct NodeId {
 impl std::fmt::Debug for NodeId {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         fmt.debug_tuple("NodeId")
-            .field(&format!("{}/{:?}", self.patch.to_base64(), self.node))
+            .field(&format!("{}/{:?}", self.patch, self.node))
             .finish()
     }
 }
@@ -92,6 +157,23 @@ impl NodeId {
     }
 }
 
+/// Resolves `path` relative to `base`.
+///
+/// Absolute paths (including UNC paths on Windows) are returned unchanged, and relative paths are
+/// joined onto `base`. Windows also has paths that are only partly relative, like `C:foo` (relative
+/// to the current directory of drive `C:`) and `\foo` (relative to the root of the current drive).
+/// Since their meaning depends on the state of the process, they are rejected with
+/// [`Error::UnsupportedPath`].
+fn resolve_path(base: &Path, path: &Path) -> Result<PathBuf, Error> {
+    if path.is_absolute() {
+        Ok(path.to_owned())
+    } else if path.has_root() || matches!(path.components().next(), Some(Component::Prefix(_))) {
+        Err(Error::UnsupportedPath(path.to_owned()))
+    } else {
+        Ok(base.join(path))
+    }
+}
+
 /// This is the main interface to a `ojo` repository.
 ///
 /// Be aware that any modifications made to a repository will not be saved unless [`Repo::write`]
@@ -109,44 +191,120 @@ pub struct Repo {
     pub current_branch: String,
 
     storage: storage::Storage,
+    hooks: hooks::Hooks,
+    cancel: CancelToken,
+    journal: journal::Journal,
+    transactions: transaction::Transactions,
 }
 
 impl Repo {
     /// Given the path of the root directory of a repository, returns the directory where ojo's data
     /// is stored.
+    ///
+    /// This is usually the `.ojo` subdirectory of the root directory. However, if `.ojo` is a file
+    /// (see [`Repo::link_repo_dir`]) then it contains the path of the directory where the data is
+    /// actually stored.
     fn repo_dir(dir: &Path) -> Result<PathBuf, Error> {
         let mut ret = dir.to_path_buf();
         ret.push(".ojo");
+        if ret.is_file() {
+            let link = fs::read_to_string(&ret)?;
+            // The link might have been written (or edited) on Windows, so allow "\r\n" too.
+            let link = link.trim_end_matches(&['\r', '\n'][..]);
+            return resolve_path(dir, Path::new(link));
+        }
         Ok(ret)
     }
 
-    /// Given the path of the root directory of a repository, returns the path containing ojo's
+    /// Given the directory where ojo's data is stored, returns the path containing ojo's
     /// serialized data.
-    fn db_path(dir: &Path) -> Result<PathBuf, Error> {
-        let mut ret = Repo::repo_dir(dir)?;
-        ret.push("db");
-        Ok(ret)
+    fn db_path(repo_dir: &Path) -> PathBuf {
+        repo_dir.join("db")
     }
 
     /// Opens the existing repository with the given root directory.
     pub fn open<P: AsRef<Path>>(dir: P) -> Result<Repo, Error> {
-        let db_path = Repo::db_path(dir.as_ref())?;
-        let db_file = fs::File::open(&db_path)?;
-        let db: Db = serde_yaml::from_reader(db_file)?;
+        let repo_dir = Repo::repo_dir(dir.as_ref())?;
+        Repo::open_with_repo_dir(dir, repo_dir)
+    }
+
+    /// Finds and opens the repository containing `start_dir`.
+    ///
+    /// The root directory of the repository is the closest ancestor of `start_dir` (possibly
+    /// `start_dir` itself) that contains a `.ojo` directory (or a `.ojo` file pointing to one; see
+    /// [`Repo::link_repo_dir`]).
+    pub fn discover<P: AsRef<Path>>(start_dir: P) -> Result<Repo, Error> {
+        let mut dir = start_dir.as_ref().to_path_buf();
+        loop {
+            if dir.join(".ojo").exists() {
+                return Repo::open(dir);
+            }
+            if !dir.pop() {
+                return Err(Error::RepoNotFound(start_dir.as_ref().to_owned()));
+            }
+        }
+    }
+
+    /// Opens the existing repository with the given root directory, whose data is stored in
+    /// `repo_dir` instead of in the usual place.
+    ///
+    /// If `repo_dir` is a relative path, it is relative to `dir`.
+    pub fn open_with_repo_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+        dir: P,
+        repo_dir: Q,
+    ) -> Result<Repo, Error> {
+        let repo_dir = resolve_path(dir.as_ref(), repo_dir.as_ref())?;
+        let db_path = Repo::db_path(&repo_dir);
+        let db_data = {
+            let _lock = lock::shared(&repo_dir)?;
+            fs::read_to_string(&db_path)?
+        };
+        let version: DbVersion = serde_yaml::from_str(&db_data)?;
+        let db = match version.format_version {
+            0 => {
+                let db: DbV0 = serde_yaml::from_str(&db_data)?;
+                Db {
+                    format_version: REPO_FORMAT_VERSION,
+                    current_branch: db.current_branch,
+                    storage: db.storage.migrate(),
+                }
+            }
+            REPO_FORMAT_VERSION => serde_yaml::from_str(&db_data)?,
+            v => return Err(Error::UnsupportedRepoVersion(v)),
+        };
+        let mut storage = db.storage;
+        storage.upgrade();
         Ok(Repo {
             root_dir: dir.as_ref().to_owned(),
-            repo_dir: Repo::repo_dir(dir.as_ref())?,
+            repo_dir,
             db_path,
             current_branch: db.current_branch,
-            storage: db.storage,
+            storage,
+            hooks: hooks::Hooks::default(),
+            cancel: CancelToken::default(),
+            journal: journal::Journal::default(),
+            transactions: transaction::Transactions::default(),
         })
     }
 
     /// Creates a repo at the given path (which should point to a directory).
     pub fn init<P: AsRef<Path>>(path: P) -> Result<Repo, Error> {
+        let repo_dir = Repo::repo_dir(path.as_ref())?;
+        Repo::init_with_repo_dir(path, repo_dir)
+    }
+
+    /// Creates a repo at the given path (which should point to a directory), but with its data
+    /// stored in `repo_dir` instead of in the usual place.
+    ///
+    /// If `repo_dir` is a relative path, it is relative to `path`. In order for [`Repo::open`] to
+    /// find the data, either call [`Repo::link_repo_dir`] or use [`Repo::open_with_repo_dir`].
+    pub fn init_with_repo_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        repo_dir: Q,
+    ) -> Result<Repo, Error> {
         let root_dir = path.as_ref().to_owned();
-        let repo_dir = Repo::repo_dir(&root_dir)?;
-        let db_path = Repo::db_path(&root_dir)?;
+        let repo_dir = resolve_path(&root_dir, repo_dir.as_ref())?;
+        let db_path = Repo::db_path(&repo_dir);
         if db_path.exists() {
             return Err(Error::RepoExists(repo_dir.clone()));
         }
@@ -154,15 +312,35 @@ impl Repo {
         let mut storage = storage::Storage::new();
         let master_inode = storage.allocate_inode();
         storage.set_inode("master", master_inode);
+        storage.identity = Some(lineage::new_identity(&repo_dir));
         Ok(Repo {
             root_dir,
             repo_dir,
             db_path,
             current_branch: "master".to_owned(),
             storage,
+            hooks: hooks::Hooks::default(),
+            cancel: CancelToken::default(),
+            journal: journal::Journal::default(),
+            transactions: transaction::Transactions::default(),
         })
     }
 
+    /// Writes a `.ojo` file in the root directory that points to the directory where this
+    /// repository's data is stored, so that [`Repo::open`] can find it.
+    pub fn link_repo_dir(&self) -> Result<(), Error> {
+        let link = self.root_dir.join(".ojo");
+        if link.exists() {
+            return Err(Error::RepoExists(link));
+        }
+        let repo_dir = self
+            .repo_dir
+            .to_str()
+            .ok_or_else(|| Error::NonUtfFilename(self.repo_dir.clone().into_os_string()))?;
+        fs::write(link, format!("{}\n", repo_dir))?;
+        Ok(())
+    }
+
     /// Creates a temporary in-memory repo that cannot be stored.
     pub fn init_tmp() -> Repo {
         let mut storage = storage::Storage::new();
@@ -175,33 +353,105 @@ impl Repo {
             db_path: PathBuf::new(),
             current_branch: "master".to_owned(),
             storage,
+            hooks: hooks::Hooks::default(),
+            cancel: CancelToken::default(),
+            journal: journal::Journal::default(),
+            transactions: transaction::Transactions::default(),
         }
     }
 
     /// Clears a branch, removing all of its patches.
+    ///
+    /// Returns [`Error::ProtectedBranch`] if the branch is protected (see
+    /// [`Repo::set_protected`]).
     pub fn clear(&mut self, branch: &str) -> Result<(), Error> {
         let inode = self.inode(branch)?;
-        self.storage.branch_patches.remove_all(branch);
+        self.check_unprotected(branch)?;
+        self.storage.clear_branch_patches(branch);
         self.storage.remove_graggle(inode);
         self.storage
             .set_graggle(inode, storage::graggle::GraggleData::new());
         Ok(())
     }
 
+    /// Checks the internal invariants of `branch`, returning [`Error::Inconsistent`] if any of
+    /// them fail.
+    ///
+    /// Among other things, this checks that every patch in `branch` has all of its dependencies
+    /// applied before it, and that `branch`'s graggle is the same as the one that we would get by
+    /// applying its patches in order to an empty branch. This is slow, and it should never fail;
+    /// it's mainly useful for testing.
+    pub fn check_branch(&self, branch: &str) -> Result<(), Error> {
+        let inode = self.inode(branch)?;
+        let patches = self
+            .patches(branch)
+            .map(|p| self.open_patch(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let changes = patches
+            .iter()
+            .map(|p| (*p.id(), p.changes()))
+            .collect::<Vec<_>>();
+        self.storage
+            .check_branch(branch, inode, &changes)
+            .map_err(|e| Error::Inconsistent(branch.to_owned(), e))
+    }
+
+    /// Checks whether the pseudo-edges in `branch`'s graggle are wrong.
+    ///
+    /// Pseudo-edges are a cache that is kept up-to-date incrementally, so this should never
+    /// happen. If it does, [`Repo::rebuild_pseudo_edges`] will fix it.
+    pub fn stale_pseudo_edges(&self, branch: &str) -> Result<bool, Error> {
+        let inode = self.inode(branch)?;
+        Ok(self.storage.stale_pseudo_edges(inode))
+    }
+
+    /// Throws away the pseudo-edges in `branch`'s graggle and recomputes them from scratch.
+    /// Returns `true` if they were wrong.
+    pub fn rebuild_pseudo_edges(&mut self, branch: &str) -> Result<bool, Error> {
+        let inode = self.inode(branch)?;
+        Ok(self.storage.rebuild_pseudo_edges(inode))
+    }
+
     /// Persists the repository to disk.
     ///
-    /// Any modifications that were previously made become permanent.
+    /// Any modifications that were previously made become permanent. This fails with
+    /// [`Error::InTransaction`] if it is called during [`Repo::transaction`].
     pub fn write(&self) -> Result<(), Error> {
+        if self.in_transaction() {
+            return Err(Error::InTransaction);
+        }
         let db = DbRef {
+            format_version: REPO_FORMAT_VERSION,
             current_branch: &self.current_branch,
             storage: &self.storage,
         };
         self.try_create_dir(&self.repo_dir)?;
-        let db_file = fs::File::create(&self.db_path)?;
-        serde_yaml::to_writer(db_file, &db)?;
+        let _lock = lock::exclusive(&self.repo_dir)?;
+
+        // Write to a temporary file and then move it into place, so that even someone who doesn't
+        // take the lock never sees a partially written database.
+        let tmp_path = self.db_path.with_extension("tmp");
+        let db_file = fs::File::create(&tmp_path)?;
+        serde_yaml::to_writer(&db_file, &db)?;
+        db_file.sync_all()?;
+        fs::rename(&tmp_path, &self.db_path)?;
+        self.write_journal()?;
+        self.hooks.run_post(&HookEvent::PostWrite);
         Ok(())
     }
 
+    /// Registers a hook, which will be called whenever an event of the given kind happens.
+    ///
+    /// Hooks of kind [`HookKind::PreCreatePatch`] can prevent the patch from being created by
+    /// returning an error. Failures of the other hooks are logged, but otherwise ignored: by the time
+    /// they run, it is too late to prevent whatever happened.
+    pub fn register_hook<F>(&mut self, kind: HookKind, hook: F)
+    where
+        F: Fn(&HookEvent<'_>) -> Result<(), String> + 'static,
+    {
+        self.hooks.register(kind, Box::new(hook));
+    }
+
     fn inode(&self, branch: &str) -> Result<storage::INode, Error> {
         Ok(self
             .storage
@@ -218,21 +468,250 @@ impl Repo {
         Ok(self.storage.graggle(inode))
     }
 
+    /// Returns hints about where the nodes in `branch` should go, for use with
+    /// [`OrderResolver::set_hints`](crate::resolver::OrderResolver::set_hints).
+    ///
+    /// The hint for a node is its position in the file that the patch introducing it was created
+    /// from. Nodes from patches that don't record positions have no hints.
+    pub fn ordering_hints(&self, branch: &str) -> Result<HashMap<NodeId, usize>, Error> {
+        let mut ret = HashMap::new();
+        for p in self.patches(branch) {
+            for ch in &self.open_patch(p)?.changes().changes {
+                if let Change::NewNode {
+                    id,
+                    line: Some(line),
+                    ..
+                } = ch
+                {
+                    ret.insert(*id, *line);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the patches in `branch` that created the file (see [`Change::CreateFile`]), in the
+    /// order that they were applied.
+    ///
+    /// Usually there is just one of these, but there could be none (for example, if the branch is
+    /// empty) or several (for example, if two independently created files were merged).
+    pub fn file_creators(&self, branch: &str) -> Result<Vec<PatchId>, Error> {
+        self.inode(branch)?;
+        let mut ret = Vec::new();
+        for p in self.patches(branch) {
+            if self.open_patch(p)?.changes().creates_file() {
+                ret.push(*p);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the creation times of the patches in `branch`, for use with
+    /// [`Ranking::Timestamps`](crate::resolver::Ranking::Timestamps).
+    // Patches don't have timestamps on wasm (see `PatchHeader::timestamp`).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn patch_timestamps(
+        &self,
+        branch: &str,
+    ) -> Result<HashMap<PatchId, chrono::DateTime<chrono::Utc>>, Error> {
+        self.inode(branch)?;
+        self.patches(branch)
+            .map(|p| Ok((*p, self.open_patch(p)?.header().timestamp)))
+            .collect()
+    }
+
     /// Retrieves the data associated with a branch, assuming that it represents a totally ordered
     /// file.
+    ///
+    /// Returns [`Error::NotOrdered`] if the branch isn't totally ordered, or if some of its lines
+    /// were edited concurrently (see [`Repo::edit_conflicts`]).
     pub fn file(&self, branch: &str) -> Result<File, Error> {
         let inode = self.inode(branch)?;
+        let graggle = self.storage.graggle(inode);
+        let live = graggle.as_live_graph();
+        let edits = self.storage.edit_conflicts(graggle);
+        match live.linear_order() {
+            Some(ref order) if edits.is_empty() => Ok(File::from_ids(order, &self.storage, inode)),
+            _ => Err(Error::NotOrdered(Disorder::of(&live).with_edits(&edits))),
+        }
+    }
+
+    /// Returns the lines of `branch` that were edited concurrently, together with the patches
+    /// that edited them (sorted by id). The lines are sorted by id too.
+    ///
+    /// A line was edited concurrently if several patches replaced its contents (see
+    /// [`Change::ReplaceContents`]) and none of them knows about all the others. None of the edits
+    /// takes precedence, so the branch isn't a file (see [`Repo::file`]) until a patch that knows
+    /// about all of them replaces or deletes the line, for example one made with
+    /// [`OrderResolver::replace_contents`](crate::resolver::OrderResolver::replace_contents). Use
+    /// [`Repo::replacement`] to find the contents that each patch gave to the line.
+    pub fn edit_conflicts(&self, branch: &str) -> Result<Vec<(NodeId, Vec<PatchId>)>, Error> {
+        let inode = self.inode(branch)?;
+        Ok(self.storage.edit_conflicts(self.storage.graggle(inode)))
+    }
+
+    /// Retrieves the contents that `patch` gave to the node `id`, when it replaced them in place.
+    ///
+    /// Returns [`Error::UnknownNode`] unless `patch` replaced the contents of `id` and is applied
+    /// to some branch.
+    pub fn replacement(&self, id: &NodeId, patch: &PatchId) -> Result<&[u8], Error> {
         self.storage
-            .graggle(inode)
-            .as_live_graph()
-            .linear_order()
-            .map(|ref order| File::from_ids(order, &self.storage))
-            .ok_or(Error::NotOrdered)
+            .try_replacement(id, patch)
+            .ok_or(Error::UnknownNode(*id))
+    }
+
+    /// Like [`Repo::file`], but also includes the nodes that were deleted.
+    ///
+    /// The live nodes are in the same order as in [`Repo::file`], and the deleted nodes are
+    /// placed in between them, consistently with the graggle's edges. (Use
+    /// [`Graggle::is_live`] to tell them apart.) Even if the live nodes are totally ordered,
+    /// there may be more than one valid position for a deleted node. Going through the file from
+    /// the start, whenever there is a choice of what comes next:
+    ///
+    /// 1. a node with a real edge (that is, not a pseudo-edge) from the previous node comes
+    ///    first, so that lines that were next to one another stay together;
+    /// 2. otherwise, a node that was introduced by an older patch (according to the patches'
+    ///    timestamps) comes first;
+    /// 3. otherwise, the smallest [`NodeId`] comes first.
+    ///
+    /// If the graggle has cycles (which can happen if they were resolved by deleting some of
+    /// their lines), there is no order that is consistent with all the edges. Then some deleted
+    /// node has to come before a node that it should come after: we take the one that is waiting
+    /// for the fewest other nodes, and then use the rules above.
+    ///
+    /// These rules only depend on the graggle and the patches' contents, so the result doesn't
+    /// depend on the order that the patches were applied in.
+    pub fn file_with_deleted(&self, branch: &str) -> Result<File, Error> {
+        // Check that the live nodes are totally ordered. If they are, they stay in the right order
+        // below, and every cycle goes through a deleted node.
+        self.file(branch)?;
+        let inode = self.inode(branch)?;
+        let graggle = self.storage.graggle(inode);
+        let order = self
+            .stable_order(graggle)
+            .ok_or_else(|| Error::NotOrdered(Disorder::of(&graggle.as_full_graph())))?;
+        Ok(File::from_ids(&order, &self.storage, inode))
+    }
+
+    // Finds the order of all the nodes in `graggle` that is described in
+    // `Repo::file_with_deleted`, or returns `None` if there isn't one (because there's a cycle
+    // without any deleted nodes).
+    fn stable_order(&self, graggle: Graggle<'_>) -> Option<Vec<NodeId>> {
+        let full = graggle.as_full_graph();
+        // Patches don't have timestamps on wasm (see `PatchHeader::timestamp`), so there we skip
+        // the second rule.
+        #[cfg(not(target_arch = "wasm32"))]
+        let time = |p: &PatchId| self.open_patch(p).ok().map(|p| p.header().timestamp);
+        #[cfg(target_arch = "wasm32")]
+        let time = |_: &PatchId| None::<()>;
+        // Opening a patch is expensive, so we only do it once per patch.
+        let mut times = HashMap::new();
+        let mut key = |u: NodeId| (*times.entry(u.patch).or_insert_with(|| time(&u.patch)), u);
+
+        // The number of in-edges of each node that hasn't been output yet, from other nodes that
+        // haven't been output yet.
+        let mut in_degrees = full.nodes().map(|u| (u, 0usize)).collect::<HashMap<_, _>>();
+        for u in full.nodes() {
+            for v in full.out_neighbors(&u) {
+                *in_degrees.entry(v).or_insert(0) += 1;
+            }
+        }
+        let mut ready = in_degrees
+            .iter()
+            .filter(|(_, &deg)| deg == 0)
+            .map(|(u, _)| key(*u))
+            .collect::<BTreeSet<_>>();
+
+        let mut ret = Vec::with_capacity(in_degrees.len());
+        while !in_degrees.is_empty() {
+            let next = match ready.iter().next() {
+                Some(&first) => ret
+                    .last()
+                    .and_then(|prev| {
+                        full.out_edges(prev)
+                            .filter(|e| e.kind != EdgeKind::Pseudo)
+                            .map(|e| key(e.dest))
+                            .filter(|k| ready.contains(k))
+                            .min()
+                    })
+                    .unwrap_or(first),
+                // Every remaining node is waiting for another one, so they're stuck on cycles.
+                None => {
+                    in_degrees
+                        .iter()
+                        .filter(|(u, _)| !graggle.is_live(u))
+                        .map(|(u, deg)| (*deg, key(*u)))
+                        .min()?
+                        .1
+                }
+            };
+            ready.remove(&next);
+            in_degrees.remove(&next.1);
+            ret.push(next.1);
+            for v in full.out_neighbors(&next.1) {
+                if let Some(deg) = in_degrees.get_mut(&v) {
+                    *deg -= 1;
+                    if *deg == 0 {
+                        ready.insert(key(v));
+                    }
+                }
+            }
+        }
+        Some(ret)
+    }
+
+    /// Writes the contents of a branch to `out`.
+    ///
+    /// Returns [`Error::NotOrdered`] (without writing anything) if the branch isn't totally
+    /// ordered.
+    pub fn write_file<W: std::io::Write>(&self, branch: &str, out: &mut W) -> Result<(), Error> {
+        let file = self.file(branch)?;
+        out.write_all(file.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes the graggle of a branch to `out`, in graphviz's `.dot` format.
+    ///
+    /// Chains of nodes are collapsed into a single box, and deleted nodes are struck out. Unlike
+    /// [`Repo::write_file`], this works even if the branch isn't totally ordered.
+    pub fn write_graph_dot<W: std::io::Write>(
+        &self,
+        branch: &str,
+        out: &mut W,
+        opts: &DotOptions,
+    ) -> Result<(), Error> {
+        dot::write_dot(self, self.graggle(branch)?, out, opts)
     }
 
     /// Retrieves the contents associated with a node.
-    pub fn contents(&self, id: &NodeId) -> &[u8] {
-        self.storage.contents(id)
+    ///
+    /// These are the contents that the node was created with; if the node's contents were later
+    /// replaced in place, use [`Repo::contents_in`] instead.
+    ///
+    /// Returns [`Error::UnknownNode`] if the node isn't in any patch that has been applied to a
+    /// branch of this repository.
+    pub fn contents(&self, id: &NodeId) -> Result<&[u8], Error> {
+        self.storage.try_contents(id).ok_or(Error::UnknownNode(*id))
+    }
+
+    /// Retrieves the contents of a node, as seen from a graggle in this repository.
+    pub fn contents_in(&self, graggle: Graggle<'_>, id: &NodeId) -> &[u8] {
+        self.storage.contents_in(graggle, id)
+    }
+
+    /// Returns an iterator over the live nodes in `branch`, together with their contents (as seen
+    /// from `branch`, like [`Repo::contents_in`]).
+    ///
+    /// The nodes come in no particular order. Unlike [`Repo::file`], this doesn't require the
+    /// branch to be totally ordered, and it doesn't copy any of the contents.
+    pub fn nodes<'a>(
+        &'a self,
+        branch: &str,
+    ) -> Result<impl Iterator<Item = (NodeId, &'a [u8])> + 'a, Error> {
+        let graggle = self.graggle(branch)?;
+        Ok(graggle
+            .nodes()
+            .map(move |id| (id, self.storage.contents_in(graggle, &id))))
     }
 
     /// Opens a patch.
@@ -250,6 +729,33 @@ impl Repo {
         }
     }
 
+    /// Returns the number of nodes and edges that a patch adds and deletes.
+    ///
+    /// This is usually much faster than opening the patch, because the statistics are computed
+    /// once, when the patch is registered.
+    pub fn patch_stats(&self, id: &PatchId) -> Result<PatchStats, Error> {
+        match self.storage.patch_stats.get(id) {
+            Some(stats) => Ok(*stats),
+            // Repositories written before we started recording statistics don't have them for
+            // patches that couldn't be parsed (see `Storage::upgrade`).
+            None => Ok(self.open_patch(id)?.changes().stats()),
+        }
+    }
+
+    /// Returns the header of a patch (its author, description, and so on).
+    ///
+    /// This is usually much faster than opening the patch, because the header is stored
+    /// separately when the patch is registered, so the patch's changes don't need to be parsed.
+    /// (The patch's dependencies are available from [`Repo::patch_deps`].)
+    pub fn patch_header(&self, id: &PatchId) -> Result<PatchHeader, Error> {
+        match self.storage.patch_headers.get(id) {
+            Some(header) => Ok(header.clone()),
+            // Repositories written before we started recording headers don't have them for
+            // patches that couldn't be parsed (see `Storage::upgrade`).
+            None => Ok(self.open_patch(id)?.header().clone()),
+        }
+    }
+
     /// Returns the data associated with a patch.
     ///
     /// Currently, this data consists of the patch's contents serialized as YAML, but that isn't
@@ -268,62 +774,257 @@ impl Repo {
     /// After registering a patch, its data will be stored in the repository and you will be able
     /// to access it by its ID.
     pub fn register_patch(&mut self, patch_data: &[u8]) -> Result<PatchId, Error> {
+        // Check the size before parsing, so that huge patches don't get parsed at all.
+        let max_size = self.storage.limits.max_patch_size;
+        if patch_data.len() > max_size {
+            return Err(Error::LimitExceeded(
+                PatchId::of_data(patch_data),
+                Limit::PatchSize,
+                patch_data.len(),
+                max_size,
+            ));
+        }
         let patch = Patch::from_reader(patch_data)?;
         let data = String::from_utf8(patch_data.to_owned())?;
         self.register_patch_with_data(&patch, data)?;
         Ok(*patch.id())
     }
 
+    /// Looks for a patch in this repository that makes the same changes as `changes`.
+    ///
+    /// The nodes introduced by `changes` should be referred to by [`NodeId::cur`], as they are
+    /// when passing `changes` to [`Repo::create_patch`] (or see [`Patch::unidentified_changes`]).
+    /// The line hints on new nodes are ignored, but otherwise the changes must be identical, and
+    /// in the same order. Since the new patch would introduce its own copies of the nodes, applying
+    /// both it and the equivalent patch would duplicate every line that they add.
+    pub fn find_equivalent_patch(&self, changes: &Changes) -> Result<Option<PatchId>, Error> {
+        fn same(a: &Change, b: &Change) -> bool {
+            match (a, b) {
+                (
+                    Change::NewNode {
+                        id: id_a,
+                        contents: contents_a,
+                        path: path_a,
+                        ..
+                    },
+                    Change::NewNode {
+                        id: id_b,
+                        contents: contents_b,
+                        path: path_b,
+                        ..
+                    },
+                ) => id_a == id_b && contents_a == contents_b && path_a == path_b,
+                _ => a == b,
+            }
+        }
+
+        let stats = changes.stats();
+        let mut candidates = self
+            .all_patches()
+            .filter(|p| self.storage.patch_stats.get(p).is_none_or(|s| *s == stats))
+            .cloned()
+            .collect::<Vec<_>>();
+        candidates.sort();
+        for id in candidates {
+            let other = self.open_patch(&id)?.unidentified_changes();
+            if other.changes.len() == changes.changes.len()
+                && other
+                    .changes
+                    .iter()
+                    .zip(&changes.changes)
+                    .all(|(a, b)| same(a, b))
+            {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Turns on (or off) checking the internal consistency of the repository's data after every
+    /// modification.
+    ///
+    /// The checks are fairly cheap, but not free. If they fail, something has gone badly wrong
+    /// inside this crate, and so they panic with a description of the problem (which is useful in a
+    /// bug report). Building with the `paranoid-checks` feature turns them on unconditionally.
+    pub fn set_paranoid(&mut self, paranoid: bool) {
+        self.storage.paranoid = paranoid;
+    }
+
+    /// Returns the limits on the size of the patches that this repository accepts.
+    pub fn limits(&self) -> Limits {
+        self.storage.limits
+    }
+
+    /// Changes the limits on the size of the patches that this repository accepts.
+    ///
+    /// This only affects patches that are registered from now on.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.storage.limits = limits;
+    }
+
+    /// Returns the identity of this repository's lineage, if it has one.
+    ///
+    /// Repositories created with [`Repo::init`] get a new identity, which is recorded in every
+    /// patch that they create. See [`LineagePolicy`] for what it's used for.
+    pub fn identity(&self) -> Option<&str> {
+        self.storage.identity.as_deref()
+    }
+
+    /// Changes the identity of this repository's lineage.
+    ///
+    /// This is mainly useful for making a new repository part of an existing lineage, so that it
+    /// can exchange patches with the other members without complaint. Patches that were already
+    /// created keep the identity that they were created with.
+    pub fn set_identity(&mut self, identity: Option<&str>) {
+        self.storage.identity = identity.map(|s| s.to_owned());
+    }
+
+    /// Returns what this repository does with patches from other lineages.
+    pub fn lineage_policy(&self) -> LineagePolicy {
+        self.storage.lineage_policy
+    }
+
+    /// Changes what this repository does with patches from other lineages.
+    ///
+    /// This only affects patches that are registered from now on.
+    pub fn set_lineage_policy(&mut self, policy: LineagePolicy) {
+        self.storage.lineage_policy = policy;
+    }
+
+    /// Returns the number of lines above which [`Repo::diff_incremental`] gives a coarse diff.
+    ///
+    /// If either of the files being diffed has more lines than this, the diff is computed on
+    /// blocks of lines instead of on single lines (see [`Diff::coarse`]).
+    pub fn coarse_diff_lines(&self) -> usize {
+        self.storage.coarse_diff_lines
+    }
+
+    /// Changes the number of lines above which [`Repo::diff_incremental`] gives a coarse diff.
+    pub fn set_coarse_diff_lines(&mut self, lines: usize) {
+        self.storage.coarse_diff_lines = lines;
+    }
+
+    /// Was `patch` created by a repository from a different lineage?
+    ///
+    /// This is only true if both this repository and the patch have an identity, and they differ.
+    pub fn is_foreign(&self, patch: &Patch) -> bool {
+        match (self.identity(), patch.header().repository()) {
+            (Some(ours), Some(theirs)) => ours != theirs,
+            _ => false,
+        }
+    }
+
     // Before making any modifications, check the patch for consistency. That means:
     // - all dependencies must already be known
     // - every node that we refer to must already be present
     // - every node that we refer to must be either new, or we must depend on its patch
+    // - new nodes must have distinct ids, and edges can't join nodes in different files
     // This part is *IMPORTANT*, because it contains all the validation for patches. After
     // this, they go from being treated as untrusted input to being internal data.
-    fn check_patch_validity(&self, patch: &Patch) -> Result<(), Error> {
+    // - the patch must be within the repository's limits
+    fn check_patch_validity(&self, patch: &Patch, size: usize) -> Result<(), Error> {
+        let limits = &self.storage.limits;
+        let (mut nodes, mut contents_size) = (0, 0);
+        for ch in &patch.changes().changes {
+            match ch {
+                Change::NewNode { contents, .. } => {
+                    nodes += 1;
+                    contents_size += contents.len();
+                }
+                Change::ReplaceContents { new_contents, .. } => {
+                    contents_size += new_contents.len();
+                }
+                _ => {}
+            }
+        }
+        for &(limit, value) in &[
+            (Limit::Nodes, nodes),
+            (Limit::PatchSize, size),
+            (Limit::ContentsSize, contents_size),
+        ] {
+            if value > limits.get(limit) {
+                return Err(Error::LimitExceeded(
+                    *patch.id(),
+                    limit,
+                    value,
+                    limits.get(limit),
+                ));
+            }
+        }
+
         for dep in patch.deps() {
             if !self.storage.patches.contains_key(dep) {
                 return Err(Error::MissingDep(*dep));
             }
         }
         let dep_set = patch.deps().iter().cloned().collect::<HashSet<_>>();
-        let new_nodes = patch
-            .changes()
-            .changes
-            .iter()
-            .filter_map(|ch| {
-                if let Change::NewNode { ref id, .. } = ch {
-                    Some(id)
-                } else {
-                    None
+        let mut new_nodes = HashMap::new();
+        for ch in &patch.changes().changes {
+            if let Change::NewNode { id, path, .. } = ch {
+                if new_nodes.insert(*id, path.as_deref()).is_some() {
+                    return Err(Error::DuplicateNode(*id));
+                }
+            }
+        }
+        // The nodes that were introduced by dependencies that have never been applied, and the
+        // files that they belong to. Opening a patch is expensive, so we only do it once per
+        // dependency.
+        let mut unapplied_dep_nodes = HashMap::<PatchId, HashMap<NodeId, Option<String>>>::new();
+        // Returns the file that a node belongs to (where `Some(None)` is the main file), or `None`
+        // if we aren't allowed to refer to the node.
+        let mut node_path = |id: &NodeId| -> Result<Option<Option<String>>, Error> {
+            if let Some(path) = new_nodes.get(id) {
+                return Ok(Some(path.map(|p| p.to_owned())));
+            }
+            if !dep_set.contains(&id.patch) {
+                return Ok(None);
+            }
+            // Nodes only get contents when their patch is applied, so if the dependency was
+            // never applied then we need to look inside it.
+            if let Some(path) = self.storage.node_path(id) {
+                return Ok(Some(path.map(|p| p.to_owned())));
+            }
+            let dep_nodes = match unapplied_dep_nodes.entry(id.patch) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    let dep = self.open_patch(&id.patch)?;
+                    e.insert(
+                        dep.changes()
+                            .changes
+                            .iter()
+                            .filter_map(|ch| match ch {
+                                Change::NewNode { id, path, .. } => Some((*id, path.clone())),
+                                _ => None,
+                            })
+                            .collect(),
+                    )
                 }
-            })
-            .collect::<HashSet<_>>();
+            };
+            Ok(dep_nodes.get(id).cloned())
+        };
         for ch in &patch.changes().changes {
             use crate::patch::Change::*;
-            let has_node = |id| {
-                new_nodes.contains(id)
-                    || (self.storage.contains_node(id) && dep_set.contains(&id.patch))
-            };
             match ch {
                 NewNode { ref id, .. } => {
-                    if !has_node(id) {
-                        return Err(Error::UnknownNode(*id));
+                    // Graggles store node indices in 32 bits.
+                    if id.node > u64::from(u32::MAX) {
+                        return Err(Error::NodeIndexTooLarge(*id));
                     }
                 }
                 NewEdge { ref src, ref dest } => {
-                    if !has_node(src) {
-                        return Err(Error::UnknownNode(*src));
-                    }
-                    if !has_node(dest) {
-                        return Err(Error::UnknownNode(*dest));
+                    let src_path = node_path(src)?.ok_or(Error::UnknownNode(*src))?;
+                    let dest_path = node_path(dest)?.ok_or(Error::UnknownNode(*dest))?;
+                    // Files are ordered independently of one another.
+                    if src_path != dest_path {
+                        return Err(Error::CrossFileEdge(*src, *dest));
                     }
                 }
-                DeleteNode { ref id } => {
-                    if !has_node(id) {
+                DeleteNode { ref id } | ReplaceContents { ref id, .. } => {
+                    if node_path(id)?.is_none() {
                         return Err(Error::UnknownNode(*id));
                     }
                 }
+                CreateFile => {}
             }
         }
         Ok(())
@@ -341,19 +1042,26 @@ impl Repo {
             }
         }
 
-        self.check_patch_validity(patch)?;
+        self.check_patch_validity(patch, data.len())?;
+        if self.lineage_policy() == LineagePolicy::Refuse && self.is_foreign(patch) {
+            let theirs = patch.header().repository().unwrap_or_default();
+            return Err(Error::ForeignPatch(*patch.id(), theirs.to_owned()));
+        }
 
         // Record the deps and reverse-deps.
         for dep in patch.deps() {
-            self.storage
-                .patch_deps
-                .insert(patch.id().clone(), dep.clone());
-            self.storage
-                .patch_rev_deps
-                .insert(dep.clone(), patch.id().clone());
+            Arc::make_mut(&mut self.storage.patch_deps).insert(patch.id().clone(), dep.clone());
+            Arc::make_mut(&mut self.storage.patch_rev_deps).insert(dep.clone(), patch.id().clone());
+        }
+        for old in patch.header().supersedes() {
+            Arc::make_mut(&mut self.storage.supersedes).insert(*patch.id(), old);
         }
 
-        self.storage.patches.insert(patch.id().clone(), data);
+        self.storage
+            .patch_stats
+            .insert(*patch.id(), patch.changes().stats());
+        Arc::make_mut(&mut self.storage.patch_headers).insert(*patch.id(), patch.header().clone());
+        self.storage.add_patch(*patch.id(), data);
         Ok(())
     }
 
@@ -362,18 +1070,14 @@ impl Repo {
     // Panics if not all of the dependencies are already present.
     fn apply_one_patch(&mut self, branch: &str, patch_id: &PatchId) -> Result<(), Error> {
         let patch = self.open_patch(patch_id)?;
-        for dep in patch.deps() {
-            debug_assert!(
-                self.storage.branch_patches.contains(branch, dep),
-                "tried to apply a patch while it was missing a dependency"
-            );
-        }
+        debug_assert!(
+            patch.deps().iter().all(|dep| self.is_applied(branch, dep)),
+            "tried to apply a patch while it was missing a dependency"
+        );
         let inode = self.storage.inode(branch).unwrap();
         self.storage
             .apply_changes(inode, patch.changes(), *patch_id);
-        self.storage
-            .branch_patches
-            .insert(branch.to_owned(), patch.id().clone());
+        self.storage.add_branch_patch(branch, *patch.id());
         Ok(())
     }
 
@@ -381,6 +1085,80 @@ impl Repo {
     ///
     /// Returns a list of all the patches that were applied.
     pub fn apply_patch(&mut self, branch: &str, patch_id: &PatchId) -> Result<Vec<PatchId>, Error> {
+        let applied = self.apply_patch_unrecorded(branch, patch_id)?;
+        self.update_cache(branch, false)?;
+        self.record_applied(branch, &applied);
+        Ok(applied)
+    }
+
+    /// Applies a patch (and all its dependencies) to a branch, without resolving the pseudo-edges.
+    ///
+    /// This is like [`Repo::apply_patch`], except that it leaves the branch needing a cache update
+    /// (see [`Repo::needs_cache_update`]), which is handy when applying many patches one at a
+    /// time: call [`Repo::flush_caches`] once at the end, instead of paying for the update after
+    /// every patch. Until then, anything that reads the branch (such as [`Repo::file`] or
+    /// [`Repo::graggle`]) might give outdated results.
+    ///
+    /// Returns a list of all the patches that were applied.
+    pub fn apply_patch_no_cache(
+        &mut self,
+        branch: &str,
+        patch_id: &PatchId,
+    ) -> Result<Vec<PatchId>, Error> {
+        let applied = self.apply_patch_unrecorded(branch, patch_id)?;
+        self.record_applied(branch, &applied);
+        Ok(applied)
+    }
+
+    /// Does `branch` have changes whose pseudo-edges haven't been resolved yet?
+    ///
+    /// This only happens after [`Repo::apply_patch_no_cache`]; see [`Repo::flush_caches`].
+    pub fn needs_cache_update(&self, branch: &str) -> Result<bool, Error> {
+        let inode = self.inode(branch)?;
+        Ok(self.storage.needs_cache_update(inode))
+    }
+
+    /// Resolves any pseudo-edges of `branch` that were left out of date by
+    /// [`Repo::apply_patch_no_cache`]. If there aren't any, this does nothing.
+    pub fn flush_caches(&mut self, branch: &str) -> Result<(), Error> {
+        self.inode(branch)?;
+        self.update_cache(branch, false)
+    }
+
+    /// Applies several patches (and all their dependencies) to a branch.
+    ///
+    /// This either applies all of the patches or none of them: if one of them fails to apply, the
+    /// ones that were already applied are rolled back before returning the error. Returns a list
+    /// of all the patches that were applied.
+    ///
+    /// Resolving the pseudo-edges after applying many patches can take a while. This can be
+    /// cancelled using [`Repo::cancel_token`], in which case nothing gets applied and this returns
+    /// [`Error::Cancelled`]. Its progress is reported to the [`HookKind::CacheProgress`] hooks.
+    pub fn apply_patches(
+        &mut self,
+        branch: &str,
+        patch_ids: &[PatchId],
+    ) -> Result<Vec<PatchId>, Error> {
+        self.inode(branch)?;
+        self.transaction(|repo| {
+            let mut applied = Vec::new();
+            for p in patch_ids {
+                applied.extend(repo.apply_patch_unrecorded(branch, p)?);
+            }
+            repo.update_cache(branch, true)?;
+            debug_assert_eq!(repo.verify_branch_closure(branch), Ok(()));
+            repo.record_applied(branch, &applied);
+            Ok(applied)
+        })
+    }
+
+    // Does the work of `apply_patch`, without recording it in the journal, running hooks or
+    // resolving the pseudo-edges.
+    fn apply_patch_unrecorded(
+        &mut self,
+        branch: &str,
+        patch_id: &PatchId,
+    ) -> Result<Vec<PatchId>, Error> {
         // If the branch already contains the patch, this is a no-op.
         if self.storage.branch_patches.contains(branch, patch_id) {
             return Ok(vec![]);
@@ -410,11 +1188,54 @@ impl Repo {
                 patch_stack.extend_from_slice(&unapplied_deps[..]);
             }
         }
+        Ok(applied)
+    }
 
-        // Having applied all the patches, resolve the cache.
+    // Resolves the pseudo-edges of `branch` (which must exist), reporting the progress to the
+    // cache-progress hooks.
+    //
+    // If `cancellable` is true, this can be cancelled using the token from `Repo::cancel_token`. In
+    // that case it returns `Error::Cancelled`, and the caller needs to roll back because the
+    // pseudo-edges are only partially resolved.
+    fn update_cache(&mut self, branch: &str, cancellable: bool) -> Result<(), Error> {
         let inode = self.storage.inode(branch).unwrap();
-        self.storage.update_cache(inode);
-        Ok(applied)
+        let hooks = &self.hooks;
+        let cancel = &self.cancel;
+        if cancellable {
+            cancel.reset();
+        }
+        let finished = self.storage.update_cache_with(inode, |done, total| {
+            hooks.run_post(&HookEvent::CacheProgress {
+                branch,
+                done,
+                total,
+            });
+            !(cancellable && cancel.is_cancelled())
+        });
+        if finished {
+            Ok(())
+        } else {
+            Err(Error::Cancelled)
+        }
+    }
+
+    /// Returns a token that can be used to cancel long-running operations on this repository (for
+    /// example, from another thread).
+    ///
+    /// Currently, the only operations that can be cancelled are [`Repo::apply_patches`] and
+    /// [`Repo::unapply_patches`].
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    fn record_applied(&mut self, branch: &str, applied: &[PatchId]) {
+        for p in applied {
+            self.journal_append(JournalEvent::PatchApplied {
+                branch: branch.to_owned(),
+                patch: *p,
+            });
+        }
+        self.run_post_apply_hooks(branch, applied);
     }
 
     fn unapply_one_patch(&mut self, branch: &str, patch_id: &PatchId) -> Result<(), Error> {
@@ -424,22 +1245,64 @@ impl Repo {
         let inode = self.inode(branch)?;
         self.storage
             .unapply_changes(inode, patch.changes(), *patch_id);
-        self.storage.branch_patches.remove(branch, patch.id());
+        self.storage.remove_branch_patch(branch, patch.id());
         Ok(())
     }
 
     /// Unapplies a patch (and everything that depends on it) to a branch.
     ///
-    /// Returns a list of all the patches that were unapplied.
+    /// Returns a list of all the patches that were unapplied. Baselines (see
+    /// [`Repo::create_baseline`]) can't be unapplied, because there is nothing to go back to, and
+    /// nothing can be unapplied from a protected branch (see [`Repo::set_protected`]).
     pub fn unapply_patch(
         &mut self,
         branch: &str,
         patch_id: &PatchId,
+    ) -> Result<Vec<PatchId>, Error> {
+        let unapplied = self.unapply_patch_unrecorded(branch, patch_id)?;
+        self.update_cache(branch, false)?;
+        self.record_unapplied(branch, &unapplied);
+        Ok(unapplied)
+    }
+
+    /// Unapplies several patches (and everything that depends on them) from a branch.
+    ///
+    /// Like [`Repo::apply_patches`], this either unapplies all of the patches or none of them, and
+    /// it can be cancelled. Returns a list of all the patches that were unapplied.
+    pub fn unapply_patches(
+        &mut self,
+        branch: &str,
+        patch_ids: &[PatchId],
+    ) -> Result<Vec<PatchId>, Error> {
+        self.inode(branch)?;
+        self.transaction(|repo| {
+            let mut unapplied = Vec::new();
+            for p in patch_ids {
+                unapplied.extend(repo.unapply_patch_unrecorded(branch, p)?);
+            }
+            repo.update_cache(branch, true)?;
+            repo.record_unapplied(branch, &unapplied);
+            Ok(unapplied)
+        })
+    }
+
+    // Does the work of `unapply_patch`, without recording it in the journal or resolving the
+    // pseudo-edges.
+    fn unapply_patch_unrecorded(
+        &mut self,
+        branch: &str,
+        patch_id: &PatchId,
     ) -> Result<Vec<PatchId>, Error> {
         // If the branch doesn't contain the patch, this is a no-op.
         if !self.storage.branch_patches.contains(branch, patch_id) {
             return Ok(vec![]);
         }
+        self.check_unprotected(branch)?;
+        // Baselines don't have any dependencies, so this is the only way that unapplying could
+        // reach one.
+        if self.is_baseline(patch_id) {
+            return Err(Error::Baseline(*patch_id));
+        }
 
         let mut patch_stack = vec![*patch_id];
         let mut unapplied = Vec::new();
@@ -465,58 +1328,501 @@ impl Repo {
                 patch_stack.extend_from_slice(&applied_rev_deps[..]);
             }
         }
-
-        // Having unapplied all the patches, resolve the cache.
-        let inode = self.storage.inode(branch).unwrap();
-        self.storage.update_cache(inode);
         Ok(unapplied)
     }
 
+    fn record_unapplied(&mut self, branch: &str, unapplied: &[PatchId]) {
+        for p in unapplied {
+            self.journal_append(JournalEvent::PatchUnapplied {
+                branch: branch.to_owned(),
+                patch: *p,
+            });
+        }
+    }
+
     /// Returns an iterator over all known patches, applied or otherwise.
-    pub fn all_patches(&self) -> impl Iterator<Item = &PatchId> {
-        self.storage.patches.keys()
+    pub fn all_patches(&self) -> PatchIter<'_> {
+        PatchIter {
+            inner: self.storage.patches.keys(),
+        }
+    }
+
+    /// Finds the patch whose id (in its [textual form](PatchId#textual-form)) starts with `prefix`.
+    ///
+    /// Only patches that are known to this repository are considered. If there is more than one of
+    /// them matching the prefix, returns [`Error::AmbiguousPatchPrefix`]. If there are none, the
+    /// error depends on what `prefix` looks like: a complete id gives [`Error::UnknownPatch`], and
+    /// something that couldn't be the beginning of an id gives [`Error::PatchId`].
+    pub fn resolve_patch_prefix(&self, prefix: &str) -> Result<PatchId, Error> {
+        // Complete ids are looked up directly, because ids written by older versions of ojo aren't
+        // prefixes of the current textual form.
+        if let Ok(id) = prefix.parse::<PatchId>() {
+            return if self.storage.patches.contains_key(&id) {
+                Ok(id)
+            } else {
+                Err(Error::UnknownPatch(id))
+            };
+        }
+        if !prefix.starts_with('P') {
+            return Err(PatchIdError::MissingPrefix.into());
+        }
+
+        let mut matches = self
+            .all_patches()
+            .filter(|p| p.to_string().starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some(p), None) => Ok(*p),
+            (Some(_), Some(_)) => Err(Error::AmbiguousPatchPrefix(prefix.to_owned())),
+            (None, _) => Err(Error::UnknownPatchPrefix(prefix.to_owned())),
+        }
+    }
+
+    /// Finds the patch with the given name, which can be either an alias (see
+    /// [`Repo::set_patch_alias`]) or a prefix of its id (see [`Repo::resolve_patch_prefix`]).
+    ///
+    /// Aliases take precedence over prefixes.
+    pub fn resolve_patch(&self, name: &str) -> Result<PatchId, Error> {
+        match self.storage.patch_aliases.get(name) {
+            Some(id) => Ok(*id),
+            None => self.resolve_patch_prefix(name),
+        }
+    }
+
+    /// Gives a human-friendly name to a patch, which can be used in [`Repo::resolve_patch`].
+    pub fn set_patch_alias(&mut self, alias: &str, id: &PatchId) -> Result<(), Error> {
+        if !self.storage.patches.contains_key(id) {
+            return Err(Error::UnknownPatch(*id));
+        }
+        if self.storage.patch_aliases.contains_key(alias) {
+            return Err(Error::AliasExists(alias.to_owned()));
+        }
+        self.storage.patch_aliases.insert(alias.to_owned(), *id);
+        Ok(())
+    }
+
+    /// Removes an alias, returning the patch that it referred to (if the alias existed).
+    pub fn remove_patch_alias(&mut self, alias: &str) -> Option<PatchId> {
+        self.storage.patch_aliases.remove(alias)
+    }
+
+    /// Returns an iterator over all the aliases of a patch.
+    pub fn patch_aliases<'a>(&'a self, id: &'a PatchId) -> AliasIter<'a> {
+        AliasIter {
+            inner: self.storage.patch_aliases.iter(),
+            id,
+        }
+    }
+
+    /// Records that the patches whose author is `from` should be shown as being by `to`.
+    ///
+    /// Patches are immutable, so this doesn't change the patches themselves (or their ids): it only
+    /// changes what [`Repo::author`] returns. Anything that was previously reattributed to `from`
+    /// gets reattributed to `to` as well, and reattributing `from` to itself undoes any earlier
+    /// reattribution of it.
+    pub fn reattribute(&mut self, from: &str, to: &str) {
+        let to = if to == from || self.author(to) == from {
+            // Either this undoes the reattribution of `from`, or `to` was itself reattributed to
+            // `from` and this reverses that.
+            self.storage.authors.remove(to);
+            to.to_owned()
+        } else {
+            self.author(to).to_owned()
+        };
+        for new in self.storage.authors.values_mut() {
+            if new == from {
+                *new = to.clone();
+            }
+        }
+        self.storage.authors.insert(from.to_owned(), to);
+        self.storage.authors.retain(|old, new| old != new);
     }
 
-    /// Returns an iterator over all of the patches being used in a branch.
-    // TODO: maybe a way to check whether a patch is applied to a branch?
-    pub fn patches(&self, branch: &str) -> impl Iterator<Item = &PatchId> {
-        self.storage.branch_patches.get(branch)
+    /// Returns the name that patches by `author` should be shown with, taking into account any
+    /// calls to [`Repo::reattribute`].
+    pub fn author<'a>(&'a self, author: &'a str) -> &'a str {
+        self.storage
+            .authors
+            .get(author)
+            .map(|s| &s[..])
+            .unwrap_or(author)
+    }
+
+    /// Returns all of the reattributions, as pairs of the original author and the new one.
+    pub fn reattributions(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.storage
+            .authors
+            .iter()
+            .map(|(old, new)| (&old[..], &new[..]))
+    }
+
+    /// Returns the shortest prefix of `id` (in its [textual form](PatchId#textual-form)) that
+    /// [`Repo::resolve_patch_prefix`] will resolve to `id`, but no shorter than
+    /// [`MIN_PATCH_PREFIX_LEN`] characters.
+    pub fn abbreviate_patch_id(&self, id: &PatchId) -> String {
+        let full = id.to_string();
+        let common_len = |other: &String| {
+            full.bytes()
+                .zip(other.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        // The ids sharing the longest prefix with `id` are the ones next to it in sorted order.
+        let ids = self.storage.sorted_patch_ids();
+        let (before, after) = match ids.binary_search(&full) {
+            Ok(i) => (i, i + 1),
+            Err(i) => (i, i),
+        };
+        let len = ids[..before]
+            .last()
+            .into_iter()
+            .chain(ids.get(after))
+            .map(common_len)
+            .max()
+            .map_or(0, |n| n + 1)
+            .max(MIN_PATCH_PREFIX_LEN)
+            .min(full.len());
+        full[..len].to_owned()
+    }
+
+    /// Returns an iterator over all of the patches being used in a branch, in the order that they
+    /// were applied.
+    pub fn patches(&self, branch: &str) -> BranchPatchIter<'_> {
+        BranchPatchIter {
+            inner: self.storage.branch_patches_in_order(branch).iter(),
+        }
+    }
+
+    /// Checks whether a patch is applied to a branch.
+    ///
+    /// Returns `false` if the branch doesn't exist.
+    pub fn is_applied(&self, branch: &str, patch: &PatchId) -> bool {
+        self.storage.branch_patches.contains(branch, patch)
+    }
+
+    /// Checks that every dependency of every patch applied to `branch` is also applied to it.
+    ///
+    /// This should always be the case, since applying a patch applies its dependencies and
+    /// unapplying a patch unapplies everything that depends on it. If it isn't, this returns the
+    /// missing dependencies, sorted and without duplicates. A branch that doesn't exist has no
+    /// patches, so it passes trivially.
+    pub fn verify_branch_closure(&self, branch: &str) -> Result<(), Vec<PatchId>> {
+        let mut missing = self
+            .patches(branch)
+            .flat_map(|p| self.patch_deps(p))
+            .filter(|dep| !self.is_applied(branch, dep))
+            .cloned()
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            missing.sort();
+            missing.dedup();
+            Err(missing)
+        }
     }
 
     /// Returns an iterator over all direct dependencies of the given patch.
-    pub fn patch_deps(&self, patch: &PatchId) -> impl Iterator<Item = &PatchId> {
-        self.storage.patch_deps.get(patch)
+    pub fn patch_deps(&self, patch: &PatchId) -> DepIter<'_> {
+        DepIter {
+            inner: self.storage.patch_deps.get(patch),
+        }
     }
 
     /// Returns an iterator over all direct dependents of the given patch.
-    pub fn patch_rev_deps(&self, patch: &PatchId) -> impl Iterator<Item = &PatchId> {
-        self.storage.patch_rev_deps.get(patch)
+    pub fn patch_rev_deps(&self, patch: &PatchId) -> DepIter<'_> {
+        DepIter {
+            inner: self.storage.patch_rev_deps.get(patch),
+        }
+    }
+
+    /// Returns the patches that were superseded by other patches (see
+    /// [`PatchHeader::supersedes`]), each together with the patches that superseded it.
+    ///
+    /// None of these patches need to be known to this repository anymore: for example,
+    /// [`Repo::create_patch_superseding`] is typically used to replace a patch that then gets
+    /// removed, and the replacement might get replaced in turn.
+    pub fn superseded_patches(&self) -> BTreeMap<PatchId, Vec<PatchId>> {
+        let mut ret = BTreeMap::<PatchId, Vec<PatchId>>::new();
+        for (new, old) in self.storage.supersedes.iter() {
+            ret.entry(*old).or_default().push(*new);
+        }
+        ret
+    }
+
+    /// Returns the patches that `patch` superseded, followed by the ones that they superseded, and
+    /// so on.
+    ///
+    /// This follows the trail through patches that are no longer in this repository, so it finds
+    /// the original patch even after it was amended several times.
+    pub fn supersession_trail(&self, patch: &PatchId) -> Vec<PatchId> {
+        let mut ret = Vec::new();
+        let mut seen = HashSet::new();
+        let mut next = vec![*patch];
+        while !next.is_empty() {
+            let mut cur = Vec::new();
+            for p in &next {
+                for old in self.storage.supersedes.get(p) {
+                    if seen.insert(*old) {
+                        ret.push(*old);
+                        cur.push(*old);
+                    }
+                }
+            }
+            next = cur;
+        }
+        ret
+    }
+
+    /// Returns all of the direct and indirect dependencies of the given patch, ordered so that
+    /// every patch comes after its dependencies.
+    pub fn transitive_deps(&self, patch: &PatchId) -> Vec<PatchId> {
+        let mut ret = preview::closure(&[*patch], &self.storage.patch_deps, |_| false);
+        // The closure puts `patch` itself last.
+        ret.pop();
+        ret
+    }
+
+    /// Returns the patches that are known to this repository but not applied to `branch`.
+    ///
+    /// The patches are ordered so that every patch comes after its dependencies, so applying them
+    /// one by one in this order always works.
+    pub fn unapplied_patches(&self, branch: &str) -> Vec<PatchId> {
+        let mut roots = self
+            .all_patches()
+            .filter(|p| !self.is_applied(branch, p))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Sort the roots so that the order doesn't depend on the order of the patch storage.
+        roots.sort();
+        preview::closure(&roots, &self.storage.patch_deps, |p| {
+            self.is_applied(branch, p)
+        })
+    }
+
+    /// Returns the pairs of patches applied to `branch` that are likely to be in conflict.
+    ///
+    /// Two patches are reported if they both touch (by deleting it, by replacing its contents, or
+    /// by adding an edge to or from it) some node that neither of them introduced, and if neither of them depends
+    /// (directly or indirectly) on the other. These are typically the patches responsible for a
+    /// graggle that isn't totally ordered. Within each pair, the smaller id comes first, and the
+    /// pairs are sorted.
+    pub fn conflicting_patches(&self, branch: &str) -> Result<Vec<(PatchId, PatchId)>, Error> {
+        let mut touched = HashMap::<NodeId, Vec<PatchId>>::new();
+        for id in self.patches(branch) {
+            let patch = self.open_patch(id)?;
+            let mut nodes = Vec::new();
+            for ch in &patch.changes().changes {
+                match ch {
+                    Change::DeleteNode { id } | Change::ReplaceContents { id, .. } => {
+                        nodes.push(*id)
+                    }
+                    Change::NewEdge { src, dest } => nodes.extend_from_slice(&[*src, *dest]),
+                    _ => {}
+                }
+            }
+            nodes.retain(|n| n.patch != *id);
+            nodes.sort();
+            nodes.dedup();
+            for n in nodes {
+                touched.entry(n).or_default().push(*id);
+            }
+        }
+
+        let mut deps = HashMap::<PatchId, HashSet<PatchId>>::new();
+        let mut depends_on = |a: &PatchId, b: &PatchId| {
+            deps.entry(*a)
+                .or_insert_with(|| self.transitive_deps(a).into_iter().collect())
+                .contains(b)
+        };
+        let mut ret = Vec::new();
+        for patches in touched.values() {
+            for (i, a) in patches.iter().enumerate() {
+                for b in &patches[(i + 1)..] {
+                    if !depends_on(a, b) && !depends_on(b, a) {
+                        ret.push(if a < b { (*a, *b) } else { (*b, *a) });
+                    }
+                }
+            }
+        }
+        ret.sort();
+        ret.dedup();
+        Ok(ret)
+    }
+
+    /// Returns all of the direct and indirect dependents of the given patch, ordered so that
+    /// every patch comes before its dependencies.
+    pub fn transitive_rev_deps(&self, patch: &PatchId) -> Vec<PatchId> {
+        let mut ret = preview::closure(&[*patch], &self.storage.patch_rev_deps, |_| false);
+        ret.pop();
+        ret
+    }
+
+    /// Removes a patch from the repository, as though it had never been registered.
+    ///
+    /// Only patches that aren't needed any more can be removed: returns [`Error::StillApplied`] if
+    /// the patch is applied to a branch, and [`Error::NeededBy`] if some other patch depends on
+    /// it.
+    pub fn unregister_patch(&mut self, id: &PatchId) -> Result<(), Error> {
+        let patch = self.open_patch(id)?;
+        if let Some(branch) = self.branches().find(|b| self.is_applied(b, id)) {
+            return Err(Error::StillApplied(*id, branch.to_owned()));
+        }
+        if let Some(dependent) = self.patch_rev_deps(id).next() {
+            return Err(Error::NeededBy(*id, *dependent));
+        }
+
+        for ch in &patch.changes().changes {
+            if let Change::ReplaceContents { id: node, .. } = ch {
+                self.storage.remove_replacement(node, id);
+            }
+        }
+        self.storage.remove_patch(id);
+        Ok(())
     }
 
     /// Creates a new patch with the given changes and metadata and returns its ID.
     ///
     /// The newly created patch will be automatically registered in the current repository, so
-    /// there is no need to call [`Repo::register_patch`] on it.
+    /// there is no need to call [`Repo::register_patch`] on it. The patch's metadata records that
+    /// it was created on the current branch; use [`Repo::create_patch_on_branch`] to record a
+    /// different branch.
     pub fn create_patch(
         &mut self,
         author: &str,
         msg: &str,
         changes: Changes,
     ) -> Result<PatchId, Error> {
+        let branch = self.current_branch.clone();
+        self.create_patch_on_branch(&branch, author, msg, changes)
+    }
+
+    /// Creates a new patch, recording in its metadata that it was created on `branch`.
+    ///
+    /// This is like [`Repo::create_patch`], except for the branch that gets recorded. The patch
+    /// is not applied to `branch`.
+    pub fn create_patch_on_branch(
+        &mut self,
+        branch: &str,
+        author: &str,
+        msg: &str,
+        changes: Changes,
+    ) -> Result<PatchId, Error> {
+        self.hooks.run(&HookEvent::PreCreatePatch {
+            author,
+            description: msg,
+            changes: &changes,
+        })?;
         let patch = UnidentifiedPatch::new(author.to_owned(), msg.to_owned(), changes);
+        self.create_patch_from(branch, patch)
+    }
 
-        // Serialize the patch to a buffer, and get back the identified patch.
-        let mut patch_data = Vec::new();
-        let patch = patch.write_out(&mut patch_data)?;
-        let patch_data =
-            String::from_utf8(patch_data).expect("YAML serializer failed to produce UTF-8");
+    /// Creates a new patch that supersedes the patches in `old`, and returns its id.
+    ///
+    /// This is like [`Repo::create_patch_on_branch`], except that the patch's metadata records
+    /// that it replaces the old patches (see [`PatchHeader::supersedes`]). This doesn't do anything
+    /// to the old patches themselves.
+    pub fn create_patch_superseding(
+        &mut self,
+        branch: &str,
+        author: &str,
+        msg: &str,
+        changes: Changes,
+        old: &[PatchId],
+    ) -> Result<PatchId, Error> {
+        self.hooks.run(&HookEvent::PreCreatePatch {
+            author,
+            description: msg,
+            changes: &changes,
+        })?;
+        let patch =
+            UnidentifiedPatch::new(author.to_owned(), msg.to_owned(), changes).superseding(old);
+        self.create_patch_from(branch, patch)
+    }
+
+    /// Creates a patch that resolves the graggle of `branch`, and returns its id.
+    ///
+    /// This is like [`Repo::create_patch_on_branch`], except that the patch also records how the
+    /// resolution was done. The `changes` and `resolution` should both come from the same
+    /// [`OrderResolver`](crate::resolver::OrderResolver).
+    pub fn create_resolution_patch(
+        &mut self,
+        branch: &str,
+        author: &str,
+        msg: &str,
+        changes: Changes,
+        resolution: Resolution,
+    ) -> Result<PatchId, Error> {
+        self.hooks.run(&HookEvent::PreCreatePatch {
+            author,
+            description: msg,
+            changes: &changes,
+        })?;
+        let patch = UnidentifiedPatch::new(author.to_owned(), msg.to_owned(), changes)
+            .with_resolution(resolution);
+        self.create_patch_from(branch, patch)
+    }
+
+    fn create_patch_from(
+        &mut self,
+        branch: &str,
+        patch: UnidentifiedPatch,
+    ) -> Result<PatchId, Error> {
+        let (patch, patch_data) = self.identify_patch(branch, patch)?;
 
         // Now that we know the patch's id, store it in the patches map.
         self.register_patch_with_data(&patch, patch_data)?;
+        self.journal_append(JournalEvent::PatchCreated { patch: *patch.id() });
 
         Ok(*patch.id())
     }
 
+    // Fills in the metadata and dependencies of a patch that is being created on `branch`, and
+    // serializes it. Returns the patch, together with its data.
+    fn identify_patch(
+        &self,
+        branch: &str,
+        patch: UnidentifiedPatch,
+    ) -> Result<(Patch, String), Error> {
+        let patch = patch
+            .with_metadata(PatchHeader::BRANCH, branch)
+            .with_metadata(
+                PatchHeader::FORMAT_VERSION,
+                &REPO_FORMAT_VERSION.to_string(),
+            );
+        let patch = match self.storage.identity {
+            Some(ref identity) => patch.with_metadata(PatchHeader::REPOSITORY, identity),
+            None => patch,
+        };
+
+        // A patch that deletes or replaces a node overrides whatever replaced that node's contents
+        // on this branch, so it has to depend on those patches (see `Change::ReplaceContents`).
+        let overridden = match self.storage.inode(branch) {
+            Some(inode) => {
+                let graggle = self.storage.graggle(inode);
+                patch
+                    .changes()
+                    .changes
+                    .iter()
+                    .filter_map(|ch| match ch {
+                        Change::DeleteNode { id } | Change::ReplaceContents { id, .. } => Some(id),
+                        _ => None,
+                    })
+                    .filter(|id| graggle.has_node(id))
+                    .flat_map(|id| graggle.replacing_patches(id))
+                    .collect::<Vec<_>>()
+            }
+            None => Vec::new(),
+        };
+        let patch = patch.with_deps(overridden);
+
+        // Serialize the patch to a buffer, and get back the identified patch.
+        let mut patch_data = Vec::new();
+        let patch = patch.write_out(&mut patch_data)?;
+        let patch_data =
+            String::from_utf8(patch_data).expect("YAML serializer failed to produce UTF-8");
+        Ok((patch, patch_data))
+    }
+
     fn try_create_dir(&self, dir: &Path) -> Result<(), Error> {
         if let Err(e) = std::fs::create_dir(dir) {
             // If the directory already exists, just swallow the error.
@@ -528,23 +1834,41 @@ impl Repo {
     }
 
     /// Returns an iterator over the names of all branches.
-    pub fn branches(&self) -> impl Iterator<Item = &str> {
-        self.storage.branches()
+    pub fn branches(&self) -> BranchIter<'_> {
+        BranchIter {
+            inner: self.storage.branches(),
+        }
+    }
+
+    /// Returns an iterator over the names of all branches that match `pattern` (see
+    /// [`BranchName::matches`]).
+    pub fn branches_matching<'a>(&'a self, pattern: &'a str) -> MatchingBranchIter<'a> {
+        MatchingBranchIter {
+            inner: self.branches(),
+            pattern,
+        }
     }
 
     /// Creates a new, empty branch.
+    ///
+    /// The name of the branch must be valid (see [`BranchName`]).
     pub fn create_branch(&mut self, branch: &str) -> Result<(), Error> {
+        BranchName::try_from(branch)?;
         if self.storage.inode(branch).is_some() {
             Err(Error::BranchExists(branch.to_owned()))
         } else {
             let inode = self.storage.allocate_inode();
             self.storage.set_inode(branch, inode);
+            self.journal_append(JournalEvent::BranchCreated {
+                branch: branch.to_owned(),
+            });
             Ok(())
         }
     }
 
-    /// Copies data to a new branch (which must not already exist).
+    /// Copies data to a new branch (which must not already exist, and must have a valid name).
     pub fn clone_branch(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        BranchName::try_from(to)?;
         if self.storage.inode(to).is_some() {
             Err(Error::BranchExists(to.to_owned()))
         } else {
@@ -557,20 +1881,54 @@ impl Repo {
 
             // Record the fact that all the patches in the old branch are also present in the new
             // branch.
-            let from_patches = self
-                .storage
-                .branch_patches
-                .get(from)
-                .cloned()
-                .collect::<Vec<_>>();
+            let from_patches = self.storage.branch_patches_in_order(from).to_vec();
             for p in from_patches {
-                self.storage.branch_patches.insert(to.to_owned(), p);
+                self.storage.add_branch_patch(to, p);
             }
+            self.journal_append(JournalEvent::BranchCreated {
+                branch: to.to_owned(),
+            });
             Ok(())
         }
     }
 
+    /// Creates a new branch named `to` that contains only some of the patches in `from`.
+    ///
+    /// The new branch starts as a copy of `from`, and then every patch that isn't in `keep` is
+    /// unapplied from it. Every patch in `keep` must be applied to `from` (otherwise, this returns
+    /// [`Error::NotApplied`]), and every dependency of a patch in `keep` must also be in `keep`
+    /// (otherwise, this returns [`Error::MissingDep`]). If this returns an error, no branch is
+    /// created.
+    pub fn fork_branch_at(&mut self, from: &str, to: &str, keep: &[PatchId]) -> Result<(), Error> {
+        let keep = keep.iter().collect::<HashSet<_>>();
+        for p in &keep {
+            if !self.is_applied(from, p) {
+                return Err(Error::NotApplied(**p, from.to_owned()));
+            }
+            if let Some(dep) = self.patch_deps(p).find(|dep| !keep.contains(dep)) {
+                return Err(Error::MissingDep(*dep));
+            }
+        }
+        let unapply = self
+            .patches(from)
+            .filter(|p| !keep.contains(p))
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(p) = unapply.iter().find(|p| self.is_baseline(p)) {
+            return Err(Error::Baseline(*p));
+        }
+
+        self.clone_branch(from, to)?;
+        // Unapply the most recently applied patches first, so that there's less to cascade.
+        for p in unapply.iter().rev() {
+            self.unapply_patch(to, p)?;
+        }
+        Ok(())
+    }
+
     /// Deletes the branch named `branch`.
+    ///
+    /// The current branch and protected branches (see [`Repo::set_protected`]) can't be deleted.
     pub fn delete_branch(&mut self, branch: &str) -> Result<(), Error> {
         if branch == self.current_branch {
             return Err(Error::CurrentBranch(branch.to_owned()));
@@ -579,18 +1937,76 @@ impl Repo {
             .storage
             .inode(branch)
             .ok_or_else(|| Error::UnknownBranch(branch.to_owned()))?;
+        self.check_unprotected(branch)?;
         self.storage.remove_graggle(inode);
         self.storage.remove_inode(branch);
-        self.storage.branch_patches.remove_all(branch);
+        self.storage.clear_branch_patches(branch);
+        self.journal_append(JournalEvent::BranchDeleted {
+            branch: branch.to_owned(),
+        });
         Ok(())
     }
 
     /// Changes the current branch to the one named `branch` (which must already exist).
     pub fn switch_branch(&mut self, branch: &str) -> Result<(), Error> {
+        BranchName::try_from(branch)?;
         if self.storage.inode(branch).is_none() {
             Err(Error::UnknownBranch(branch.to_owned()))
         } else {
             self.current_branch = branch.to_owned();
+            self.journal_append(JournalEvent::BranchSwitched {
+                branch: branch.to_owned(),
+            });
+            Ok(())
+        }
+    }
+
+    /// Marks `branch` as archived (if `archived` is true) or not archived (if it's false).
+    ///
+    /// Archiving a branch doesn't change its contents; it just marks it as something that doesn't
+    /// need to be listed by default. The current branch can't be archived.
+    pub fn set_archived(&mut self, branch: &str, archived: bool) -> Result<(), Error> {
+        if self.storage.inode(branch).is_none() {
+            return Err(Error::UnknownBranch(branch.to_owned()));
+        }
+        if archived && branch == self.current_branch {
+            return Err(Error::CurrentBranch(branch.to_owned()));
+        }
+        let mut metadata = self.storage.branch_metadata(branch);
+        metadata.archived = archived;
+        self.storage.set_branch_metadata(branch, metadata);
+        Ok(())
+    }
+
+    /// Is `branch` archived (see [`Repo::set_archived`])?
+    pub fn is_archived(&self, branch: &str) -> bool {
+        self.storage.branch_metadata(branch).archived
+    }
+
+    /// Marks `branch` as protected (if `protected` is true) or not protected (if it's false).
+    ///
+    /// A protected branch can't be cleared or deleted, and patches can't be unapplied from it:
+    /// trying to do so returns [`Error::ProtectedBranch`]. To do it anyway, remove the protection
+    /// first. Patches can still be applied to a protected branch.
+    pub fn set_protected(&mut self, branch: &str, protected: bool) -> Result<(), Error> {
+        if self.storage.inode(branch).is_none() {
+            return Err(Error::UnknownBranch(branch.to_owned()));
+        }
+        let mut metadata = self.storage.branch_metadata(branch);
+        metadata.protected = protected;
+        self.storage.set_branch_metadata(branch, metadata);
+        Ok(())
+    }
+
+    /// Is `branch` protected (see [`Repo::set_protected`])?
+    pub fn is_protected(&self, branch: &str) -> bool {
+        self.storage.branch_metadata(branch).protected
+    }
+
+    fn check_unprotected(&self, branch: &str) -> Result<(), Error> {
+        if self.is_protected(branch) {
+            Err(Error::ProtectedBranch(branch.to_owned()))
+        } else {
             Ok(())
         }
     }
@@ -599,35 +2015,138 @@ impl Repo {
     /// something), returns the result of diffing the given branch against `file`.
     pub fn diff(&self, branch: &str, file: &[u8]) -> Result<Diff, Error> {
         let file_a = self.file(branch)?;
-        let lines_a = (0..file_a.num_nodes())
-            .map(|i| file_a.node(i))
-            .collect::<Vec<_>>();
+        let file_b = File::from_bytes(file);
+        Ok(Diff::new(file_a, file_b))
+    }
 
+    /// Like [`Repo::diff`], but also works if `branch` isn't totally ordered.
+    ///
+    /// In that case, the diff is taken against a linearization of the branch, in which the lines
+    /// are in an order consistent with the graggle's edges as far as possible, and `ranking`
+    /// decides whenever there is a choice (see [`Ranking`](crate::resolver::Ranking)). Ties are
+    /// broken by [`NodeId`], so the linearization only depends on the graggle and the ranking.
+    /// Lines that are on a cycle are kept next to one another.
+    ///
+    /// This allows a patch (see [`Changes::from_diff`]) to be made on top of a branch that has
+    /// conflicts. Note that such a patch doesn't resolve the conflicts by itself, because it
+    /// doesn't add any edges between the lines that it keeps.
+    pub fn diff_with_resolution(
+        &self,
+        branch: &str,
+        file: &[u8],
+        ranking: &resolver::Ranking,
+    ) -> Result<Diff, Error> {
+        let inode = self.inode(branch)?;
+        let order = resolver::linearize(self.storage.graggle(inode), ranking);
+        let file_a = File::from_ids(&order, &self.storage, inode);
         let file_b = File::from_bytes(file);
-        let lines_b = (0..file_b.num_nodes())
-            .map(|i| file_b.node(i))
-            .collect::<Vec<_>>();
+        Ok(Diff::new(file_a, file_b))
+    }
 
-        let diff = ojo_diff::diff(&lines_a, &lines_b);
+    /// Like [`Repo::diff`], but faster for large files with few changes.
+    ///
+    /// This hashes every line once, and then cuts both files into blocks of lines and compares
+    /// the blocks. Only the regions of blocks that changed are diffed line by line (see
+    /// [`ojo_diff::Algorithm::Chunked`]), so the cost of the precise diff depends on the size of
+    /// the changes rather than on the size of the file. The result is usually the same as the one
+    /// from [`Repo::diff`], and it is always a valid diff: since hashes may collide, it is checked
+    /// against the actual lines and if there is a mismatch we fall back to [`Repo::diff`].
+    ///
+    /// For files with more than [`Repo::coarse_diff_lines`] lines, even the changed regions
+    /// aren't diffed line by line, so the diff is coarse: a small change can show up as a bigger
+    /// one. Use [`Repo::diff`] to get a precise diff anyway.
+    pub fn diff_incremental(&self, branch: &str, file: &[u8]) -> Result<Diff, Error> {
+        let file_a = self.file(branch)?;
+        let file_b = File::from_bytes(file);
+        let hashes_a = line_hashes(&file_a);
+        let hashes_b = line_hashes(&file_b);
+
+        let coarse = hashes_a.len().max(hashes_b.len()) > self.coarse_diff_lines();
+        let algorithm = if coarse {
+            ojo_diff::Algorithm::Blocks {
+                block_len: DIFF_BLOCK_LEN,
+            }
+        } else {
+            ojo_diff::Algorithm::Chunked {
+                block_len: DIFF_BLOCK_LEN,
+            }
+        };
+        let options = ojo_diff::DiffOptions {
+            algorithm,
+            ..ojo_diff::DiffOptions::default()
+        };
+        let diff = ojo_diff::diff_with_options(&hashes_a, &hashes_b, &options);
+        let collision = diff.iter().any(|d| match *d {
+            LineDiff::Keep(i, j) => file_a.node(i) != file_b.node(j),
+            _ => false,
+        });
+        if collision {
+            debug!("found a hash collision while diffing, falling back to the slow diff");
+            return self.diff(branch, file);
+        }
         Ok(Diff {
             diff,
             file_a,
             file_b,
+            coarse,
         })
     }
+
+    /// Like [`Repo::diff_incremental`], but reads the new contents of the file from `reader`.
+    ///
+    /// This reads everything until the end of the input, so (for example) it can be used to diff
+    /// against data that is piped in through stdin.
+    pub fn diff_reader<R: Read>(&self, branch: &str, mut reader: R) -> Result<Diff, Error> {
+        let mut file = Vec::new();
+        reader
+            .read_to_end(&mut file)
+            .map_err(|e| Error::Io(e, "failed to read the new file contents".to_owned()))?;
+        self.diff_incremental(branch, &file)
+    }
+}
+
+// Hashes every line of a file.
+fn line_hashes(file: &File) -> Vec<u64> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    file.iter_lines()
+        .map(|line| {
+            let mut hasher = DefaultHasher::new();
+            line.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
 }
 
 /// This struct, serialized, is the contents of the database.
 #[derive(Debug, Deserialize, Serialize)]
 struct Db {
+    format_version: u32,
     current_branch: String,
     storage: storage::Storage,
 }
 
+// Just the version of a Db, so that we know how to read the rest of it. Databases that were written
+// before the version was recorded have version 0.
+#[derive(Debug, Deserialize)]
+struct DbVersion {
+    #[serde(default)]
+    format_version: u32,
+}
+
+// A Db with version 0, which needs to be migrated to the current format.
+#[derive(Deserialize)]
+struct DbV0 {
+    current_branch: String,
+    storage: storage::StorageV0,
+}
+
 // The auto-generated Serialize implementation here should be compatible with the auto-generated
 // Seserialize implementation for Db.
 #[derive(Debug, Serialize)]
 struct DbRef<'a> {
+    format_version: u32,
     current_branch: &'a str,
     storage: &'a storage::Storage,
 }
@@ -641,4 +2160,1418 @@ pub struct Diff {
     pub file_b: File,
     /// The diff going from `file_a` to `file_b`.
     pub diff: Vec<LineDiff>,
+    /// Whether the diff was computed on blocks of lines, because the files were too big for a
+    /// precise diff (see [`Repo::diff_incremental`]). A coarse diff is still valid, but its
+    /// changes can be much bigger than necessary.
+    pub coarse: bool,
+}
+
+impl Diff {
+    /// Computes the diff between two files, line by line.
+    pub fn new(file_a: File, file_b: File) -> Diff {
+        let lines_a = file_a.iter_lines().collect::<Vec<_>>();
+        let lines_b = file_b.iter_lines().collect::<Vec<_>>();
+
+        let diff = ojo_diff::diff(&lines_a, &lines_b);
+        Diff {
+            diff,
+            file_a,
+            file_b,
+            coarse: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Creates a temporary repo in which the master branch contains `contents`.
+    fn repo_with_file(contents: &[u8]) -> Repo {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", contents).unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        repo
+    }
+
+    #[test]
+    fn coarse_diff() {
+        let contents = (0..1000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        let mut repo = repo_with_file(contents.as_bytes());
+        assert_eq!(repo.coarse_diff_lines(), DEFAULT_COARSE_DIFF_LINES);
+        let changed = contents
+            .replace("line 200\n", "changed\n")
+            .replace("line 800\n", "changed\n");
+
+        let precise = repo.diff_incremental("master", changed.as_bytes()).unwrap();
+        assert!(!precise.coarse);
+        assert_eq!(precise.diff.len(), 1002);
+
+        repo.set_coarse_diff_lines(999);
+        let coarse = repo.diff_incremental("master", changed.as_bytes()).unwrap();
+        assert!(coarse.coarse);
+        assert!(coarse.diff.len() > 1002);
+        let changes = Changes::from_diff(&coarse.file_a, &coarse.file_b, &coarse.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), changed.as_bytes());
+    }
+
+    #[test]
+    fn diff_incremental_agrees() {
+        let repo = repo_with_file(b"a\nb\nc\nd\ne\n");
+        for new in &[
+            &b"a\nb\nc\nd\ne\n"[..],
+            b"",
+            b"a\nc\nx\ne\n",
+            b"e\nd\nc\nb\na\n",
+        ] {
+            assert_eq!(
+                repo.diff("master", new).unwrap(),
+                repo.diff_incremental("master", new).unwrap()
+            );
+            assert_eq!(
+                repo.diff("master", new).unwrap(),
+                repo.diff_reader("master", &new[..]).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn apply_patches_atomically() {
+        let mut repo = Repo::init_tmp();
+        let mut ids = Vec::new();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"a\nb\nc\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            ids.push(id);
+        }
+        repo.create_branch("other").unwrap();
+
+        // If one of the patches is missing, nothing gets applied.
+        assert!(matches!(
+            repo.apply_patches("other", &[ids[0], PatchId::cur()]),
+            Err(Error::UnknownPatch(_))
+        ));
+        assert_eq!(repo.patches("other").count(), 0);
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"");
+
+        let applied = repo.apply_patches("other", &[ids[0], ids[2]]).unwrap();
+        assert_eq!(applied, ids);
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"a\nb\nc\n");
+
+        repo.unapply_patches("other", &[ids[2]]).unwrap();
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"a\nb\n");
+        let unapplied = repo.unapply_patches("other", &[ids[1], ids[0]]).unwrap();
+        assert_eq!(unapplied, vec![ids[1], ids[0]]);
+        assert_eq!(repo.patches("other").count(), 0);
+    }
+
+    #[test]
+    fn deferred_cache_update() {
+        let mut repo = repo_with_file(b"a\nb\nc\n");
+        let first = *repo.patches("master").next().unwrap();
+        let diff = repo.diff("master", b"a\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.create_branch("other").unwrap();
+        assert!(!repo.needs_cache_update("other").unwrap());
+
+        assert_eq!(
+            repo.apply_patch_no_cache("other", &second).unwrap(),
+            vec![first, second]
+        );
+        assert!(repo.needs_cache_update("other").unwrap());
+        repo.flush_caches("other").unwrap();
+        assert!(!repo.needs_cache_update("other").unwrap());
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"a\nc\n");
+
+        // Flushing a branch that's up to date does nothing.
+        repo.flush_caches("other").unwrap();
+        assert!(matches!(
+            repo.needs_cache_update("nonexistent"),
+            Err(Error::UnknownBranch(_))
+        ));
+    }
+
+    #[test]
+    fn archive() {
+        let mut repo = Repo::init_tmp();
+        repo.create_branch("old").unwrap();
+        assert!(!repo.is_archived("old"));
+        repo.set_archived("old", true).unwrap();
+        assert!(repo.is_archived("old"));
+        repo.set_archived("old", false).unwrap();
+        assert!(!repo.is_archived("old"));
+
+        assert!(matches!(
+            repo.set_archived("master", true),
+            Err(Error::CurrentBranch(_))
+        ));
+        assert!(matches!(
+            repo.set_archived("missing", true),
+            Err(Error::UnknownBranch(_))
+        ));
+
+        // A new branch with the name of a deleted one doesn't inherit its metadata.
+        repo.set_archived("old", true).unwrap();
+        repo.delete_branch("old").unwrap();
+        repo.create_branch("old").unwrap();
+        assert!(!repo.is_archived("old"));
+    }
+
+    #[test]
+    fn protect() {
+        let mut repo = repo_with_file(b"a\n");
+        let id = *repo.patches("master").next().unwrap();
+        repo.clone_branch("master", "other").unwrap();
+        repo.set_protected("master", true).unwrap();
+        repo.set_protected("other", true).unwrap();
+        assert!(repo.is_protected("master"));
+        assert!(matches!(
+            repo.set_protected("missing", true),
+            Err(Error::UnknownBranch(_))
+        ));
+
+        fn protected<T>(r: Result<T, Error>) -> bool {
+            matches!(r, Err(Error::ProtectedBranch(_)))
+        }
+        assert!(protected(repo.unapply_patch("master", &id)));
+        assert!(protected(repo.unapply_patches("master", &[id])));
+        assert!(protected(repo.clear("master")));
+        assert!(protected(repo.delete_branch("other")));
+        assert!(repo.is_applied("master", &id));
+        assert!(repo.branches().any(|b| b == "other"));
+
+        // Applying patches is still allowed, and protection can be lifted.
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &second).unwrap();
+        repo.set_protected("master", false).unwrap();
+        assert_eq!(repo.unapply_patch("master", &second).unwrap(), vec![second]);
+        repo.set_protected("other", false).unwrap();
+        repo.delete_branch("other").unwrap();
+    }
+
+    #[test]
+    fn fork_branch_at() {
+        let mut repo = Repo::init_tmp();
+        let mut ids = Vec::new();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"a\nb\nc\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            ids.push(id);
+        }
+
+        assert!(matches!(
+            repo.fork_branch_at("master", "fork", &ids[1..]),
+            Err(Error::MissingDep(_))
+        ));
+        repo.create_branch("empty").unwrap();
+        assert!(matches!(
+            repo.fork_branch_at("empty", "fork", &ids[..1]),
+            Err(Error::NotApplied(_, _))
+        ));
+        assert!(repo.inode("fork").is_err());
+
+        repo.fork_branch_at("master", "fork", &ids[..2]).unwrap();
+        assert_eq!(
+            repo.patches("fork").collect::<Vec<_>>(),
+            vec![&ids[0], &ids[1]]
+        );
+        assert_eq!(repo.file("fork").unwrap().as_bytes(), b"a\nb\n");
+        // The original branch (including the contents of the unapplied patch) is untouched.
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nb\nc\n");
+    }
+
+    #[test]
+    fn file_with_deleted() {
+        let mut repo = repo_with_file(b"a\nb\nc\nd\n");
+        let diff = repo.diff("master", b"a\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        let file = repo.file_with_deleted("master").unwrap();
+        assert_eq!(file.as_bytes(), b"a\nb\nc\nd\n");
+        let graggle = repo.graggle("master").unwrap();
+        let live = (0..file.num_nodes())
+            .map(|i| graggle.is_live(file.node_id(i)))
+            .collect::<Vec<_>>();
+        assert_eq!(live, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn file_with_deleted_cycle() {
+        let mut repo = repo_with_file(b"a\nb\nc\n");
+        let file = repo.file("master").unwrap();
+        let (a, b, c) = (*file.node_id(0), *file.node_id(1), *file.node_id(2));
+        // An edge from "c" back to "b" makes a cycle, which is then resolved by deleting "c".
+        let changes = Changes {
+            changes: vec![Change::NewEdge { src: c, dest: b }],
+        };
+        let cycle = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &cycle).unwrap();
+        assert!(matches!(repo.file("master"), Err(Error::NotOrdered(_))));
+        let changes = Changes {
+            changes: vec![Change::DeleteNode { id: c }],
+        };
+        let resolve = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &resolve).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nb\n");
+
+        // The deleted node has to go before "b" to break the cycle.
+        let file = repo.file_with_deleted("master").unwrap();
+        assert_eq!(file.as_bytes(), b"a\nc\nb\n");
+        let ids = (0..file.num_nodes())
+            .map(|i| *file.node_id(i))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![a, c, b]);
+    }
+
+    #[test]
+    fn file_with_deleted_order() {
+        // Add "b" and "d" in the same place, in independent patches, and then delete "d". There's
+        // no edge between "b" and "d", so they could go either way around, but "b" is older.
+        let mut repo = repo_with_file(b"a\nc\n");
+        let diff = repo.diff("master", b"a\nb\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let add_b = repo.create_patch("Author", "Msg", changes).unwrap();
+        let diff = repo.diff("master", b"a\nd\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let add_d = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &add_d).unwrap();
+        let diff = repo.diff("master", b"a\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let delete_d = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &delete_d).unwrap();
+        repo.apply_patch("master", &add_b).unwrap();
+
+        // The order doesn't depend on the order in which the patches were applied.
+        repo.create_branch("other").unwrap();
+        repo.apply_patch("other", &add_b).unwrap();
+        repo.apply_patch("other", &delete_d).unwrap();
+        for branch in &["master", "other"] {
+            assert_eq!(repo.file(branch).unwrap().as_bytes(), b"a\nb\nc\n");
+            let file = repo.file_with_deleted(branch).unwrap();
+            assert_eq!(file.as_bytes(), b"a\nb\nd\nc\n");
+        }
+    }
+
+    #[test]
+    fn contents() {
+        let mut repo = repo_with_file(b"a\n");
+        let id = *repo.patches("master").next().unwrap();
+        let a = NodeId { patch: id, node: 0 };
+        let b = NodeId { patch: id, node: 1 };
+        assert_eq!(repo.contents(&a).unwrap(), b"a\n");
+        assert!(matches!(repo.contents(&b), Err(Error::UnknownNode(n)) if n == b));
+
+        // Unapplying the only patch that has the node makes it unknown again.
+        repo.unapply_patch("master", &id).unwrap();
+        assert!(matches!(repo.contents(&a), Err(Error::UnknownNode(_))));
+    }
+
+    #[test]
+    fn nodes() {
+        let mut repo = repo_with_file(b"first line\nsecond line\nthird line\n");
+        let diff = repo.diff("master", b"first line\nsecond lime\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        // Deleted nodes are skipped, and replaced contents are seen from the branch.
+        let mut contents = repo
+            .nodes("master")
+            .unwrap()
+            .map(|(_, contents)| contents)
+            .collect::<Vec<_>>();
+        contents.sort();
+        assert_eq!(contents, vec![&b"first line\n"[..], b"second lime\n"]);
+        assert!(repo
+            .nodes("master")
+            .unwrap()
+            .all(|(id, _)| repo.graggle("master").unwrap().is_live(&id)));
+        assert!(matches!(repo.nodes("nope"), Err(Error::UnknownBranch(_))));
+    }
+
+    #[test]
+    fn verify_branch_closure() {
+        let mut repo = repo_with_file(b"a\n");
+        let first = *repo.patches("master").next().unwrap();
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &second).unwrap();
+        assert_eq!(repo.verify_branch_closure("master"), Ok(()));
+        assert_eq!(repo.verify_branch_closure("nope"), Ok(()));
+
+        repo.storage.remove_branch_patch("master", &first);
+        assert_eq!(repo.verify_branch_closure("master"), Err(vec![first]));
+    }
+
+    #[test]
+    fn check_branch() {
+        let mut repo = repo_with_file(b"a\n");
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        repo.check_branch("master").unwrap();
+
+        // Forget that the first patch was applied, leaving the second without its dependency.
+        let first = *repo.patches("master").next().unwrap();
+        repo.storage.remove_branch_patch("master", &first);
+        assert!(matches!(
+            repo.check_branch("master"),
+            Err(Error::Inconsistent(b, _)) if b == "master"
+        ));
+    }
+
+    #[test]
+    fn node_index_too_large() {
+        let mut repo = Repo::init_tmp();
+        let changes = Changes {
+            changes: vec![Change::NewNode {
+                id: NodeId {
+                    patch: PatchId::cur(),
+                    node: 1 << 32,
+                },
+                contents: b"a\n".to_vec(),
+                line: None,
+                path: None,
+            }],
+        };
+        assert!(matches!(
+            repo.create_patch("Author", "Msg", changes),
+            Err(Error::NodeIndexTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn multi_file_patch() {
+        let node = |n: u64, path: Option<&str>| Change::NewNode {
+            id: NodeId::cur(n),
+            contents: format!("{}\n", n).into_bytes(),
+            line: None,
+            path: path.map(|p| p.to_owned()),
+        };
+        let edge = |src, dest| Change::NewEdge {
+            src: NodeId::cur(src),
+            dest: NodeId::cur(dest),
+        };
+
+        let mut repo = Repo::init_tmp();
+        let changes = Changes {
+            changes: vec![
+                node(0, None),
+                node(1, None),
+                node(2, Some("other")),
+                node(3, Some("other")),
+                edge(0, 1),
+                edge(2, 3),
+            ],
+        };
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        // Only the main file's nodes end up in the branch (and in storage), because the branch
+        // doesn't have the other file.
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"0\n1\n");
+        let other = NodeId { patch: id, node: 2 };
+        assert!(!repo.graggle("master").unwrap().has_node(&other));
+        assert_eq!(repo.storage.node_path(&other), None);
+        let main = NodeId { patch: id, node: 0 };
+        assert_eq!(repo.storage.node_path(&main), Some(None));
+        repo.check_branch("master").unwrap();
+
+        repo.unapply_patch("master", &id).unwrap();
+        assert_eq!(repo.storage.node_path(&main), None);
+
+        let changes = Changes {
+            changes: vec![node(0, None), node(1, Some("other")), edge(0, 1)],
+        };
+        assert!(matches!(
+            repo.create_patch("Author", "Msg", changes),
+            Err(Error::CrossFileEdge(..))
+        ));
+
+        let changes = Changes {
+            changes: vec![node(0, None), node(0, Some("other"))],
+        };
+        assert!(matches!(
+            repo.create_patch("Author", "Msg", changes),
+            Err(Error::DuplicateNode(n)) if n.node == 0
+        ));
+    }
+
+    #[test]
+    fn supersedes() {
+        let mut repo = repo_with_file(b"a\n");
+        let old = *repo.patches("master").next().unwrap();
+        // The new patch doesn't depend on the old one, so that the old one can be removed.
+        repo.create_branch("other").unwrap();
+        let diff = repo.diff("other", b"b\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let new = repo
+            .create_patch_superseding("other", "Author", "Msg", changes, &[old])
+            .unwrap();
+        assert_eq!(
+            repo.open_patch(&new).unwrap().header().supersedes(),
+            vec![old]
+        );
+        assert!(repo
+            .open_patch(&old)
+            .unwrap()
+            .header()
+            .supersedes()
+            .is_empty());
+
+        let expected = vec![(old, vec![new])]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(repo.superseded_patches(), expected);
+
+        // The trail is still there after the old patch is removed, and after the new one is.
+        repo.clear("master").unwrap();
+        repo.unregister_patch(&old).unwrap();
+        assert_eq!(repo.superseded_patches(), expected);
+        repo.unregister_patch(&new).unwrap();
+        assert_eq!(repo.superseded_patches(), expected);
+
+        // So it can be followed back from the patch that superseded the new one.
+        let diff = repo.diff("other", b"c\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let newer = repo
+            .create_patch_superseding("other", "Author", "Msg", changes, &[new])
+            .unwrap();
+        assert_eq!(repo.supersession_trail(&newer), vec![new, old]);
+        assert_eq!(repo.supersession_trail(&new), vec![old]);
+        assert!(repo.supersession_trail(&old).is_empty());
+    }
+
+    #[test]
+    fn lineage() {
+        let mut theirs = Repo::init_tmp();
+        theirs.set_identity(Some("theirs"));
+        let diff = theirs.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = theirs.create_patch("Author", "Msg", changes).unwrap();
+        let data = theirs.open_patch_data(&id).unwrap().to_owned();
+        let patch = theirs.open_patch(&id).unwrap();
+        assert_eq!(patch.header().repository(), Some("theirs"));
+        assert!(!theirs.is_foreign(&patch));
+
+        // Repositories without an identity accept everything.
+        let mut ours = Repo::init_tmp();
+        assert!(!ours.is_foreign(&patch));
+
+        ours.set_identity(Some("ours"));
+        assert!(ours.is_foreign(&patch));
+        assert_eq!(ours.lineage_policy(), LineagePolicy::Warn);
+        ours.set_lineage_policy(LineagePolicy::Refuse);
+        assert!(matches!(
+            ours.register_patch(&data),
+            Err(Error::ForeignPatch(p, ref r)) if p == id && r == "theirs"
+        ));
+        assert!(ours.open_patch(&id).is_err());
+
+        ours.set_lineage_policy(LineagePolicy::Ignore);
+        assert_eq!(ours.register_patch(&data).unwrap(), id);
+
+        // Patches that are already known are fine, even if the policy changes.
+        ours.set_lineage_policy(LineagePolicy::Refuse);
+        assert_eq!(ours.register_patch(&data).unwrap(), id);
+    }
+
+    #[test]
+    fn unregister_patch() {
+        let mut repo = repo_with_file(b"a\n");
+        let first = *repo.patches("master").next().unwrap();
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.set_patch_alias("second", &second).unwrap();
+
+        assert_eq!(repo.transitive_deps(&second), vec![first]);
+        assert_eq!(repo.transitive_rev_deps(&first), vec![second]);
+
+        assert!(matches!(
+            repo.unregister_patch(&first),
+            Err(Error::StillApplied(p, b)) if p == first && b == "master"
+        ));
+        repo.unapply_patch("master", &first).unwrap();
+        assert!(matches!(
+            repo.unregister_patch(&first),
+            Err(Error::NeededBy(p, q)) if p == first && q == second
+        ));
+
+        repo.unregister_patch(&second).unwrap();
+        assert!(matches!(
+            repo.open_patch(&second),
+            Err(Error::UnknownPatch(_))
+        ));
+        assert_eq!(repo.patch_aliases(&second).count(), 0);
+        assert_eq!(repo.patch_rev_deps(&first).count(), 0);
+        repo.unregister_patch(&first).unwrap();
+        assert_eq!(repo.all_patches().count(), 0);
+    }
+
+    // Repositories written by older versions are missing some fields.
+    #[test]
+    fn missing_storage_fields() {
+        let mut repo = repo_with_file(b"a\nb\n");
+        let diff = repo.diff("master", b"a\nc\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &second).unwrap();
+        let order = repo.patches("master").cloned().collect::<Vec<_>>();
+
+        // Removes every occurrence of a field (and its value) from some YAML.
+        let remove_field = |yaml: String, field: &str| {
+            let indent = |line: &str| line.len() - line.trim_start().len();
+            let mut ret = String::new();
+            let mut removing = None;
+            for line in yaml.lines() {
+                match removing {
+                    Some(i) if indent(line) > i => continue,
+                    _ => removing = None,
+                }
+                if line.trim_start().starts_with(&format!("{}:", field)) {
+                    removing = Some(indent(line));
+                } else {
+                    ret.push_str(line);
+                    ret.push('\n');
+                }
+            }
+            assert_ne!(ret, yaml);
+            ret
+        };
+        let mut yaml = serde_yaml::to_string(&repo.storage).unwrap();
+        for field in &[
+            "baselines",
+            "stashes",
+            "patch_aliases",
+            "branch_patch_order",
+            "patch_edges",
+        ] {
+            yaml = remove_field(yaml, field);
+        }
+
+        repo.storage = serde_yaml::from_str(&yaml).unwrap();
+        repo.storage.upgrade();
+        repo.set_paranoid(true);
+        assert_eq!(repo.patches("master").cloned().collect::<Vec<_>>(), order);
+        // Unapplying uses the index of the edges that each patch added.
+        repo.unapply_patch("master", &second).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nb\n");
+    }
+
+    #[test]
+    fn repo_format_version() {
+        let dir = std::env::temp_dir().join(format!("ojo-format-version-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut repo = Repo::init(&dir).unwrap();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        repo.write().unwrap();
+
+        // Databases without a version are migrated, and newer ones are rejected.
+        let db = std::fs::read_to_string(&repo.db_path).unwrap();
+        let version = format!("format_version: {}\n", REPO_FORMAT_VERSION);
+        assert!(db.contains(&version));
+        let storage = storage::tests::v0_yaml(&repo.storage)
+            .lines()
+            .skip(1)
+            .map(|line| format!("  {}\n", line))
+            .collect::<String>();
+        let old = format!("---\ncurrent_branch: master\nstorage:\n{}", storage);
+        std::fs::write(&repo.db_path, old).unwrap();
+        assert_eq!(
+            Repo::open(&dir).unwrap().file("master").unwrap().as_bytes(),
+            b"a\n"
+        );
+        let newer = format!("format_version: {}\n", REPO_FORMAT_VERSION + 1);
+        std::fs::write(&repo.db_path, db.replace(&version, &newer)).unwrap();
+        assert!(matches!(
+            Repo::open(&dir),
+            Err(Error::UnsupportedRepoVersion(v)) if v == REPO_FORMAT_VERSION + 1
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn patch_stats() {
+        let mut repo = repo_with_file(b"a\nb\n");
+        let diff = repo.diff("master", b"a\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        let expected = PatchStats {
+            nodes_added: 1,
+            nodes_deleted: 1,
+            edges_added: 1,
+        };
+        assert_eq!(repo.patch_stats(&id).unwrap(), expected);
+
+        let first = *repo.patches("master").next().unwrap();
+        let expected = PatchStats {
+            nodes_added: 2,
+            nodes_deleted: 0,
+            edges_added: 1,
+        };
+        assert_eq!(repo.patch_stats(&first).unwrap(), expected);
+        // Older repositories don't have the statistics stored.
+        repo.storage.patch_stats.clear();
+        assert_eq!(repo.patch_stats(&first).unwrap(), expected);
+    }
+
+    #[test]
+    fn patch_header() {
+        let mut repo = repo_with_file(b"a\n");
+        let id = *repo.patches("master").next().unwrap();
+        let expected = repo.open_patch(&id).unwrap().header().clone();
+        assert_eq!(repo.patch_header(&id).unwrap(), expected);
+        assert!(repo.storage.patch_headers.contains_key(&id));
+
+        // Older repositories don't have the headers stored, so they're filled in when the
+        // repository is opened.
+        repo.storage.patch_headers = Arc::default();
+        repo.storage.patch_stats.clear();
+        assert_eq!(repo.patch_header(&id).unwrap(), expected);
+        repo.storage.upgrade();
+        assert_eq!(repo.storage.patch_headers.get(&id), Some(&expected));
+        assert!(repo.storage.patch_stats.contains_key(&id));
+
+        repo.unapply_patch("master", &id).unwrap();
+        repo.unregister_patch(&id).unwrap();
+        assert!(repo.patch_header(&id).is_err());
+    }
+
+    #[test]
+    fn patch_metadata() {
+        let mut repo = Repo::init_tmp();
+        let id = repo
+            .create_patch("Author", "Msg", Changes { changes: vec![] })
+            .unwrap();
+        let patch = repo.open_patch(&id).unwrap();
+        assert_eq!(patch.header().branch(), Some("master"));
+        assert_eq!(patch.header().format_version(), Some(REPO_FORMAT_VERSION));
+
+        let id = repo
+            .create_patch_on_branch("other", "Author", "Msg", Changes { changes: vec![] })
+            .unwrap();
+        let patch = repo.open_patch(&id).unwrap();
+        assert_eq!(patch.header().branch(), Some("other"));
+    }
+
+    #[test]
+    fn unapplied_patches() {
+        let mut repo = repo_with_file(b"a\n");
+        let first = *repo.patches("master").next().unwrap();
+        assert!(repo.unapplied_patches("master").is_empty());
+
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &second).unwrap();
+        let diff = repo.diff("master", b"a\nb\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let third = repo.create_patch("Author", "Msg", changes).unwrap();
+        assert_eq!(repo.unapplied_patches("master"), vec![third]);
+
+        repo.create_branch("empty").unwrap();
+        assert_eq!(repo.unapplied_patches("empty"), vec![first, second, third]);
+        // Unknown branches have nothing applied.
+        assert_eq!(repo.unapplied_patches("nonexistent").len(), 3);
+    }
+
+    #[test]
+    fn find_equivalent_patch() {
+        let mut repo = repo_with_file(b"a\n");
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        assert_eq!(repo.find_equivalent_patch(&changes).unwrap(), None);
+        let id = repo.create_patch("Author", "Msg", changes.clone()).unwrap();
+        assert_eq!(
+            repo.open_patch(&id).unwrap().unidentified_changes(),
+            changes
+        );
+
+        // The same changes, but without the line hints.
+        let mut unhinted = changes.clone();
+        for ch in &mut unhinted.changes {
+            if let Change::NewNode { line, .. } = ch {
+                *line = None;
+            }
+        }
+        assert_eq!(repo.find_equivalent_patch(&unhinted).unwrap(), Some(id));
+
+        let diff = repo.diff("master", b"a\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        assert_eq!(repo.find_equivalent_patch(&changes).unwrap(), None);
+    }
+
+    #[test]
+    fn limits() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\nbb\nccc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let patch = UnidentifiedPatch::new("Author".to_owned(), "Msg".to_owned(), changes);
+        let mut patch_data = Vec::new();
+        patch.write_out(&mut patch_data).unwrap();
+        assert_eq!(repo.limits(), Limits::default());
+
+        let tight = |limit| {
+            let mut limits = Limits::default();
+            match limit {
+                Limit::Nodes => limits.max_nodes = 2,
+                Limit::PatchSize => limits.max_patch_size = 10,
+                Limit::ContentsSize => limits.max_contents_size = 8,
+            }
+            limits
+        };
+        for &limit in &Limit::ALL {
+            repo.set_limits(tight(limit));
+            match repo.register_patch(&patch_data) {
+                Err(Error::LimitExceeded(id, l, _, max)) => {
+                    assert_eq!(id, PatchId::of_data(&patch_data));
+                    assert_eq!(l, limit);
+                    assert_eq!(max, tight(limit).get(limit));
+                }
+                other => panic!("expected a limit error, got {:?}", other),
+            }
+            assert_eq!(repo.all_patches().count(), 0);
+        }
+
+        // Patches that are too big aren't even parsed.
+        repo.set_limits(tight(Limit::PatchSize));
+        assert!(matches!(
+            repo.register_patch(b"not a patch, and too big"),
+            Err(Error::LimitExceeded(id, Limit::PatchSize, 24, 10))
+                if id == PatchId::of_data(b"not a patch, and too big")
+        ));
+
+        repo.set_limits(Limits {
+            max_nodes: 3,
+            max_contents_size: 9,
+            ..Limits::default()
+        });
+        repo.register_patch(&patch_data).unwrap();
+    }
+
+    #[test]
+    fn write_file_and_dot() {
+        let mut repo = repo_with_file(b"a\n<b>\n");
+        let mut out = Vec::new();
+        repo.write_file("master", &mut out).unwrap();
+        assert_eq!(out, b"a\n<b>\n");
+
+        let mut out = Vec::new();
+        repo.write_graph_dot("master", &mut out, &DotOptions::default())
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("digraph {\n"));
+        assert!(out.ends_with("}\n"));
+        assert!(out.contains("&lt;b&gt;"));
+        // The two lines form a single chain.
+        assert_eq!(out.matches("shape=box").count(), 1);
+
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        let opts = DotOptions {
+            deleted_only: true,
+            ..DotOptions::default()
+        };
+        let mut out = Vec::new();
+        repo.write_graph_dot("master", &mut out, &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("<s>"));
+        assert!(!out.contains(" a</font>") && !out.contains(": a<"));
+
+        let repo = repo_with_file(b"1\n2\n3\n4\n5\n");
+        let opts = DotOptions {
+            condense_chains: true,
+            ..DotOptions::default()
+        };
+        let mut out = Vec::new();
+        repo.write_graph_dot("master", &mut out, &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("shape=box").count(), 1);
+        assert!(out.contains("</font> 1<"));
+        assert!(out.contains("3 more lines"));
+        assert!(out.contains("</font> 5<"));
+        assert!(!out.contains("</font> 3<"));
+    }
+
+    #[test]
+    fn not_ordered_disorder() {
+        let mut repo = repo_with_file(b"a\nc\n");
+        let first = *repo.patches("master").next().unwrap();
+        let node = |node| NodeId { patch: first, node };
+        let mut ids = vec![];
+        for line in &[&b"a\nb\nc\n"[..], b"a\nB\nc\n"] {
+            let diff = repo.diff("master", line).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            ids.push(repo.create_patch("Author", "Msg", changes).unwrap());
+        }
+        for id in &ids {
+            repo.apply_patch("master", id).unwrap();
+        }
+        match repo.file("master") {
+            Err(Error::NotOrdered(d)) => {
+                assert_eq!((d.cycles, d.unordered), (0, 1));
+                assert_eq!(d.nodes.len(), 2);
+                assert!(!d.nodes.contains(&node(0)) && !d.nodes.contains(&node(1)));
+            }
+            other => panic!("expected NotOrdered, got {:?}", other.map(|_| ())),
+        }
+
+        // Add an edge from the last line back to the first.
+        let mut repo = repo_with_file(b"a\nb\nc\n");
+        let first = *repo.patches("master").next().unwrap();
+        let node = |node| NodeId { patch: first, node };
+        let changes = Changes {
+            changes: vec![Change::NewEdge {
+                src: node(2),
+                dest: node(0),
+            }],
+        };
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        match repo.file("master") {
+            Err(Error::NotOrdered(d)) => {
+                assert_eq!((d.cycles, d.unordered), (1, 0));
+                assert_eq!(d.nodes, vec![node(0)]);
+            }
+            other => panic!("expected NotOrdered, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn diff_with_resolution() {
+        let mut repo = repo_with_file(b"a\nc\n");
+        let diff = repo.diff("master", b"a\nb\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let first = repo.create_patch("Author", "Msg", changes).unwrap();
+        let diff = repo.diff("master", b"a\nB\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patches("master", &[first, second]).unwrap();
+        assert!(matches!(
+            repo.diff("master", b"a\nb\nB\nc\n"),
+            Err(Error::NotOrdered(_))
+        ));
+
+        // The older patch's line goes first.
+        let ranking = resolver::Ranking::Timestamps(repo.patch_timestamps("master").unwrap());
+        let diff = repo
+            .diff_with_resolution("master", b"a\nb\nB\nc\nd\n", &ranking)
+            .unwrap();
+        assert_eq!(diff.file_a.as_bytes(), b"a\nb\nB\nc\n");
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        // The new line is there, but the conflict is still there too.
+        assert!(matches!(repo.file("master"), Err(Error::NotOrdered(_))));
+        let diff = repo
+            .diff_with_resolution("master", b"a\nb\nB\nc\nd\n", &ranking)
+            .unwrap();
+        assert_eq!(diff.file_a.as_bytes(), b"a\nb\nB\nc\nd\n");
+        assert!(diff.diff.iter().all(|d| matches!(d, LineDiff::Keep(..))));
+    }
+
+    #[test]
+    fn conflicting_patches() {
+        let mut repo = repo_with_file(b"a\nc\n");
+        let diff = repo.diff("master", b"a\nb\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let first = repo.create_patch("Author", "Msg", changes).unwrap();
+        let diff = repo.diff("master", b"a\nB\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &first).unwrap();
+
+        // This one touches the same nodes as `first`, but it depends on `first`.
+        let diff = repo.diff("master", b"a\nx\nb\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let third = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &third).unwrap();
+        assert!(repo.conflicting_patches("master").unwrap().is_empty());
+
+        repo.apply_patch("master", &second).unwrap();
+        let mut expected = vec![];
+        for p in &[first, third] {
+            expected.push(if *p < second {
+                (*p, second)
+            } else {
+                (second, *p)
+            });
+        }
+        expected.sort();
+        assert_eq!(repo.conflicting_patches("master").unwrap(), expected);
+    }
+
+    #[test]
+    fn ordering_hints() {
+        let mut repo = repo_with_file(b"a\nb\n");
+        let first = *repo.patches("master").next().unwrap();
+        let diff = repo.diff("master", b"c\na\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &second).unwrap();
+
+        let hints = repo.ordering_hints("master").unwrap();
+        assert_eq!(hints.len(), 3);
+        assert_eq!(
+            hints[&NodeId {
+                patch: first,
+                node: 1
+            }],
+            1
+        );
+        assert_eq!(
+            hints[&NodeId {
+                patch: second,
+                node: 0
+            }],
+            0
+        );
+    }
+
+    #[test]
+    fn file_creators() {
+        let mut repo = Repo::init_tmp();
+        assert!(repo.file_creators("master").unwrap().is_empty());
+
+        let mut ids = Vec::new();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"", b"c\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            ids.push(id);
+        }
+        assert!(repo.open_patch(&ids[0]).unwrap().changes().creates_file());
+        assert!(!repo.open_patch(&ids[1]).unwrap().changes().creates_file());
+
+        // Emptying the file and then filling it again counts as creating it again.
+        assert_eq!(repo.file_creators("master").unwrap(), vec![ids[0], ids[3]]);
+        repo.unapply_patch("master", &ids[3]).unwrap();
+        assert_eq!(repo.file_creators("master").unwrap(), vec![ids[0]]);
+    }
+
+    #[test]
+    fn patch_timestamps() {
+        use crate::resolver::{CycleResolver, Ranking};
+
+        // Make a conflict between two lines that were added to the same file, on different
+        // branches.
+        let mut repo = repo_with_file(b"a\n");
+        repo.clone_branch("master", "other").unwrap();
+        let mut ids = Vec::new();
+        for (branch, contents) in &[("master", b"a\nb\n"), ("other", b"a\nc\n")] {
+            let diff = repo.diff(branch, *contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            ids.push(id);
+        }
+
+        let mut times = repo.patch_timestamps("master").unwrap();
+        assert_eq!(times.len(), 3);
+        for p in repo.patches("master") {
+            assert_eq!(times[p], repo.open_patch(p).unwrap().header().timestamp);
+        }
+
+        // Whichever patch is newer goes last.
+        let firsts = |times: &HashMap<PatchId, _>| {
+            let graggle = repo.graggle("master").unwrap();
+            let mut res = CycleResolver::new(graggle)
+                .into_order_resolver_with(Ranking::Timestamps(times.clone()));
+            res.auto_advance();
+            res.candidates()
+                .map(|c| c.first().patch)
+                .collect::<Vec<_>>()
+        };
+        let (b, c) = (ids[0], ids[1]);
+        times.insert(c, times[&b] + chrono::Duration::seconds(1));
+        assert_eq!(firsts(&times), vec![b, c]);
+        times.insert(c, times[&b] - chrono::Duration::seconds(1));
+        assert_eq!(firsts(&times), vec![c, b]);
+
+        // Patches without a time go last.
+        times.remove(&c);
+        assert_eq!(firsts(&times), vec![b, c]);
+    }
+
+    #[test]
+    fn resolution_patch() {
+        let mut repo = repo_with_file(b"a\nb\n");
+        let id = *repo.patches("master").next().unwrap();
+        let patch = repo.open_patch(&id).unwrap();
+        assert_eq!(patch.header().resolution, None);
+        assert!(!std::str::from_utf8(repo.open_patch_data(&id).unwrap())
+            .unwrap()
+            .contains("resolution"));
+
+        let a = NodeId { patch: id, node: 0 };
+        let b = NodeId { patch: id, node: 1 };
+        let resolution = Resolution {
+            representatives: vec![],
+            ordered: vec![],
+            deleted: vec![b],
+            replaced: vec![],
+        };
+        let changes = Changes {
+            changes: vec![Change::DeleteNode { id: b }],
+        };
+        let res_id = repo
+            .create_resolution_patch("master", "Author", "Msg", changes, resolution.clone())
+            .unwrap();
+        let patch = repo.open_patch(&res_id).unwrap();
+        assert_eq!(patch.header().resolution.as_ref(), Some(&resolution));
+        assert_eq!(patch.deps(), &[id]);
+        repo.apply_patch("master", &res_id).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\n");
+        assert!(repo.graggle("master").unwrap().has_node(&a));
+    }
+
+    #[test]
+    fn edit_in_place() {
+        let mut repo = repo_with_file(b"first line\nsecond line\n");
+        repo.clone_branch("master", "other").unwrap();
+        let new = b"first line\nsecond lime\n";
+        let diff = repo.diff("master", new).unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        assert!(changes
+            .changes
+            .iter()
+            .any(|ch| matches!(ch, Change::ReplaceContents { .. })));
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        // The edited line kept its node, and the other branch still sees the old contents.
+        assert_eq!(repo.file("master").unwrap().as_bytes(), &new[..]);
+        assert_eq!(
+            repo.graggle("master")
+                .unwrap()
+                .as_full_graph()
+                .nodes()
+                .count(),
+            2
+        );
+        assert_eq!(
+            repo.file("other").unwrap().as_bytes(),
+            &b"first line\nsecond line\n"[..]
+        );
+
+        repo.unapply_patch("master", &id).unwrap();
+        assert_eq!(
+            repo.file("master").unwrap().as_bytes(),
+            &b"first line\nsecond line\n"[..]
+        );
+    }
+
+    // Creates (but doesn't apply) a patch that changes `branch` to have the given contents.
+    fn edit_patch(repo: &mut Repo, branch: &str, contents: &[u8]) -> PatchId {
+        let diff = repo.diff(branch, contents).unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        repo.create_patch_on_branch(branch, "Author", "Msg", changes)
+            .unwrap()
+    }
+
+    #[test]
+    fn concurrent_replacements() {
+        let mut repo = repo_with_file(b"a\nsecond line\n");
+        repo.clone_branch("master", "other").unwrap();
+        let x = edit_patch(&mut repo, "master", b"a\nsecond lime\n");
+        let y = edit_patch(&mut repo, "other", b"a\nsecond line!\n");
+        repo.apply_patch("master", &x).unwrap();
+        repo.apply_patch("other", &y).unwrap();
+
+        // After merging the branches (in either order), neither edit knows about the other, so
+        // neither of them wins.
+        repo.apply_patch("master", &y).unwrap();
+        repo.apply_patch("other", &x).unwrap();
+        let conflicts = repo.edit_conflicts("master").unwrap();
+        assert_eq!(conflicts.len(), 1);
+        let node = conflicts[0].0;
+        assert_eq!(repo.contents(&node).unwrap(), b"second line\n");
+        assert_eq!(conflicts[0].1, vec![x.min(y), x.max(y)]);
+        assert_eq!(repo.edit_conflicts("other").unwrap(), conflicts);
+        assert_eq!(repo.replacement(&node, &x).unwrap(), b"second lime\n");
+        assert_eq!(repo.replacement(&node, &y).unwrap(), b"second line!\n");
+        for branch in &["master", "other"] {
+            match repo.file(branch) {
+                Err(Error::NotOrdered(d)) => {
+                    assert_eq!((d.cycles, d.unordered, d.edits), (0, 0, 1));
+                    assert_eq!(d.nodes, vec![node]);
+                }
+                other => panic!("expected NotOrdered, got {:?}", other.map(|_| ())),
+            }
+        }
+        assert_eq!(
+            repo.conflicting_patches("master").unwrap(),
+            vec![(x.min(y), x.max(y))]
+        );
+
+        // Unapplying one of the edits settles the conflict.
+        repo.unapply_patch("other", &x).unwrap();
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"a\nsecond line!\n");
+
+        // A resolution knows about both edits, so its contents take precedence.
+        let (changes, resolution) = {
+            let graggle = repo.graggle("master").unwrap();
+            let mut res = resolver::CycleResolver::new(graggle).into_order_resolver();
+            res.auto_advance();
+            assert!(res.is_finished());
+            res.replace_contents(&node, b"second lime!\n".to_vec());
+            (res.changes(), res.resolution())
+        };
+        assert_eq!(resolution.replaced, vec![node]);
+        let res = repo
+            .create_resolution_patch("master", "Author", "Msg", changes, resolution)
+            .unwrap();
+        let deps = repo.open_patch(&res).unwrap().deps().to_vec();
+        assert!(deps.contains(&x) && deps.contains(&y));
+        repo.apply_patch("master", &res).unwrap();
+        assert!(repo.edit_conflicts("master").unwrap().is_empty());
+        assert_eq!(
+            repo.file("master").unwrap().as_bytes(),
+            b"a\nsecond lime!\n"
+        );
+    }
+
+    #[test]
+    fn sequential_replacements() {
+        let mut repo = repo_with_file(b"a\nsecond line\n");
+        let x = edit_patch(&mut repo, "master", b"a\nsecond lime\n");
+        repo.apply_patch("master", &x).unwrap();
+        let y = edit_patch(&mut repo, "master", b"a\nsecond line!\n");
+        assert!(repo.open_patch(&y).unwrap().deps().contains(&x));
+        repo.apply_patch("master", &y).unwrap();
+
+        // The later edit wins, whatever the ids are.
+        assert_eq!(
+            repo.file("master").unwrap().as_bytes(),
+            b"a\nsecond line!\n"
+        );
+        repo.clone_branch("master", "other").unwrap();
+        repo.unapply_patch("other", &x).unwrap();
+        repo.apply_patch("other", &y).unwrap();
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"a\nsecond line!\n");
+    }
+
+    #[test]
+    fn delete_and_concurrent_replace() {
+        let mut repo = repo_with_file(b"a\nsecond line\n");
+        repo.clone_branch("master", "other").unwrap();
+        let edit = edit_patch(&mut repo, "other", b"a\nsecond lime\n");
+        let delete = edit_patch(&mut repo, "master", b"a\n");
+        repo.apply_patch("master", &delete).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\n");
+
+        // The deletion didn't know about the edit, so the edit isn't lost.
+        repo.apply_patch("master", &edit).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nsecond lime\n");
+        repo.apply_patch("other", &edit).unwrap();
+        repo.apply_patch("other", &delete).unwrap();
+        assert_eq!(repo.file("other").unwrap().as_bytes(), b"a\nsecond lime\n");
+
+        repo.unapply_patch("master", &edit).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\n");
+    }
+
+    #[test]
+    fn delete_after_replace() {
+        let mut repo = repo_with_file(b"a\nsecond line\n");
+        let edit = edit_patch(&mut repo, "master", b"a\nsecond lime\n");
+        repo.apply_patch("master", &edit).unwrap();
+        let delete = edit_patch(&mut repo, "master", b"a\n");
+        assert!(repo.open_patch(&delete).unwrap().deps().contains(&edit));
+        repo.apply_patch("master", &delete).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\n");
+
+        repo.unapply_patch("master", &delete).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nsecond lime\n");
+    }
+
+    #[test]
+    fn hooks() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut repo = repo_with_file(b"a\n");
+        let applied = Rc::new(Cell::new(0));
+        let applied_clone = Rc::clone(&applied);
+        repo.register_hook(HookKind::PostApply, move |event| {
+            if let HookEvent::PostApply { patches, .. } = event {
+                applied_clone.set(applied_clone.get() + patches.len());
+            }
+            Ok(())
+        });
+        repo.register_hook(HookKind::PreCreatePatch, |event| match event {
+            HookEvent::PreCreatePatch { author, .. } if *author == "Bad" => Err("no".to_owned()),
+            _ => Ok(()),
+        });
+
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        match repo.create_patch("Bad", "Msg", changes.clone()) {
+            Err(Error::HookFailed(HookKind::PreCreatePatch, _)) => {}
+            other => panic!("expected the hook to fail, got {:?}", other),
+        }
+
+        let id = repo.create_patch("Good", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        assert_eq!(applied.get(), 1);
+    }
+
+    #[test]
+    fn patch_prefixes() {
+        let mut repo = repo_with_file(b"a\n");
+        let first = *repo.patches("master").next().unwrap();
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = repo.create_patch("Author", "Msg", changes).unwrap();
+
+        for id in &[first, second] {
+            let abbrev = repo.abbreviate_patch_id(id);
+            assert!(abbrev.len() >= MIN_PATCH_PREFIX_LEN);
+            assert_eq!(repo.resolve_patch_prefix(&abbrev).unwrap(), *id);
+            assert_eq!(repo.resolve_patch_prefix(&id.to_string()).unwrap(), *id);
+        }
+        match repo.resolve_patch_prefix("P") {
+            Err(Error::AmbiguousPatchPrefix(_)) => {}
+            other => panic!("expected an ambiguous prefix, got {:?}", other),
+        }
+        match repo.resolve_patch_prefix("Pnope") {
+            Err(Error::UnknownPatchPrefix(_)) => {}
+            other => panic!("expected an unknown prefix, got {:?}", other),
+        }
+
+        // The abbreviations keep up with patches being added and removed.
+        let mut ids = vec![first, second];
+        for i in 0..20 {
+            let diff = repo.diff("master", format!("{}\n", i).as_bytes()).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            ids.push(repo.create_patch("Author", "Msg", changes).unwrap());
+            let id = ids.last().unwrap();
+            assert_eq!(
+                repo.resolve_patch_prefix(&repo.abbreviate_patch_id(id))
+                    .unwrap(),
+                *id
+            );
+        }
+        repo.unregister_patch(&second).unwrap();
+        assert!(repo
+            .abbreviate_patch_id(&second)
+            .starts_with(&second.to_string()[..MIN_PATCH_PREFIX_LEN]));
+        for id in ids.iter().filter(|id| **id != second) {
+            let abbrev = repo.abbreviate_patch_id(id);
+            assert_eq!(abbrev.len(), MIN_PATCH_PREFIX_LEN);
+            assert_eq!(repo.resolve_patch_prefix(&abbrev).unwrap(), *id);
+        }
+    }
+
+    #[test]
+    fn reattribute() {
+        let mut repo = Repo::init_tmp();
+        assert_eq!(repo.author("Me"), "Me");
+
+        repo.reattribute("Me", "Myself");
+        assert_eq!(repo.author("Me"), "Myself");
+        assert_eq!(repo.author("Myself"), "Myself");
+
+        // Chains of reattributions are followed.
+        repo.reattribute("Myself", "I");
+        repo.reattribute("Someone", "Me");
+        assert_eq!(repo.author("Me"), "I");
+        assert_eq!(repo.author("Myself"), "I");
+        assert_eq!(repo.author("Someone"), "I");
+
+        // The latest reattribution wins.
+        repo.reattribute("I", "Me");
+        assert_eq!(repo.author("I"), "Me");
+        assert_eq!(repo.author("Me"), "Me");
+        assert_eq!(repo.author("Myself"), "Me");
+
+        repo.reattribute("Myself", "Myself");
+        assert_eq!(repo.author("Myself"), "Myself");
+        assert_eq!(
+            repo.reattributions().collect::<Vec<_>>(),
+            vec![("I", "Me"), ("Someone", "Me")]
+        );
+    }
+
+    #[test]
+    fn patch_aliases() {
+        let mut repo = repo_with_file(b"a\n");
+        let id = *repo.patches("master").next().unwrap();
+        repo.set_patch_alias("first", &id).unwrap();
+        assert_eq!(repo.resolve_patch("first").unwrap(), id);
+        assert_eq!(repo.patch_aliases(&id).collect::<Vec<_>>(), vec!["first"]);
+        match repo.set_patch_alias("first", &id) {
+            Err(Error::AliasExists(_)) => {}
+            other => panic!("expected an existing alias, got {:?}", other),
+        }
+
+        assert_eq!(repo.remove_patch_alias("first"), Some(id));
+        assert!(repo.resolve_patch("first").is_err());
+    }
+
+    #[test]
+    fn is_applied() {
+        let mut repo = repo_with_file(b"a\n");
+        let id = *repo.patches("master").next().unwrap();
+        assert!(repo.is_applied("master", &id));
+        assert!(!repo.is_applied("nonexistent", &id));
+
+        repo.unapply_patch("master", &id).unwrap();
+        assert!(!repo.is_applied("master", &id));
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let base = Path::new("base");
+        assert_eq!(
+            resolve_path(base, Path::new("ojo")).unwrap(),
+            Path::new("base").join("ojo")
+        );
+        let abs = std::env::temp_dir();
+        assert_eq!(resolve_path(base, &abs).unwrap(), abs);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn resolve_windows_paths() {
+        let base = Path::new(r"C:\base");
+        for path in &[r"\\server\share\ojo", r"\\?\C:\ojo", r"D:\ojo"] {
+            assert_eq!(
+                resolve_path(base, Path::new(path)).unwrap(),
+                Path::new(path)
+            );
+        }
+        for path in &[r"D:ojo", r"\ojo"] {
+            assert!(matches!(
+                resolve_path(base, Path::new(path)),
+                Err(Error::UnsupportedPath(_))
+            ));
+        }
+    }
 }
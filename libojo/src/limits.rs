@@ -0,0 +1,91 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::fmt;
+
+/// Limits on the size of the patches that a [`Repo`](crate::Repo) will accept.
+///
+/// Patches that exceed these limits are rejected (with [`Error::LimitExceeded`](crate::Error))
+/// when they are registered, so that an accidentally (or maliciously) huge patch can't make the
+/// repository unusable. The defaults are generous; they can be changed with
+/// [`Repo::set_limits`](crate::Repo::set_limits), and the changes are saved with the repository.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Limits {
+    /// The maximum number of nodes that a single patch can add.
+    pub max_nodes: usize,
+    /// The maximum size, in bytes, of a patch's serialized data.
+    pub max_patch_size: usize,
+    /// The maximum total size, in bytes, of the contents that a single patch can add to the file
+    /// (counting both new nodes and replaced contents).
+    pub max_contents_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_nodes: 4_000_000,
+            max_patch_size: 1 << 30,
+            max_contents_size: 256 << 20,
+        }
+    }
+}
+
+impl Limits {
+    /// Returns the value of one of the limits.
+    pub fn get(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::Nodes => self.max_nodes,
+            Limit::PatchSize => self.max_patch_size,
+            Limit::ContentsSize => self.max_contents_size,
+        }
+    }
+}
+
+/// One of the limits in [`Limits`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Limit {
+    /// [`Limits::max_nodes`].
+    Nodes,
+    /// [`Limits::max_patch_size`].
+    PatchSize,
+    /// [`Limits::max_contents_size`].
+    ContentsSize,
+}
+
+impl Limit {
+    /// All the different limits.
+    pub const ALL: [Limit; 3] = [Limit::Nodes, Limit::PatchSize, Limit::ContentsSize];
+
+    /// The name of this limit, in kebab-case (for example, `"max-nodes"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Limit::Nodes => "max-nodes",
+            Limit::PatchSize => "max-patch-size",
+            Limit::ContentsSize => "max-contents-size",
+        }
+    }
+
+    // Describes the quantity that this limit applies to, for error messages.
+    pub(crate) fn noun(self) -> &'static str {
+        match self {
+            Limit::Nodes => "new nodes",
+            Limit::PatchSize => "bytes",
+            Limit::ContentsSize => "bytes of new contents",
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+
+/// What a [`Repo`](crate::Repo) does with patches that were created in a different repository.
+///
+/// Repositories created by [`Repo::init`](crate::Repo::init) get a random identity, which is
+/// recorded in the metadata of every patch that they create (see
+/// [`PatchHeader::repository`](crate::PatchHeader::repository)). Repositories that share patches
+/// should share an identity (see [`Repo::set_identity`](crate::Repo::set_identity)); a patch from
+/// a repository with a different identity was probably meant for some other project. Patches (or
+/// repositories) without an identity are never considered foreign.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineagePolicy {
+    /// Accept foreign patches without comment.
+    Ignore,
+    /// Accept foreign patches, but let the user know (this is up to the caller: see
+    /// [`Repo::is_foreign`](crate::Repo::is_foreign)).
+    #[default]
+    Warn,
+    /// Refuse to register foreign patches, with [`Error::ForeignPatch`](crate::Error).
+    Refuse,
+}
+
+impl LineagePolicy {
+    /// All the different policies.
+    pub const ALL: [LineagePolicy; 3] = [
+        LineagePolicy::Ignore,
+        LineagePolicy::Warn,
+        LineagePolicy::Refuse,
+    ];
+
+    /// The name of this policy (for example, `"warn"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            LineagePolicy::Ignore => "ignore",
+            LineagePolicy::Warn => "warn",
+            LineagePolicy::Refuse => "refuse",
+        }
+    }
+}
+
+impl fmt::Display for LineagePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Makes up an identity for a new repository at `path`, in the format of a (version 4) UUID.
+//
+// It only needs to be unique in practice, so instead of finding a source of randomness we hash
+// some things that are unlikely to be repeated.
+pub(crate) fn new_identity(path: &Path) -> String {
+    let seed = format!(
+        "{:?} {} {:?}",
+        std::time::SystemTime::now(),
+        std::process::id(),
+        path
+    );
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&Sha256::digest(seed.as_bytes())[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
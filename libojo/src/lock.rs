@@ -0,0 +1,52 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+// Advisory locks on a repository's data directory. Writing the database takes an exclusive lock
+// and reading it takes a shared lock, so that nobody reads a database in the middle of a write.
+// (Since the database is also replaced atomically, this mostly matters for platforms where a file
+// can't be replaced while someone has it open.) The locks are released when the `Lock` is dropped.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use crate::Error;
+
+// The name of the lock file, in the repository's data directory.
+const LOCK_FILE: &str = "lock";
+
+pub(crate) struct Lock {
+    _file: Option<File>,
+}
+
+pub(crate) fn shared(repo_dir: &Path) -> Result<Lock, Error> {
+    // We don't create the lock file here, because readers shouldn't need to write anything. If
+    // the lock file doesn't exist, nobody is holding the lock.
+    match File::open(repo_dir.join(LOCK_FILE)) {
+        Ok(file) => {
+            file.lock_shared()?;
+            Ok(Lock { _file: Some(file) })
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Lock { _file: None }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) fn exclusive(repo_dir: &Path) -> Result<Lock, Error> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(repo_dir.join(LOCK_FILE))?;
+    file.lock()?;
+    Ok(Lock { _file: Some(file) })
+}
@@ -0,0 +1,427 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::storage::BranchMetadata;
+use crate::{Error, Limits, LineagePolicy, PatchId, Repo, UnidentifiedPatch};
+
+/// The version of the pack format that is written by [`Pack::write_out`].
+///
+/// Version 1 had no repository settings. Version 2 added `limits`, `branch_metadata` and
+/// `authors`. Packs of either version can be read.
+pub const PACK_FORMAT_VERSION: u32 = 2;
+
+/// A self-contained archive of an entire repository.
+///
+/// A pack contains every patch that the repository knows about, together with the metadata (like
+/// branches and aliases) that is needed to reconstruct the repository. It doesn't contain any of
+/// the repository's derived data (like graggles), because that can be recomputed from the patches.
+///
+/// # Layout
+///
+/// A pack is a YAML document (see [`Pack::write_out`]) with the following fields:
+///
+/// - `format_version`: the version of this layout, currently [`PACK_FORMAT_VERSION`]. Readers
+///   must refuse to read versions that they don't know about.
+/// - `patches`: the contents of all the patches, as strings in exactly the format that they would
+///   be exported in. Every patch appears after all of its dependencies.
+/// - `branches`: a map from branch names to the patches applied to them, in the order that they
+///   were applied.
+/// - `current_branch`: the name of the current branch.
+/// - `aliases`: a map from patch aliases to the patches that they refer to.
+/// - `baselines`: the patches that are baselines (see [`Repo::create_baseline`]). This field is
+///   optional, and it is only present if the history in the pack is truncated.
+/// - `identity`: the identity of the repository's lineage (see [`Repo::identity`]). This field is
+///   optional, and it is only present if the repository has an identity.
+/// - `lineage_policy`: what the repository does with patches from other lineages (see
+///   [`LineagePolicy`]).
+/// - `limits`: the limits on the size of patches (see [`Repo::limits`]).
+/// - `branch_metadata`: a map from branch names to whether those branches are archived and
+///   whether they are protected (see [`Repo::set_archived`] and [`Repo::set_protected`]). Branches
+///   that are neither don't appear in it.
+/// - `authors`: a map from patch authors to the names that they are shown with (see
+///   [`Repo::reattribute`]).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Pack {
+    format_version: u32,
+    patches: Vec<String>,
+    branches: BTreeMap<String, Vec<PatchId>>,
+    current_branch: String,
+    aliases: BTreeMap<String, PatchId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    baselines: Vec<PatchId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+    #[serde(default)]
+    lineage_policy: LineagePolicy,
+    #[serde(default)]
+    limits: Limits,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    branch_metadata: BTreeMap<String, BranchMetadata>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    authors: BTreeMap<String, String>,
+}
+
+impl Pack {
+    /// Writes out this pack in YAML format.
+    pub fn write_out<W: Write>(&self, writer: W) -> Result<(), Error> {
+        serde_yaml::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Reads a pack in the format written by [`Pack::write_out`].
+    pub fn from_reader<R: Read>(input: R) -> Result<Pack, Error> {
+        let pack: Pack = serde_yaml::from_reader(input)?;
+        if pack.format_version == 0 || pack.format_version > PACK_FORMAT_VERSION {
+            return Err(Error::UnsupportedPackVersion(pack.format_version));
+        }
+        Ok(pack)
+    }
+}
+
+impl Repo {
+    // Returns the contents of `roots` and all of their (direct and indirect) dependencies, ordered
+    // so that every patch comes after its dependencies.
+    fn patch_closure(&self, roots: impl Iterator<Item = PatchId>) -> Vec<String> {
+        let mut roots = roots.collect::<Vec<_>>();
+        roots.sort();
+        let mut seen = HashSet::new();
+        let mut patches = Vec::new();
+        for root in roots {
+            let mut stack = vec![(root, false)];
+            while let Some((p, deps_done)) = stack.pop() {
+                if deps_done {
+                    patches.push(self.storage.patches[&p].clone());
+                } else if seen.insert(p) {
+                    stack.push((p, true));
+                    stack.extend(self.patch_deps(&p).map(|&d| (d, false)));
+                }
+            }
+        }
+        patches
+    }
+
+    // Returns the metadata of those of `branches` that have any.
+    fn branch_metadata<'a>(
+        &self,
+        branches: impl Iterator<Item = &'a str>,
+    ) -> BTreeMap<String, BranchMetadata> {
+        branches
+            .map(|b| (b.to_owned(), self.storage.branch_metadata(b)))
+            .filter(|(_, m)| *m != BranchMetadata::default())
+            .collect()
+    }
+
+    /// Packs all the patches in this repository (and all of the repository's metadata) into a
+    /// single archive.
+    pub fn pack(&self) -> Pack {
+        let branches = self
+            .branches()
+            .map(|b| (b.to_owned(), self.patches(b).cloned().collect()))
+            .collect();
+
+        Pack {
+            format_version: PACK_FORMAT_VERSION,
+            patches: self.patch_closure(self.all_patches().cloned()),
+            branches,
+            current_branch: self.current_branch.clone(),
+            aliases: self.storage.patch_aliases.clone(),
+            baselines: self.storage.baselines.iter().cloned().collect(),
+            identity: self.storage.identity.clone(),
+            lineage_policy: self.storage.lineage_policy,
+            limits: self.storage.limits,
+            branch_metadata: self.branch_metadata(self.branches()),
+            authors: self.storage.authors.clone(),
+        }
+    }
+
+    /// Packs only the patches that are applied to `branch`, for example in order to share a
+    /// branch without also sharing any unrelated (or abandoned) patches.
+    ///
+    /// The resulting [`Pack`] contains only the one branch, which is also its current branch, and
+    /// only the aliases that refer to patches in that branch. The branch keeps its protection, but
+    /// it isn't archived in the pack (because the current branch can't be).
+    pub fn export_branch(&self, branch: &str) -> Result<Pack, Error> {
+        let _ = self.inode(branch)?;
+        let patches = self.patches(branch).cloned().collect::<Vec<_>>();
+        let included = patches.iter().collect::<HashSet<_>>();
+        let aliases = self
+            .storage
+            .patch_aliases
+            .iter()
+            .filter(|(_, p)| included.contains(p))
+            .map(|(a, p)| (a.clone(), *p))
+            .collect();
+
+        let baselines = patches
+            .iter()
+            .filter(|p| self.is_baseline(p))
+            .cloned()
+            .collect();
+
+        let mut branches = BTreeMap::new();
+        branches.insert(branch.to_owned(), patches.clone());
+        Ok(Pack {
+            format_version: PACK_FORMAT_VERSION,
+            patches: self.patch_closure(patches.into_iter()),
+            branches,
+            current_branch: branch.to_owned(),
+            aliases,
+            baselines,
+            identity: self.storage.identity.clone(),
+            lineage_policy: self.storage.lineage_policy,
+            limits: self.storage.limits,
+            branch_metadata: self.exported_branch_metadata(branch),
+            authors: self.storage.authors.clone(),
+        })
+    }
+
+    // Returns the metadata of `branch`, for a pack in which it is the current branch.
+    fn exported_branch_metadata(&self, branch: &str) -> BTreeMap<String, BranchMetadata> {
+        let mut ret = self.branch_metadata(std::iter::once(branch));
+        ret.retain(|_, m| {
+            m.archived = false;
+            *m != BranchMetadata::default()
+        });
+        ret
+    }
+
+    /// Packs the contents of `branch`, but not its history.
+    ///
+    /// This makes a new baseline patch (like [`Repo::create_baseline`]) with the given author and
+    /// description, and the resulting [`Pack`] contains only that patch. The baseline only goes
+    /// into the pack: this repository isn't modified. The repository that is unpacked from the
+    /// pack will have a truncated history.
+    pub fn export_shallow(&self, branch: &str, author: &str, msg: &str) -> Result<Pack, Error> {
+        let changes = self.baseline_changes(branch)?;
+        let patch = UnidentifiedPatch::new(author.to_owned(), msg.to_owned(), changes);
+        let (patch, data) = self.identify_patch(branch, patch)?;
+        let baseline = *patch.id();
+        let mut branches = BTreeMap::new();
+        branches.insert(branch.to_owned(), vec![baseline]);
+        Ok(Pack {
+            format_version: PACK_FORMAT_VERSION,
+            patches: vec![data],
+            branches,
+            current_branch: branch.to_owned(),
+            aliases: BTreeMap::new(),
+            baselines: vec![baseline],
+            identity: self.storage.identity.clone(),
+            lineage_policy: self.storage.lineage_policy,
+            limits: self.storage.limits,
+            branch_metadata: self.exported_branch_metadata(branch),
+            authors: self.storage.authors.clone(),
+        })
+    }
+
+    /// Creates a new repository at the given path, containing everything in `pack`.
+    ///
+    /// As with [`Repo::init`], nothing is written to disk until [`Repo::write`] is called. The new
+    /// repository gets the settings of the packed one, including its identity and its lineage
+    /// policy (so if the packed repository had no identity, neither does the new one).
+    pub fn unpack<P: AsRef<Path>>(path: P, pack: &Pack) -> Result<Repo, Error> {
+        let mut repo = Repo::init(path)?;
+        repo.set_identity(pack.identity.as_deref());
+        for patch in &pack.patches {
+            repo.register_patch(patch.as_bytes())?;
+        }
+        // The packed repository already accepted all of its patches, so the policy only applies
+        // to the ones that come later.
+        repo.set_lineage_policy(pack.lineage_policy);
+        repo.set_limits(pack.limits);
+        repo.storage
+            .baselines
+            .extend(pack.baselines.iter().cloned());
+        for (branch, patches) in &pack.branches {
+            if repo.storage.inode(branch).is_none() {
+                repo.create_branch(branch)?;
+            }
+            for p in patches {
+                repo.apply_patch_no_cache(branch, p)?;
+            }
+            repo.flush_caches(branch)?;
+        }
+        repo.switch_branch(&pack.current_branch)?;
+        for (alias, p) in &pack.aliases {
+            repo.set_patch_alias(alias, p)?;
+        }
+        for (branch, metadata) in &pack.branch_metadata {
+            if repo.storage.inode(branch).is_none() {
+                return Err(Error::UnknownBranch(branch.clone()));
+            }
+            repo.storage.set_branch_metadata(branch, metadata.clone());
+        }
+        repo.storage.authors = pack.authors.clone();
+        Ok(repo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Changes;
+
+    #[test]
+    fn pack_round_trip() {
+        let mut repo = Repo::init_tmp();
+        let mut ids = Vec::new();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"c\na\nb\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            ids.push(id);
+        }
+        repo.create_branch("other").unwrap();
+        repo.apply_patch("other", &ids[1]).unwrap();
+        repo.set_patch_alias("middle", &ids[1]).unwrap();
+        repo.set_identity(Some("lineage"));
+        repo.set_lineage_policy(LineagePolicy::Refuse);
+        let limits = Limits {
+            max_nodes: 10,
+            ..Limits::default()
+        };
+        repo.set_limits(limits);
+        repo.set_protected("master", true).unwrap();
+        repo.set_archived("other", true).unwrap();
+        repo.reattribute("Author", "Someone Else");
+
+        let mut data = Vec::new();
+        repo.pack().write_out(&mut data).unwrap();
+        let pack = Pack::from_reader(&data[..]).unwrap();
+        let unpacked = Repo::unpack("", &pack).unwrap();
+
+        assert_eq!(unpacked.pack(), repo.pack());
+        assert_eq!(
+            unpacked.file("master").unwrap(),
+            repo.file("master").unwrap()
+        );
+        assert_eq!(unpacked.file("other").unwrap(), repo.file("other").unwrap());
+        assert_eq!(unpacked.resolve_patch("middle").unwrap(), ids[1]);
+        assert_eq!(unpacked.identity(), Some("lineage"));
+        assert_eq!(unpacked.lineage_policy(), LineagePolicy::Refuse);
+        assert_eq!(unpacked.limits(), limits);
+        assert!(unpacked.is_protected("master"));
+        assert!(!unpacked.is_archived("master"));
+        assert!(unpacked.is_archived("other"));
+        assert!(!unpacked.is_protected("other"));
+        assert_eq!(unpacked.author("Author"), "Someone Else");
+    }
+
+    #[test]
+    fn pack_format_version() {
+        let pack = |version| {
+            format!(
+                "format_version: {}\npatches: []\nbranches:\n  master: []\n\
+                 current_branch: master\naliases: {{}}\n",
+                version
+            )
+        };
+
+        // Version 1 didn't have any of the settings, so the unpacked repository gets the defaults.
+        let unpacked = Repo::unpack("", &Pack::from_reader(pack(1).as_bytes()).unwrap()).unwrap();
+        assert_eq!(unpacked.limits(), Limits::default());
+        assert!(!unpacked.is_protected("master"));
+        assert_eq!(unpacked.reattributions().count(), 0);
+
+        for &version in &[0, PACK_FORMAT_VERSION + 1] {
+            assert!(matches!(
+                Pack::from_reader(pack(version).as_bytes()),
+                Err(Error::UnsupportedPackVersion(v)) if v == version
+            ));
+        }
+    }
+
+    #[test]
+    fn unpack_large_patches() {
+        // When unpacking, every patch is registered before any of them is applied, so the second
+        // patch refers to lots of nodes in a dependency that was never applied. Each dependency
+        // only gets opened once, not once per node.
+        let mut repo = Repo::init_tmp();
+        let contents = (0..500)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        for contents in &[contents.as_bytes(), b"other\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+        }
+
+        let unpacked = Repo::unpack("", &repo.pack()).unwrap();
+        assert_eq!(unpacked.file("master").unwrap().as_bytes(), b"other\n");
+    }
+
+    #[test]
+    fn export_branch() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let base = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &base).unwrap();
+        repo.clone_branch("master", "feature").unwrap();
+
+        // An abandoned patch on master, which shouldn't be exported along with "feature".
+        let diff = repo.diff("master", b"a\nabandoned\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let abandoned = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.set_patch_alias("abandoned", &abandoned).unwrap();
+
+        let diff = repo.diff("feature", b"b\na\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let feature = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("feature", &feature).unwrap();
+        repo.set_protected("feature", true).unwrap();
+
+        let pack = repo.export_branch("feature").unwrap();
+        assert_eq!(pack.patches.len(), 2);
+        assert!(pack.aliases.is_empty());
+
+        let unpacked = Repo::unpack("", &pack).unwrap();
+        assert_eq!(unpacked.current_branch, "feature");
+        assert!(unpacked.is_protected("feature"));
+        assert_eq!(
+            unpacked.file("feature").unwrap(),
+            repo.file("feature").unwrap()
+        );
+        assert!(unpacked.open_patch(&abandoned).is_err());
+        assert!(repo.export_branch("nonexistent").is_err());
+    }
+
+    #[test]
+    fn export_shallow() {
+        let mut repo = Repo::init_tmp();
+        for contents in &[&b"a\n"[..], b"a\nb\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+        }
+
+        let mut data = Vec::new();
+        let pack = repo.export_shallow("master", "Author", "Baseline").unwrap();
+        // The baseline only went into the pack.
+        assert!(!repo.is_shallow());
+        assert_eq!(repo.all_patches().count(), 2);
+        pack.write_out(&mut data).unwrap();
+        let unpacked = Repo::unpack("", &Pack::from_reader(&data[..]).unwrap()).unwrap();
+
+        assert!(unpacked.is_shallow());
+        assert_eq!(unpacked.patches("master").count(), 1);
+        assert_eq!(
+            unpacked.file("master").unwrap().as_bytes(),
+            repo.file("master").unwrap().as_bytes()
+        );
+    }
+}
@@ -10,16 +10,21 @@
 // of this distribution.
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_yaml;
 use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::io::{self, prelude::*};
+use std::str::FromStr;
 
 use crate::error::PatchIdError;
+use crate::resolver::Resolution;
 use crate::Error;
 
+mod builder;
 mod change;
-pub use self::change::{Change, Changes};
+pub use self::builder::ChangesBuilder;
+pub use self::change::{Change, Changes, PatchStats};
 
 // This is just a wrapper around some instance of io::Write that calculates a hash of everything
 // that's written.
@@ -70,10 +75,10 @@ impl<R: Read> Read for HashingReader<R> {
     }
 }
 
-// PatchId contains a [u8; 32], which by default serializes to an array in yaml (and other
+// Content hashes contain a [u8; 32], which by default serializes to an array in yaml (and other
 // human-readable formats). To make the output more compact and readable, it's better to convert it
 // to a base64 string.
-mod patch_id_base64 {
+pub(crate) mod hash_base64 {
     pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
@@ -91,9 +96,12 @@ mod patch_id_base64 {
     {
         if deserializer.is_human_readable() {
             let s = <String as serde::Deserialize>::deserialize(deserializer)?;
-            let mut ret = [0; 32];
             let vec =
                 base64::decode_config(&s, base64::URL_SAFE).map_err(serde::de::Error::custom)?;
+            let mut ret = [0; 32];
+            if vec.len() != ret.len() {
+                return Err(serde::de::Error::invalid_length(vec.len(), &"32 bytes"));
+            }
             ret.copy_from_slice(&vec[..]);
             Ok(ret)
         } else {
@@ -106,16 +114,99 @@ mod patch_id_base64 {
 ///
 /// A `PatchId` is derived from a patch by hashing its contents. It must be unique: a repository
 /// cannot simultaneously contain two patches with the same id.
-#[derive(Copy, Clone, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
-#[serde(transparent)]
+///
+/// Specifically, the id is the SHA-256 hash of the patch's serialized bytes. These are the bytes
+/// that were produced by [`UnidentifiedPatch::write_out`] when the patch was created, and a patch
+/// is never serialized again after that: repositories and [packs](crate::Pack) store the original
+/// bytes, and [`Patch::from_reader`] hashes exactly the bytes that it reads. So even if the
+/// serialization format changes (for example, because of a new version of `serde_yaml`), the ids
+/// of existing patches stay the same; only newly created patches are affected.
+///
+/// # Textual form
+///
+/// The [`Display`](std::fmt::Display) and [`FromStr`] implementations represent a `PatchId` as the
+/// letter 'P' followed by the hash in the URL-safe base64 alphabet, without padding. The URL-safe
+/// alphabet is used because ids need to be valid paths (e.g. no slashes), and the 'P' is there
+/// because otherwise the first character might be '-', which the CLI would mistake for a flag.
+/// Every id has exactly one textual form: parsing rejects anything that the `Display`
+/// implementation wouldn't produce, except that (for compatibility with older versions of `ojo`)
+/// a trailing '=' is allowed. The same form is used for serialization.
+#[derive(Copy, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct PatchId {
-    #[serde(with = "patch_id_base64")]
-    pub(crate) data: [u8; 32],
+    data: [u8; 32],
 }
 
 impl std::fmt::Debug for PatchId {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        fmt.debug_tuple("PatchId").field(&self.to_base64()).finish()
+        fmt.debug_tuple("PatchId").field(&self.to_string()).finish()
+    }
+}
+
+impl std::fmt::Display for PatchId {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            fmt,
+            "P{}",
+            base64::encode_config(&self.data[..], base64::URL_SAFE_NO_PAD)
+        )
+    }
+}
+
+// Decodes the base64 part of a patch id (i.e., without the 'P' or any padding).
+fn decode_patch_id(encoded: &[u8]) -> Result<PatchId, PatchIdError> {
+    let data = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)?;
+    let ret = PatchId::from_bytes(&data)?;
+
+    // The last character of the encoding has some bits left over, which the decoder ignores. We
+    // insist on them being zero, so that every id has only one textual form.
+    if base64::encode_config(&ret.data[..], base64::URL_SAFE_NO_PAD).as_bytes() != encoded {
+        return Err(PatchIdError::NonCanonical);
+    }
+    Ok(ret)
+}
+
+fn parse_patch_id(s: &[u8]) -> Result<PatchId, PatchIdError> {
+    let encoded = s.strip_prefix(b"P").ok_or(PatchIdError::MissingPrefix)?;
+    // Older versions of ojo padded the encoding.
+    let encoded = encoded.strip_suffix(b"=").unwrap_or(encoded);
+    decode_patch_id(encoded)
+}
+
+impl FromStr for PatchId {
+    type Err = PatchIdError;
+
+    fn from_str(s: &str) -> Result<PatchId, PatchIdError> {
+        parse_patch_id(s.as_bytes())
+    }
+}
+
+impl Serialize for PatchId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_bytes(&self.data)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PatchId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PatchId, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            // Older versions of ojo serialized ids without the 'P', but with padding. The encoding
+            // of 32 bytes is 43 characters long, so these have exactly 44 characters and end in
+            // '=', whereas the current form never ends in '='.
+            let ret = if s.len() == 44 && s.ends_with('=') {
+                decode_patch_id(&s.as_bytes()[..43])
+            } else {
+                s.parse()
+            };
+            ret.map_err(serde::de::Error::custom)
+        } else {
+            let data = <[u8; 32]>::deserialize(deserializer)?;
+            Ok(PatchId { data })
+        }
     }
 }
 
@@ -126,38 +217,57 @@ impl PatchId {
         PatchId { data: [0; 32] }
     }
 
+    /// Returns a made-up `PatchId`, which is determined by `n`.
+    ///
+    /// This is meant for tests and for synthesizing graggles, which need lots of distinct ids that
+    /// don't belong to any real patch. The ids aren't hashes of anything, so they have none of the
+    /// guarantees of real ids: in particular, anyone can make a synthetic id that clashes with
+    /// one made elsewhere. Different values of `n` give different ids, and none of them is equal
+    /// to [`PatchId::cur`].
+    pub fn synthetic(n: u64) -> PatchId {
+        let mut ret = PatchId::cur();
+        ret.data[..8].copy_from_slice(&n.to_le_bytes());
+        // Mark the id as synthetic, which also makes sure that it's never `cur`.
+        ret.data[31] = 1;
+        ret
+    }
+
     /// Checks whether this `PatchId` is the one decribed in [`PatchId::cur`].
     pub fn is_cur(&self) -> bool {
         self.data == [0; 32]
     }
 
+    /// Returns the raw bytes of this `PatchId` (for a real patch, they're the SHA-256 hash of its
+    /// contents).
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Converts from raw bytes (as returned by [`PatchId::as_bytes`]) to a `PatchId`.
+    ///
+    /// Returns [`PatchIdError::InvalidLength`] if there are the wrong number of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PatchId, PatchIdError> {
+        let mut ret = PatchId::cur();
+        if bytes.len() != ret.data.len() {
+            return Err(PatchIdError::InvalidLength(bytes.len()));
+        }
+        ret.data.copy_from_slice(bytes);
+        Ok(ret)
+    }
+
     /// Represents this `PatchId` in base64.
     ///
-    /// We encode in the URL_SAFE encoding because it needs to be a valid path (e.g. no
-    /// slashes). We prepend the letter 'P', because otherwise there's a chance that
-    /// the first character will be '-', which is annoying because then the CLI might
-    /// misinterpret it as a flag.
+    /// This is the same as `to_string` (see [the textual form](PatchId#textual-form)).
     pub fn to_base64(&self) -> String {
-        // base64 requires 44 characters to represent 32 bytes. Add one for the 'P'.
-        let mut ret = vec![0; 45];
-        ret[0] = b'P';
-        base64::encode_config_slice(&self.data[..], base64::URL_SAFE, &mut ret[1..]);
-
-        // We can safely unwrap because base64 is guaranteed to be ASCII.
-        String::from_utf8(ret).unwrap()
+        self.to_string()
     }
 
     /// Converts from base64 (as returned by [`PatchId::to_base64`]) to a `PatchId`.
+    ///
+    /// This is the same as `parse` (see [the textual form](PatchId#textual-form)), except for the
+    /// error type.
     pub fn from_base64<S: ?Sized + AsRef<[u8]>>(name: &S) -> Result<PatchId, Error> {
-        let data = base64::decode_config(&name.as_ref()[1..], base64::URL_SAFE)
-            .map_err(PatchIdError::from)?;
-        let mut ret = PatchId::cur();
-        if data.len() != ret.data.len() {
-            Err(PatchIdError::InvalidLength(data.len()).into())
-        } else {
-            ret.data.copy_from_slice(&data);
-            Ok(ret)
-        }
+        Ok(parse_patch_id(name.as_ref())?)
     }
 
     // Creates a PatchId from a Sha256 hasher
@@ -166,6 +276,37 @@ impl PatchId {
         ret.data.copy_from_slice(&hasher.result()[..]);
         ret
     }
+
+    // Computes the id of the patch whose data (as returned by `Repo::open_patch_data`) is `data`,
+    // without parsing it.
+    pub(crate) fn of_data(data: &[u8]) -> PatchId {
+        let mut hasher = Sha256::default();
+        hasher.input(data);
+        PatchId::from_sha256(hasher)
+    }
+}
+
+/// The version of the format of [`Changes`] that is written by [`UnidentifiedPatch::write_out`].
+///
+/// Patches record the version that their changes were written in, and reading a patch with a
+/// newer version (or with a kind of change that we don't know about) fails with
+/// [`Error::UnsupportedChangesVersion`] (or [`Error::UnknownChange`]). Patches from before the
+/// version was recorded are version 1.
+///
+/// The versions are:
+/// - 1: the original format, consisting of `NewNode`, `DeleteNode` and `NewEdge` changes.
+/// - 2: adds the `ReplaceContents` and `CreateFile` changes, and the `position` and `path` of
+///   `NewNode`.
+///
+/// This is unrelated to the [`PatchHeader::FORMAT_VERSION`] metadata, which records the
+/// [`REPO_FORMAT_VERSION`](crate::REPO_FORMAT_VERSION) of the repository that created a patch.
+/// That describes how the repository stores its data, not how the patch is written, so it is
+/// purely informational and is never checked when reading a patch.
+pub const CHANGES_FORMAT_VERSION: u32 = 2;
+
+// The version of patches that don't record one.
+fn original_changes_version() -> u32 {
+    1
 }
 
 /// Like a [`Patch`], but without the unique id.
@@ -183,6 +324,10 @@ impl PatchId {
 /// it can be serialized to a file, and it can be turned into an identified patch.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct UnidentifiedPatch {
+    // The version of the format of `changes` (see `CHANGES_FORMAT_VERSION`).
+    #[serde(default = "original_changes_version")]
+    changes_version: u32,
+
     changes: Changes,
 
     // Various metadata associated with this patch.
@@ -192,8 +337,9 @@ pub struct UnidentifiedPatch {
     // change.
     header: PatchHeader,
 
-    // The list of other patches on which this depends. This should coincide with the set of all
-    // other PatchIds that are referenced in `changes`.
+    // The list of other patches on which this depends. This includes all the other PatchIds that
+    // are referenced in `changes`, and also the patches whose contents replacements this patch
+    // overrides (see `Change::ReplaceContents`).
     deps: Vec<PatchId>,
 }
 
@@ -205,7 +351,7 @@ impl UnidentifiedPatch {
         let mut deps = HashSet::new();
         for c in &changes.changes {
             match *c {
-                Change::DeleteNode { ref id } => {
+                Change::DeleteNode { ref id } | Change::ReplaceContents { ref id, .. } => {
                     if !id.patch.is_cur() {
                         deps.insert(id.patch);
                     }
@@ -223,17 +369,63 @@ impl UnidentifiedPatch {
         }
 
         UnidentifiedPatch {
+            changes_version: CHANGES_FORMAT_VERSION,
             header: PatchHeader {
                 author,
                 description,
                 #[cfg(not(target_arch = "wasm32"))]
                 timestamp: Utc::now(),
+                resolution: None,
+                metadata: BTreeMap::new(),
             },
             changes,
             deps: deps.into_iter().collect(),
         }
     }
 
+    /// Records that this patch was created by resolving a graggle, and how the resolution was
+    /// done.
+    pub fn with_resolution(mut self, resolution: Resolution) -> UnidentifiedPatch {
+        self.header.resolution = Some(resolution);
+        self
+    }
+
+    /// Records that this patch supersedes the patches in `old` (see [`PatchHeader::supersedes`]).
+    pub fn superseding(self, old: &[PatchId]) -> UnidentifiedPatch {
+        if old.is_empty() {
+            return self;
+        }
+        let ids = old.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        self.with_metadata(PatchHeader::SUPERSEDES, &ids.join(" "))
+    }
+
+    // The changes in this patch.
+    pub(crate) fn changes(&self) -> &Changes {
+        &self.changes
+    }
+
+    // Adds some extra dependencies to this patch, on top of the ones that its changes refer to.
+    pub(crate) fn with_deps(
+        mut self,
+        deps: impl IntoIterator<Item = PatchId>,
+    ) -> UnidentifiedPatch {
+        for dep in deps {
+            if !self.deps.contains(&dep) {
+                self.deps.push(dep);
+            }
+        }
+        self
+    }
+
+    /// Adds an entry to the patch's metadata (see [`PatchHeader::metadata`]), replacing any
+    /// previous entry with the same key.
+    pub fn with_metadata(mut self, key: &str, value: &str) -> UnidentifiedPatch {
+        self.header
+            .metadata
+            .insert(key.to_owned(), value.to_owned());
+        self
+    }
+
     // Assigns an id to this UnidentifiedPatch, and in doing so turns it into a Patch.
     fn set_id(self, id: PatchId) -> Patch {
         let mut ret = Patch {
@@ -251,6 +443,10 @@ impl UnidentifiedPatch {
     ///
     /// While writing out the patch, we compute the hash of its contents and use that to derive an
     /// id for this patch. Assuming that the writing succeeds, we return the resulting [`Patch`].
+    ///
+    /// The written bytes are the canonical form of the patch: they must be stored exactly as they
+    /// are, because re-serializing the patch might produce different bytes, and hence a different
+    /// id.
     pub fn write_out<W: Write>(self, writer: W) -> Result<Patch, serde_yaml::Error> {
         let mut w = HashingWriter::new(writer);
         serde_yaml::to_writer(&mut w, &self)?;
@@ -260,6 +456,40 @@ impl UnidentifiedPatch {
     }
 }
 
+// Just enough of a patch to tell whether we failed to read it because it was written by a newer
+// version.
+#[derive(Deserialize)]
+struct VersionedChanges {
+    #[serde(default = "original_changes_version")]
+    changes_version: u32,
+    #[serde(default)]
+    changes: Vec<serde_yaml::Value>,
+}
+
+// Explains why the patch in `data` couldn't be read, if it's because the patch is too new: either
+// its changes have a newer version or there's a change that we don't know about. Returns `None`
+// if the patch is just malformed.
+fn unreadable_changes(data: &[u8]) -> Option<Error> {
+    let versioned: VersionedChanges = serde_yaml::from_slice(data).ok()?;
+    if versioned.changes_version > CHANGES_FORMAT_VERSION {
+        return Some(Error::UnsupportedChangesVersion(versioned.changes_version));
+    }
+    // Changes are serialized either as the name of the change (if it has no fields), or as a
+    // mapping from the name to the fields.
+    versioned.changes.iter().find_map(|ch| {
+        let name = match ch {
+            serde_yaml::Value::String(name) => name.as_str(),
+            serde_yaml::Value::Mapping(m) if m.len() == 1 => m.iter().next()?.0.as_str()?,
+            _ => return None,
+        };
+        if Change::NAMES.contains(&name) {
+            None
+        } else {
+            Some(Error::UnknownChange(name.to_owned()))
+        }
+    })
+}
+
 /// A set of changes together with some metadata (author, description, etc.) and a unique id.
 ///
 /// There are two ways to create a patch:
@@ -277,11 +507,25 @@ pub struct Patch {
 impl Patch {
     /// Creates a patch by deserializing it from a reader.
     ///
-    /// The id of the resulting patch will be the SHA256 hash of the contents.
+    /// The id of the resulting patch will be the SHA256 hash of the contents. This is computed
+    /// from the raw bytes rather than from the deserialized patch, so it doesn't depend on how
+    /// the patch was formatted.
+    ///
+    /// Fails with [`Error::UnsupportedChangesVersion`] or [`Error::UnknownChange`] if the patch was
+    /// written by a newer version of this library, in a format that we can't read.
     pub fn from_reader<R: Read>(input: R) -> Result<Patch, Error> {
         let mut reader = HashingReader::new(input);
-        let up: UnidentifiedPatch = serde_yaml::from_reader(&mut reader)?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
         let id = PatchId::from_sha256(reader.hasher);
+
+        let up: UnidentifiedPatch = match serde_yaml::from_slice(&data) {
+            Ok(up) => up,
+            Err(e) => return Err(unreadable_changes(&data).unwrap_or_else(|| e.into())),
+        };
+        if up.changes_version > CHANGES_FORMAT_VERSION {
+            return Err(Error::UnsupportedChangesVersion(up.changes_version));
+        }
         Ok(up.set_id(id))
     }
 
@@ -300,6 +544,30 @@ impl Patch {
         &self.changes
     }
 
+    /// The changes that this patch makes, with the nodes that it introduces referred to by
+    /// [`NodeId::cur`](crate::NodeId::cur), as they were before the patch was given its id.
+    pub fn unidentified_changes(&self) -> Changes {
+        let unidentify = |n: &mut crate::NodeId| {
+            if n.patch == self.id {
+                n.patch = PatchId::cur();
+            }
+        };
+        let mut ret = self.changes.clone();
+        for ch in &mut ret.changes {
+            match ch {
+                Change::NewNode { id, .. }
+                | Change::DeleteNode { id }
+                | Change::ReplaceContents { id, .. } => unidentify(id),
+                Change::NewEdge { src, dest } => {
+                    unidentify(src);
+                    unidentify(dest);
+                }
+                Change::CreateFile => {}
+            }
+        }
+        ret
+    }
+
     /// The dependencies of this patch.
     ///
     /// Before this patch can be applied, all of its dependencies must already have been applied.
@@ -325,4 +593,265 @@ pub struct PatchHeader {
     // We currently disable this on wasm, since chrono::Utc::now() panics there.
     #[cfg(not(target_arch = "wasm32"))]
     pub timestamp: DateTime<Utc>,
+
+    /// If this patch was created by resolving a graggle, the decisions that were made while
+    /// resolving it.
+    // This is skipped when it's empty, so that it doesn't change the ids of other patches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+
+    /// Any other information about the patch, as key-value pairs.
+    ///
+    /// Patches created by [`Repo`](crate::Repo) record the branch that they were created on
+    /// (under [`PatchHeader::BRANCH`]) and the format version of the repository that created them
+    /// (under [`PatchHeader::FORMAT_VERSION`]). Patches that replace other patches list them under
+    /// [`PatchHeader::SUPERSEDES`].
+    // This is skipped when it's empty, so that it doesn't change the ids of older patches.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl PatchHeader {
+    /// The metadata key for the branch that a patch was created on.
+    pub const BRANCH: &'static str = "branch";
+
+    /// The metadata key for the format version of the repository that created a patch.
+    ///
+    /// This is informational only: the format of the patch itself is described by its
+    /// changes version (see [`CHANGES_FORMAT_VERSION`]).
+    pub const FORMAT_VERSION: &'static str = "format-version";
+
+    /// The metadata key for the patches that a patch supersedes, separated by spaces.
+    pub const SUPERSEDES: &'static str = "supersedes";
+
+    /// The metadata key for the identity of the repository that created a patch.
+    pub const REPOSITORY: &'static str = "repository";
+
+    /// The branch that this patch was created on, if it was recorded.
+    pub fn branch(&self) -> Option<&str> {
+        self.metadata.get(PatchHeader::BRANCH).map(|s| s.as_str())
+    }
+
+    /// The format version of the repository that created this patch, if it was recorded.
+    pub fn format_version(&self) -> Option<u32> {
+        self.metadata
+            .get(PatchHeader::FORMAT_VERSION)
+            .and_then(|v| v.parse().ok())
+    }
+
+    /// The identity of the repository that created this patch, if it was recorded (see
+    /// [`LineagePolicy`](crate::LineagePolicy)).
+    pub fn repository(&self) -> Option<&str> {
+        self.metadata
+            .get(PatchHeader::REPOSITORY)
+            .map(|s| s.as_str())
+    }
+
+    /// The patches that this patch supersedes.
+    ///
+    /// A patch supersedes another one if it was made to replace it, for example by amending it.
+    /// This is purely informational: the superseded patches don't need to be known to the
+    /// repository, and nothing stops them from being applied. Anything in the metadata that isn't
+    /// a valid patch id is ignored.
+    pub fn supersedes(&self) -> Vec<PatchId> {
+        self.metadata
+            .get(PatchHeader::SUPERSEDES)
+            .map(|v| {
+                v.split_whitespace()
+                    .filter_map(|p| p.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A patch that was written by `UnidentifiedPatch::write_out`. This must never be changed: it
+    // checks that the ids of existing patches stay the same.
+    const PATCH: &str = r#"---
+changes:
+  - NewNode:
+      id:
+        patch: AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=
+        node: 0
+      contents:
+        - 97
+        - 10
+  - NewNode:
+      id:
+        patch: AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=
+        node: 1
+      contents:
+        - 98
+        - 10
+  - NewEdge:
+      src:
+        patch: AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=
+        node: 0
+      dest:
+        patch: AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=
+        node: 1
+header:
+  author: Author
+  description: Msg
+  timestamp: "2019-01-01T00:00:00Z"
+deps: []"#;
+
+    #[test]
+    fn stable_id() {
+        let patch = Patch::from_reader(PATCH.as_bytes()).unwrap();
+        assert_eq!(
+            patch.id().to_string(),
+            "PkaBNbP5iR6e-kc6fMWgNV5juRNZGMsCbJ35GUj7dVSw"
+        );
+
+        // The id depends on the bytes, not just on the contents.
+        let reformatted = PATCH.replace("author: Author", "author: \"Author\"");
+        let other = Patch::from_reader(reformatted.as_bytes()).unwrap();
+        assert_eq!(other.header(), patch.header());
+        assert_ne!(other.id(), patch.id());
+    }
+
+    // If this fails then the serialization format has changed. That doesn't affect the ids of
+    // existing patches (which is checked by `stable_id`), but it should be done on purpose.
+    //
+    // `PATCH` was written before patch ids were serialized in their textual form and before the
+    // version of the changes was recorded, so writing it out again changes the ids and adds the
+    // version that it was read as (but nothing else).
+    #[test]
+    fn write_out_is_canonical() {
+        let up: UnidentifiedPatch = serde_yaml::from_str(PATCH).unwrap();
+        let mut data = Vec::new();
+        let patch = up.write_out(&mut data).unwrap();
+        let expected = PATCH
+            .replace(
+                "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+                "PAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+            )
+            .replace("---\n", "---\nchanges_version: 1\n");
+        assert_eq!(std::str::from_utf8(&data).unwrap(), expected);
+        let reparsed = Patch::from_reader(PATCH.as_bytes()).unwrap();
+        assert_eq!(reparsed.header(), patch.header());
+    }
+
+    #[test]
+    fn changes_version() {
+        // `PATCH` doesn't record a version, so it has the original one.
+        let up: UnidentifiedPatch = serde_yaml::from_str(PATCH).unwrap();
+        assert_eq!(up.changes_version, 1);
+
+        // Both the original version and the current one can be read.
+        let current = PATCH.replace("---\n", "---\nchanges_version: 2\n");
+        let up: UnidentifiedPatch = serde_yaml::from_str(&current).unwrap();
+        assert_eq!(up.changes_version, CHANGES_FORMAT_VERSION);
+        Patch::from_reader(current.as_bytes()).unwrap();
+
+        // New patches are written with the current version.
+        let changes = Changes { changes: vec![] };
+        let up = UnidentifiedPatch::new("Author".to_owned(), "Msg".to_owned(), changes);
+        let mut data = Vec::new();
+        up.write_out(&mut data).unwrap();
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.contains("changes_version: 2\n"));
+
+        let newer = PATCH.replace("---\n", "---\nchanges_version: 3\n");
+        match Patch::from_reader(newer.as_bytes()) {
+            Err(Error::UnsupportedChangesVersion(3)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // A newer version is reported even if we can't read its changes.
+        let newer = newer.replace("NewEdge", "NewFile");
+        match Patch::from_reader(newer.as_bytes()) {
+            Err(Error::UnsupportedChangesVersion(3)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let unknown = PATCH.replace("NewEdge", "NewFile");
+        match Patch::from_reader(unknown.as_bytes()) {
+            Err(Error::UnknownChange(name)) => assert_eq!(name, "NewFile"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let unknown = PATCH.replace("changes:\n", "changes:\n  - RenameFile\n");
+        match Patch::from_reader(unknown.as_bytes()) {
+            Err(Error::UnknownChange(name)) => assert_eq!(name, "RenameFile"),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Other problems are reported as they were.
+        let malformed = PATCH.replace("node: 1", "node: one");
+        match Patch::from_reader(malformed.as_bytes()) {
+            Err(Error::Serde(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn patch_id_text() {
+        let id = *Patch::from_reader(PATCH.as_bytes()).unwrap().id();
+        let text = id.to_string();
+        assert_eq!(text.len(), 44);
+        assert_eq!(text.parse::<PatchId>().unwrap(), id);
+        // The padded form that older versions used.
+        assert_eq!(format!("{}=", text).parse::<PatchId>().unwrap(), id);
+
+        let err = |s: &str| s.parse::<PatchId>().unwrap_err();
+        assert!(matches!(err(&text[1..]), PatchIdError::MissingPrefix));
+        assert!(matches!(err(""), PatchIdError::MissingPrefix));
+        assert!(matches!(err("P"), PatchIdError::InvalidLength(0)));
+        assert!(matches!(err(&text[..40]), PatchIdError::InvalidLength(_)));
+        assert!(matches!(err("P!"), PatchIdError::Base64Decode(_)));
+
+        assert_eq!(PatchId::from_bytes(id.as_bytes()).unwrap(), id);
+        assert!(matches!(
+            PatchId::from_bytes(&id.as_bytes()[1..]),
+            Err(PatchIdError::InvalidLength(31))
+        ));
+        // The last character has two unused bits, which must be zero. In both 'x' and 'y', they
+        // aren't.
+        let last = text.as_bytes()[43];
+        let mut other = text[..43].to_owned();
+        other.push(if last == b'x' { 'y' } else { 'x' });
+        assert!(matches!(err(&other), PatchIdError::NonCanonical));
+
+        // Serialization uses the same form, but still understands the old one.
+        let yaml = serde_yaml::to_string(&id).unwrap();
+        assert!(yaml.contains(&text));
+        assert_eq!(serde_yaml::from_str::<PatchId>(&yaml).unwrap(), id);
+        let old = base64::encode_config(&id.data[..], base64::URL_SAFE);
+        assert_eq!(serde_yaml::from_str::<PatchId>(&old).unwrap(), id);
+    }
+
+    #[test]
+    fn synthetic() {
+        let ids = [0, 1, 2, u64::MAX].map(PatchId::synthetic);
+        for (i, id) in ids.iter().enumerate() {
+            assert!(!id.is_cur());
+            assert_eq!(id.to_string().parse::<PatchId>().unwrap(), *id);
+            assert!(ids[(i + 1)..].iter().all(|other| other != id));
+        }
+        assert_eq!(PatchId::synthetic(7), PatchId::synthetic(7));
+    }
+
+    #[test]
+    fn supersedes() {
+        let old = [PatchId::synthetic(1), PatchId::synthetic(2)];
+        let new = || {
+            let changes = Changes { changes: vec![] };
+            UnidentifiedPatch::new("me".to_owned(), "msg".to_owned(), changes)
+        };
+        let patch = new().superseding(&old).write_out(Vec::new()).unwrap();
+        assert_eq!(patch.header().supersedes(), old.to_vec());
+
+        // Anything that isn't a patch id is ignored.
+        let value = format!("junk {}  P", old[1]);
+        let patch = new()
+            .with_metadata(PatchHeader::SUPERSEDES, &value)
+            .write_out(Vec::new())
+            .unwrap();
+        assert_eq!(patch.header().supersedes(), vec![old[1]]);
+    }
 }
@@ -0,0 +1,180 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{Change, Changes};
+use crate::storage::File;
+use crate::{Error, NodeId};
+
+/// Builds [`Changes`] one line at a time, for modifying a file without going through a diff.
+///
+/// A `ChangesBuilder` starts from a file (usually obtained from [`Repo::file`](crate::Repo::file))
+/// and keeps track of how that file looks after the changes that were made so far. New lines are
+/// given ids belonging to the current patch (see [`NodeId::cur`]), and they are connected by edges
+/// to the lines before and after them.
+///
+/// The contents of new lines are used as-is, so they should normally end with a newline.
+#[derive(Clone, Debug)]
+pub struct ChangesBuilder {
+    // The ids of the lines in the file, as it looks after the changes that were made so far.
+    lines: Vec<NodeId>,
+    // The lines of the original file. Lines that were added by this builder can't be deleted.
+    original: HashSet<NodeId>,
+    changes: Vec<Change>,
+    next_node: u64,
+}
+
+impl ChangesBuilder {
+    /// Creates a builder for changing `file`.
+    pub fn new(file: &File) -> ChangesBuilder {
+        let lines = (0..file.num_nodes())
+            .map(|i| *file.node_id(i))
+            .collect::<Vec<_>>();
+        ChangesBuilder {
+            original: lines.iter().cloned().collect(),
+            lines,
+            changes: Vec::new(),
+            next_node: 0,
+        }
+    }
+
+    fn position(&self, node: &NodeId) -> Result<usize, Error> {
+        self.lines
+            .iter()
+            .position(|id| id == node)
+            .ok_or(Error::UnknownNode(*node))
+    }
+
+    // Adds a new line, so that it ends up at position `idx` in the file.
+    fn insert_line_at(&mut self, idx: usize, contents: &[u8]) -> NodeId {
+        let id = NodeId::cur(self.next_node);
+        self.next_node += 1;
+        self.changes.push(Change::NewNode {
+            id,
+            contents: contents.to_owned(),
+            // We fill this in once we know where the line ends up.
+            line: None,
+            path: None,
+        });
+        if idx > 0 {
+            self.changes.push(Change::NewEdge {
+                src: self.lines[idx - 1],
+                dest: id,
+            });
+        }
+        if idx < self.lines.len() {
+            self.changes.push(Change::NewEdge {
+                src: id,
+                dest: self.lines[idx],
+            });
+        }
+        self.lines.insert(idx, id);
+        id
+    }
+
+    /// Adds a new line immediately after `node`, and returns its id.
+    ///
+    /// `node` can be either a line of the original file or a line that was added by this builder.
+    /// Returns [`Error::UnknownNode`] if there is no such line (for example, because it was
+    /// deleted).
+    pub fn insert_line_after(&mut self, node: &NodeId, contents: &[u8]) -> Result<NodeId, Error> {
+        let idx = self.position(node)?;
+        Ok(self.insert_line_at(idx + 1, contents))
+    }
+
+    /// Adds a new line at the start of the file, and returns its id.
+    pub fn prepend_line(&mut self, contents: &[u8]) -> NodeId {
+        self.insert_line_at(0, contents)
+    }
+
+    /// Adds a new line at the end of the file, and returns its id.
+    pub fn append_line(&mut self, contents: &[u8]) -> NodeId {
+        let len = self.lines.len();
+        self.insert_line_at(len, contents)
+    }
+
+    /// Deletes a line of the original file.
+    ///
+    /// Returns [`Error::UnknownNode`] if `node` isn't a line of the original file, or if it was
+    /// already deleted.
+    pub fn delete_line(&mut self, node: &NodeId) -> Result<(), Error> {
+        if !self.original.contains(node) {
+            return Err(Error::UnknownNode(*node));
+        }
+        let idx = self.position(node)?;
+        self.lines.remove(idx);
+        self.changes.push(Change::DeleteNode { id: *node });
+        Ok(())
+    }
+
+    /// Returns the ids of the lines in the file, as it looks after the changes so far.
+    pub fn lines(&self) -> &[NodeId] {
+        &self.lines
+    }
+
+    /// Returns the changes.
+    pub fn build(self) -> Changes {
+        let positions = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect::<HashMap<_, _>>();
+        let mut changes = self.changes;
+        for ch in &mut changes {
+            if let Change::NewNode { id, line, .. } = ch {
+                *line = positions.get(id).cloned();
+            }
+        }
+        Changes { changes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChangesBuilder;
+    use crate::{Changes, Error, Repo};
+
+    #[test]
+    fn builder() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\nb\nc\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        let file = repo.file("master").unwrap();
+        let (a, b) = (*file.node_id(0), *file.node_id(1));
+        let mut builder = ChangesBuilder::new(&file);
+        builder.delete_line(&b).unwrap();
+        let x = builder.insert_line_after(&a, b"x\n").unwrap();
+        builder.insert_line_after(&x, b"y\n").unwrap();
+        builder.append_line(b"d\n");
+        builder.prepend_line(b"0\n");
+
+        assert!(matches!(
+            builder.insert_line_after(&b, b"z\n"),
+            Err(Error::UnknownNode(n)) if n == b
+        ));
+        assert!(matches!(
+            builder.delete_line(&x),
+            Err(Error::UnknownNode(_))
+        ));
+
+        let id = repo.create_patch("Author", "Msg", builder.build()).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        assert_eq!(
+            repo.file("master").unwrap().as_bytes(),
+            b"0\na\nx\ny\nc\nd\n"
+        );
+    }
+}
@@ -10,6 +10,7 @@
 // of this distribution.
 
 use ojo_diff::LineDiff;
+use std::collections::{HashMap, HashSet};
 
 use crate::storage::File;
 use crate::{NodeId, PatchId};
@@ -45,32 +46,132 @@ impl<'a> LastLine<'a> {
     }
 }
 
+// Adds the `i`th line of `file2` as a new node, and connects it to the last line.
+fn new_line<'a>(file2: &'a File, changes: &mut Vec<Change>, last: &mut LastLine<'a>, i: usize) {
+    let id = file2.node_id(i);
+    changes.push(Change::NewNode {
+        id: *id,
+        contents: file2.node(i).to_owned(),
+        line: Some(i),
+        path: None,
+    });
+
+    // We are adding a new line, so we need to connect it to whatever line came before it, no
+    // matter where it came from.
+    if let Some(last_id) = last.either() {
+        changes.push(Change::NewEdge {
+            src: *last_id,
+            dest: *id,
+        });
+    }
+    *last = LastLine::File2(id);
+}
+
+// Two lines are considered to be edits of one another if at least this fraction of their bytes
+// (counting both lines) belong to a longest common subsequence.
+const SIMILARITY_THRESHOLD: f64 = 0.6;
+
+// Decides whether the line `new` is similar enough to the line `old` that it should be treated as
+// an edit of it, rather than as a deletion followed by an unrelated insertion.
+fn similar(old: &[u8], new: &[u8]) -> bool {
+    let common = ojo_diff::diff(old, new)
+        .iter()
+        .filter(|d| matches!(d, LineDiff::Keep(..)))
+        .count();
+    let total = old.len() + new.len();
+    total > 0 && (2 * common) as f64 >= SIMILARITY_THRESHOLD * total as f64
+}
+
+// Converts a hunk (i.e. a maximal run of deletions and insertions) into changes.
+//
+// The deleted and inserted lines are paired up in order; whenever a pair looks like an edit of a
+// single line, we replace the contents of the old node instead of deleting it and adding a new one.
+// Since the pairing preserves the order of both files, the old nodes that we keep end up in the
+// right order relative to each other.
+fn hunk<'a>(
+    file1: &'a File,
+    file2: &'a File,
+    hunk: &[LineDiff],
+    changes: &mut Vec<Change>,
+    last: &mut LastLine<'a>,
+) {
+    let deleted = hunk.iter().filter_map(|d| match *d {
+        LineDiff::Delete(i) => Some(i),
+        _ => None,
+    });
+    let added = hunk.iter().filter_map(|d| match *d {
+        LineDiff::New(i) => Some(i),
+        _ => None,
+    });
+    // A map from lines of the new file to the lines of the old file that they replace.
+    let replaced = deleted
+        .clone()
+        .zip(added.clone())
+        .filter(|&(old, new)| similar(file1.node(old), file2.node(new)))
+        .map(|(old, new)| (new, old))
+        .collect::<HashMap<_, _>>();
+    let kept = replaced.values().cloned().collect::<HashSet<_>>();
+
+    for old in deleted.filter(|old| !kept.contains(old)) {
+        changes.push(Change::DeleteNode {
+            id: *file1.node_id(old),
+        });
+    }
+    for new in added {
+        if let Some(&old) = replaced.get(&new) {
+            let id = file1.node_id(old);
+            changes.push(Change::ReplaceContents {
+                id: *id,
+                new_contents: file2.node(new).to_owned(),
+            });
+            if let LastLine::File2(last_id) = *last {
+                changes.push(Change::NewEdge {
+                    src: *last_id,
+                    dest: *id,
+                });
+            }
+            *last = LastLine::File1(id);
+        } else {
+            new_line(file2, changes, last, new);
+        }
+    }
+}
+
 impl Changes {
     /// Converts a [`diff::LineDiff`] into a set of changes.
     ///
     /// The two `File` arguments should be the same ones (in the same order) as those that were
     /// used to create the diff.
+    ///
+    /// A deleted line that is immediately replaced by a similar line is turned into a
+    /// [`Change::ReplaceContents`], so that the node keeps its identity.
+    ///
+    /// If `file1` is empty and `file2` isn't, the changes start with a [`Change::CreateFile`].
+    ///
+    /// Applying the changes to `file1` gives exactly the bytes of `file2`. Since every line keeps
+    /// its own newline (see [`File::from_bytes`]), no newlines are ever added or removed: if the
+    /// last line of `file2` is missing its newline then so is the last line of the result, and
+    /// blank lines at the end are ordinary (if empty-looking) lines. In particular, adding a line
+    /// after a last line without a newline changes that line's contents, because it gains a
+    /// newline.
     pub fn from_diff(file1: &File, file2: &File, diff: &[LineDiff]) -> Changes {
         let mut changes = Vec::new();
+        if file1.num_nodes() == 0 && file2.num_nodes() > 0 {
+            changes.push(Change::CreateFile);
+        }
         let mut last = LastLine::Start;
-        for d in diff {
-            match *d {
-                LineDiff::New(i) => {
-                    let id = file2.node_id(i);
-                    changes.push(Change::NewNode {
-                        id: *id,
-                        contents: file2.node(i).to_owned(),
-                    });
-
-                    // We are adding a new line, so we need to connect it to whatever line came
-                    // before it, no matter where it came from.
-                    if let Some(last_id) = last.either() {
-                        changes.push(Change::NewEdge {
-                            src: *last_id,
-                            dest: *id,
-                        });
-                    }
-                    last = LastLine::File2(id);
+
+        let mut i = 0;
+        while i < diff.len() {
+            match diff[i] {
+                LineDiff::New(_) | LineDiff::Delete(_) => {
+                    let len = diff[i..]
+                        .iter()
+                        .take_while(|d| matches!(d, LineDiff::New(_) | LineDiff::Delete(_)))
+                        .count();
+                    hunk(file1, file2, &diff[i..(i + len)], &mut changes, &mut last);
+                    i += len;
+                    continue;
                 }
                 LineDiff::Keep(i, _) => {
                     let id = file1.node_id(i);
@@ -84,11 +185,8 @@ impl Changes {
                     }
                     last = LastLine::File1(id);
                 }
-                LineDiff::Delete(i) => {
-                    let id = file1.node_id(i);
-                    changes.push(Change::DeleteNode { id: *id });
-                }
             }
+            i += 1;
         }
         Changes { changes }
     }
@@ -99,6 +197,36 @@ impl Changes {
             ch.set_patch_id(new_id);
         }
     }
+
+    /// Counts the nodes and edges that are added and deleted by these changes.
+    pub fn stats(&self) -> PatchStats {
+        let mut ret = PatchStats::default();
+        for ch in &self.changes {
+            match ch {
+                Change::NewNode { .. } => ret.nodes_added += 1,
+                Change::DeleteNode { .. } => ret.nodes_deleted += 1,
+                Change::NewEdge { .. } => ret.edges_added += 1,
+                Change::ReplaceContents { .. } | Change::CreateFile => {}
+            }
+        }
+        ret
+    }
+
+    /// Do these changes create the file (see [`Change::CreateFile`])?
+    pub fn creates_file(&self) -> bool {
+        self.changes.contains(&Change::CreateFile)
+    }
+}
+
+/// A summary of the size of a set of [`Changes`] (see [`Changes::stats`]).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PatchStats {
+    /// The number of [`Change::NewNode`]s.
+    pub nodes_added: usize,
+    /// The number of [`Change::DeleteNode`]s.
+    pub nodes_deleted: usize,
+    /// The number of [`Change::NewEdge`]s.
+    pub edges_added: usize,
 }
 
 /// A single change.
@@ -111,6 +239,22 @@ pub enum Change {
         id: NodeId,
         /// The contents of the new node.
         contents: Vec<u8>,
+        /// The position (starting from zero) of the new node in the file that the patch was
+        /// created from, if known.
+        ///
+        /// This isn't needed for applying the patch. It's a hint that helps to put nodes in a
+        /// sensible order when resolving conflicts (see
+        /// [`OrderResolver::set_hints`](crate::resolver::OrderResolver::set_hints)).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+        /// The file that the new node belongs to, for patches that touch more than one file.
+        /// `None` means the main file, which (for now) is the only file that a branch has.
+        ///
+        /// The other changes don't say which file they're in: they refer to nodes, and every node
+        /// stays in the file that it was created in. The new nodes in a patch must all have
+        /// different ids, even if they belong to different files.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
     },
     /// Marks a node as deleted. Note that deleted nodes are never actually removed; they remain
     /// but they are simply marked as deleted.
@@ -118,6 +262,33 @@ pub enum Change {
         /// The ID of the node to delete.
         id: NodeId,
     },
+    /// Replaces the contents of an existing node, leaving its position in the graggle unchanged.
+    ///
+    /// This is used for lines that were edited in place: compared to deleting the old node and
+    /// adding a new one, it avoids growing the set of deleted nodes.
+    ///
+    /// A patch that replaces or deletes a node depends on the patches that replaced the node's
+    /// contents on the branch it was created on, and it only overrides those replacements. If
+    /// several patches replace the contents of the same node without depending on one another,
+    /// none of them wins: the edits conflict (see
+    /// [`Repo::edit_conflicts`](crate::Repo::edit_conflicts)) until a patch that knows about all
+    /// of them replaces or deletes the node. A deleted node stays live (with the replaced contents) if one of its replacements isn't
+    /// known to any of the patches that deleted it, so that deleting a line never silently discards
+    /// a concurrent edit of it.
+    ReplaceContents {
+        /// The ID of the node whose contents are being replaced.
+        id: NodeId,
+        /// The new contents of the node.
+        new_contents: Vec<u8>,
+    },
+    /// Records that this patch created the file, as opposed to editing a file that already
+    /// existed.
+    ///
+    /// This doesn't change the graggle at all: a branch without any patches renders as an empty
+    /// file, whether or not the file was ever created. It records which patch started a file's
+    /// history (see [`Repo::file_creators`](crate::Repo::file_creators)), and [`Changes::from_diff`]
+    /// adds it to the changes when diffing against an empty file.
+    CreateFile,
     /// Adds a new edge (i.e. a new ordering relation) between two nodes. Those nodes must either
     /// already exist in the graggle at the time this change is applied. (If this `Change` is part of
     /// a `Changes` that adds some nodes and also an edge between them, then that's ok too.)
@@ -130,6 +301,15 @@ pub enum Change {
 }
 
 impl Change {
+    /// The names of the different kinds of change, as they appear in serialized patches.
+    pub const NAMES: [&'static str; 5] = [
+        "NewNode",
+        "DeleteNode",
+        "ReplaceContents",
+        "CreateFile",
+        "NewEdge",
+    ];
+
     // Modifies the PatchId of this Change.
     fn set_patch_id(&mut self, new_id: &PatchId) {
         match *self {
@@ -146,6 +326,10 @@ impl Change {
             Change::DeleteNode { ref mut id } => {
                 id.set_patch_id(new_id);
             }
+            Change::ReplaceContents { ref mut id, .. } => {
+                id.set_patch_id(new_id);
+            }
+            Change::CreateFile => {}
         }
     }
 }
@@ -153,21 +337,129 @@ impl Change {
 #[cfg(test)]
 mod tests {
     use super::Change::*;
-    use super::Changes;
+    use super::{Change, Changes};
     use crate::storage::File;
     use crate::NodeId;
     use ojo_diff::LineDiff::*;
 
+    // Every kind of change is serialized under one of `Change::NAMES`.
+    #[test]
+    fn names() {
+        let id = NodeId::cur(0);
+        let changes = [
+            NewNode {
+                id,
+                contents: Vec::new(),
+                line: None,
+                path: None,
+            },
+            DeleteNode { id },
+            ReplaceContents {
+                id,
+                new_contents: Vec::new(),
+            },
+            CreateFile,
+            NewEdge { src: id, dest: id },
+        ];
+        for (ch, name) in changes.iter().zip(&Change::NAMES) {
+            // This stops compiling when there's a new kind of change, as a reminder to add it.
+            match ch {
+                NewNode { .. } | DeleteNode { .. } | ReplaceContents { .. } | CreateFile => {}
+                NewEdge { .. } => {}
+            }
+            let yaml = serde_yaml::to_string(ch).unwrap();
+            assert!(yaml.starts_with(&format!("---\n{}", name)), "{}", yaml);
+        }
+        assert_eq!(changes.len(), Change::NAMES.len());
+    }
+
     #[test]
     fn from_diff_empty_first() {
         let file1 = File::from_bytes(b"");
         let file2 = File::from_bytes(b"something");
         let diff = vec![New(0)];
 
-        let expected = vec![NewNode {
-            id: NodeId::cur(0),
-            contents: b"something".to_vec(),
-        }];
+        let expected = vec![
+            CreateFile,
+            NewNode {
+                id: NodeId::cur(0),
+                contents: b"something".to_vec(),
+                line: Some(0),
+                path: None,
+            },
+        ];
+        assert_eq!(Changes::from_diff(&file1, &file2, &diff).changes, expected);
+    }
+
+    #[test]
+    fn from_diff_empty_both() {
+        let file = File::from_bytes(b"");
+        let changes = Changes::from_diff(&file, &file, &[]);
+        assert!(changes.changes.is_empty());
+        assert!(!changes.creates_file());
+    }
+
+    #[test]
+    fn from_diff_edit_in_place() {
+        let file1 = File::from_bytes(b"unchanged\nsome text\ndeleted\n");
+        let file2 = File::from_bytes(b"unchanged\nsome test\nsomething else entirely\n");
+        let diff = vec![Keep(0, 0), Delete(1), Delete(2), New(1), New(2)];
+
+        let expected = vec![
+            DeleteNode { id: NodeId::cur(2) },
+            ReplaceContents {
+                id: NodeId::cur(1),
+                new_contents: b"some test\n".to_vec(),
+            },
+            NewNode {
+                id: NodeId::cur(2),
+                contents: b"something else entirely\n".to_vec(),
+                line: Some(2),
+                path: None,
+            },
+            NewEdge {
+                src: NodeId::cur(1),
+                dest: NodeId::cur(2),
+            },
+        ];
         assert_eq!(Changes::from_diff(&file1, &file2, &diff).changes, expected);
     }
+
+    // Files whose first or last lines are unusual in some way.
+    const EDGE_CASES: &[&[u8]] = &[
+        b"", b"\n", b"\n\n\n", b"a", b"a\n", b"a\n\n", b"a\n\n\n", b"\na", b"a\nb", b"a\nb\n",
+        b"a\r\nb", b"b\n\n",
+    ];
+
+    // Going from any of the files in `EDGE_CASES` to any other one gives exactly the right bytes,
+    // whether the changes are previewed or applied as a patch.
+    #[test]
+    fn round_trip() {
+        for old in EDGE_CASES {
+            for new in EDGE_CASES {
+                let mut repo = crate::Repo::init_tmp();
+                let diff = repo.diff("master", old).unwrap();
+                let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+                if !changes.changes.is_empty() {
+                    let id = repo.create_patch("Author", "Old", changes).unwrap();
+                    repo.apply_patch("master", &id).unwrap();
+                }
+                assert_eq!(repo.file("master").unwrap().as_bytes(), *old);
+
+                let diff = repo.diff("master", new).unwrap();
+                let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+                assert_eq!(changes.changes.is_empty(), old == new);
+                let diff = repo.diff_incremental("master", new).unwrap();
+                let incremental = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+                assert_eq!(incremental, changes);
+                let preview = repo.preview_with_changes("master", &changes).unwrap();
+                assert_eq!(preview.as_bytes(), *new, "{:?} -> {:?}", old, new);
+                if !changes.changes.is_empty() {
+                    let id = repo.create_patch("Author", "New", changes).unwrap();
+                    repo.apply_patch("master", &id).unwrap();
+                }
+                assert_eq!(repo.file("master").unwrap().as_bytes(), *new);
+            }
+        }
+    }
 }
@@ -0,0 +1,317 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use ojo_graph::Graph;
+use ojo_multimap::MMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::storage::INode;
+use crate::{Change, Changes, Disorder, Error, File, NodeId, Patch, PatchId, Repo};
+
+// Returns `roots` together with everything reachable from them in `edges` (but skipping over
+// anything for which `skip` returns true), ordered so that everything comes after the things that
+// it points to.
+pub(crate) fn closure<F: Fn(&PatchId) -> bool>(
+    roots: &[PatchId],
+    edges: &MMap<PatchId, PatchId>,
+    skip: F,
+) -> Vec<PatchId> {
+    let mut seen = HashSet::new();
+    let mut ret = Vec::new();
+    for root in roots {
+        let mut stack = vec![(*root, false)];
+        while let Some((p, done)) = stack.pop() {
+            if done {
+                ret.push(p);
+            } else if !skip(&p) && seen.insert(p) {
+                stack.push((p, true));
+                stack.extend(edges.get(&p).map(|&q| (q, false)));
+            }
+        }
+    }
+    ret
+}
+
+fn patch_changes(patches: &[Patch]) -> Vec<(PatchId, &Changes)> {
+    patches.iter().map(|p| (*p.id(), p.changes())).collect()
+}
+
+/// What [`Repo::clear`] would remove from a branch, as returned by [`Repo::preview_clear`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClearSummary {
+    /// The number of patches applied to the branch.
+    pub patches: usize,
+    /// The number of nodes in the branch's graggle that are still live.
+    pub live_nodes: usize,
+    /// The number of nodes in the branch's graggle that have been deleted.
+    pub deleted_nodes: usize,
+}
+
+impl Repo {
+    fn check_known_patches(&self, patches: &[PatchId]) -> Result<(), Error> {
+        match patches
+            .iter()
+            .find(|p| !self.storage.patches.contains_key(p))
+        {
+            Some(p) => Err(Error::UnknownPatch(*p)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the patches that [`Repo::apply_patches`] would apply to `branch`, in the order
+    /// that it would apply them.
+    ///
+    /// These are the given patches together with all of their (direct or indirect) dependencies,
+    /// leaving out the ones that are already applied. The repository isn't modified.
+    pub fn patches_to_apply(
+        &self,
+        branch: &str,
+        patches: &[PatchId],
+    ) -> Result<Vec<PatchId>, Error> {
+        self.inode(branch)?;
+        self.check_known_patches(patches)?;
+        Ok(closure(patches, &self.storage.patch_deps, |p| {
+            self.is_applied(branch, p)
+        }))
+    }
+
+    /// Returns the patches that [`Repo::unapply_patches`] would unapply from `branch`, in the
+    /// order that it would unapply them.
+    ///
+    /// These are the given patches together with everything that (directly or indirectly) depends
+    /// on them, leaving out the ones that aren't applied. The repository isn't modified.
+    pub fn patches_to_unapply(
+        &self,
+        branch: &str,
+        patches: &[PatchId],
+    ) -> Result<Vec<PatchId>, Error> {
+        self.inode(branch)?;
+        self.check_known_patches(patches)?;
+        Ok(closure(patches, &self.storage.patch_rev_deps, |p| {
+            !self.is_applied(branch, p)
+        }))
+    }
+
+    /// Renders `branch` as it would look after unapplying the patches in `unapply` and then
+    /// applying the patches in `apply`.
+    ///
+    /// As with [`Repo::unapply_patch`] and [`Repo::apply_patch`], everything that depends on an
+    /// unapplied patch also gets unapplied, and the dependencies of an applied patch also get
+    /// applied. However, the repository itself isn't modified: the patches are only applied to an
+    /// in-memory copy of the branch's graggle.
+    ///
+    /// Returns [`Error::NotOrdered`] if the result isn't totally ordered.
+    pub fn preview_with_patches(
+        &self,
+        branch: &str,
+        apply: &[PatchId],
+        unapply: &[PatchId],
+    ) -> Result<File, Error> {
+        let inode = self.inode(branch)?;
+        let mut applied = self.patches(branch).cloned().collect::<HashSet<_>>();
+
+        let unapply = closure(unapply, &self.storage.patch_rev_deps, |p| {
+            !applied.contains(p)
+        });
+        for p in &unapply {
+            applied.remove(p);
+        }
+        let apply = closure(apply, &self.storage.patch_deps, |p| applied.contains(p));
+
+        let open = |ids: &[PatchId]| -> Result<Vec<Patch>, Error> {
+            ids.iter().map(|p| self.open_patch(p)).collect()
+        };
+        let unapply = open(&unapply)?;
+        let apply = open(&apply)?;
+        self.preview_changes(inode, &patch_changes(&unapply), &patch_changes(&apply))
+    }
+
+    /// Renders `branch` as it would have looked when `patch` was applied to it, if only `patch` and
+    /// its (direct or indirect) dependencies were applied.
+    ///
+    /// This is the same as [`Repo::preview_with_patches`] with every other patch in `branch`
+    /// unapplied; in particular, the repository isn't modified. Returns [`Error::NotApplied`]
+    /// unless `patch` is applied to `branch`.
+    pub fn preview_at_patch(&self, branch: &str, patch: &PatchId) -> Result<File, Error> {
+        if !self.is_applied(branch, patch) {
+            return Err(Error::NotApplied(*patch, branch.to_owned()));
+        }
+        let keep = closure(&[*patch], &self.storage.patch_deps, |_| false)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let unapply = self
+            .patches(branch)
+            .filter(|p| !keep.contains(p))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.preview_with_patches(branch, &[], &unapply)
+    }
+
+    /// Summarizes what [`Repo::clear`] would remove from `branch`, without modifying the
+    /// repository.
+    pub fn preview_clear(&self, branch: &str) -> Result<ClearSummary, Error> {
+        let graggle = self.graggle(branch)?;
+        let live_nodes = graggle.nodes().count();
+        Ok(ClearSummary {
+            patches: self.patches(branch).count(),
+            live_nodes,
+            deleted_nodes: graggle.as_full_graph().nodes().count() - live_nodes,
+        })
+    }
+
+    /// Renders `branch` as it would look after applying `changes` to it.
+    ///
+    /// The changes are treated as belonging to a patch with id [`PatchId::cur`], as returned by
+    /// [`Changes::from_diff`](crate::Changes::from_diff). The repository isn't modified.
+    pub fn preview_with_changes(&self, branch: &str, changes: &Changes) -> Result<File, Error> {
+        let inode = self.inode(branch)?;
+        self.preview_changes(inode, &[], &[(PatchId::cur(), changes)])
+    }
+
+    fn preview_changes(
+        &self,
+        inode: INode,
+        unapply: &[(PatchId, &Changes)],
+        apply: &[(PatchId, &Changes)],
+    ) -> Result<File, Error> {
+        // Check that all the changes refer to nodes that we know about, because otherwise the
+        // graggle would panic.
+        let graggle = self.storage.graggle(inode);
+        let all_changes = || apply.iter().flat_map(|(_, changes)| &changes.changes);
+        let new_nodes = all_changes()
+            .filter_map(|ch| match ch {
+                Change::NewNode { id, .. } => Some(id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        for ch in all_changes() {
+            let nodes = match ch {
+                Change::NewNode { .. } | Change::CreateFile => vec![],
+                Change::DeleteNode { id } | Change::ReplaceContents { id, .. } => vec![id],
+                Change::NewEdge { src, dest } => vec![src, dest],
+            };
+            if let Some(id) = nodes
+                .into_iter()
+                .find(|id| !graggle.has_node(id) && !new_nodes.contains(id))
+            {
+                return Err(Error::UnknownNode(*id));
+            }
+        }
+
+        let graggle_data = self.storage.preview_graggle(inode, unapply, apply);
+        let graggle = graggle_data.as_graggle();
+
+        // The newly applied patches might not have their contents in storage yet, so we take them
+        // from the patches instead.
+        let mut new_contents = HashMap::new();
+        let mut new_replacements = HashMap::new();
+        for (p, changes) in apply {
+            for ch in &changes.changes {
+                match ch {
+                    Change::NewNode { id, contents, .. } => {
+                        new_contents.insert(*id, &contents[..]);
+                    }
+                    Change::ReplaceContents { id, new_contents } => {
+                        new_replacements.insert((*id, *p), &new_contents[..]);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let node_contents = |id: &NodeId| match graggle.contents_patch(id) {
+            Some(patch) => new_replacements
+                .get(&(*id, patch))
+                .cloned()
+                .unwrap_or_else(|| self.storage.replacement(id, &patch)),
+            None => new_contents
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| self.storage.contents(id)),
+        };
+
+        let live = graggle.as_live_graph();
+        let edits = self.storage.edit_conflicts(graggle);
+        match live.linear_order() {
+            Some(ref order) if edits.is_empty() => Ok(File::from_ids_with(order, node_contents)),
+            _ => Err(Error::NotOrdered(Disorder::of(&live).with_edits(&edits))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Changes, Error, Repo};
+
+    #[test]
+    fn preview() {
+        let mut repo = Repo::init_tmp();
+        let mut ids = Vec::new();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"a\nc\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            ids.push(id);
+        }
+        repo.create_branch("empty").unwrap();
+
+        let preview = |repo: &Repo, branch, apply: &[_], unapply: &[_]| {
+            repo.preview_with_patches(branch, apply, unapply)
+                .unwrap()
+                .as_bytes()
+                .to_owned()
+        };
+
+        // Unapplying a patch also unapplies the patches that depend on it.
+        assert_eq!(preview(&repo, "master", &[], &ids[2..]), b"a\nb\n");
+        assert_eq!(preview(&repo, "master", &[], &ids[1..2]), b"a\n");
+        // Applying a patch also applies its dependencies.
+        assert_eq!(preview(&repo, "empty", &ids[1..2], &[]), b"a\nb\n");
+        assert_eq!(preview(&repo, "master", &ids[2..], &ids[1..2]), b"a\nc\n");
+
+        // The patches that would be applied and unapplied.
+        assert_eq!(
+            repo.patches_to_apply("empty", &ids[1..2]).unwrap(),
+            &ids[0..2]
+        );
+        assert_eq!(
+            repo.patches_to_unapply("master", &ids[0..1]).unwrap(),
+            vec![ids[2], ids[1], ids[0]]
+        );
+        assert!(repo
+            .patches_to_apply("master", &ids[1..])
+            .unwrap()
+            .is_empty());
+
+        // Nothing actually changed.
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nc\n");
+        assert_eq!(repo.patches("empty").count(), 0);
+
+        // Going back to an earlier patch.
+        let at = |repo: &Repo, branch, patch| {
+            repo.preview_at_patch(branch, patch)
+                .unwrap()
+                .as_bytes()
+                .to_owned()
+        };
+        assert_eq!(at(&repo, "master", &ids[0]), b"a\n");
+        assert_eq!(at(&repo, "master", &ids[1]), b"a\nb\n");
+        assert_eq!(at(&repo, "master", &ids[2]), b"a\nc\n");
+        assert!(matches!(
+            repo.preview_at_patch("empty", &ids[0]),
+            Err(Error::NotApplied(..))
+        ));
+
+        // Previewing a patch whose contents aren't in storage yet.
+        repo.unapply_patch("master", &ids[0]).unwrap();
+        assert_eq!(preview(&repo, "master", &ids[1..2], &[]), b"a\nb\n");
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+// Property-based tests that run sequences of operations on a whole `Repo`, as opposed to the ones
+// in `storage::graggle::tests`, which only exercise a single graggle.
+
+use ojo_graph::Graph;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::collections::BTreeSet;
+
+use crate::{Change, Changes, EdgeKind, Error, NodeId, PatchId, Repo};
+
+// The branches that the operations act on. The first one is the current branch, so it never gets
+// deleted.
+const BRANCHES: [&str; 3] = ["master", "left", "right"];
+
+#[derive(Clone, Debug)]
+enum Op {
+    // Replaces the contents of a branch with the given lines, and records the change as a new
+    // patch (but only if the branch is ordered).
+    Edit { branch: usize, lines: Vec<u8> },
+    // Applies one of the patches that were created so far (the index wraps around).
+    Apply { branch: usize, patch: usize },
+    Unapply { branch: usize, patch: usize },
+    // Replaces the second branch with a copy of the first.
+    CloneBranch { from: usize, to: usize },
+    Clear { branch: usize },
+}
+
+fn arb_op() -> BoxedStrategy<Op> {
+    let branch = 0..BRANCHES.len();
+    prop_oneof![
+        (branch.clone(), vec(0..4u8, 0..6)).prop_map(|(branch, lines)| Op::Edit { branch, lines }),
+        (branch.clone(), 0..16usize).prop_map(|(branch, patch)| Op::Apply { branch, patch }),
+        (branch.clone(), 0..16usize).prop_map(|(branch, patch)| Op::Unapply { branch, patch }),
+        (branch.clone(), 1..BRANCHES.len()).prop_map(|(from, to)| Op::CloneBranch { from, to }),
+        branch.prop_map(|branch| Op::Clear { branch }),
+    ]
+    .boxed()
+}
+
+// Everything that is observable about a branch's graggle: its nodes (and whether they're live),
+// its edges, and the rendered file (if there is one).
+type Snapshot = (
+    BTreeSet<(NodeId, bool)>,
+    BTreeSet<(NodeId, NodeId, EdgeKind)>,
+    Option<Vec<u8>>,
+);
+
+fn snapshot(repo: &Repo, branch: &str) -> Snapshot {
+    let graggle = repo.graggle(branch).unwrap();
+    let full = graggle.as_full_graph();
+    let nodes = full.nodes().map(|u| (u, graggle.is_live(&u))).collect();
+    let edges = full
+        .nodes()
+        .flat_map(|u| graggle.all_out_edges(&u).map(move |e| (u, e.dest, e.kind)))
+        .collect();
+    let file = repo.file(branch).ok().map(|f| f.as_bytes().to_owned());
+    (nodes, edges, file)
+}
+
+fn check_invariants(repo: &Repo) {
+    for branch in repo.branches() {
+        if let Err(e) = repo.check_branch(branch) {
+            panic!("{}", e);
+        }
+        assert_eq!(repo.verify_branch_closure(branch), Ok(()));
+    }
+}
+
+// Applies `patch` to `branch`, and checks that unapplying everything that got applied puts the
+// branch back the way it was.
+fn apply_and_unapply(repo: &mut Repo, branch: &str, patch: &PatchId) {
+    let before = snapshot(repo, branch);
+    let applied = repo.apply_patch(branch, patch).unwrap();
+    assert!(applied.contains(patch));
+    check_invariants(repo);
+
+    for p in applied.iter().rev() {
+        if repo.is_applied(branch, p) {
+            repo.unapply_patch(branch, p).unwrap();
+        }
+    }
+    check_invariants(repo);
+    assert_eq!(before, snapshot(repo, branch));
+
+    repo.apply_patch(branch, patch).unwrap();
+}
+
+// Would applying `patch` to `branch` delete some node more than once? (This can happen if two
+// independent patches delete the same line.) Graggles don't support that yet, so we avoid it.
+fn deletes_twice(repo: &Repo, branch: &str, patch: &PatchId) -> bool {
+    let graggle = repo.graggle(branch).unwrap();
+    let mut deleted = BTreeSet::new();
+    for p in repo.patches_to_apply(branch, &[*patch]).unwrap() {
+        for ch in &repo.open_patch(&p).unwrap().changes().changes {
+            if let Change::DeleteNode { id } = ch {
+                if !deleted.insert(*id) || (graggle.has_node(id) && !graggle.is_live(id)) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+fn run_op(repo: &mut Repo, patches: &mut Vec<PatchId>, op: &Op) {
+    match *op {
+        Op::Edit { branch, ref lines } => {
+            let branch = BRANCHES[branch];
+            let contents = lines
+                .iter()
+                .map(|x| format!("line {}\n", x))
+                .collect::<String>();
+            let diff = match repo.diff(branch, contents.as_bytes()) {
+                Ok(diff) => diff,
+                Err(Error::NotOrdered(_)) => return,
+                Err(e) => panic!("{}", e),
+            };
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            if changes.changes.is_empty() {
+                return;
+            }
+            // Make sure that every patch gets a different id.
+            let msg = format!("Patch {}", patches.len());
+            let id = repo
+                .create_patch_on_branch(branch, "Author", &msg, changes)
+                .unwrap();
+            patches.push(id);
+            apply_and_unapply(repo, branch, &id);
+            assert_eq!(repo.file(branch).unwrap().as_bytes(), contents.as_bytes());
+        }
+        Op::Apply { branch, patch } => {
+            let branch = BRANCHES[branch];
+            if let Some(p) = patches.get(patch % patches.len().max(1)).cloned() {
+                if !repo.is_applied(branch, &p) && !deletes_twice(repo, branch, &p) {
+                    apply_and_unapply(repo, branch, &p);
+                }
+            }
+        }
+        Op::Unapply { branch, patch } => {
+            let branch = BRANCHES[branch];
+            if let Some(p) = patches.get(patch % patches.len().max(1)).cloned() {
+                if repo.is_applied(branch, &p) {
+                    let unapplied = repo.unapply_patch(branch, &p).unwrap();
+                    assert!(unapplied.contains(&p));
+                    assert!(unapplied.iter().all(|q| !repo.is_applied(branch, q)));
+                } else {
+                    // Unapplying a patch that isn't applied does nothing.
+                    let before = snapshot(repo, branch);
+                    assert!(repo.unapply_patch(branch, &p).unwrap().is_empty());
+                    assert_eq!(before, snapshot(repo, branch));
+                }
+            }
+        }
+        Op::CloneBranch { from, to } => {
+            let (from, to) = (BRANCHES[from], BRANCHES[to]);
+            if from != to {
+                repo.delete_branch(to).unwrap();
+                repo.clone_branch(from, to).unwrap();
+                assert_eq!(snapshot(repo, from), snapshot(repo, to));
+            }
+        }
+        Op::Clear { branch } => {
+            let branch = BRANCHES[branch];
+            repo.clear(branch).unwrap();
+            assert!(repo.patches(branch).next().is_none());
+            assert_eq!(repo.file(branch).unwrap().as_bytes(), b"");
+        }
+    }
+    check_invariants(repo);
+}
+
+proptest! {
+    #[test]
+    fn repo_ops(ref ops in vec(arb_op(), 1..20)) {
+        let mut repo = Repo::init_tmp();
+        for branch in &BRANCHES[1..] {
+            repo.create_branch(branch).unwrap();
+        }
+        let mut patches = Vec::new();
+        for op in ops {
+            run_op(&mut repo, &mut patches, op);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::ops::Deref;
+use std::path::Path;
+
+use crate::{Error, Repo};
+
+/// A repository that was opened for reading only.
+///
+/// This dereferences to a [`Repo`], but only immutably: none of the methods that modify a
+/// repository (or write it to disk) are available. It is meant for tools (like status bars,
+/// editors or dashboards) that poll a repository while other commands might be modifying it.
+///
+/// A `ReadOnlyRepo` is a snapshot of the repository at the time that it was opened. To see later
+/// changes, open it again.
+pub struct ReadOnlyRepo {
+    repo: Repo,
+}
+
+impl Deref for ReadOnlyRepo {
+    type Target = Repo;
+
+    fn deref(&self) -> &Repo {
+        &self.repo
+    }
+}
+
+impl Repo {
+    /// Opens the existing repository with the given root directory for reading only.
+    ///
+    /// This never writes anything to the repository's directory. While reading the database, it
+    /// takes a shared lock, so it never sees a database that is only partially written. The lock
+    /// is released before this returns, so holding onto the returned `ReadOnlyRepo` doesn't
+    /// prevent anyone from modifying the repository.
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Result<ReadOnlyRepo, Error> {
+        Ok(ReadOnlyRepo {
+            repo: Repo::open(dir)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Changes, Repo};
+
+    #[test]
+    fn read_only() {
+        let dir = std::env::temp_dir().join(format!("ojo-read-only-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut repo = Repo::init(&dir).unwrap();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        repo.write().unwrap();
+
+        let read_only = Repo::open_read_only(&dir).unwrap();
+        assert_eq!(read_only.file("master").unwrap().as_bytes(), b"a\n");
+
+        // Writing again while the read-only handle is open is fine.
+        repo.create_branch("other").unwrap();
+        repo.write().unwrap();
+        assert_eq!(read_only.branches().count(), 1);
+        assert_eq!(Repo::open_read_only(&dir).unwrap().branches().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
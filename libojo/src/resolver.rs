@@ -18,10 +18,12 @@
 //! using [`CycleResolver`](crate::resolver::CycleResolver); then, we add any necessary edges using
 //! [`OrderResolver`](crate::resolver::OrderResolver).
 
+use chrono::{DateTime, Utc};
 use ojo_graph::Graph;
-use std::collections::{HashMap, HashSet};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::{Change, Changes, Graggle, LiveGraph, NodeId};
+use crate::{Change, Changes, Graggle, LiveGraph, NodeId, PatchId};
 
 // TODO: implement undo
 
@@ -91,6 +93,12 @@ impl<'a> CycleResolver<'a> {
     /// Assuming that all cycles have already been taken care of, moves to the next stage of
     /// resolution.
     pub fn into_order_resolver(self) -> OrderResolver<'a> {
+        self.into_order_resolver_with(Ranking::Unranked)
+    }
+
+    /// Like [`CycleResolver::into_order_resolver`], but the candidates of the returned resolver
+    /// are ranked according to `ranking`.
+    pub fn into_order_resolver_with(self, ranking: Ranking) -> OrderResolver<'a> {
         assert!(self.large_sccs.is_empty());
 
         let scc_reps = (0..self.sccs.num_components())
@@ -120,7 +128,7 @@ impl<'a> CycleResolver<'a> {
             .map(|(u, _)| *u)
             .collect::<Vec<_>>();
 
-        OrderResolver {
+        let mut ret = OrderResolver {
             graggle: self.graggle,
             ordered: vec![],
             seen: HashSet::new(),
@@ -128,8 +136,129 @@ impl<'a> CycleResolver<'a> {
             scc_reps,
             remaining_in_edges: in_edge_count,
             candidates,
+            ranking,
+            replaced: BTreeMap::new(),
+        };
+        ret.sort_candidates();
+        ret
+    }
+}
+
+/// The ways in which an [`OrderResolver`] can rank its candidates.
+///
+/// The ranking determines which candidates [`OrderResolver::candidates`] returns first, so that a
+/// user interface can offer the most likely choice first. It doesn't affect which choices are
+/// valid.
+#[derive(Clone, Debug, Default)]
+pub enum Ranking {
+    /// The candidates aren't ranked.
+    #[default]
+    Unranked,
+    /// The candidates whose first nodes have the smallest hints come first, followed by the
+    /// candidates without hints.
+    ///
+    /// The hints are usually the ones returned by
+    /// [`Repo::ordering_hints`](crate::Repo::ordering_hints), namely the positions that nodes had
+    /// in the files that their patches were created from.
+    Hints(HashMap<NodeId, usize>),
+    /// The candidates are ranked by the creation times of the patches that introduced their first
+    /// nodes, with the newest last. Candidates from patches without a time go last of all.
+    ///
+    /// The times are usually the ones returned by
+    /// [`Repo::patch_timestamps`](crate::Repo::patch_timestamps).
+    Timestamps(HashMap<PatchId, DateTime<Utc>>),
+}
+
+impl Ranking {
+    // Compares two nodes according to this ranking. Nodes without a hint (or a time) go last.
+    fn compare(&self, u: &NodeId, v: &NodeId) -> Ordering {
+        match *self {
+            Ranking::Unranked => Ordering::Equal,
+            Ranking::Hints(ref hints) => {
+                let key = |u: &NodeId| {
+                    let hint = hints.get(u);
+                    (hint.is_none(), hint.cloned())
+                };
+                key(u).cmp(&key(v))
+            }
+            Ranking::Timestamps(ref times) => {
+                let key = |u: &NodeId| {
+                    let time = times.get(&u.patch);
+                    (time.is_none(), time.cloned())
+                };
+                key(u).cmp(&key(v))
+            }
+        }
+    }
+}
+
+/// Puts all of the live nodes of `graggle` in order, even if they aren't totally ordered.
+///
+/// The strongly connected components of the graggle are put in an order that is consistent with
+/// its edges, and the nodes within each component (that is, each cycle) are put next to one
+/// another. Whenever there is a choice, the best node according to `ranking` comes first, with
+/// ties broken by taking the smallest [`NodeId`]. (When choosing the next component, we compare the
+/// first nodes of the components.) In particular, the result only depends on the graggle and the
+/// ranking.
+pub(crate) fn linearize(graggle: Graggle<'_>, ranking: &Ranking) -> Vec<NodeId> {
+    let cmp = |u: &NodeId, v: &NodeId| ranking.compare(u, v).then_with(|| u.cmp(v));
+    let sccs = graggle.as_live_graph().tarjan();
+    let parts = sccs
+        .parts()
+        .map(|part| {
+            let mut part = part.iter().cloned().collect::<Vec<_>>();
+            part.sort_by(cmp);
+            part
+        })
+        .collect::<Vec<_>>();
+
+    let mut remaining_in_edges = sccs
+        .nodes()
+        .map(|i| sccs.in_edges(&i).count())
+        .collect::<Vec<_>>();
+    let mut candidates = sccs
+        .nodes()
+        .filter(|&i| remaining_in_edges[i] == 0)
+        .collect::<Vec<_>>();
+    let mut ret = Vec::new();
+    while let Some((idx, _)) = candidates
+        .iter()
+        .enumerate()
+        .min_by(|(_, &i), (_, &j)| cmp(&parts[i][0], &parts[j][0]))
+    {
+        let i = candidates.swap_remove(idx);
+        ret.extend_from_slice(&parts[i]);
+        for j in sccs.out_neighbors(&i) {
+            remaining_in_edges[j] -= 1;
+            if remaining_in_edges[j] == 0 {
+                candidates.push(j);
+            }
         }
     }
+    ret
+}
+
+/// A record of the decisions that were made while resolving a graggle.
+///
+/// The changes returned by [`OrderResolver::changes`] only say what the resolved file looks like;
+/// this says how it got that way. It can be stored in a patch (see
+/// [`Repo::create_resolution_patch`](crate::Repo::create_resolution_patch)) so that the
+/// resolution can be displayed, or replayed, later.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Resolution {
+    /// For each cycle that was broken, the node that was chosen to survive.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub representatives: Vec<NodeId>,
+    /// Pairs of nodes that were put in order, but that the graggle didn't already order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ordered: Vec<(NodeId, NodeId)>,
+    /// Nodes that were deleted, either because they were part of a cycle or because they were
+    /// explicitly deleted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<NodeId>,
+    /// Nodes whose contents were replaced, for example to settle concurrent edits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaced: Vec<NodeId>,
 }
 
 /// A sequence of nodes that might come next in the file.
@@ -186,6 +315,12 @@ pub struct OrderResolver<'a> {
     seen: HashSet<usize>,
     candidates: Vec<usize>,
     remaining_in_edges: HashMap<usize, usize>,
+
+    // How to rank the candidates; `candidates` is kept sorted according to this.
+    ranking: Ranking,
+
+    // The new contents of the nodes whose contents were replaced.
+    replaced: BTreeMap<NodeId, Vec<u8>>,
 }
 
 impl<'a> OrderResolver<'a> {
@@ -225,6 +360,30 @@ impl<'a> OrderResolver<'a> {
                 self.candidates.insert(idx, u);
             }
         }
+        self.sort_candidates();
+    }
+
+    fn sort_candidates(&mut self) {
+        let scc_reps = &self.scc_reps;
+        let ranking = &self.ranking;
+        // The sort is stable, so candidates that the ranking doesn't distinguish keep their
+        // relative order.
+        self.candidates
+            .sort_by(|u, v| ranking.compare(&scc_reps[*u], &scc_reps[*v]));
+    }
+
+    /// Provides hints about where nodes are expected to go, which are used to rank the
+    /// candidates.
+    ///
+    /// This is the same as calling [`OrderResolver::set_ranking`] with [`Ranking::Hints`].
+    pub fn set_hints(&mut self, hints: HashMap<NodeId, usize>) {
+        self.set_ranking(Ranking::Hints(hints));
+    }
+
+    /// Changes the way that the candidates are ranked.
+    pub fn set_ranking(&mut self, ranking: Ranking) {
+        self.ranking = ranking;
+        self.sort_candidates();
     }
 
     /// Chooses a node to go next in the ordered output.
@@ -260,6 +419,61 @@ impl<'a> OrderResolver<'a> {
         self.advance_past(u_idx);
     }
 
+    // If there is exactly one candidate and taking it is the only reasonable thing to do, returns
+    // that candidate.
+    //
+    // Taking a candidate is the only reasonable thing to do if it follows directly on from the
+    // last node that was taken: in that case, it isn't involved in any conflict and taking it
+    // doesn't require adding any edges. Anything else (for example, a node whose predecessor was
+    // deleted, or a node that only became a candidate after choosing between several others) is
+    // left for the user to decide.
+    fn forced_candidate(&self) -> Option<NodeId> {
+        if self.candidates.len() != 1 {
+            return None;
+        }
+        let u = self.scc_reps[self.candidates[0]];
+        let follows_last = match self.ordered.last() {
+            Some(last) => self.graggle.out_neighbors(last).any(|w| w == u),
+            None => self.graggle.in_neighbors(&u).next().is_none(),
+        };
+        if follows_last {
+            Some(u)
+        } else {
+            None
+        }
+    }
+
+    /// Takes all the candidates that don't require a decision, and returns the number of nodes
+    /// that were taken.
+    ///
+    /// A candidate doesn't require a decision if it is the only candidate and it directly follows
+    /// the last node that was taken (or, if no nodes have been taken yet, if nothing comes before
+    /// it). In particular, this never deletes anything and never takes a node that would require
+    /// adding a new edge, so it doesn't change the result of [`OrderResolver::changes`] beyond
+    /// what the equivalent sequence of calls to [`OrderResolver::choose`] would do.
+    ///
+    /// Interactive tools can call this between prompts, so that the user only needs to be asked
+    /// about the parts of the graggle that actually need resolving.
+    pub fn auto_advance(&mut self) -> usize {
+        let mut count = 0;
+        while let Some(u) = self.forced_candidate() {
+            self.choose(&u);
+            count += 1;
+        }
+        count
+    }
+
+    /// Replaces the contents of a node.
+    ///
+    /// This is how concurrent edits of a line (see
+    /// [`Repo::edit_conflicts`](crate::Repo::edit_conflicts)) are settled: a patch with the
+    /// resulting changes knows about all the edits, so its contents take precedence. This doesn't
+    /// affect the order, and it can be done at any time; if the node ends up being deleted, its
+    /// contents aren't replaced after all.
+    pub fn replace_contents(&mut self, id: &NodeId, contents: Vec<u8>) {
+        self.replaced.insert(*id, contents);
+    }
+
     // TODO:
     // pub fn insert(&mut self, ...)
 
@@ -268,6 +482,39 @@ impl<'a> OrderResolver<'a> {
         self.candidates.is_empty()
     }
 
+    /// Assuming that the entire graggle has already been put in order, returns a record of the
+    /// decisions that were made in order to resolve it.
+    ///
+    /// This is consistent with [`OrderResolver::changes`]: the nodes in
+    /// [`Resolution::deleted`] are exactly the ones that `changes` deletes, the pairs in
+    /// [`Resolution::ordered`] are exactly the edges that `changes` adds, and the nodes in
+    /// [`Resolution::replaced`] are exactly the ones whose contents `changes` replaces.
+    pub fn resolution(&self) -> Resolution {
+        let representatives = (0..self.sccs.num_components())
+            .filter(|&i| self.sccs.part(i).len() >= 2)
+            .map(|i| self.scc_reps[i])
+            .collect();
+
+        let mut deleted = vec![];
+        let mut ordered = vec![];
+        let mut replaced = vec![];
+        for ch in self.changes().changes {
+            match ch {
+                Change::DeleteNode { id } => deleted.push(id),
+                Change::NewEdge { src, dest } => ordered.push((src, dest)),
+                Change::ReplaceContents { id, .. } => replaced.push(id),
+                _ => {}
+            }
+        }
+
+        Resolution {
+            representatives,
+            ordered,
+            deleted,
+            replaced,
+        }
+    }
+
     /// Assuming that the entire graggle has already been put in order, returns a [`Changes`] that,
     /// when applied to the graggle, will turn it from the original graggle into the linear order that
     /// we have just created (and which can be retrieved by [`OrderResolver::ordered_nodes`]).
@@ -286,11 +533,20 @@ impl<'a> OrderResolver<'a> {
         for i in 1..self.ordered.len() {
             let u = self.ordered[i - 1];
             let v = self.ordered[i];
-            if !self.graggle.out_neighbors(&u).any(|w| *w == v) {
+            if !self.graggle.out_neighbors(&u).any(|w| w == v) {
                 changes.push(Change::NewEdge { src: u, dest: v });
             }
         }
 
+        for (id, contents) in &self.replaced {
+            if not_deleted.contains(id) {
+                changes.push(Change::ReplaceContents {
+                    id: *id,
+                    new_contents: contents.clone(),
+                });
+            }
+        }
+
         // TODO: once we allow insertions, add those changes too.
 
         Changes { changes }
@@ -330,7 +586,7 @@ impl<'a> Iterator for ChainIter<'a> {
                     && next_in.next().is_some()
                     && next_in.next().is_none()
                 {
-                    self.next = Some(*next);
+                    self.next = Some(next);
                 }
             }
         }
@@ -367,6 +623,27 @@ mod tests {
         check(5, vec![5]);
     }
 
+    #[test]
+    fn linearize() {
+        // Nodes 1 and 2 form a cycle, which is unordered relative to nodes 3 and 4.
+        let graggle = graggle!(
+            live: 0, 1, 2, 3, 4, 5
+            edges: 0-1, 1-2, 2-1, 2-5, 0-3, 3-4, 4-5
+        );
+        let ids = |xs: &[u64]| xs.iter().map(|&x| NodeId::cur(x)).collect::<Vec<_>>();
+        assert_eq!(
+            super::linearize(graggle.as_graggle(), &Ranking::Unranked),
+            ids(&[0, 1, 2, 3, 4, 5])
+        );
+
+        let hints = vec![(NodeId::cur(3), 0), (NodeId::cur(2), 5)];
+        let ranking = Ranking::Hints(hints.into_iter().collect());
+        assert_eq!(
+            super::linearize(graggle.as_graggle(), &ranking),
+            ids(&[0, 3, 2, 1, 4, 5])
+        );
+    }
+
     #[test]
     fn resolver_diamond() {
         let graggle = graggle!(
@@ -420,4 +697,111 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn auto_advance() {
+        let graggle = graggle!(
+            live: 0, 1, 2, 3, 4, 5
+            edges: 0-1, 1-2, 1-3, 2-4, 3-4, 4-5
+        );
+        let mut res = CycleResolver::new(graggle.as_graggle()).into_order_resolver();
+
+        // The first two nodes are forced, but then there's a choice.
+        assert_eq!(res.auto_advance(), 2);
+        assert_eq!(res.ordered_nodes(), &[NodeId::cur(0), NodeId::cur(1)]);
+        assert_eq!(res.candidates().count(), 2);
+        assert_eq!(res.auto_advance(), 0);
+
+        // Taking 2 leaves 3 as the only candidate, but it doesn't follow 2 so it isn't forced.
+        res.choose(&NodeId::cur(2));
+        assert_eq!(res.auto_advance(), 0);
+        assert_eq!(res.candidates().count(), 1);
+
+        // After 3, the rest is forced.
+        res.choose(&NodeId::cur(3));
+        assert_eq!(res.auto_advance(), 2);
+        assert!(res.is_finished());
+        assert_eq!(
+            res.changes(),
+            Changes {
+                changes: vec![Change::NewEdge {
+                    src: NodeId::cur(2),
+                    dest: NodeId::cur(3)
+                }]
+            }
+        );
+    }
+
+    #[test]
+    fn hints() {
+        let graggle = graggle!(
+            live: 0, 1, 2, 3, 4
+            edges: 0-1, 0-2, 0-3, 3-4
+        );
+        let mut res = CycleResolver::new(graggle.as_graggle()).into_order_resolver();
+        let hints = [(0, 0), (1, 3), (3, 1), (4, 2)]
+            .iter()
+            .map(|&(u, line)| (NodeId::cur(u), line))
+            .collect();
+        res.set_hints(hints);
+        res.choose(&NodeId::cur(0));
+
+        // Node 2 has no hint, so it goes last.
+        let firsts = |res: &OrderResolver| res.candidates().map(|c| c.first()).collect::<Vec<_>>();
+        assert_eq!(
+            firsts(&res),
+            vec![NodeId::cur(3), NodeId::cur(1), NodeId::cur(2)]
+        );
+        res.choose(&NodeId::cur(3));
+        assert_eq!(
+            firsts(&res),
+            vec![NodeId::cur(4), NodeId::cur(1), NodeId::cur(2)]
+        );
+    }
+
+    #[test]
+    fn resolution() {
+        let graggle = graggle!(
+            live: 0, 1, 2, 3, 4
+            edges: 0-1, 1-0, 0-2, 1-2, 2-3, 2-4
+        );
+        let mut res = CycleResolver::new(graggle.as_graggle());
+        res.resolve_component(NodeId::cur(1));
+        let mut res = res.into_order_resolver();
+        res.choose(&NodeId::cur(1));
+        res.choose(&NodeId::cur(2));
+        res.choose(&NodeId::cur(4));
+        res.delete(&NodeId::cur(3));
+
+        assert_eq!(
+            res.resolution(),
+            Resolution {
+                representatives: vec![NodeId::cur(1)],
+                ordered: vec![],
+                deleted: vec![NodeId::cur(0), NodeId::cur(3)],
+                replaced: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn auto_advance_after_delete() {
+        let graggle = graggle!(
+            live: 0, 1, 2
+            edges: 0-1, 1-2
+        );
+        let mut res = CycleResolver::new(graggle.as_graggle()).into_order_resolver();
+
+        res.delete(&NodeId::cur(0));
+        // 1 doesn't follow anything that was taken, so the user needs to decide about it.
+        assert_eq!(res.auto_advance(), 0);
+        res.choose(&NodeId::cur(1));
+        assert_eq!(res.auto_advance(), 1);
+        assert_eq!(
+            res.changes(),
+            Changes {
+                changes: vec![Change::DeleteNode { id: NodeId::cur(0) }]
+            }
+        );
+    }
 }
@@ -0,0 +1,196 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use crate::{Change, Changes, Error, NodeId, PatchId, Repo};
+
+impl Repo {
+    /// Creates a "baseline" patch, which summarizes the current contents of a branch without
+    /// any of the history that led to them.
+    ///
+    /// The baseline has no dependencies: applying it to an empty branch produces the same file
+    /// as the one in `branch`, but with new node ids. This means that a repository can be
+    /// shared by sending only the baseline (see [`Repo::export_shallow`]), but patches that were
+    /// made on top of the original history can't be applied on top of the baseline.
+    ///
+    /// The branch must be totally ordered, otherwise this returns [`Error::NotOrdered`]. The new
+    /// patch is registered (and recorded as being created on `branch`), but not applied to any
+    /// branch. Since this repository then contains a baseline, it counts as shallow (see
+    /// [`Repo::is_shallow`]). To share a branch's contents without that, use
+    /// [`Repo::export_shallow`].
+    pub fn create_baseline(
+        &mut self,
+        branch: &str,
+        author: &str,
+        msg: &str,
+    ) -> Result<PatchId, Error> {
+        let changes = self.baseline_changes(branch)?;
+        let id = self.create_patch_on_branch(branch, author, msg, changes)?;
+        self.storage.baselines.insert(id);
+        Ok(id)
+    }
+
+    // The changes of a baseline patch for `branch` (see `Repo::create_baseline`).
+    pub(crate) fn baseline_changes(&self, branch: &str) -> Result<Changes, Error> {
+        let file = self.file(branch)?;
+        let mut changes = Vec::new();
+        for i in 0..file.num_nodes() {
+            let id = NodeId::cur(i as u64);
+            changes.push(Change::NewNode {
+                id,
+                contents: file.node(i).to_owned(),
+                line: Some(i),
+                path: None,
+            });
+            if i > 0 {
+                changes.push(Change::NewEdge {
+                    src: NodeId::cur(i as u64 - 1),
+                    dest: id,
+                });
+            }
+        }
+
+        Ok(Changes { changes })
+    }
+
+    /// Clears a branch like [`Repo::clear`], but without changing its contents.
+    ///
+    /// This creates a baseline patch (see [`Repo::create_baseline`]) with the given author and
+    /// description, clears `branch`, and then applies the baseline to it. The returned baseline
+    /// is the only patch left on `branch`. If anything fails, the repository is left unchanged.
+    pub fn clear_keeping_contents(
+        &mut self,
+        branch: &str,
+        author: &str,
+        msg: &str,
+    ) -> Result<PatchId, Error> {
+        self.transaction(|repo| {
+            let baseline = repo.create_baseline(branch, author, msg)?;
+            repo.clear(branch)?;
+            repo.apply_patch(branch, &baseline)?;
+            Ok(baseline)
+        })
+    }
+
+    /// Is this patch a baseline, as created by [`Repo::create_baseline`]?
+    pub fn is_baseline(&self, patch: &PatchId) -> bool {
+        self.storage.baselines.contains(patch)
+    }
+
+    /// Is the history of this repository truncated? That is, does it contain any baselines?
+    pub fn is_shallow(&self) -> bool {
+        !self.storage.baselines.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Changes, ClearSummary, Error, Repo};
+
+    #[test]
+    fn baseline() {
+        let mut repo = Repo::init_tmp();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"c\nb\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+        }
+        assert!(!repo.is_shallow());
+
+        let baseline = repo
+            .create_baseline("master", "Author", "Baseline")
+            .unwrap();
+        assert!(repo.is_shallow());
+        assert!(repo.is_baseline(&baseline));
+        assert!(repo.patch_deps(&baseline).next().is_none());
+        assert_eq!(
+            repo.patch_header(&baseline).unwrap().branch(),
+            Some("master")
+        );
+
+        repo.create_branch("shallow").unwrap();
+        repo.apply_patch("shallow", &baseline).unwrap();
+        assert_eq!(
+            repo.file("shallow").unwrap().as_bytes(),
+            repo.file("master").unwrap().as_bytes()
+        );
+
+        // Patches on top of the baseline can be unapplied, but the baseline itself can't.
+        let diff = repo.diff("shallow", b"c\nb\nd\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("shallow", &id).unwrap();
+        assert_eq!(repo.unapply_patch("shallow", &id).unwrap(), vec![id]);
+        match repo.unapply_patch("shallow", &baseline) {
+            Err(Error::Baseline(p)) => assert_eq!(p, baseline),
+            x => panic!("expected a baseline error, got {:?}", x),
+        }
+        assert!(repo.is_applied("shallow", &baseline));
+    }
+
+    #[test]
+    fn clear_keeping_contents() {
+        let mut repo = Repo::init_tmp();
+        for contents in &[&b"a\n"[..], b"a\nb\n", b"c\nb\n"] {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+        }
+        assert_eq!(
+            repo.preview_clear("master").unwrap(),
+            ClearSummary {
+                patches: 3,
+                live_nodes: 2,
+                deleted_nodes: 1,
+            }
+        );
+
+        let baseline = repo
+            .clear_keeping_contents("master", "Author", "Baseline")
+            .unwrap();
+        assert!(repo.is_baseline(&baseline));
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"c\nb\n");
+        assert_eq!(repo.patches("master").collect::<Vec<_>>(), vec![&baseline]);
+        assert_eq!(
+            repo.preview_clear("master").unwrap(),
+            ClearSummary {
+                patches: 1,
+                live_nodes: 2,
+                deleted_nodes: 0,
+            }
+        );
+
+        repo.clear("master").unwrap();
+        assert_eq!(
+            repo.preview_clear("master").unwrap(),
+            ClearSummary::default()
+        );
+    }
+
+    #[test]
+    fn failed_clear_keeping_contents() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+        repo.set_protected("master", true).unwrap();
+
+        // Clearing fails after the baseline was created, but the baseline is forgotten.
+        assert!(matches!(
+            repo.clear_keeping_contents("master", "Author", "Baseline"),
+            Err(Error::ProtectedBranch(_))
+        ));
+        assert!(!repo.is_shallow());
+        assert_eq!(repo.all_patches().collect::<Vec<_>>(), vec![&id]);
+    }
+}
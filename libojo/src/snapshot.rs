@@ -0,0 +1,96 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+use crate::{hooks, journal, transaction, CancelToken, Repo};
+
+/// An in-memory copy of a repository, as returned by [`Repo::snapshot`].
+///
+/// This dereferences (mutably) to a [`Repo`], so it can be modified in all the usual ways, for
+/// example to try out applying some patches. None of the modifications affect the original
+/// repository, and they are all forgotten when the snapshot is dropped. Like a repository created
+/// by [`Repo::init_tmp`], a snapshot can't be written to disk.
+pub struct RepoSnapshot {
+    repo: Repo,
+}
+
+impl Deref for RepoSnapshot {
+    type Target = Repo;
+
+    fn deref(&self) -> &Repo {
+        &self.repo
+    }
+}
+
+impl DerefMut for RepoSnapshot {
+    fn deref_mut(&mut self) -> &mut Repo {
+        &mut self.repo
+    }
+}
+
+impl Repo {
+    /// Returns a copy of this repository that can be modified without affecting this one.
+    ///
+    /// This is cheap, even for a large repository: the patches, the contents of the files, the
+    /// relations between the patches and the branches' graggles are shared between the copies, and
+    /// each of them only gets copied when one of the copies modifies it. The snapshot doesn't have
+    /// any of this repository's hooks, and it has its own journal (which starts out empty).
+    pub fn snapshot(&self) -> RepoSnapshot {
+        RepoSnapshot {
+            repo: Repo {
+                root_dir: self.root_dir.clone(),
+                repo_dir: PathBuf::new(),
+                db_path: PathBuf::new(),
+                current_branch: self.current_branch.clone(),
+                storage: self.storage.clone(),
+                hooks: hooks::Hooks::default(),
+                cancel: CancelToken::default(),
+                journal: journal::Journal::default(),
+                transactions: transaction::Transactions::default(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Changes, Repo};
+
+    #[test]
+    fn snapshot() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let first = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &first).unwrap();
+
+        let mut snapshot = repo.snapshot();
+        let diff = snapshot.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let second = snapshot.create_patch("Author", "Msg", changes).unwrap();
+        snapshot.apply_patch("master", &second).unwrap();
+        snapshot.create_branch("other").unwrap();
+        assert_eq!(snapshot.file("master").unwrap().as_bytes(), b"a\nb\n");
+
+        // None of that changed the original.
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\n");
+        assert!(repo.open_patch(&second).is_err());
+        assert_eq!(repo.branches().collect::<Vec<_>>(), vec!["master"]);
+        assert_eq!(repo.patches("master").collect::<Vec<_>>(), vec![&first]);
+
+        // And changing the original doesn't change the snapshot.
+        repo.unapply_patch("master", &first).unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"");
+        assert_eq!(snapshot.file("master").unwrap().as_bytes(), b"a\nb\n");
+    }
+}
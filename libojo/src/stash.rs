@@ -0,0 +1,121 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use crate::{Changes, Error, File, Repo};
+
+/// Some changes to the working file that were set aside without creating a patch.
+///
+/// Unlike a patch, a stash has no id and nothing can depend on it. Its changes are stored exactly
+/// as returned by [`Changes::from_diff`], so they refer to their new nodes using
+/// [`PatchId::cur`](crate::PatchId::cur).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Stash {
+    /// The branch that the changes were made against.
+    pub branch: String,
+    /// A description of the changes.
+    pub description: String,
+    /// The changes themselves.
+    pub changes: Changes,
+}
+
+impl Repo {
+    /// Sets aside some changes that were made against `branch`. The new stash has index zero, and
+    /// the indices of all the older ones increase by one.
+    pub fn stash_push(&mut self, branch: &str, description: &str, changes: Changes) {
+        self.storage.stashes.insert(
+            0,
+            Stash {
+                branch: branch.to_owned(),
+                description: description.to_owned(),
+                changes,
+            },
+        );
+    }
+
+    /// Returns all the stashes, newest first.
+    pub fn stashes(&self) -> &[Stash] {
+        &self.storage.stashes
+    }
+
+    /// Removes the stash with the given index and returns the file that results from applying its
+    /// changes to `branch`.
+    ///
+    /// If the changes can't be applied (for example, because some of the lines they refer to are
+    /// no longer in `branch`, or because the result wouldn't be totally ordered), returns an error
+    /// and keeps the stash.
+    pub fn stash_pop(&mut self, branch: &str, index: usize) -> Result<File, Error> {
+        let stash = self
+            .storage
+            .stashes
+            .get(index)
+            .ok_or(Error::UnknownStash(index))?;
+        let file = self.preview_with_changes(branch, &stash.changes)?;
+        self.storage.stashes.remove(index);
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Changes, Error, Repo};
+
+    #[test]
+    fn push_and_pop() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        let diff = repo.diff("master", b"a\nc\nb\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        repo.stash_push("master", "first", changes);
+        let diff = repo.diff("master", b"b\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        repo.stash_push("master", "second", changes);
+        assert_eq!(repo.stashes()[0].description, "second");
+
+        assert_eq!(
+            repo.stash_pop("master", 1).unwrap().as_bytes(),
+            b"a\nc\nb\n"
+        );
+        assert_eq!(repo.stash_pop("master", 0).unwrap().as_bytes(), b"b\n");
+        assert!(repo.stashes().is_empty());
+        assert!(matches!(
+            repo.stash_pop("master", 0),
+            Err(Error::UnknownStash(0))
+        ));
+
+        // The branch itself was never changed.
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nb\n");
+    }
+
+    #[test]
+    fn pop_onto_other_branch() {
+        let mut repo = Repo::init_tmp();
+        let diff = repo.diff("master", b"a\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = repo.create_patch("Author", "Msg", changes).unwrap();
+        repo.apply_patch("master", &id).unwrap();
+
+        let diff = repo.diff("master", b"b\n").unwrap();
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        repo.stash_push("master", "Msg", changes);
+
+        // The stash deletes a line that "empty" doesn't have, so it can't be popped there.
+        repo.create_branch("empty").unwrap();
+        assert!(matches!(
+            repo.stash_pop("empty", 0),
+            Err(Error::UnknownNode(_))
+        ));
+        assert_eq!(repo.stashes().len(), 1);
+    }
+}
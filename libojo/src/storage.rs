@@ -9,10 +9,13 @@
 // See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
 // of this distribution.
 
-use crate::patch::{Change, Changes};
-use crate::{NodeId, PatchId};
+use crate::patch::{Change, Changes, PatchHeader, PatchStats};
+use crate::{Bisect, Limits, LineagePolicy, NodeId, PatchId, Stash};
+use ojo_graph::Graph;
 use ojo_multimap::MMap;
-use std::collections::{BTreeMap, HashMap};
+use sha2::{Digest, Sha256};
+use std::collections::{btree_map, BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
 
 #[macro_use]
 pub mod graggle;
@@ -27,69 +30,345 @@ use self::graggle::GraggleData;
 ///
 /// Since we currently only support a single Graggle per branch, `INode`s are in one-to-one
 /// correspondence with branches. However, branches may be renamed while `INode`s are immutable.
+///
+/// Each `INode` holds one file: either the main file, or one of the other files that patches can
+/// add nodes to (see the `path` of [`Change::NewNode`]).
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct INode {
     n: u64,
 }
 
+// The SHA256 hash of the contents of a node. Nodes with identical contents share a single copy of
+// those contents, which is identified by this hash.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+struct ContentHash {
+    #[serde(with = "crate::patch::hash_base64")]
+    data: [u8; 32],
+}
+
+impl ContentHash {
+    fn new(contents: &[u8]) -> ContentHash {
+        let mut data = [0; 32];
+        data.copy_from_slice(&Sha256::digest(contents)[..]);
+        ContentHash { data }
+    }
+}
+
+// Some contents, together with the number of times that they are referred to (either by a node or
+// by a replacement of a node's contents).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Blob {
+    refs: usize,
+    data: Vec<u8>,
+}
+
+// The storage of a repository with format version 0 (see `REPO_FORMAT_VERSION`), in which
+// `contents` held the lines themselves, and there were no `blobs`.
+#[derive(Deserialize)]
+pub(crate) struct StorageV0 {
+    contents: BTreeMap<NodeId, Vec<u8>>,
+    #[serde(flatten)]
+    storage: Storage,
+}
+
+impl StorageV0 {
+    // Converts the storage to the current format, by moving the contents into `blobs`.
+    pub(crate) fn migrate(self) -> Storage {
+        let mut storage = self.storage;
+        for (id, contents) in self.contents {
+            let hash = storage.intern(contents);
+            Arc::make_mut(&mut storage.contents).insert(id, hash);
+        }
+        storage
+    }
+}
+
+/// Information about a branch, other than its contents.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct BranchMetadata {
+    /// Archived branches are hidden from listings by default.
+    #[serde(default)]
+    pub archived: bool,
+    /// Protected branches can't be cleared or deleted, and patches can't be unapplied from them.
+    #[serde(default)]
+    pub protected: bool,
+}
+
 // This contains all of the "large" data in the repository; that is, all the parts that grow as the
 // repository history grows. A real implementation would need to page in this storage on-demand
 // and would also need to implement copy-on-write in various important places. For now, though, we
 // just serialize and deserialize as a giant chunk.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct Storage {
     // We generate unique INodes by assigning numbers in an increasing sequence. This is the next
     // one to be assigned.
     next_inode: u64,
 
-    // These are the actual, textual contents of the lines. If we wanted to be clever, we could do
-    // deduplication and/or compression.
-    contents: BTreeMap<NodeId, Vec<u8>>,
+    // These are the hashes of the actual, textual contents of the lines in the main file. The
+    // contents themselves are in `blobs`.
+    //
+    // Like the graggles, this and the other maps that grow with the size of the files (or of the
+    // history) are shared with any clones of the storage until they are modified.
+    #[serde(default)]
+    contents: Arc<BTreeMap<NodeId, ContentHash>>,
+
+    // The same as `contents`, but for the lines in the other files, indexed by path. Every node
+    // lives in exactly one of these namespaces (or in `contents`), and only the graggles holding
+    // that file can refer to it.
+    #[serde(default)]
+    file_contents: Arc<BTreeMap<String, BTreeMap<NodeId, ContentHash>>>,
+
+    // These are the contents of nodes that were replaced in place (see `Change::ReplaceContents`),
+    // indexed by node and then by the patch that replaced them. Which of these (if any) is
+    // current depends on the graggle.
+    #[serde(default)]
+    replaced_contents: Arc<BTreeMap<NodeId, BTreeMap<PatchId, ContentHash>>>,
+
+    // The contents of all the nodes (and of all the replacements), indexed by their hashes. Since
+    // many lines in a file tend to be identical, this saves quite a bit of space. If we wanted to
+    // be clever, we could also do compression.
+    #[serde(default)]
+    blobs: Arc<HashMap<ContentHash, Blob>>,
 
     // This is a map from the names of branches to the inodes where those branches' data is stored.
     branches: BTreeMap<String, INode>,
 
-    // This is a map from inodes to the actual data contained in them.
-    graggles: BTreeMap<INode, GraggleData>,
+    // Extra information about branches, indexed by branch name. Branches that don't have an entry
+    // here have the default metadata.
+    #[serde(default)]
+    branch_metadata: BTreeMap<String, BranchMetadata>,
+
+    // This is a map from inodes to the actual data contained in them. The graggles are shared
+    // with any clones of the storage until they are modified, so that cloning is cheap.
+    graggles: BTreeMap<INode, Arc<GraggleData>>,
+
+    // The paths of the files that are held by inodes, for inodes that don't hold the main file.
+    #[serde(default)]
+    inode_paths: BTreeMap<INode, String>,
 
     // These are all the patches that we know about, and have ever known about.
     //
-    // The contents of the patches are YAML.
-    pub patches: HashMap<PatchId, String>,
+    // The contents of the patches are YAML. Like the graggles, they are shared with any clones of
+    // the storage until they are modified.
+    pub patches: Arc<HashMap<PatchId, String>>,
+
+    // The number of nodes and edges that each patch adds and deletes, so that we don't need to
+    // parse the patches to find out. Repositories written by older versions don't have this (see
+    // `Storage::upgrade`), so it can be missing the patches that couldn't be parsed.
+    #[serde(default)]
+    pub patch_stats: HashMap<PatchId, PatchStats>,
+
+    // The header of each patch, so that listing patches doesn't need to parse their changes. Like
+    // `patch_stats`, this can be missing the patches that couldn't be parsed.
+    #[serde(default)]
+    pub patch_headers: Arc<HashMap<PatchId, PatchHeader>>,
+
+    // Patches that summarize the contents of a branch in place of its history (see
+    // `Repo::create_baseline`). If this is non-empty, the history of this repository was truncated.
+    #[serde(default)]
+    pub baselines: BTreeSet<PatchId>,
+
+    // Changes to the working file that were set aside without making a patch, newest first.
+    #[serde(default)]
+    pub stashes: Vec<Stash>,
+
+    // The bisection that is in progress, if any (see `Repo::bisect_start`).
+    #[serde(default)]
+    pub bisect: Option<Bisect>,
+
+    // Human-friendly names for patches. Each name refers to a single patch, but a patch can have
+    // many names.
+    #[serde(default)]
+    pub patch_aliases: BTreeMap<String, PatchId>,
+
+    // Corrections to the authors recorded in patches: if this contains the key-value pair
+    // (old, new), patches by `old` are shown as being by `new`. The values never appear as keys.
+    #[serde(default)]
+    pub authors: BTreeMap<String, String>,
 
     // If this contains the key-value pair (branch, patch), it means that the named branch contains
     // the named patch.
-    pub branch_patches: MMap<String, PatchId>,
+    pub branch_patches: Arc<MMap<String, PatchId>>,
+
+    // For each branch, the patches that it contains in the order that they were applied. This
+    // contains the same information as `branch_patches` (which is faster for checking whether a
+    // branch contains a patch), so the two must be modified together. Repositories written by
+    // older versions don't have this (see `Storage::upgrade`).
+    #[serde(default)]
+    branch_patch_order: BTreeMap<String, Vec<PatchId>>,
 
     // If this contains the key-value pair (p1, p2), it means that patch p1 depends on patch p2.
     // (The same information can be obtained by reading the file containing patch p1, but it's more
     // convenient to keep a copy here.)
-    pub patch_deps: MMap<PatchId, PatchId>,
+    pub patch_deps: Arc<MMap<PatchId, PatchId>>,
 
     // This is the reverse of `patch_deps`: if this contains the key-value pair (p1, p2), it means
     // that patch p2 depends on patch p1.
-    pub patch_rev_deps: MMap<PatchId, PatchId>,
+    pub patch_rev_deps: Arc<MMap<PatchId, PatchId>>,
+
+    // If this contains the key-value pair (p1, p2), it means that patch p1 supersedes patch p2
+    // (see `PatchHeader::supersedes`). This is kept after p1 and p2 are removed, so that the trail
+    // can still be followed from any patch that superseded p1 (for example, after amending a patch
+    // twice).
+    #[serde(default)]
+    pub supersedes: Arc<MMap<PatchId, PatchId>>,
+
+    // Limits on the size of the patches that we accept.
+    #[serde(default)]
+    pub limits: Limits,
+
+    // The identity of this repository's lineage (see `LineagePolicy`), and what to do about
+    // patches from other lineages.
+    #[serde(default)]
+    pub identity: Option<String>,
+    #[serde(default)]
+    pub lineage_policy: LineagePolicy,
+
+    // Files with more lines than this get a coarse diff (see `Repo::coarse_diff_lines`).
+    #[serde(default = "default_coarse_diff_lines")]
+    pub coarse_diff_lines: usize,
+
+    // Whether to check the graggles' invariants after every modification, even without the
+    // `paranoid-checks` feature. This is a runtime setting, so it isn't saved.
+    #[serde(skip)]
+    pub paranoid: bool,
+
+    // The textual forms of the ids in `patches`, sorted, for finding the patches whose ids are
+    // closest to a given one (see `Repo::abbreviate_patch_id`). This is computed when it's first
+    // needed, and forgotten whenever `patches` changes.
+    #[serde(skip)]
+    sorted_patch_ids: OnceLock<Arc<Vec<String>>>,
+}
+
+fn default_coarse_diff_lines() -> usize {
+    crate::DEFAULT_COARSE_DIFF_LINES
 }
 
 impl Storage {
     pub fn new() -> Storage {
         Storage {
             next_inode: 0,
-            contents: BTreeMap::new(),
+            contents: Arc::default(),
+            file_contents: Arc::default(),
+            replaced_contents: Arc::default(),
+            blobs: Arc::default(),
             branches: BTreeMap::new(),
+            branch_metadata: BTreeMap::new(),
             graggles: BTreeMap::new(),
-            patches: HashMap::new(),
-            branch_patches: MMap::new(),
-            patch_deps: MMap::new(),
-            patch_rev_deps: MMap::new(),
+            inode_paths: BTreeMap::new(),
+            patches: Arc::default(),
+            patch_stats: HashMap::new(),
+            patch_headers: Arc::default(),
+            baselines: BTreeSet::new(),
+            stashes: Vec::new(),
+            bisect: None,
+            patch_aliases: BTreeMap::new(),
+            authors: BTreeMap::new(),
+            branch_patches: Arc::default(),
+            branch_patch_order: BTreeMap::new(),
+            patch_deps: Arc::default(),
+            patch_rev_deps: Arc::default(),
+            supersedes: Arc::default(),
+            limits: Limits::default(),
+            identity: None,
+            lineage_policy: LineagePolicy::default(),
+            coarse_diff_lines: crate::DEFAULT_COARSE_DIFF_LINES,
+            paranoid: false,
+            sorted_patch_ids: OnceLock::new(),
+        }
+    }
+
+    // Fills in the data that repositories written by older versions don't have.
+    pub fn upgrade(&mut self) {
+        let branches = self.branches.keys().cloned().collect::<Vec<_>>();
+        for branch in branches {
+            let patches = self
+                .branch_patches
+                .get(&branch)
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            if patches.is_empty() || self.branch_patch_order.contains_key(&branch) {
+                continue;
+            }
+
+            // We don't know the order that the patches were applied in, but it must have been one
+            // in which every patch came after its dependencies.
+            let mut order = Vec::new();
+            let mut placed = HashSet::new();
+            while order.len() < patches.len() {
+                let ready = patches
+                    .iter()
+                    .filter(|p| !placed.contains(*p))
+                    .filter(|p| self.patch_deps.get(p).all(|d| placed.contains(d)))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if ready.is_empty() {
+                    // The dependencies are broken, but that's for `Repo::verify_branch_closure` to
+                    // report.
+                    order.extend(patches.iter().filter(|p| !placed.contains(*p)));
+                    break;
+                }
+                placed.extend(ready.iter().cloned());
+                order.extend(ready);
+            }
+            self.branch_patch_order.insert(branch, order);
+        }
+
+        // Older graggles don't record which patches deleted their nodes, so we have to look in
+        // the patches.
+        let branches = self.branches.clone();
+        for (branch, inode) in branches {
+            if !self.graggles[&inode].missing_deleters() {
+                continue;
+            }
+            let order = self.branch_patches_in_order(&branch).to_vec();
+            let graggle = Arc::make_mut(self.graggles.get_mut(&inode).unwrap());
+            for id in order {
+                let patch = match crate::Patch::from_reader(self.patches[&id].as_bytes()) {
+                    Ok(patch) => patch,
+                    Err(_) => continue,
+                };
+                for ch in &patch.changes().changes {
+                    if let Change::DeleteNode { id: node } = ch {
+                        if graggle.as_graggle().has_node(node) {
+                            graggle.add_deleter(node, id);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Older repositories don't record the headers and statistics of their patches.
+        let missing = self
+            .patches
+            .keys()
+            .filter(|id| !self.patch_headers.contains_key(id) || !self.patch_stats.contains_key(id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in missing {
+            let patch = match crate::Patch::from_reader(self.patches[&id].as_bytes()) {
+                Ok(patch) => patch,
+                Err(_) => continue,
+            };
+            self.patch_stats.insert(id, patch.changes().stats());
+            Arc::make_mut(&mut self.patch_headers).insert(id, patch.header().clone());
         }
     }
 
     pub fn allocate_inode(&mut self) -> INode {
+        self.allocate_file_inode(None)
+    }
+
+    /// Allocates an inode holding the file at `path` (or the main file, if `path` is `None`).
+    pub fn allocate_file_inode(&mut self, path: Option<&str>) -> INode {
         let ret = INode { n: self.next_inode };
         self.next_inode += 1;
 
-        self.graggles.insert(ret, GraggleData::new());
+        self.graggles.insert(ret, Arc::new(GraggleData::new()));
+        if let Some(path) = path {
+            self.inode_paths.insert(ret, path.to_owned());
+        }
         ret
     }
 
@@ -97,33 +376,224 @@ impl Storage {
         let ret = INode { n: self.next_inode };
         self.next_inode += 1;
 
-        let old_graggle = self.graggles[&inode].clone();
+        // The new graggle shares the old one's data until one of them is modified.
+        let old_graggle = Arc::clone(&self.graggles[&inode]);
         self.graggles.insert(ret, old_graggle);
+        if let Some(path) = self.inode_paths.get(&inode).cloned() {
+            self.inode_paths.insert(ret, path);
+        }
         ret
     }
 
+    /// Returns the path of the file held by `inode`, or `None` if it holds the main file.
+    pub fn inode_path(&self, inode: INode) -> Option<&str> {
+        self.inode_paths.get(&inode).map(|p| p.as_str())
+    }
+
+    // The namespace containing the contents of the nodes in the file at `path`.
+    fn namespace(&self, path: Option<&str>) -> Option<&BTreeMap<NodeId, ContentHash>> {
+        match path {
+            Some(path) => self.file_contents.get(path),
+            None => Some(&self.contents),
+        }
+    }
+
     pub fn contents(&self, id: &NodeId) -> &[u8] {
-        self.contents[id].as_slice()
+        self.try_contents(id).expect("unknown node")
+    }
+
+    /// Returns the contents of a node, in whichever file it belongs to.
+    ///
+    /// Node ids are unique across files (see [`Change::NewNode`]), so this is unambiguous.
+    pub fn try_contents(&self, id: &NodeId) -> Option<&[u8]> {
+        self.node_path(id)
+            .and_then(|path| self.try_contents_at(path, id))
+    }
+
+    /// Returns the contents of a node, if it belongs to the file at `path`.
+    pub fn try_contents_at(&self, path: Option<&str>, id: &NodeId) -> Option<&[u8]> {
+        self.namespace(path)?
+            .get(id)
+            .map(|hash| self.blobs[hash].data.as_slice())
+    }
+
+    /// Returns the path of the file that a node belongs to (where `Some(None)` means the main
+    /// file), or `None` if we don't know about the node.
+    pub fn node_path(&self, id: &NodeId) -> Option<Option<&str>> {
+        if self.contents.contains_key(id) {
+            return Some(None);
+        }
+        self.file_contents
+            .iter()
+            .find(|(_, ns)| ns.contains_key(id))
+            .map(|(path, _)| Some(path.as_str()))
+    }
+
+    /// Returns the contents of a node, as seen from the given graggle.
+    ///
+    /// This differs from [`Storage::contents`] if some patch in the graggle replaced the contents
+    /// of the node in place.
+    pub fn contents_in(&self, graggle: Graggle<'_>, id: &NodeId) -> &[u8] {
+        match graggle.contents_patch(id) {
+            Some(patch) => self.replacement(id, &patch),
+            None => self.contents(id),
+        }
+    }
+
+    /// Returns the contents that `patch` gave to the node `id`, when it replaced them in place.
+    pub fn replacement(&self, id: &NodeId, patch: &PatchId) -> &[u8] {
+        self.try_replacement(id, patch)
+            .expect("unknown replacement")
+    }
+
+    /// Like [`Storage::replacement`], but returns `None` if `patch` didn't replace the contents
+    /// of `id` (or isn't applied anywhere).
+    pub fn try_replacement(&self, id: &NodeId, patch: &PatchId) -> Option<&[u8]> {
+        self.replaced_contents
+            .get(id)
+            .and_then(|replacements| replacements.get(patch))
+            .map(|hash| self.blobs[hash].data.as_slice())
     }
 
+    /// Returns the live nodes of `graggle` that were edited concurrently, together with the
+    /// patches that edited them (sorted by id). The nodes are sorted too.
+    ///
+    /// A node was edited concurrently if several patches replaced its contents and none of them
+    /// knows about all the others (see `settle_node`).
+    pub fn edit_conflicts(&self, graggle: Graggle<'_>) -> Vec<(NodeId, Vec<PatchId>)> {
+        let mut ret = graggle
+            .nodes()
+            .filter_map(|id| {
+                let replacers = graggle.replacing_patches(&id);
+                if replacers.len() < 2 {
+                    return None;
+                }
+                let mut latest = latest_replacers(&self.patch_deps, &replacers);
+                if latest.len() < 2 {
+                    return None;
+                }
+                latest.sort();
+                Some((id, latest))
+            })
+            .collect::<Vec<_>>();
+        ret.sort();
+        ret
+    }
+
+    // Stores some contents (if they aren't already stored), and adds a reference to them.
+    fn intern(&mut self, contents: Vec<u8>) -> ContentHash {
+        let hash = ContentHash::new(&contents);
+        Arc::make_mut(&mut self.blobs)
+            .entry(hash)
+            .or_insert_with(|| Blob {
+                refs: 0,
+                data: contents,
+            })
+            .refs += 1;
+        hash
+    }
+
+    // Removes a reference to some contents, and forgets them if that was the last reference.
+    fn release(&mut self, hash: &ContentHash) {
+        let blobs = Arc::make_mut(&mut self.blobs);
+        let blob = blobs.get_mut(hash).unwrap();
+        blob.refs -= 1;
+        if blob.refs == 0 {
+            blobs.remove(hash);
+        }
+    }
+
+    /// Adds the contents of a node in the file at `path`.
+    ///
     /// Panics if the node already has contents that differ from the current ones.
-    pub fn add_contents(&mut self, id: NodeId, contents: Vec<u8>) {
-        use std::collections::btree_map::Entry;
+    pub fn add_contents(&mut self, path: Option<&str>, id: NodeId, contents: Vec<u8>) {
+        let existing = self.namespace(path).and_then(|ns| ns.get(&id)).cloned();
+        if let Some(hash) = existing {
+            assert_eq!(hash, ContentHash::new(&contents), "contents mismatch");
+        } else {
+            let hash = self.intern(contents);
+            let ns = match path {
+                Some(path) => Arc::make_mut(&mut self.file_contents)
+                    .entry(path.to_owned())
+                    .or_default(),
+                None => Arc::make_mut(&mut self.contents),
+            };
+            ns.insert(id, hash);
+        }
+    }
 
-        match self.contents.entry(id) {
-            Entry::Occupied(o) => assert_eq!(o.get(), &contents, "contents mismatch"),
-            Entry::Vacant(v) => {
-                v.insert(contents);
+    pub fn remove_contents(&mut self, path: Option<&str>, id: &NodeId) {
+        let hash = match path {
+            Some(path) => {
+                if !self.file_contents.contains_key(path) {
+                    return;
+                }
+                let file_contents = Arc::make_mut(&mut self.file_contents);
+                let ns = file_contents.get_mut(path).unwrap();
+                let hash = ns.remove(id);
+                if ns.is_empty() {
+                    file_contents.remove(path);
+                }
+                hash
             }
+            None => Arc::make_mut(&mut self.contents).remove(id),
+        };
+        if let Some(hash) = hash {
+            self.release(&hash);
         }
     }
 
-    pub fn remove_contents(&mut self, id: &NodeId) {
-        self.contents.remove(id);
+    // Forgets the contents that `patch` gave to `id` when it replaced its contents.
+    pub fn remove_replacement(&mut self, id: &NodeId, patch: &PatchId) {
+        if !self.replaced_contents.contains_key(id) {
+            return;
+        }
+        let replaced_contents = Arc::make_mut(&mut self.replaced_contents);
+        let replacements = replaced_contents.get_mut(id).unwrap();
+        let hash = replacements.remove(patch);
+        if replacements.is_empty() {
+            replaced_contents.remove(id);
+        }
+        if let Some(hash) = hash {
+            self.release(&hash);
+        }
+    }
+
+    // Forgets everything about a patch, apart from the contents that it gave to nodes (which are
+    // only present while it's applied), its replacements (see `remove_replacement`) and the patches
+    // that it superseded (see `supersedes`).
+    pub fn remove_patch(&mut self, id: &PatchId) {
+        Arc::make_mut(&mut self.patches).remove(id);
+        self.sorted_patch_ids.take();
+        self.patch_stats.remove(id);
+        Arc::make_mut(&mut self.patch_headers).remove(id);
+        self.baselines.remove(id);
+        self.patch_aliases.retain(|_, p| p != id);
+        let deps = self.patch_deps.get(id).cloned().collect::<Vec<_>>();
+        let patch_rev_deps = Arc::make_mut(&mut self.patch_rev_deps);
+        for dep in &deps {
+            patch_rev_deps.remove(dep, id);
+        }
+        Arc::make_mut(&mut self.patch_deps).remove_all(id);
     }
 
-    pub fn contains_node(&self, id: &NodeId) -> bool {
-        self.contents.contains_key(id)
+    // Records the data of a new patch.
+    pub fn add_patch(&mut self, id: PatchId, data: String) {
+        Arc::make_mut(&mut self.patches).insert(id, data);
+        self.sorted_patch_ids.take();
+    }
+
+    // Returns the textual forms of the ids of all the patches, sorted.
+    pub fn sorted_patch_ids(&self) -> &[String] {
+        self.sorted_patch_ids.get_or_init(|| {
+            let mut ids = self
+                .patches
+                .keys()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>();
+            ids.sort();
+            Arc::new(ids)
+        })
     }
 
     pub fn inode(&self, branch: &str) -> Option<INode> {
@@ -136,11 +606,156 @@ impl Storage {
 
     pub fn remove_inode(&mut self, branch: &str) {
         self.branches.remove(branch);
+        self.branch_metadata.remove(branch);
     }
 
-    pub fn update_cache(&mut self, inode: INode) {
-        let graggle = self.graggles.get_mut(&inode).unwrap();
-        graggle.resolve_pseudo_edges();
+    pub fn branch_metadata(&self, branch: &str) -> BranchMetadata {
+        self.branch_metadata
+            .get(branch)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_branch_metadata(&mut self, branch: &str, metadata: BranchMetadata) {
+        if metadata == BranchMetadata::default() {
+            self.branch_metadata.remove(branch);
+        } else {
+            self.branch_metadata.insert(branch.to_owned(), metadata);
+        }
+    }
+
+    /// Records that `patch` was applied to `branch`.
+    pub fn add_branch_patch(&mut self, branch: &str, patch: PatchId) {
+        Arc::make_mut(&mut self.branch_patches).insert(branch.to_owned(), patch);
+        self.branch_patch_order
+            .entry(branch.to_owned())
+            .or_default()
+            .push(patch);
+    }
+
+    /// Records that `patch` was unapplied from `branch`.
+    pub fn remove_branch_patch(&mut self, branch: &str, patch: &PatchId) {
+        Arc::make_mut(&mut self.branch_patches).remove(branch, patch);
+        if let Some(order) = self.branch_patch_order.get_mut(branch) {
+            order.retain(|p| p != patch);
+        }
+    }
+
+    /// Records that `branch` no longer contains any patches.
+    pub fn clear_branch_patches(&mut self, branch: &str) {
+        Arc::make_mut(&mut self.branch_patches).remove_all(branch);
+        self.branch_patch_order.remove(branch);
+    }
+
+    /// Returns the patches in `branch`, in the order that they were applied.
+    pub fn branch_patches_in_order(&self, branch: &str) -> &[PatchId] {
+        self.branch_patch_order
+            .get(branch)
+            .map(|v| v.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Resolves the pseudo-edges of the graggle at `inode`, calling `progress` along the way (see
+    /// [`GraggleData::resolve_pseudo_edges_with`]). Returns `false` if `progress` stopped it.
+    pub fn update_cache_with<F>(&mut self, inode: INode, progress: F) -> bool
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
+        let finished = self.graggle_mut(inode).resolve_pseudo_edges_with(progress);
+        self.paranoid_check(inode, format_args!("resolving pseudo-edges"));
+        finished
+    }
+
+    /// Are the pseudo-edges of the graggle at `inode` out of date?
+    pub fn needs_cache_update(&self, inode: INode) -> bool {
+        self.graggles[&inode].needs_pseudo_edge_update()
+    }
+
+    /// Recomputes the pseudo-edges of the graggle at `inode` from scratch, returning `true` if they
+    /// were wrong.
+    pub fn rebuild_pseudo_edges(&mut self, inode: INode) -> bool {
+        let ret = self.graggle_mut(inode).rebuild_pseudo_edges();
+        self.paranoid_check(inode, format_args!("rebuilding pseudo-edges"));
+        ret
+    }
+
+    /// If paranoid checks are enabled (by the `paranoid-checks` feature, or at runtime), checks
+    /// the cheap invariants of the graggle at `inode`, which was just modified by `what`.
+    ///
+    /// Panics if the checks fail: the graggle is already broken, and carrying on would only make
+    /// things more confusing.
+    fn paranoid_check(&self, inode: INode, what: std::fmt::Arguments<'_>) {
+        if cfg!(feature = "paranoid-checks") || self.paranoid {
+            if let Err(e) = self.graggles[&inode].check_consistency(false) {
+                panic!("the graggle became inconsistent after {}: {}", what, e);
+            }
+        }
+    }
+
+    /// Checks whether the pseudo-edges of the graggle at `inode` are wrong, without fixing them.
+    pub fn stale_pseudo_edges(&self, inode: INode) -> bool {
+        GraggleData::clone(&self.graggles[&inode]).rebuild_pseudo_edges()
+    }
+
+    /// Checks that the data stored about `branch` (whose graggle is at `inode`) is consistent,
+    /// returning a description of the first problem found.
+    ///
+    /// `patches` should contain the changes of every patch in the branch, in the order that they
+    /// were applied; the graggle is checked against the one obtained by applying them from
+    /// scratch.
+    pub fn check_branch(
+        &self,
+        branch: &str,
+        inode: INode,
+        patches: &[(PatchId, &Changes)],
+    ) -> Result<(), String> {
+        let order = self.branch_patches_in_order(branch);
+        let mut seen = BTreeSet::new();
+        for p in order {
+            if !seen.insert(p) {
+                return Err(format!("patch {} was applied twice", p));
+            }
+            if !self.branch_patches.contains(branch, p) {
+                return Err(format!("patch {} is missing from the set of patches", p));
+            }
+            if let Some(dep) = self.patch_deps.get(p).find(|d| !seen.contains(d)) {
+                return Err(format!(
+                    "patch {} is applied, but its dependency {} is not applied before it",
+                    p, dep
+                ));
+            }
+        }
+        if let Some(p) = self.branch_patches.get(branch).find(|p| !seen.contains(p)) {
+            return Err(format!("patch {} is missing from the order of patches", p));
+        }
+
+        let graggle = &self.graggles[&inode];
+        let path = self.inode_path(inode);
+        graggle.check_consistency(true)?;
+        let full = graggle.as_graggle().as_full_graph();
+        if let Some(u) = full
+            .nodes()
+            .find(|u| self.try_contents_at(path, u).is_none())
+        {
+            return Err(match self.node_path(&u) {
+                Some(other) => format!(
+                    "node {:?} belongs to {}",
+                    u,
+                    other.unwrap_or("the main file")
+                ),
+                None => format!("node {:?} has no contents", u),
+            });
+        }
+
+        let mut rebuilt = GraggleData::new();
+        for (id, changes) in patches {
+            apply_to_graggle(&mut rebuilt, path, changes, *id, &self.patch_deps);
+        }
+        rebuilt.resolve_pseudo_edges();
+        if rebuilt != **graggle {
+            return Err("the graggle differs from the one built from its patches".to_owned());
+        }
+        Ok(())
     }
 
     pub fn graggle(&'_ self, inode: INode) -> Graggle<'_> {
@@ -149,79 +764,430 @@ impl Storage {
 
     pub fn remove_graggle(&mut self, inode: INode) {
         self.graggles.remove(&inode);
+        self.inode_paths.remove(&inode);
     }
 
     pub fn set_graggle(&mut self, inode: INode, graggle: GraggleData) {
-        self.graggles.insert(inode, graggle);
+        self.graggles.insert(inode, Arc::new(graggle));
+    }
+
+    // Returns the graggle at `inode` for modifying, first making a private copy of it if it's
+    // shared.
+    fn graggle_mut(&mut self, inode: INode) -> &mut GraggleData {
+        Arc::make_mut(self.graggles.get_mut(&inode).unwrap())
     }
 
-    pub fn branches(&self) -> impl Iterator<Item = &str> {
-        self.branches.keys().map(|s| s.as_str())
+    pub fn branches(&self) -> btree_map::Keys<'_, String, INode> {
+        self.branches.keys()
+    }
+
+    /// Returns a copy of the graggle at `inode`, after unapplying the changes in `unapply` and then
+    /// applying the changes in `apply`. Each set of changes is paired with the id of the patch
+    /// that it belongs to.
+    ///
+    /// Unlike [`Storage::apply_changes`] and [`Storage::unapply_changes`], this doesn't modify
+    /// the storage (and in particular, it doesn't store the contents of any new nodes).
+    pub fn preview_graggle(
+        &self,
+        inode: INode,
+        unapply: &[(PatchId, &Changes)],
+        apply: &[(PatchId, &Changes)],
+    ) -> GraggleData {
+        let path = self.inode_path(inode);
+        let mut graggle = GraggleData::clone(&self.graggles[&inode]);
+        for (id, changes) in unapply {
+            unapply_to_graggle(&mut graggle, path, changes, *id, &self.patch_deps);
+        }
+        for (id, changes) in apply {
+            apply_to_graggle(&mut graggle, path, changes, *id, &self.patch_deps);
+        }
+        graggle.resolve_pseudo_edges();
+        graggle
     }
 
     pub fn apply_changes(&mut self, inode: INode, changes: &Changes, patch: PatchId) {
-        let graggle = self.graggles.get_mut(&inode).unwrap();
-        for ch in &changes.changes {
+        let path = self.inode_paths.get(&inode).cloned();
+        let path = path.as_deref();
+        // This is `graggle_mut`, but borrowing only `graggles`.
+        let graggle = Arc::make_mut(self.graggles.get_mut(&inode).unwrap());
+        let relevant = changes_in(graggle, path, changes);
+        apply_to_graggle(graggle, path, changes, patch, &self.patch_deps);
+        self.paranoid_check(inode, format_args!("applying {}", patch));
+
+        for ch in relevant {
             match *ch {
-                Change::NewNode { ref id, .. } => {
-                    debug!("adding node {:?}", id);
-                    graggle.add_node(id.clone());
+                Change::NewNode {
+                    ref id,
+                    ref contents,
+                    ..
+                } => {
+                    self.add_contents(path, id.clone(), contents.to_owned());
                 }
-                Change::DeleteNode { ref id } => {
-                    debug!("deleting node {:?}", id);
-                    graggle.delete_node(&id);
-                }
-                Change::NewEdge { ref src, ref dest } => {
-                    debug!("adding edge {:?} -- {:?}", src, dest);
-                    graggle.add_edge(src.clone(), dest.clone(), patch);
+                Change::ReplaceContents {
+                    ref id,
+                    ref new_contents,
+                } => {
+                    let hash = self.intern(new_contents.to_owned());
+                    let old = Arc::make_mut(&mut self.replaced_contents)
+                        .entry(*id)
+                        .or_default()
+                        .insert(patch, hash);
+                    if let Some(old) = old {
+                        self.release(&old);
+                    }
                 }
+                _ => {}
             }
         }
+    }
 
-        // Because we borrowed self.graggles, the borrow checker isn't smart enough to allow this
-        // into the previous loop.
+    pub fn unapply_changes(&mut self, inode: INode, changes: &Changes, patch: PatchId) {
+        let path = self.inode_paths.get(&inode).cloned();
+        let path = path.as_deref();
+        let graggle = Arc::make_mut(self.graggles.get_mut(&inode).unwrap());
+        unapply_to_graggle(graggle, path, changes, patch, &self.patch_deps);
+        self.paranoid_check(inode, format_args!("unapplying {}", patch));
+
+        // If the patch is still applied to some other branch, that branch still needs the
+        // contents. (The branch that we're unapplying from still counts the patch at this point.)
+        let applied_count = self
+            .branches
+            .keys()
+            .filter(|b| self.branch_patches.contains(*b, &patch))
+            .count();
+        if applied_count > 1 {
+            return;
+        }
         for ch in &changes.changes {
             if let Change::NewNode {
                 ref id,
-                ref contents,
+                path: ref node_path,
+                ..
             } = *ch
             {
-                self.add_contents(id.clone(), contents.to_owned());
+                if node_path.as_deref() == path {
+                    self.remove_contents(path, id);
+                }
             }
         }
     }
+}
 
-    pub fn unapply_changes(&mut self, inode: INode, changes: &Changes, patch: PatchId) {
-        let graggle = self.graggles.get_mut(&inode).unwrap();
+// Returns the changes in `changes` that affect the file at `path`, whose graggle is `graggle`.
+//
+// New nodes say which file they belong to, and the other changes belong to the same file as the
+// nodes that they refer to (which is either the graggle, or the new nodes in `changes`).
+fn changes_in<'a>(
+    graggle: &GraggleData,
+    path: Option<&str>,
+    changes: &'a Changes,
+) -> Vec<&'a Change> {
+    let new_nodes = changes
+        .changes
+        .iter()
+        .filter_map(|ch| match ch {
+            Change::NewNode {
+                id,
+                path: node_path,
+                ..
+            } if node_path.as_deref() == path => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let graggle = graggle.as_graggle();
+    let belongs = |id: &NodeId| new_nodes.contains(id) || graggle.has_node(id);
+    changes
+        .changes
+        .iter()
+        .filter(|ch| match ch {
+            Change::NewNode { id, .. } => new_nodes.contains(id),
+            Change::DeleteNode { id } | Change::ReplaceContents { id, .. } => belongs(id),
+            Change::NewEdge { src, .. } => belongs(src),
+            // This records the creation of the main file.
+            Change::CreateFile => path.is_none(),
+        })
+        .collect()
+}
 
-        // Because of the requirements of `unadd_edge`, we need to unadd all edges before we unadd
-        // all nodes.
-        for ch in &changes.changes {
-            match *ch {
-                Change::DeleteNode { ref id } => {
-                    debug!("undeleting node {:?}", id);
-                    graggle.undelete_node(id);
-                }
-                Change::NewEdge { ref src, ref dest } => {
-                    debug!("unadding edge {:?} -- {:?}", src, dest);
-                    graggle.unadd_edge(src, dest, patch);
-                }
-                Change::NewNode { .. } => {}
+// Makes the changes to the graggle's structure that are described by `changes`, ignoring the ones
+// that belong to files other than `path`. This is the part of applying a patch that doesn't involve
+// the contents of nodes.
+fn apply_to_graggle(
+    graggle: &mut GraggleData,
+    path: Option<&str>,
+    changes: &Changes,
+    patch: PatchId,
+    patch_deps: &MMap<PatchId, PatchId>,
+) {
+    let mut touched = Vec::new();
+    for ch in changes_in(graggle, path, changes) {
+        match *ch {
+            Change::NewNode { ref id, .. } => {
+                debug!("adding node {:?}", id);
+                graggle.add_node(*id);
+            }
+            Change::DeleteNode { ref id } => {
+                debug!("deleting node {:?}", id);
+                graggle.add_deleter(id, patch);
+                touched.push(*id);
+            }
+            Change::ReplaceContents { ref id, .. } => {
+                debug!("replacing contents of node {:?}", id);
+                graggle.replace_contents(id, patch);
+                touched.push(*id);
             }
+            Change::NewEdge { ref src, ref dest } => {
+                debug!("adding edge {:?} -- {:?}", src, dest);
+                graggle.add_edge(*src, *dest, patch);
+            }
+            Change::CreateFile => {}
         }
-        for ch in &changes.changes {
-            if let Change::NewNode { ref id, .. } = *ch {
-                debug!("unadding node {:?}", id);
-                graggle.unadd_node(id);
+    }
+    for id in &touched {
+        settle_node(graggle, patch_deps, id);
+    }
+}
+
+// The inverse of `apply_to_graggle`.
+fn unapply_to_graggle(
+    graggle: &mut GraggleData,
+    path: Option<&str>,
+    changes: &Changes,
+    patch: PatchId,
+    patch_deps: &MMap<PatchId, PatchId>,
+) {
+    let relevant = changes_in(graggle, path, changes);
+    for ch in &relevant {
+        match *ch {
+            Change::DeleteNode { ref id } => {
+                debug!("undeleting node {:?}", id);
+                graggle.remove_deleter(id, patch);
+                settle_node(graggle, patch_deps, id);
             }
+            Change::ReplaceContents { ref id, .. } => {
+                debug!("unreplacing contents of node {:?}", id);
+                graggle.unreplace_contents(id, patch);
+                settle_node(graggle, patch_deps, id);
+            }
+            _ => {}
         }
+    }
 
-        // Because we borrowed self.graggles, the borrow checker isn't smart enough to allow this
-        // into the previous loop.
-        for ch in &changes.changes {
-            if let Change::NewNode { ref id, .. } = *ch {
-                self.remove_contents(id);
+    // Because of the requirements of `unadd_edge`, we need to unadd all edges before we unadd
+    // all nodes.
+    debug!("unadding edges from patch {:?}", patch);
+    graggle.unadd_patch_edges(patch);
+    for ch in relevant {
+        if let Change::NewNode { ref id, .. } = *ch {
+            debug!("unadding node {:?}", id);
+            graggle.unadd_node(id);
+        }
+    }
+}
+
+// Returns true if the patch `a` depends, directly or indirectly, on the patch `b`.
+//
+// Changes that aren't in a patch yet (see `PatchId::cur`) were made against the current state of
+// the branch, so they're treated as depending on everything.
+fn depends_on(patch_deps: &MMap<PatchId, PatchId>, a: &PatchId, b: &PatchId) -> bool {
+    if a.is_cur() {
+        return a != b;
+    }
+    let mut seen = HashSet::new();
+    let mut stack = vec![*a];
+    while let Some(p) = stack.pop() {
+        for dep in patch_deps.get(&p) {
+            if dep == b {
+                return true;
+            }
+            if seen.insert(*dep) {
+                stack.push(*dep);
             }
         }
     }
+    false
+}
+
+// Returns the patches among `replacers` that none of the others knows about.
+fn latest_replacers(patch_deps: &MMap<PatchId, PatchId>, replacers: &[PatchId]) -> Vec<PatchId> {
+    replacers
+        .iter()
+        .filter(|r| {
+            !replacers
+                .iter()
+                .any(|other| depends_on(patch_deps, other, r))
+        })
+        .cloned()
+        .collect()
+}
+
+// Decides whether the node `id` is deleted, and which of the patches that replaced its contents
+// provides the current contents, after a patch that deleted or replaced it was applied or
+// unapplied. This only depends on which patches are applied, not on the order they were applied
+// in.
+//
+// A patch only overrides the replacements that it knows about; that is, the ones that it depends
+// on. So the current contents come from a replacement that no other replacement knows about. If
+// there are several (because the node was edited concurrently), the edits conflict: the branch
+// isn't a file (see `Repo::file`) until some patch that knows about all of them replaces or deletes
+// the node. In the meantime, the one with the largest patch id provides the contents, so that the
+// graggle doesn't depend on the order that the patches were applied in. Similarly, a node that was
+// deleted stays live if some replacement of its contents wasn't known to any of the patches that
+// deleted it: otherwise, deleting a line while someone else edits it would silently lose the edit.
+fn settle_node(graggle: &mut GraggleData, patch_deps: &MMap<PatchId, PatchId>, id: &NodeId) {
+    let replacers = graggle.replacers(id);
+    let deleters = graggle.deleters(id);
+    let knows = |a: &PatchId, b: &PatchId| depends_on(patch_deps, a, b);
+
+    if let Some(current) = latest_replacers(patch_deps, &replacers).into_iter().max() {
+        graggle.set_current_replacement(id, current);
+    }
+
+    let deleted = !deleters.is_empty()
+        && replacers
+            .iter()
+            .all(|r| deleters.iter().any(|d| knows(d, r)));
+    let live = graggle.as_graggle().is_live(id);
+    if deleted && live {
+        graggle.delete_node(id);
+    } else if !deleted && !live {
+        graggle.undelete_node(id);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Serializes `storage` in the format of version 0 (see `StorageV0`).
+    pub(crate) fn v0_yaml(storage: &Storage) -> String {
+        let contents = storage
+            .contents
+            .keys()
+            .map(|id| (*id, storage.contents(id).to_vec()))
+            .collect::<BTreeMap<_, _>>();
+        let contents = serde_yaml::to_string(&contents)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| format!("  {}\n", line))
+            .collect::<String>();
+        let mut storage = storage.clone();
+        Arc::make_mut(&mut storage.contents).clear();
+        Arc::make_mut(&mut storage.blobs).clear();
+        serde_yaml::to_string(&storage)
+            .unwrap()
+            .replace("\ncontents: {}\n", &format!("\ncontents:\n{}", contents))
+            .replace("\nblobs: {}\n", "\n")
+    }
+
+    #[test]
+    fn dedup_contents() {
+        let mut storage = Storage::new();
+        storage.add_contents(None, NodeId::cur(0), b"same\n".to_vec());
+        storage.add_contents(None, NodeId::cur(1), b"same\n".to_vec());
+        storage.add_contents(None, NodeId::cur(2), b"different\n".to_vec());
+        assert_eq!(storage.blobs.len(), 2);
+        assert_eq!(storage.contents(&NodeId::cur(1)), b"same\n");
+
+        // Re-adding the same contents for a node doesn't add a reference.
+        storage.add_contents(None, NodeId::cur(0), b"same\n".to_vec());
+        storage.remove_contents(None, &NodeId::cur(0));
+        assert_eq!(storage.contents(&NodeId::cur(1)), b"same\n");
+        storage.remove_contents(None, &NodeId::cur(1));
+        storage.remove_contents(None, &NodeId::cur(2));
+        assert!(storage.blobs.is_empty());
+    }
+
+    #[test]
+    fn migrate_v0() {
+        let mut storage = Storage::new();
+        storage.add_contents(None, NodeId::cur(0), b"same\n".to_vec());
+        storage.add_contents(None, NodeId::cur(1), b"same\n".to_vec());
+        storage.add_contents(None, NodeId::cur(2), b"different\n".to_vec());
+
+        // Originally, `contents` held the lines themselves, and there were no `blobs`.
+        let yaml = v0_yaml(&storage);
+        assert!(yaml.contains("- 10\n") && !yaml.contains("blobs"));
+        let migrated = serde_yaml::from_str::<StorageV0>(&yaml).unwrap().migrate();
+        assert_eq!(migrated.contents, storage.contents);
+        assert_eq!(migrated.contents(&NodeId::cur(0)), b"same\n");
+        assert_eq!(migrated.contents(&NodeId::cur(2)), b"different\n");
+        assert_eq!(migrated.blobs.len(), 2);
+        assert_eq!(migrated.blobs[&ContentHash::new(b"same\n")].refs, 2);
+    }
+
+    #[test]
+    fn file_namespaces() {
+        let mut storage = Storage::new();
+        let main = storage.allocate_inode();
+        let other = storage.allocate_file_inode(Some("other"));
+        assert_eq!(storage.inode_path(main), None);
+        assert_eq!(storage.inode_path(other), Some("other"));
+        let copy = storage.clone_inode(other);
+        assert_eq!(storage.inode_path(copy), Some("other"));
+
+        let new_node = |n, path: Option<&str>| Change::NewNode {
+            id: NodeId::cur(n),
+            contents: b"same\n".to_vec(),
+            line: None,
+            path: path.map(|p| p.to_owned()),
+        };
+        let changes = Changes {
+            changes: vec![
+                new_node(0, None),
+                new_node(1, Some("other")),
+                new_node(2, Some("other")),
+                Change::NewEdge {
+                    src: NodeId::cur(1),
+                    dest: NodeId::cur(2),
+                },
+                Change::CreateFile,
+            ],
+        };
+        let patch = PatchId::cur();
+        storage.apply_changes(main, &changes, patch);
+        storage.apply_changes(other, &changes, patch);
+
+        let main_nodes = storage.graggle(main).nodes().collect::<Vec<_>>();
+        assert_eq!(main_nodes, vec![NodeId::cur(0)]);
+        assert_eq!(storage.graggle(other).nodes().count(), 2);
+        let out = storage
+            .graggle(other)
+            .out_neighbors(&NodeId::cur(1))
+            .collect::<Vec<_>>();
+        assert_eq!(out, vec![NodeId::cur(2)]);
+        assert_eq!(storage.node_path(&NodeId::cur(1)), Some(Some("other")));
+        assert!(storage.try_contents_at(None, &NodeId::cur(1)).is_none());
+        assert_eq!(storage.try_contents(&NodeId::cur(1)), Some(&b"same\n"[..]));
+        // All three nodes share the same contents.
+        assert_eq!(storage.blobs.len(), 1);
+
+        storage.unapply_changes(other, &changes, patch);
+        assert_eq!(storage.graggle(other).nodes().count(), 0);
+        assert!(storage.file_contents.is_empty());
+        assert_eq!(storage.try_contents(&NodeId::cur(0)), Some(&b"same\n"[..]));
+    }
+
+    #[test]
+    fn clone_shares_contents() {
+        let mut storage = Storage::new();
+        storage.add_contents(None, NodeId::cur(0), b"same\n".to_vec());
+        storage.add_branch_patch("master", PatchId::cur());
+
+        let mut clone = storage.clone();
+        assert!(Arc::ptr_eq(&clone.contents, &storage.contents));
+        assert!(Arc::ptr_eq(&clone.blobs, &storage.blobs));
+        assert!(Arc::ptr_eq(&clone.branch_patches, &storage.branch_patches));
+
+        // Modifying the clone copies what it modifies, and leaves the original alone.
+        clone.add_contents(None, NodeId::cur(1), b"different\n".to_vec());
+        clone.clear_branch_patches("master");
+        assert!(!Arc::ptr_eq(&clone.contents, &storage.contents));
+        assert!(!Arc::ptr_eq(&clone.blobs, &storage.blobs));
+        assert!(storage.try_contents(&NodeId::cur(1)).is_none());
+        assert_eq!(storage.blobs.len(), 1);
+        assert!(storage.branch_patches.contains("master", &PatchId::cur()));
+        assert_eq!(clone.contents(&NodeId::cur(1)), b"different\n");
+    }
 }
@@ -9,7 +9,7 @@
 // See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
 // of this distribution.
 
-use crate::storage::Storage;
+use crate::storage::{INode, Storage};
 use crate::NodeId;
 
 /// A `File` is a special case of a [`Graggle`](crate::Graggle), in which there is just a linear order.
@@ -31,13 +31,23 @@ pub struct File {
 
 impl File {
     /// Creates a `File` from a slice of node ids. The contents of those nodes will be retrieved
-    /// from `storage`.
-    pub(crate) fn from_ids(ids: &[NodeId], storage: &Storage) -> File {
+    /// from `storage`, as seen from the graggle at `inode`.
+    pub(crate) fn from_ids(ids: &[NodeId], storage: &Storage, inode: INode) -> File {
+        let graggle = storage.graggle(inode);
+        File::from_ids_with(ids, |id| storage.contents_in(graggle, id))
+    }
+
+    /// Creates a `File` from a slice of node ids, using `node_contents` to look up the contents of
+    /// each node.
+    pub(crate) fn from_ids_with<'a, F>(ids: &[NodeId], node_contents: F) -> File
+    where
+        F: Fn(&NodeId) -> &'a [u8],
+    {
         let mut contents = Vec::new();
         let mut boundaries = Vec::new();
         for id in ids {
             boundaries.push(contents.len());
-            contents.extend_from_slice(storage.contents(id));
+            contents.extend_from_slice(node_contents(id));
         }
         boundaries.push(contents.len());
         File {
@@ -49,6 +59,10 @@ impl File {
 
     /// Creates a [`File`] from the raw bytes, by dividing them into lines.
     ///
+    /// Each line includes its `\n` (so `\r\n` line endings stay intact too). Only the last line
+    /// can be missing one, and only if `bytes` doesn't end with a newline; an empty slice has no
+    /// lines at all. This means that [`File::as_bytes`] always gives back exactly `bytes`.
+    ///
     /// The [`NodeId`]s will be synthesized: they will have empty [`PatchId`](crate::PatchId)s, and
     /// their node indices will be consecutive, starting from zero.
     pub fn from_bytes(bytes: &[u8]) -> File {
@@ -106,6 +120,70 @@ impl File {
     pub fn as_bytes(&self) -> &[u8] {
         &self.contents[..]
     }
+
+    /// Gets the contents of the line at the given index. This includes the `\n` character, if
+    /// there was one.
+    ///
+    /// This is the same as [`File::node`], since nodes are lines. It is also available through
+    /// indexing: `file[i]`.
+    pub fn line(&self, idx: usize) -> &[u8] {
+        self.node(idx)
+    }
+
+    /// Returns an iterator over all the lines in this file.
+    pub fn iter_lines(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        self.boundaries
+            .windows(2)
+            .map(move |w| &self.contents[w[0]..w[1]])
+    }
+
+    /// Returns the byte offset (in [`File::as_bytes`]) at which the line at the given index
+    /// starts.
+    ///
+    /// `idx` may also be equal to [`File::num_nodes`], in which case this returns the length of
+    /// the file.
+    pub fn line_offset(&self, idx: usize) -> usize {
+        self.boundaries[idx]
+    }
+
+    /// Returns the index of the line containing the byte at `offset` (in [`File::as_bytes`]), or
+    /// `None` if `offset` is past the end of the file.
+    pub fn line_at_offset(&self, offset: usize) -> Option<usize> {
+        if offset >= self.contents.len() {
+            return None;
+        }
+        // boundaries[0] is zero, and offset is smaller than the last boundary, so the index is in
+        // range.
+        match self.boundaries.binary_search(&offset) {
+            Ok(idx) => Some(idx),
+            Err(idx) => Some(idx - 1),
+        }
+    }
+
+    /// Returns the index of the first line containing `needle`, if there is one.
+    ///
+    /// Matches that span multiple lines are not found.
+    pub fn find(&self, needle: &[u8]) -> Option<usize> {
+        self.find_iter(needle).next()
+    }
+
+    /// Returns an iterator over the indices of all lines containing `needle`.
+    pub fn find_iter<'a>(&'a self, needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        self.iter_lines()
+            .enumerate()
+            .filter(move |(_, line)| {
+                needle.is_empty() || line.windows(needle.len()).any(|w| w == needle)
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+impl std::ops::Index<usize> for File {
+    type Output = [u8];
+
+    fn index(&self, idx: usize) -> &[u8] {
+        self.line(idx)
+    }
 }
 
 #[cfg(test)]
@@ -155,4 +233,47 @@ mod tests {
         assert_eq!(f.node(0), b"test1\n");
         assert_eq!(f.node(1), b"test2\n");
     }
+
+    #[test]
+    fn from_bytes_round_trip() {
+        let cases: &[&[u8]] = &[b"", b"\n", b"\n\n", b"a", b"a\n\n", b"\na", b"a\r\nb\r\n"];
+        for bytes in cases {
+            let f = File::from_bytes(bytes);
+            assert_eq!(f.as_bytes(), *bytes);
+            assert_eq!(f.iter_lines().collect::<Vec<_>>().concat(), *bytes);
+            // Only the last line can be missing its newline.
+            let lines = f.iter_lines().collect::<Vec<_>>();
+            if let Some((_, init)) = lines.split_last() {
+                assert!(init.iter().all(|line| line.ends_with(b"\n")));
+            }
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let f = File::from_bytes(b"first\nsecond\nthird");
+        assert_eq!(&f[1], b"second\n");
+        assert_eq!(f.line(2), b"third");
+        assert_eq!(
+            f.iter_lines().collect::<Vec<_>>(),
+            vec![&b"first\n"[..], b"second\n", b"third"]
+        );
+        assert_eq!(f.line_offset(1), 6);
+        assert_eq!(f.line_offset(3), 18);
+        assert_eq!(f.line_at_offset(0), Some(0));
+        assert_eq!(f.line_at_offset(5), Some(0));
+        assert_eq!(f.line_at_offset(6), Some(1));
+        assert_eq!(f.line_at_offset(17), Some(2));
+        assert_eq!(f.line_at_offset(18), None);
+    }
+
+    #[test]
+    fn find() {
+        let f = File::from_bytes(b"first\nsecond\nthird\n");
+        assert_eq!(f.find(b"ir"), Some(0));
+        assert_eq!(f.find_iter(b"ir").collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(f.find(b"t\ns"), None);
+        assert_eq!(f.find(b"fourth"), None);
+        assert_eq!(f.find(b""), Some(0));
+    }
 }
@@ -12,10 +12,15 @@
 use ojo_graph::Graph;
 use ojo_multimap::MMap;
 use ojo_partition::Partition;
+use std::collections::BTreeMap;
 use std::collections::BTreeSet as Set;
 use std::collections::HashSet;
 
-use crate::{NodeId, PatchId};
+use crate::{Disorder, NodeId, PatchId};
+
+mod compact;
+
+use self::compact::{LocalEdge, LocalId, PatchIdx, PatchTable, SerializedGraggle};
 
 /// The different kinds of edges.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
@@ -67,83 +72,121 @@ pub struct Edge {
     pub patch: PatchId,
 }
 
-impl Edge {
-    fn not_deleted(&self) -> bool {
-        self.kind != EdgeKind::Deleted
-    }
-
-    fn new_pseudo(dest: NodeId) -> Edge {
-        Edge {
-            dest: dest,
-            kind: EdgeKind::Pseudo,
-            patch: PatchId::cur(),
-        }
-    }
-
-    fn new_live(dest: NodeId, patch: PatchId) -> Edge {
-        Edge {
-            dest,
-            kind: EdgeKind::Live,
-            patch,
-        }
-    }
-
-    fn new_deleted(dest: NodeId, patch: PatchId) -> Edge {
-        Edge {
-            dest,
-            kind: EdgeKind::Deleted,
-            patch,
-        }
-    }
-
-    // "Real" means either live or deleted, but not pseudo
-    fn new_real(dest: NodeId, deleted: bool, patch: PatchId) -> Edge {
-        Edge {
-            dest,
-            kind: EdgeKind::from_deleted(deleted),
-            patch,
-        }
-    }
-}
-
 impl ojo_graph::Edge<NodeId> for Edge {
     fn target(&self) -> NodeId {
         self.dest
     }
 }
 
+impl ojo_graph::LabeledEdge<NodeId> for Edge {
+    type Label = EdgeKind;
+
+    fn label(&self) -> EdgeKind {
+        self.kind
+    }
+}
+
+// Internally, nodes and patches are referred to by their compact, `Local` versions (see the
+// `compact` module), which are translated to and from the public versions using `table`.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
-#[serde(rename = "Graggle")]
+#[serde(try_from = "SerializedGraggle", into = "SerializedGraggle")]
 pub(crate) struct GraggleData {
-    nodes: Set<NodeId>,
-    deleted_nodes: Set<NodeId>,
-    edges: MMap<NodeId, Edge>,
-    back_edges: MMap<NodeId, Edge>,
+    // All the patches that are referred to by this graggle.
+    table: PatchTable,
+
+    nodes: Set<LocalId>,
+    deleted_nodes: Set<LocalId>,
+    edges: MMap<LocalId, LocalEdge>,
+    back_edges: MMap<LocalId, LocalEdge>,
 
     // A partition of all the deleted nodes into weakly connected components.
-    deleted_partition: Partition<NodeId>,
+    deleted_partition: Partition<LocalId>,
     // A map from pseudo-edges (the forward-pointing ones only) to the set of parts (identified by
     // their representative) that are responsible for the pseudo-edge.
-    pseudo_edge_reasons: MMap<(NodeId, NodeId), NodeId>,
+    pseudo_edge_reasons: MMap<(LocalId, LocalId), LocalId>,
     // A map from "reasons" (i.e. representatives of a partition) to edges that are there because
     // of that reason.
-    reason_pseudo_edges: MMap<NodeId, (NodeId, NodeId)>,
+    reason_pseudo_edges: MMap<LocalId, (LocalId, LocalId)>,
     // These are the component representatives whose components are dirty (i.e. we need to
     // recalculate the connectedness relation that they induce).
-    dirty_reps: Set<NodeId>,
+    dirty_reps: Set<LocalId>,
+    // A map from patches to the (real, i.e. not pseudo) edges that they introduced, as (src, dest)
+    // pairs.
+    patch_edges: MMap<PatchIdx, (LocalId, LocalId)>,
+    // For every node whose contents were replaced in place, the patches that replaced them. The
+    // last one determines the current contents, and the others are ordered by patch id (see
+    // `GraggleData::set_current_replacement`).
+    replacements: BTreeMap<LocalId, Vec<PatchIdx>>,
+    // For every node that some patch deleted, the patches that deleted it. The node is only
+    // actually deleted if those patches knew about all the replacements of its contents (which is
+    // for `Storage` to decide).
+    deleters: BTreeMap<LocalId, Set<PatchIdx>>,
 }
 
-// Two Graggles compare as equal if they have the same nodes and edges (including pseudo-edges). We
-// don't check the rest of the fields, as they are only there for optimization.
+// Two Graggles compare as equal if they have the same nodes and edges (including pseudo-edges), and
+// if the same patches replaced and deleted their nodes. We don't check the rest of the fields, as
+// they are only there for optimization.
 impl PartialEq<GraggleData> for GraggleData {
     fn eq(&self, other: &GraggleData) -> bool {
-        self.nodes.eq(&other.nodes)
-            && self.deleted_nodes.eq(&other.deleted_nodes)
-            && self.edges.eq(&other.edges)
-            && self.back_edges.eq(&other.back_edges)
+        if self.table == other.table {
+            return self.nodes.eq(&other.nodes)
+                && self.deleted_nodes.eq(&other.deleted_nodes)
+                && self.edges.eq(&other.edges)
+                && self.back_edges.eq(&other.back_edges)
+                && self.replacements.eq(&other.replacements)
+                && self.deleters.eq(&other.deleters);
+        }
+
+        // If the tables are different, the same nodes could have different local ids.
+        let nodes = |d: &GraggleData, set: &Set<LocalId>| {
+            set.iter().map(|u| d.table.node(*u)).collect::<Set<_>>()
+        };
+        let edges = |d: &GraggleData, map: &MMap<LocalId, LocalEdge>| {
+            map.iter()
+                .map(|(u, e)| (d.table.node(*u), d.table.edge(e)))
+                .collect::<Set<_>>()
+        };
+        let replacements = |d: &GraggleData| {
+            d.replacements
+                .iter()
+                .map(|(u, ps)| {
+                    let ps = ps.iter().map(|p| d.table.patch(*p)).collect::<Vec<_>>();
+                    (d.table.node(*u), ps)
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        let deleters = |d: &GraggleData| {
+            d.deleters
+                .iter()
+                .map(|(u, ps)| {
+                    let ps = ps.iter().map(|p| d.table.patch(*p)).collect::<Set<_>>();
+                    (d.table.node(*u), ps)
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        nodes(self, &self.nodes) == nodes(other, &other.nodes)
+            && nodes(self, &self.deleted_nodes) == nodes(other, &other.deleted_nodes)
+            && edges(self, &self.edges) == edges(other, &other.edges)
+            && edges(self, &self.back_edges) == edges(other, &other.back_edges)
+            && replacements(self) == replacements(other)
+            && deleters(self) == deleters(other)
     }
 }
 
+// The data in a `GraggleData` that can be recomputed from the rest (see
+// `GraggleData::rebuild_pseudo_edges`), in a form that can be compared between two graggles.
+#[derive(PartialEq)]
+struct DerivedData {
+    edges: MMap<LocalId, LocalEdge>,
+    back_edges: MMap<LocalId, LocalEdge>,
+    // Maps each node in `deleted_partition` to the smallest node in its part.
+    parts: BTreeMap<LocalId, LocalId>,
+    // Like the fields of `GraggleData` with the same names, but with the reasons identified by
+    // the smallest nodes in their parts (or `Err(reason)`, if the reason isn't a representative).
+    pseudo_edge_reasons: Set<((LocalId, LocalId), Result<LocalId, LocalId>)>,
+    reason_pseudo_edges: Set<(Result<LocalId, LocalId>, (LocalId, LocalId))>,
+}
+
 impl GraggleData {
     pub fn new() -> GraggleData {
         Default::default()
@@ -153,22 +196,35 @@ impl GraggleData {
         Graggle { data: self }
     }
 
-    pub fn all_out_edges<'b>(&'b self, node: &NodeId) -> impl Iterator<Item = &'b Edge> + 'b {
+    // Returns the local id of a node in this graggle.
+    //
+    // # Panics
+    //
+    // Panics if the node doesn't belong to this graggle.
+    fn local(&self, id: &NodeId) -> LocalId {
+        self.table
+            .local(id)
+            .filter(|u| self.nodes.contains(u) || self.deleted_nodes.contains(u))
+            .unwrap_or_else(|| panic!("unknown node {:?}", id))
+    }
+
+    fn all_out_edges<'b>(&'b self, node: &LocalId) -> impl Iterator<Item = &'b LocalEdge> + 'b {
         self.edges.get(node)
     }
 
-    pub fn all_in_edges<'b>(&'b self, node: &NodeId) -> impl Iterator<Item = &'b Edge> + 'b {
+    fn all_in_edges<'b>(&'b self, node: &LocalId) -> impl Iterator<Item = &'b LocalEdge> + 'b {
         self.back_edges.get(node)
     }
 
     pub fn add_node(&mut self, id: NodeId) {
+        let id = self.table.intern_node(id);
         self.nodes.insert(id);
     }
 
-    fn has_live_edge(&self, src: &NodeId, dest: &NodeId) -> bool {
+    fn has_live_edge(&self, src: &LocalId, dest: &LocalId) -> bool {
         // Construct the smallest (in the sense of Edge's order) edge that could possibly go from
         // src to dest.
-        let e = Edge::new_live(*dest, PatchId::cur());
+        let e = LocalEdge::new_live(*dest, PatchIdx::CUR);
         if let Some(actual_e) = self.edges.get_from(src, &e).next() {
             // actual_e is an edge going from src to something greater than or equal to dest.
             // There's an edge from src to dest if and only if actual_e goes to dest.
@@ -180,7 +236,7 @@ impl GraggleData {
 
     // We just deleted the pseudo-edge from src to dest. Clean up the corresponding entries in
     // pseudo_edge_reasons and reason_pseudo_edges.
-    fn remove_pseudo_edge_reasons(&mut self, src: &NodeId, dest: &NodeId) {
+    fn remove_pseudo_edge_reasons(&mut self, src: &LocalId, dest: &LocalId) {
         let reasons = self
             .pseudo_edge_reasons
             .get(&(*src, *dest))
@@ -194,9 +250,9 @@ impl GraggleData {
 
     // Deletes an edge (both forward and back), but does nothing else to ensure consistency and
     // maintain invariants.
-    fn internal_delete_edge(&mut self, src: &NodeId, edge: &Edge) {
+    fn internal_delete_edge(&mut self, src: &LocalId, edge: &LocalEdge) {
         self.edges.remove(src, edge);
-        let back_edge = Edge {
+        let back_edge = LocalEdge {
             dest: *src,
             // NOTE: This is not really correct: to get the right kind, we should really check
             // whether src is live. However, it still works because (assuming we resolve patch
@@ -208,9 +264,9 @@ impl GraggleData {
         self.back_edges.remove(&edge.dest, &back_edge);
     }
 
-    fn internal_delete_back_edge(&mut self, dest: &NodeId, back_edge: &Edge) {
+    fn internal_delete_back_edge(&mut self, dest: &LocalId, back_edge: &LocalEdge) {
         self.back_edges.remove(dest, back_edge);
-        let edge = Edge {
+        let edge = LocalEdge {
             dest: *dest,
             kind: back_edge.kind,
             patch: back_edge.patch,
@@ -222,6 +278,7 @@ impl GraggleData {
         // If we are unadding a node, it means we are unapplying the patch in which the node was
         // introduced. Since we must have already unapplied any reverse-dependencies of the patch,
         // the node must be live (it can't have been marked as deleted).
+        let id = &self.local(id);
         assert!(self.nodes.contains(id));
         self.nodes.remove(id);
 
@@ -247,19 +304,100 @@ impl GraggleData {
         // so no need to update them.
     }
 
+    /// Records that `patch` replaced the contents of `id`.
+    pub fn replace_contents(&mut self, id: &NodeId, patch: PatchId) {
+        let id = self.local(id);
+        let patch = self.table.intern(patch);
+        self.replacements.entry(id).or_default().push(patch);
+    }
+
+    /// Undoes [`GraggleData::replace_contents`].
+    pub fn unreplace_contents(&mut self, id: &NodeId, patch: PatchId) {
+        let (id, patch) = match (self.table.local(id), self.table.index(&patch)) {
+            (Some(id), Some(patch)) => (id, patch),
+            _ => return,
+        };
+        if let Some(patches) = self.replacements.get_mut(&id) {
+            patches.retain(|p| *p != patch);
+            if patches.is_empty() {
+                self.replacements.remove(&id);
+            }
+        }
+    }
+
+    /// Makes `patch` (which must have replaced the contents of `id`) the one that determines the
+    /// current contents of `id`.
+    pub fn set_current_replacement(&mut self, id: &NodeId, patch: PatchId) {
+        let table = &self.table;
+        let id = self.local(id);
+        let patch = table.index(&patch).unwrap();
+        if let Some(patches) = self.replacements.get_mut(&id) {
+            // The order of the others doesn't matter, but it's canonical so that graggles that
+            // were built in different orders compare as equal.
+            patches.sort_by_key(|p| (*p == patch, table.patch(*p)));
+        }
+    }
+
+    /// The patches that replaced the contents of `id`, the current one last.
+    pub fn replacers(&self, id: &NodeId) -> Vec<PatchId> {
+        self.table
+            .local(id)
+            .and_then(|u| self.replacements.get(&u))
+            .map(|ps| ps.iter().map(|p| self.table.patch(*p)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Records that `patch` deleted `id`, without actually deleting it (see
+    /// [`GraggleData::delete_node`]).
+    pub fn add_deleter(&mut self, id: &NodeId, patch: PatchId) {
+        let id = self.local(id);
+        let patch = self.table.intern(patch);
+        self.deleters.entry(id).or_default().insert(patch);
+    }
+
+    /// Undoes [`GraggleData::add_deleter`].
+    pub fn remove_deleter(&mut self, id: &NodeId, patch: PatchId) {
+        let (id, patch) = match (self.table.local(id), self.table.index(&patch)) {
+            (Some(id), Some(patch)) => (id, patch),
+            _ => return,
+        };
+        if let Some(patches) = self.deleters.get_mut(&id) {
+            patches.remove(&patch);
+            if patches.is_empty() {
+                self.deleters.remove(&id);
+            }
+        }
+    }
+
+    /// The patches that deleted `id`.
+    pub fn deleters(&self, id: &NodeId) -> Vec<PatchId> {
+        self.table
+            .local(id)
+            .and_then(|u| self.deleters.get(&u))
+            .map(|ps| ps.iter().map(|p| self.table.patch(*p)).collect())
+            .unwrap_or_default()
+    }
+
+    // Returns true if there are deleted nodes, but no record of which patches deleted them. This
+    // happens for graggles that were written by older versions.
+    pub fn missing_deleters(&self) -> bool {
+        !self.deleted_nodes.is_empty() && self.deleters.is_empty()
+    }
+
     /// Given a live node, marks it as deleted. That is, the node doesn't vanish; it turns into a
     /// tombstone.
     ///
     /// # Panics
     /// Panics if the node doesn't exist, or if exists but is not live.
     pub fn delete_node(&mut self, id: &NodeId) {
+        let id = &self.local(id);
         assert!(self.nodes.contains(id));
         self.nodes.remove(id);
-        self.deleted_nodes.insert(id.clone());
+        self.deleted_nodes.insert(*id);
         // It's possible that deleted_partition already contains this node (if pseudo-edges weren't
         // resolved recently).
-        if !self.deleted_partition.contains(id.clone()) {
-            self.deleted_partition.insert(id.clone());
+        if !self.deleted_partition.contains(*id) {
+            self.deleted_partition.insert(*id);
         }
 
         // All the edges (both forward and backwards) pointing towards the newly deleted node need
@@ -276,9 +414,10 @@ impl GraggleData {
     }
 
     pub fn undelete_node(&mut self, id: &NodeId) {
+        let id = &self.local(id);
         assert!(self.deleted_nodes.contains(id));
         self.deleted_nodes.remove(id);
-        self.nodes.insert(id.clone());
+        self.nodes.insert(*id);
 
         // All the edges (both forward and backwards) pointing towards the newly deleted node need
         // to be marked as live.
@@ -300,7 +439,12 @@ impl GraggleData {
 
     // The node `src` has just been deleted, and `edge` is an edge pointing out from it (either
     // forwards or backwards). We want to delete the edge pointing from edge.dest to src.
-    fn delete_opposite_edge(&mut self, src: &NodeId, edge: &Edge, edge_points_forwards: bool) {
+    fn delete_opposite_edge(
+        &mut self,
+        src: &LocalId,
+        edge: &LocalEdge,
+        edge_points_forwards: bool,
+    ) {
         // This is the edge_map that points in the opposite direction as `edge`.
         let opposite_edges = if edge_points_forwards {
             &mut self.back_edges
@@ -310,12 +454,12 @@ impl GraggleData {
 
         if edge.kind == EdgeKind::Pseudo {
             // Pseudo-edges don't get marked as deleted, they just get removed.
-            let opposite_edge = Edge::new_pseudo(*src);
+            let opposite_edge = LocalEdge::new_pseudo(*src);
             opposite_edges.remove(&edge.dest, &opposite_edge);
         } else {
             // To mark the edge as deleted, we actually remove it and then add it back in again
             // (because deleted edges appear in a different position in the map).
-            let mut opposite_edge = Edge::new_live(*src, edge.patch);
+            let mut opposite_edge = LocalEdge::new_live(*src, edge.patch);
             opposite_edges.remove(&edge.dest, &opposite_edge);
             opposite_edge.kind = EdgeKind::Deleted;
             opposite_edges.insert(edge.dest, opposite_edge);
@@ -329,7 +473,12 @@ impl GraggleData {
     }
 
     // The node `src` was just undeleted, and `edge` points out from `src`.
-    fn undelete_opposite_edge(&mut self, src: &NodeId, edge: &Edge, edge_points_forwards: bool) {
+    fn undelete_opposite_edge(
+        &mut self,
+        src: &LocalId,
+        edge: &LocalEdge,
+        edge_points_forwards: bool,
+    ) {
         // This is the edge_map that points in the opposite direction as `edge`.
         let opposite_edges = if edge_points_forwards {
             &mut self.back_edges
@@ -340,7 +489,7 @@ impl GraggleData {
         // Unlike `delete_opposite_edge`, there's no change of encountering a pseudo-edge pointing
         // from `edge.dest` to `src` (because `src` was just undeleted, and while it was deleted no
         // pseudo-edges pointed at it).
-        let mut opposite_edge = Edge::new_deleted(*src, edge.patch);
+        let mut opposite_edge = LocalEdge::new_deleted(*src, edge.patch);
         opposite_edges.remove(&edge.dest, &opposite_edge);
         opposite_edge.kind = EdgeKind::Live;
         opposite_edges.insert(edge.dest, opposite_edge);
@@ -353,7 +502,7 @@ impl GraggleData {
     // `id` and `other` are two deleted nodes that have just been connected by an edge. We need to
     // mark them as being in the same connected component of deleted nodes. This also entails
     // marking the merged component as dirty, and removing any obsolete pseudo-edges.
-    fn merge_components(&mut self, id1: &NodeId, id2: &NodeId) {
+    fn merge_components(&mut self, id1: &LocalId, id2: &LocalId) {
         let rep1 = self.deleted_partition.representative(*id1);
         let rep2 = self.deleted_partition.representative(*id2);
         self.deleted_partition.merge(rep1, rep2);
@@ -369,7 +518,7 @@ impl GraggleData {
 
     // `reason` was (and possibly still is) the representative of a component that got modified. We
     // can't trust any pseudo-edges coming from that component, so delete them all.
-    fn delete_obsolete_reason(&mut self, reason: &NodeId) {
+    fn delete_obsolete_reason(&mut self, reason: &LocalId) {
         let obsolete_pairs = self
             .reason_pseudo_edges
             .get(reason)
@@ -377,7 +526,7 @@ impl GraggleData {
             .collect::<Vec<_>>();
 
         for (src, dest) in obsolete_pairs {
-            let e = Edge::new_pseudo(dest);
+            let e = LocalEdge::new_pseudo(dest);
             self.pseudo_edge_reasons.remove(&(src, dest), reason);
             // If that was the last reason for the pseudo-edge, delete it.
             if self.pseudo_edge_reasons.get(&(src, dest)).next().is_none() {
@@ -388,22 +537,24 @@ impl GraggleData {
     }
 
     // Marks the component containing `id` as dirty.
-    fn mark_dirty(&mut self, id: &NodeId) {
+    fn mark_dirty(&mut self, id: &LocalId) {
         let rep = self.deleted_partition.representative(*id);
         self.delete_obsolete_reason(&rep);
         self.dirty_reps.insert(rep);
     }
 
     pub fn add_edge(&mut self, from: NodeId, to: NodeId, patch: PatchId) {
+        let from = self.local(&from);
+        let to = self.local(&to);
+        let patch = self.table.intern(patch);
         let from_deleted = !self.nodes.contains(&from);
         let to_deleted = !self.nodes.contains(&to);
-        assert!(!from_deleted || self.deleted_nodes.contains(&from));
-        assert!(!to_deleted || self.deleted_nodes.contains(&to));
 
         self.edges
-            .insert(from, Edge::new_real(to, to_deleted, patch));
+            .insert(from, LocalEdge::new_real(to, to_deleted, patch));
         self.back_edges
-            .insert(to, Edge::new_real(from, from_deleted, patch));
+            .insert(to, LocalEdge::new_real(from, from_deleted, patch));
+        self.patch_edges.insert(patch, (from, to));
 
         if from_deleted && to_deleted {
             self.merge_components(&from, &to);
@@ -415,15 +566,33 @@ impl GraggleData {
     }
 
     pub fn resolve_pseudo_edges(&mut self) {
+        self.resolve_pseudo_edges_with(|_, _| true);
+    }
+
+    /// Are there any changes that [`GraggleData::resolve_pseudo_edges`] hasn't handled yet?
+    pub fn needs_pseudo_edge_update(&self) -> bool {
+        !self.dirty_reps.is_empty()
+    }
+
+    /// Like [`GraggleData::resolve_pseudo_edges`], but reports on its progress.
+    ///
+    /// Before handling each of the `total` dirty components, this calls `progress(done, total)`,
+    /// and it calls `progress(total, total)` at the end (if there were no dirty components, it
+    /// doesn't call `progress` at all). If `progress` returns `false`, this stops and returns
+    /// `false`; the components that weren't handled yet stay dirty, so it's safe to try again
+    /// later.
+    pub fn resolve_pseudo_edges_with<F>(&mut self, mut progress: F) -> bool
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
         let mut dirty_reps = Set::new();
         std::mem::swap(&mut dirty_reps, &mut self.dirty_reps);
 
         // Each partition represented by a dirty rep needs to be rechecked, because it's possible
         // that it actually encompasses multiple connected components in the new graggle.
-        let graggle = self.as_graggle();
-        let graph = graggle.as_full_graph();
+        let graph = LocalGraph(self);
         let sub_graph = graph.node_filtered(|u| {
-            !graggle.is_live(u) && dirty_reps.contains(&self.deleted_partition.representative(*u))
+            !self.is_live(u) && dirty_reps.contains(&self.deleted_partition.representative(*u))
         });
         let components = sub_graph.weak_components().into_parts();
 
@@ -445,25 +614,126 @@ impl GraggleData {
         }
 
         // Add in the required pseudo-edges and fix up the partition.
-        for component in components {
+        let total = components.len();
+        let mut components = components.into_iter().enumerate();
+        while let Some((done, component)) = components.next() {
+            if !progress(done, total) {
+                // We haven't added any pseudo-edges for these components, so it's enough to mark
+                // them as dirty.
+                for component in std::iter::once(component).chain(components.map(|(_, c)| c)) {
+                    // The unwrap is ok because the components are non-empty.
+                    let u = component.iter().next().unwrap();
+                    self.dirty_reps
+                        .insert(self.deleted_partition.representative(*u));
+                }
+                return false;
+            }
             self.add_component_pseudo_edges(&component);
         }
+        if total > 0 {
+            progress(total, total);
+        }
+        true
+    }
+
+    /// Throws away all of the pseudo-edges (together with the records of why they were added) and
+    /// recomputes them from scratch. Returns `true` if this changed any pseudo-edges.
+    ///
+    /// The pseudo-edges are normally kept up-to-date incrementally, so this should never be
+    /// necessary. It exists as a way to recover if they somehow get corrupted.
+    pub fn rebuild_pseudo_edges(&mut self) -> bool {
+        let old = self.derived_data();
+
+        let pseudo = |(u, e): (&LocalId, &LocalEdge)| {
+            if e.kind == EdgeKind::Pseudo {
+                Some((*u, *e))
+            } else {
+                None
+            }
+        };
+        let pseudo_edges = self.edges.iter().filter_map(pseudo).collect::<Vec<_>>();
+        let pseudo_back_edges = self
+            .back_edges
+            .iter()
+            .filter_map(pseudo)
+            .collect::<Vec<_>>();
+        // We don't use `internal_delete_edge`, because a corrupted graggle might have forward
+        // pseudo-edges without the corresponding back edges (or vice versa).
+        for (u, e) in pseudo_edges {
+            self.edges.remove(&u, &e);
+        }
+        for (u, e) in pseudo_back_edges {
+            self.back_edges.remove(&u, &e);
+        }
+        self.pseudo_edge_reasons = MMap::new();
+        self.reason_pseudo_edges = MMap::new();
+
+        // Start off with every deleted node in its own part, and all of them dirty. Then resolving
+        // the pseudo-edges will figure out the connected components.
+        self.deleted_partition = Partition::new();
+        for u in &self.deleted_nodes {
+            self.deleted_partition.insert(*u);
+        }
+        self.dirty_reps = self.deleted_nodes.clone();
+        self.resolve_pseudo_edges();
+
+        self.derived_data() != old
+    }
+
+    // Collects everything that `rebuild_pseudo_edges` recomputes.
+    fn derived_data(&self) -> DerivedData {
+        // The representative of a part depends on the order that it was built in, so we identify
+        // parts by their smallest elements instead.
+        let mut parts = BTreeMap::new();
+        let mut smallest = BTreeMap::new();
+        for part in self.deleted_partition.iter_parts() {
+            let part = part.collect::<Vec<_>>();
+            if let Some(&min) = part.iter().min() {
+                smallest.insert(self.deleted_partition.representative(min), min);
+                parts.extend(part.into_iter().map(|u| (u, min)));
+            }
+        }
+        // Reasons should always be representatives, but a corrupted graggle might have others.
+        let reason = |u: &LocalId| smallest.get(u).copied().ok_or(*u);
+        DerivedData {
+            edges: self.edges.clone(),
+            back_edges: self.back_edges.clone(),
+            pseudo_edge_reasons: self
+                .pseudo_edge_reasons
+                .iter()
+                .map(|(edge, r)| (*edge, reason(r)))
+                .collect(),
+            reason_pseudo_edges: self
+                .reason_pseudo_edges
+                .iter()
+                .map(|(r, edge)| (reason(r), *edge))
+                .collect(),
+            parts,
+        }
     }
 
     /// # Panics
     ///
     /// Panics unless `from` and `to` are nodes in this graggle. In particular, if you're planning to
     /// remove some nodes and the edge between them, you need to remove the edge first.
+    #[cfg(test)]
     pub fn unadd_edge(&mut self, from: &NodeId, to: &NodeId, patch: PatchId) {
-        let from_deleted = self.deleted_nodes.contains(&from);
-        let to_deleted = self.deleted_nodes.contains(&to);
-        assert!(from_deleted || self.nodes.contains(&from));
-        assert!(to_deleted || self.nodes.contains(&to));
+        let from = &self.local(from);
+        let to = &self.local(to);
+        if let Some(patch) = self.table.index(&patch) {
+            self.unadd_local_edge(from, to, patch);
+        }
+    }
+
+    fn unadd_local_edge(&mut self, from: &LocalId, to: &LocalId, patch: PatchIdx) {
+        let from_deleted = self.deleted_nodes.contains(from);
+        let to_deleted = self.deleted_nodes.contains(to);
 
-        let forward_edge = Edge::new_real(*to, to_deleted, patch);
-        let back_edge = Edge::new_real(*from, from_deleted, patch);
-        self.edges.remove(&from, &forward_edge);
-        self.back_edges.remove(&to, &back_edge);
+        let forward_edge = LocalEdge::new_real(*to, to_deleted, patch);
+        let back_edge = LocalEdge::new_real(*from, from_deleted, patch);
+        self.edges.remove(from, &forward_edge);
+        self.back_edges.remove(to, &back_edge);
+        self.patch_edges.remove(&patch, &(*from, *to));
 
         if from_deleted {
             self.mark_dirty(from);
@@ -473,12 +743,26 @@ impl GraggleData {
         }
     }
 
+    /// Unadds all of the edges that were introduced by `patch`.
+    ///
+    /// This is equivalent to calling [`GraggleData::unadd_edge`] on every edge that `patch` added,
+    /// but it doesn't require knowing what those edges were.
+    pub fn unadd_patch_edges(&mut self, patch: PatchId) {
+        let patch = match self.table.index(&patch) {
+            Some(patch) => patch,
+            None => return,
+        };
+        let edges = self.patch_edges.get(&patch).cloned().collect::<Vec<_>>();
+        for (from, to) in edges {
+            self.unadd_local_edge(&from, &to, patch);
+        }
+    }
+
     // Adds all the pseudo-edges that are induced by a single connected component of deleted nodes.
     //
     // `component` must be a non-empty connected component of the deleted nodes.
-    fn add_component_pseudo_edges(&mut self, component: &HashSet<NodeId>) {
-        let graggle = self.as_graggle();
-        let graph = graggle.as_full_graph();
+    fn add_component_pseudo_edges(&mut self, component: &HashSet<LocalId>) {
+        let graph = LocalGraph(self);
         let mut neighborhood = graph.neighbor_set(component.iter());
         neighborhood.extend(component.iter().cloned());
 
@@ -492,7 +776,7 @@ impl GraggleData {
         // component of deleted nodes. We will compute the complete connectivity relation that
         // the deleted nodes induce on these boundary nodes, and then we will add a pseudo-edge
         // for each connected pair.
-        let boundary = neighborhood.iter().filter(|u| graggle.is_live(u));
+        let boundary = neighborhood.iter().filter(|u| self.is_live(u));
 
         let mut pairs = Vec::new();
         for u in boundary {
@@ -503,7 +787,7 @@ impl GraggleData {
                 if let ojo_graph::dfs::Visit::Edge { dst, status, .. } = visit {
                     // Only take into account the first visit to a node. Besides being more
                     // efficient, this means we'll avoid adding self-loops.
-                    if status == ojo_graph::dfs::Status::New && graggle.is_live(&dst) {
+                    if status == ojo_graph::dfs::Status::New && self.is_live(&dst) {
                         pairs.push((*u, dst));
                     }
                 }
@@ -512,49 +796,71 @@ impl GraggleData {
         for (src, dest) in pairs {
             // Only add a pseudo-edge if there is not already an edge present.
             if !self.has_live_edge(&src, &dest) {
-                self.edges.insert(src, Edge::new_pseudo(dest));
-                self.back_edges.insert(dest, Edge::new_pseudo(src));
+                self.edges.insert(src, LocalEdge::new_pseudo(dest));
+                self.back_edges.insert(dest, LocalEdge::new_pseudo(src));
                 self.pseudo_edge_reasons.insert((src, dest), rep);
                 self.reason_pseudo_edges.insert(rep, (src, dest));
             }
         }
     }
 
-    fn is_live(&self, node: &NodeId) -> bool {
+    fn is_live(&self, node: &LocalId) -> bool {
         self.nodes.contains(node)
     }
 
     // Brute-force compute the pseudo-edges that should start at node u.
-    fn pseudo_edges(&self, u: &NodeId) -> HashSet<NodeId> {
+    fn pseudo_edges(&self, u: &LocalId) -> HashSet<LocalId> {
         use ojo_graph::dfs::{Status, Visit};
 
         let mut ret = HashSet::new();
         // Pseudo-edges that should start at u are those that can be reached from u by ignoring
         // other pseudo-edges, and only going through deleted intermediate edges. This latter
-        // property can be enforced by only traversing edges that either go from u to a deleted
-        // node or else start at a deleted node.
-        let graph = self.as_graggle().as_full_graph();
-        let u_graph = graph.edge_filtered(|src, edge| {
-            edge.kind != EdgeKind::Pseudo
-                && ((src == u && !self.is_live(&edge.dest)) || !self.is_live(src))
-        });
-        for visit in u_graph.dfs_from(u) {
-            if let Visit::Edge { dst, status, .. } = visit {
-                if status == Status::New
-                    && dst != *u
-                    && self.is_live(&dst)
-                    && !self.has_live_edge(u, &dst)
-                {
-                    ret.insert(dst);
+        // property can be enforced by not exploring past any live node other than u.
+        let graph = LocalGraph(self);
+        let real_graph = graph.label_filtered(|kind| *kind != EdgeKind::Pseudo);
+        let mut dfs = real_graph.dfs_from(u);
+        while let Some(visit) = dfs.next() {
+            if let Visit::Edge {
+                dst,
+                status: Status::New,
+                ..
+            } = visit
+            {
+                if self.is_live(&dst) {
+                    dfs.prune();
+                    if !self.has_live_edge(u, &dst) {
+                        ret.insert(dst);
+                    }
                 }
             }
         }
         ret
     }
 
+    #[cfg(test)]
     pub fn assert_consistent(&self) {
+        if let Err(e) = self.check_consistency(true) {
+            panic!("inconsistent graggle: {}", e);
+        }
+    }
+
+    // Checks the internal invariants of this graggle, returning a description of the first one
+    // that fails. Unless `thorough` is true, we skip the expensive check that every pseudo-edge is
+    // correct.
+    pub fn check_consistency(&self, thorough: bool) -> Result<(), String> {
+        macro_rules! check {
+            ($cond:expr, $($msg:tt)+) => {
+                if !$cond {
+                    return Err(format!($($msg)+));
+                }
+            };
+        }
+
         // The live and deleted nodes should be disjoint.
-        assert!(self.nodes.is_disjoint(&self.deleted_nodes));
+        check!(
+            self.nodes.is_disjoint(&self.deleted_nodes),
+            "some nodes are both live and deleted"
+        );
 
         let node_exists = |id| self.nodes.contains(id) || self.deleted_nodes.contains(id);
         // The source and destination of every edge should exist somewhere, and they should not be
@@ -563,15 +869,21 @@ impl GraggleData {
         // There should be a one-to-one correspondence between edges and back_edges.
         let mut seen_back_edges = HashSet::new();
         for (src, edge) in self.edges.iter() {
-            assert!(node_exists(src));
-            assert!(node_exists(&edge.dest));
-            assert!(src != &edge.dest);
-            assert_eq!(
-                self.deleted_nodes.contains(&edge.dest),
-                edge.kind == EdgeKind::Deleted
+            check!(node_exists(src), "edge from missing node {:?}", src);
+            check!(
+                node_exists(&edge.dest),
+                "edge to missing node {:?}",
+                edge.dest
+            );
+            check!(src != &edge.dest, "self-loop at {:?}", src);
+            check!(
+                self.deleted_nodes.contains(&edge.dest) == (edge.kind == EdgeKind::Deleted),
+                "edge {:?} -> {:?} has the wrong kind",
+                src,
+                edge.dest
             );
 
-            let back_edge = Edge {
+            let back_edge = LocalEdge {
                 dest: *src,
                 kind: if edge.kind == EdgeKind::Pseudo {
                     EdgeKind::Pseudo
@@ -580,60 +892,162 @@ impl GraggleData {
                 },
                 patch: edge.patch,
             };
-            assert!(self.back_edges.contains(&edge.dest, &back_edge));
+            check!(
+                self.back_edges.contains(&edge.dest, &back_edge),
+                "edge {:?} -> {:?} has no back edge",
+                src,
+                edge.dest
+            );
             seen_back_edges.insert((edge.dest, back_edge));
         }
         // We've checked that every forward edge corresponds to a backward edge; now check that
         // every backward edge was encountered in this way.
         for (src, back_edge) in self.back_edges.iter() {
-            assert!(seen_back_edges.contains(&(*src, *back_edge)));
+            check!(
+                seen_back_edges.contains(&(*src, *back_edge)),
+                "back edge {:?} -> {:?} has no forward edge",
+                src,
+                back_edge.dest
+            );
+        }
+
+        // Every real edge should be indexed by the patch that introduced it, and every indexed edge
+        // should exist.
+        for (src, edge) in self.edges.iter() {
+            if edge.kind != EdgeKind::Pseudo {
+                check!(
+                    self.patch_edges.contains(&edge.patch, &(*src, edge.dest)),
+                    "edge {:?} -> {:?} isn't indexed by its patch",
+                    src,
+                    edge.dest
+                );
+            }
+        }
+        for (patch, &(src, dest)) in self.patch_edges.iter() {
+            let to_deleted = self.deleted_nodes.contains(&dest);
+            check!(
+                self.edges
+                    .contains(&src, &LocalEdge::new_real(dest, to_deleted, *patch)),
+                "indexed edge {:?} -> {:?} doesn't exist",
+                src,
+                dest
+            );
+        }
+
+        // Only nodes that exist can have their contents replaced.
+        for (id, patches) in &self.replacements {
+            check!(
+                node_exists(id),
+                "replaced contents of missing node {:?}",
+                id
+            );
+            check!(!patches.is_empty(), "empty replacements for node {:?}", id);
+        }
+        for (id, patches) in &self.deleters {
+            check!(node_exists(id), "deleters of missing node {:?}", id);
+            check!(!patches.is_empty(), "empty deleters for node {:?}", id);
         }
 
         // The deleted partition should contain all of the deleted nodes (if the pseudo-edges
         // haven't been resolved yet, it may also contain nodes that have been undeleted).
         for u in &self.deleted_nodes {
-            assert!(self.deleted_partition.contains(*u));
+            check!(
+                self.deleted_partition.contains(*u),
+                "deleted node {:?} isn't in the deleted partition",
+                u
+            );
         }
 
         // If the pseudo-edges are up-to-date, there are some additional checks we can do.
         if self.dirty_reps.is_empty() {
             // Everything in the deleted partition should be a deleted node.
             for u in self.deleted_partition.iter_parts().flat_map(|p| p) {
-                assert!(self.deleted_nodes.contains(&u));
+                check!(
+                    self.deleted_nodes.contains(&u),
+                    "live node {:?} is in the deleted partition",
+                    u
+                );
             }
 
             // Every pseudo-edge should have at least one reason.
             for (src, edge) in self.edges.iter() {
                 if edge.kind == EdgeKind::Pseudo {
-                    assert!(self
-                        .pseudo_edge_reasons
-                        .get(&(*src, edge.dest))
-                        .next()
-                        .is_some());
+                    check!(
+                        self.pseudo_edge_reasons
+                            .get(&(*src, edge.dest))
+                            .next()
+                            .is_some(),
+                        "pseudo-edge {:?} -> {:?} has no reason",
+                        src,
+                        edge.dest
+                    );
                 }
             }
 
             // Every reason should correspond to a pseudo-edge.
             for (&(src, dest), _) in self.pseudo_edge_reasons.iter() {
-                assert!(self.edges.contains(&src, &Edge::new_pseudo(dest)));
+                check!(
+                    self.edges.contains(&src, &LocalEdge::new_pseudo(dest)),
+                    "missing pseudo-edge {:?} -> {:?}",
+                    src,
+                    dest
+                );
             }
 
             // Every reason should be a representative in the partition.
             for (reason, _) in self.reason_pseudo_edges.iter() {
-                assert!(self.deleted_partition.is_rep(reason));
+                check!(
+                    self.deleted_partition.is_rep(reason),
+                    "pseudo-edge reason {:?} isn't a representative",
+                    reason
+                );
             }
 
             // Check that the pseudo-edges are correct.
-            for u in &self.nodes {
-                let correct_pseudo_edges = self.pseudo_edges(u);
-                let actual_pseudo_edges = self
-                    .all_out_edges(u)
-                    .filter(|e| e.kind == EdgeKind::Pseudo)
-                    .map(|e| e.dest)
-                    .collect::<HashSet<_>>();
-                assert_eq!(correct_pseudo_edges, actual_pseudo_edges);
+            if thorough {
+                for u in &self.nodes {
+                    let correct_pseudo_edges = self.pseudo_edges(u);
+                    let actual_pseudo_edges = self
+                        .all_out_edges(u)
+                        .filter(|e| e.kind == EdgeKind::Pseudo)
+                        .map(|e| e.dest)
+                        .collect::<HashSet<_>>();
+                    check!(
+                        correct_pseudo_edges == actual_pseudo_edges,
+                        "wrong pseudo-edges from {:?}: expected {:?}, found {:?}",
+                        u,
+                        correct_pseudo_edges,
+                        actual_pseudo_edges
+                    );
+                }
             }
         }
+        Ok(())
+    }
+}
+
+// The whole graggle (including deleted nodes) as a graph on local ids. This is what we use for
+// computing pseudo-edges, because it avoids translating ids back and forth.
+struct LocalGraph<'a>(&'a GraggleData);
+
+impl<'a> ojo_graph::Graph for LocalGraph<'a> {
+    type Node = LocalId;
+    type Edge = LocalEdge;
+
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = Self::Node> + use<'a, 'b> {
+        self.0
+            .nodes
+            .iter()
+            .chain(self.0.deleted_nodes.iter())
+            .cloned()
+    }
+
+    fn out_edges<'b>(&'b self, u: &LocalId) -> impl Iterator<Item = Self::Edge> + use<'a, 'b> {
+        self.0.edges.get(u).cloned()
+    }
+
+    fn in_edges<'b>(&'b self, u: &LocalId) -> impl Iterator<Item = Self::Edge> + use<'a, 'b> {
+        self.0.back_edges.get(u).cloned()
     }
 }
 
@@ -658,48 +1072,213 @@ pub struct Graggle<'a> {
 }
 
 impl<'a> Graggle<'a> {
+    // Returns all the edges in `map` that start at `node`.
+    fn local_edges(
+        self,
+        map: &'a MMap<LocalId, LocalEdge>,
+        node: &NodeId,
+    ) -> impl Iterator<Item = &'a LocalEdge> + 'a {
+        self.data
+            .table
+            .local(node)
+            .map(|u| map.get(&u))
+            .into_iter()
+            .flatten()
+    }
+
+    // The order of local ids depends on the order in which patches were added to the table, so we
+    // sort everything by the public ids before returning it. That way, the order only depends on
+    // the contents of the graggle.
+    fn edges(self, edges: impl Iterator<Item = &'a LocalEdge> + 'a) -> impl Iterator<Item = Edge> {
+        let mut ret = edges
+            .map(move |e| self.data.table.edge(e))
+            .collect::<Vec<_>>();
+        ret.sort();
+        ret.into_iter()
+    }
+
+    // Returns the destinations of `edges`, in the same order as `Graggle::edges`.
+    fn dests(
+        self,
+        edges: impl Iterator<Item = &'a LocalEdge> + 'a,
+    ) -> impl Iterator<Item = NodeId> {
+        self.edges(edges).map(|e| e.dest)
+    }
+
+    fn sorted_nodes(self, nodes: &'a Set<LocalId>) -> impl Iterator<Item = NodeId> {
+        let mut ret = nodes
+            .iter()
+            .map(|u| self.data.table.node(*u))
+            .collect::<Vec<_>>();
+        ret.sort();
+        ret.into_iter()
+    }
+
     /// Returns an iterator over all live nodes of this graggle.
     pub fn nodes(self) -> impl Iterator<Item = NodeId> + 'a {
-        self.data.nodes.iter().cloned()
+        self.sorted_nodes(&self.data.nodes)
     }
 
     /// Returns an iterator over all edges pointing from `node` to another live node.
-    pub fn out_edges(self, node: &NodeId) -> impl Iterator<Item = &'a Edge> + 'a {
-        self.data.edges.get(node).take_while(|e| e.not_deleted())
+    pub fn out_edges(self, node: &NodeId) -> impl Iterator<Item = Edge> + 'a {
+        self.edges(self.live_out_edges(node))
+    }
+
+    fn live_out_edges(self, node: &NodeId) -> impl Iterator<Item = &'a LocalEdge> + 'a {
+        self.local_edges(&self.data.edges, node)
+            .take_while(|e| e.not_deleted())
+    }
+
+    fn live_in_edges(self, node: &NodeId) -> impl Iterator<Item = &'a LocalEdge> + 'a {
+        self.local_edges(&self.data.back_edges, node)
+            .take_while(|e| e.not_deleted())
     }
 
     /// Returns an iterator over all live out-neighbors of `node`.
-    pub fn out_neighbors(self, node: &NodeId) -> impl Iterator<Item = &'a NodeId> + 'a {
-        self.out_edges(node).map(|e| &e.dest)
+    pub fn out_neighbors(self, node: &NodeId) -> impl Iterator<Item = NodeId> + 'a {
+        self.dests(self.live_out_edges(node))
     }
 
     /// Returns an iterator over all live in-neighbors of `node`.
-    pub fn in_neighbors(self, node: &NodeId) -> impl Iterator<Item = &'a NodeId> + 'a {
-        self.in_edges(node).map(|e| &e.dest)
+    pub fn in_neighbors(self, node: &NodeId) -> impl Iterator<Item = NodeId> + 'a {
+        self.dests(self.live_in_edges(node))
+    }
+
+    /// Returns an iterator over all live out-neighbors of `node` that are connected to it by a
+    /// real edge.
+    ///
+    /// Unlike [`Graggle::out_neighbors`], this skips the neighbors that are only reachable through
+    /// a pseudo-edge.
+    pub fn live_out_neighbors(self, node: &NodeId) -> impl Iterator<Item = NodeId> + 'a {
+        self.dests(
+            self.live_out_edges(node)
+                .filter(|e| e.kind == EdgeKind::Live),
+        )
+    }
+
+    /// Returns an iterator over all live in-neighbors of `node` that are connected to it by a
+    /// real edge.
+    ///
+    /// Unlike [`Graggle::in_neighbors`], this skips the neighbors that are only reachable through
+    /// a pseudo-edge.
+    pub fn live_in_neighbors(self, node: &NodeId) -> impl Iterator<Item = NodeId> + 'a {
+        self.dests(
+            self.live_in_edges(node)
+                .filter(|e| e.kind == EdgeKind::Live),
+        )
+    }
+
+    /// Returns an iterator over all deleted nodes that are adjacent to `node`, whether as
+    /// in-neighbors or out-neighbors.
+    ///
+    /// A node that is both an in-neighbor and an out-neighbor of `node` will be returned twice.
+    pub fn deleted_neighbors(self, node: &NodeId) -> impl Iterator<Item = NodeId> + 'a {
+        let deleted = self
+            .local_edges(&self.data.edges, node)
+            .skip_while(|e| e.not_deleted())
+            .chain(
+                self.local_edges(&self.data.back_edges, node)
+                    .skip_while(|e| e.not_deleted()),
+            );
+        self.dests(deleted)
+    }
+
+    /// Returns the number of edges (in either direction) connecting `node` to live nodes.
+    ///
+    /// This includes pseudo-edges, so it agrees with the degree of `node` in
+    /// [`Graggle::as_live_graph`].
+    pub fn degree(self, node: &NodeId) -> usize {
+        self.live_out_edges(node).count() + self.live_in_edges(node).count()
+    }
+
+    /// Returns an iterator over all the patches that introduced an edge from `src` to `dest`.
+    ///
+    /// Usually there will be at most one such patch, but it is possible for several patches to
+    /// introduce the same edge. Pseudo-edges are not introduced by any patch, so they are ignored.
+    pub fn edge_patches(self, src: &NodeId, dest: &NodeId) -> impl Iterator<Item = PatchId> + 'a {
+        let table = &self.data.table;
+        let src_dest = table.local(src).and_then(|s| Some((s, table.local(dest)?)));
+        // Edges are ordered first by kind and then by destination, so the edges from `src` to
+        // `dest` form a contiguous range (or two, because the edge could be live or deleted).
+        let range = move |kind| {
+            src_dest
+                .map(|(src, dest)| {
+                    let start = LocalEdge {
+                        kind,
+                        dest,
+                        patch: PatchIdx::CUR,
+                    };
+                    self.data
+                        .edges
+                        .get_from(&src, &start)
+                        .take_while(move |e| e.kind == kind && e.dest == dest)
+                        .map(move |e| table.patch(e.patch))
+                })
+                .into_iter()
+                .flatten()
+        };
+        range(EdgeKind::Live).chain(range(EdgeKind::Deleted))
+    }
+
+    /// Returns an iterator over all the edges that were introduced by the given patch, as
+    /// `(source, destination)` pairs.
+    ///
+    /// Only patches that are applied to this graggle will have any edges. The returned edges may
+    /// point to (or from) deleted nodes.
+    pub fn edges_introduced_by(
+        self,
+        patch: &PatchId,
+    ) -> impl Iterator<Item = (NodeId, NodeId)> + 'a {
+        let table = &self.data.table;
+        table
+            .index(patch)
+            .map(|p| self.data.patch_edges.get(&p))
+            .into_iter()
+            .flatten()
+            .map(move |&(u, v)| (table.node(u), table.node(v)))
     }
 
     /// Returns an iterator over all edges pointing out of `node`, including those that point to
     /// deleted edges.
-    pub fn all_out_edges(self, node: &NodeId) -> impl Iterator<Item = &'a Edge> + 'a {
-        self.data.edges.get(node)
+    pub fn all_out_edges(self, node: &NodeId) -> impl Iterator<Item = Edge> + 'a {
+        self.edges(self.local_edges(&self.data.edges, node))
     }
     /// Returns an iterator over all backwards edges pointing from `node` to another live node.
-    pub fn in_edges(self, node: &NodeId) -> impl Iterator<Item = &'a Edge> + 'a {
-        self.data
-            .back_edges
-            .get(node)
-            .take_while(|e| e.not_deleted())
+    pub fn in_edges(self, node: &NodeId) -> impl Iterator<Item = Edge> + 'a {
+        self.edges(self.live_in_edges(node))
     }
 
     /// Returns an iterator over all backwards edges pointing out of `node`, including those that
     /// point to deleted edges.
-    pub fn all_in_edges(self, node: &NodeId) -> impl Iterator<Item = &'a Edge> + 'a {
-        self.data.back_edges.get(node)
+    pub fn all_in_edges(self, node: &NodeId) -> impl Iterator<Item = Edge> + 'a {
+        self.edges(self.local_edges(&self.data.back_edges, node))
     }
 
     /// Returns `true` if `node` belongs to this graggle (whether it is live or deleted).
     pub fn has_node(self, node: &NodeId) -> bool {
-        self.data.nodes.contains(node) || self.data.deleted_nodes.contains(node)
+        self.data
+            .table
+            .local(node)
+            .map(|u| self.data.nodes.contains(&u) || self.data.deleted_nodes.contains(&u))
+            .unwrap_or(false)
+    }
+
+    /// If the contents of `node` were replaced in place, returns the patch that provided its
+    /// current contents.
+    pub fn contents_patch(self, node: &NodeId) -> Option<PatchId> {
+        let table = &self.data.table;
+        let u = table.local(node)?;
+        self.data
+            .replacements
+            .get(&u)
+            .and_then(|patches| patches.last())
+            .map(|p| table.patch(*p))
+    }
+
+    /// Returns all the patches that replaced the contents of `node` in place, the one that provided
+    /// its current contents (see [`Graggle::contents_patch`]) last.
+    pub fn replacing_patches(self, node: &NodeId) -> Vec<PatchId> {
+        self.data.replacers(node)
     }
 
     /// Returns `true` if `node` is live.
@@ -708,8 +1287,13 @@ impl<'a> Graggle<'a> {
     ///
     /// Panics unless `node` belongs to this graggle.
     pub fn is_live(self, node: &NodeId) -> bool {
-        assert!(self.has_node(node));
-        self.data.nodes.contains(node)
+        self.data.nodes.contains(&self.data.local(node))
+    }
+
+    /// Summarizes the cycles and unordered regions that stop the live nodes of this graggle from
+    /// forming a file. If they already form a file, this is [`Disorder::default`].
+    pub fn disorder(self) -> Disorder {
+        Disorder::of(&self.as_live_graph())
     }
 
     /// Wraps `self` in [`LiveGraph`], which implements [`graph::Graph`] over the live nodes of
@@ -741,16 +1325,16 @@ impl<'a> ojo_graph::Graph for LiveGraph<'a> {
     type Node = NodeId;
     type Edge = Edge;
 
-    fn nodes<'b>(&'b self) -> Box<dyn Iterator<Item = Self::Node> + 'b> {
-        Box::new(self.0.data.nodes.iter().cloned())
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = Self::Node> + use<'a, 'b> {
+        self.0.nodes()
     }
 
-    fn out_edges<'b>(&'b self, u: &NodeId) -> Box<dyn Iterator<Item = Self::Edge> + 'b> {
-        Box::new(self.0.out_edges(u).cloned())
+    fn out_edges<'b>(&'b self, u: &NodeId) -> impl Iterator<Item = Self::Edge> + use<'a, 'b> {
+        self.0.out_edges(u)
     }
 
-    fn in_edges<'b>(&'b self, u: &NodeId) -> Box<dyn Iterator<Item = Self::Edge> + 'b> {
-        Box::new(self.0.in_edges(u).cloned())
+    fn in_edges<'b>(&'b self, u: &NodeId) -> impl Iterator<Item = Self::Edge> + use<'a, 'b> {
+        self.0.in_edges(u)
     }
 }
 
@@ -764,23 +1348,18 @@ impl<'a> ojo_graph::Graph for FullGraph<'a> {
     type Node = NodeId;
     type Edge = Edge;
 
-    fn nodes<'b>(&'b self) -> Box<dyn Iterator<Item = Self::Node> + 'b> {
-        Box::new(
-            self.0
-                .data
-                .nodes
-                .iter()
-                .chain(self.0.data.deleted_nodes.iter())
-                .cloned(),
-        )
+    fn nodes<'b>(&'b self) -> impl Iterator<Item = Self::Node> + use<'a, 'b> {
+        self.0
+            .nodes()
+            .chain(self.0.sorted_nodes(&self.0.data.deleted_nodes))
     }
 
-    fn out_edges<'b>(&'b self, u: &NodeId) -> Box<dyn Iterator<Item = Self::Edge> + 'b> {
-        Box::new(self.0.all_out_edges(u).cloned())
+    fn out_edges<'b>(&'b self, u: &NodeId) -> impl Iterator<Item = Self::Edge> + use<'a, 'b> {
+        self.0.all_out_edges(u)
     }
 
-    fn in_edges<'b>(&'b self, u: &NodeId) -> Box<dyn Iterator<Item = Self::Edge> + 'b> {
-        Box::new(self.0.all_in_edges(u).cloned())
+    fn in_edges<'b>(&'b self, u: &NodeId) -> impl Iterator<Item = Self::Edge> + use<'a, 'b> {
+        self.0.all_in_edges(u)
     }
 }
 
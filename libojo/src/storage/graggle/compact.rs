@@ -0,0 +1,362 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+// Every `NodeId` contains the full hash of the patch that introduced it, which makes it 40 bytes
+// long (and an `Edge` contains two hashes, making it even longer). Since a graggle only ever
+// refers to a few patches, it's much cheaper to store each hash once, in a `PatchTable`, and to
+// refer to patches by their index in the table. This module contains the compact versions of
+// nodes and edges that `GraggleData` uses internally, and the translations between them and the
+// public versions.
+//
+// Note that the order of the compact versions depends on the order in which patches were added to
+// the table, and so on the history of the graggle. Anything that `Graggle` returns in order is
+// sorted by the public versions first.
+
+use ojo_multimap::MMap;
+use ojo_partition::Partition;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet as Set;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::{Edge, EdgeKind, GraggleData};
+use crate::{Error, NodeId, PatchId};
+
+// The index of a patch in a `PatchTable`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct PatchIdx(u32);
+
+impl PatchIdx {
+    // The index of `PatchId::cur`, which belongs to every table. Since it's the smallest index,
+    // it can be used for finding the smallest edge with a given kind and destination.
+    pub const CUR: PatchIdx = PatchIdx(0);
+}
+
+// A `NodeId` whose patch is given by its index in a `PatchTable`. This takes 8 bytes instead of
+// 40.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct LocalId {
+    pub patch: PatchIdx,
+    pub node: u32,
+}
+
+// An `Edge` whose destination and patch are given by their indices in a `PatchTable`. This takes
+// 16 bytes instead of 80.
+//
+// Like `Edge`, these are ordered first by kind, so that the deleted edges come last.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct LocalEdge {
+    pub kind: EdgeKind,
+    pub dest: LocalId,
+    pub patch: PatchIdx,
+}
+
+impl LocalEdge {
+    pub fn not_deleted(&self) -> bool {
+        self.kind != EdgeKind::Deleted
+    }
+
+    pub fn new_pseudo(dest: LocalId) -> LocalEdge {
+        LocalEdge {
+            dest,
+            kind: EdgeKind::Pseudo,
+            patch: PatchIdx::CUR,
+        }
+    }
+
+    pub fn new_live(dest: LocalId, patch: PatchIdx) -> LocalEdge {
+        LocalEdge {
+            dest,
+            kind: EdgeKind::Live,
+            patch,
+        }
+    }
+
+    pub fn new_deleted(dest: LocalId, patch: PatchIdx) -> LocalEdge {
+        LocalEdge {
+            dest,
+            kind: EdgeKind::Deleted,
+            patch,
+        }
+    }
+
+    // "Real" means either live or deleted, but not pseudo
+    pub fn new_real(dest: LocalId, deleted: bool, patch: PatchIdx) -> LocalEdge {
+        LocalEdge {
+            dest,
+            kind: EdgeKind::from_deleted(deleted),
+            patch,
+        }
+    }
+}
+
+impl ojo_graph::Edge<LocalId> for LocalEdge {
+    fn target(&self) -> LocalId {
+        self.dest
+    }
+}
+
+impl ojo_graph::LabeledEdge<LocalId> for LocalEdge {
+    type Label = EdgeKind;
+
+    fn label(&self) -> EdgeKind {
+        self.kind
+    }
+}
+
+// The patches that a graggle refers to, indexed by `PatchIdx`. Patches are never removed from the
+// table, so indices stay valid for as long as the graggle exists.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PatchTable {
+    ids: Vec<PatchId>,
+    indices: HashMap<PatchId, PatchIdx>,
+}
+
+impl Default for PatchTable {
+    fn default() -> PatchTable {
+        let mut ret = PatchTable {
+            ids: Vec::new(),
+            indices: HashMap::new(),
+        };
+        ret.intern(PatchId::cur());
+        ret
+    }
+}
+
+impl PatchTable {
+    // Returns the index of `patch`, adding it to the table if necessary.
+    pub fn intern(&mut self, patch: PatchId) -> PatchIdx {
+        let ids = &mut self.ids;
+        *self.indices.entry(patch).or_insert_with(|| {
+            // The unwrap is ok unless we have billions of patches.
+            let idx = PatchIdx(u32::try_from(ids.len()).unwrap());
+            ids.push(patch);
+            idx
+        })
+    }
+
+    pub fn index(&self, patch: &PatchId) -> Option<PatchIdx> {
+        self.indices.get(patch).cloned()
+    }
+
+    pub fn patch(&self, idx: PatchIdx) -> PatchId {
+        self.ids[idx.0 as usize]
+    }
+
+    // Returns the local version of `id`, adding its patch to the table if necessary.
+    //
+    // # Panics
+    //
+    // Panics if the node's index doesn't fit in 32 bits. Patches with such nodes are rejected when
+    // they're registered, so this shouldn't happen.
+    pub fn intern_node(&mut self, id: NodeId) -> LocalId {
+        self.try_intern_node(id).expect("node index too large")
+    }
+
+    // Like `intern_node`, but returns `None` (without changing the table) if the node's index
+    // doesn't fit in 32 bits.
+    pub fn try_intern_node(&mut self, id: NodeId) -> Option<LocalId> {
+        let node = u32::try_from(id.node).ok()?;
+        Some(LocalId {
+            patch: self.intern(id.patch),
+            node,
+        })
+    }
+
+    // Returns the local version of `id`, if its patch is in the table.
+    pub fn local(&self, id: &NodeId) -> Option<LocalId> {
+        Some(LocalId {
+            patch: self.index(&id.patch)?,
+            node: u32::try_from(id.node).ok()?,
+        })
+    }
+
+    pub fn node(&self, id: LocalId) -> NodeId {
+        NodeId {
+            patch: self.patch(id.patch),
+            node: u64::from(id.node),
+        }
+    }
+
+    pub fn edge(&self, e: &LocalEdge) -> Edge {
+        Edge {
+            kind: e.kind,
+            dest: self.node(e.dest),
+            patch: self.patch(e.patch),
+        }
+    }
+}
+
+// This is how `GraggleData` gets serialized. It refers to nodes and patches by their full ids, so
+// that the format doesn't depend on the order in which patches were added to the `PatchTable`.
+#[derive(Deserialize, Serialize)]
+#[serde(rename = "Graggle")]
+pub(crate) struct SerializedGraggle {
+    nodes: Set<NodeId>,
+    deleted_nodes: Set<NodeId>,
+    edges: MMap<NodeId, Edge>,
+    back_edges: MMap<NodeId, Edge>,
+    deleted_partition: Partition<NodeId>,
+    pseudo_edge_reasons: MMap<(NodeId, NodeId), NodeId>,
+    reason_pseudo_edges: MMap<NodeId, (NodeId, NodeId)>,
+    dirty_reps: Set<NodeId>,
+    // Graggles written by older versions don't have these. The patch edges are rebuilt from the
+    // edges when they're missing.
+    #[serde(default)]
+    patch_edges: MMap<PatchId, (NodeId, NodeId)>,
+    #[serde(default)]
+    replacements: BTreeMap<NodeId, Vec<PatchId>>,
+    // If this is missing, `Storage::upgrade` rebuilds it from the patches.
+    #[serde(default)]
+    deleters: BTreeMap<NodeId, Set<PatchId>>,
+}
+
+fn map_mmap<K, V, L, W, F, G>(map: &MMap<K, V>, mut f: F, mut g: G) -> MMap<L, W>
+where
+    K: Ord,
+    V: Ord,
+    L: Ord,
+    W: Ord,
+    F: FnMut(&K) -> L,
+    G: FnMut(&V) -> W,
+{
+    let mut ret = MMap::new();
+    for (k, v) in map.iter() {
+        ret.insert(f(k), g(v));
+    }
+    ret
+}
+
+impl From<GraggleData> for SerializedGraggle {
+    fn from(d: GraggleData) -> SerializedGraggle {
+        let t = &d.table;
+        let node = |u: &LocalId| t.node(*u);
+        let pair = |&(u, v): &(LocalId, LocalId)| (t.node(u), t.node(v));
+        let edge = |e: &LocalEdge| t.edge(e);
+        SerializedGraggle {
+            nodes: d.nodes.iter().map(node).collect(),
+            deleted_nodes: d.deleted_nodes.iter().map(node).collect(),
+            edges: map_mmap(&d.edges, node, edge),
+            back_edges: map_mmap(&d.back_edges, node, edge),
+            deleted_partition: d.deleted_partition.map(|u| t.node(u)),
+            pseudo_edge_reasons: map_mmap(&d.pseudo_edge_reasons, pair, node),
+            reason_pseudo_edges: map_mmap(&d.reason_pseudo_edges, node, pair),
+            dirty_reps: d.dirty_reps.iter().map(node).collect(),
+            patch_edges: map_mmap(&d.patch_edges, |p| t.patch(*p), pair),
+            replacements: d
+                .replacements
+                .iter()
+                .map(|(u, ps)| (t.node(*u), ps.iter().map(|p| t.patch(*p)).collect()))
+                .collect(),
+            deleters: d
+                .deleters
+                .iter()
+                .map(|(u, ps)| (t.node(*u), ps.iter().map(|p| t.patch(*p)).collect()))
+                .collect(),
+        }
+    }
+}
+
+// This fails if some node's index doesn't fit in 32 bits. We never write such a graggle, so it must
+// have been corrupted.
+impl TryFrom<SerializedGraggle> for GraggleData {
+    type Error = Error;
+
+    fn try_from(s: SerializedGraggle) -> Result<GraggleData, Error> {
+        // The deleted partition and the pseudo-edge reasons can briefly refer to nodes that are no
+        // longer deleted (or no longer exist), so we need to look everywhere for nodes.
+        let mut table = PatchTable::default();
+        let parts = s.deleted_partition.iter_parts().flatten();
+        let reasons = s
+            .pseudo_edge_reasons
+            .iter()
+            .chain(s.reason_pseudo_edges.iter().map(|(r, e)| (e, r)))
+            .flat_map(|(&(u, v), &r)| vec![u, v, r]);
+        // Everything else should only refer to those nodes, but we check anyway, in case of
+        // corruption.
+        let edges = s
+            .edges
+            .iter()
+            .chain(s.back_edges.iter())
+            .flat_map(|(&u, e)| vec![u, e.dest]);
+        let patch_edges = s.patch_edges.iter().flat_map(|(_, &(u, v))| vec![u, v]);
+        for u in s
+            .nodes
+            .iter()
+            .chain(&s.deleted_nodes)
+            .chain(&s.dirty_reps)
+            .chain(s.replacements.keys())
+            .chain(s.deleters.keys())
+            .cloned()
+            .chain(parts)
+            .chain(reasons)
+            .chain(edges)
+            .chain(patch_edges)
+        {
+            table.try_intern_node(u).ok_or(Error::DbCorruption)?;
+        }
+        for p in s.patch_edges.iter().map(|(p, _)| p) {
+            table.intern(*p);
+        }
+        for (_, e) in s.edges.iter().chain(s.back_edges.iter()) {
+            table.intern(e.patch);
+        }
+        for p in s.replacements.values().flatten() {
+            table.intern(*p);
+        }
+        for p in s.deleters.values().flatten() {
+            table.intern(*p);
+        }
+
+        let t = &table;
+        // The unwraps are ok because we just interned everything.
+        let node = |u: &NodeId| t.local(u).unwrap();
+        let pair = |(u, v): &(NodeId, NodeId)| (node(u), node(v));
+        let patch = |p: &PatchId| t.index(p).unwrap();
+        let edge = |e: &Edge| LocalEdge {
+            kind: e.kind,
+            dest: node(&e.dest),
+            patch: patch(&e.patch),
+        };
+        // Every real edge is indexed by its patch, so if there are real edges but no index then the
+        // graggle was written before we kept the index.
+        let mut patch_edges = map_mmap(&s.patch_edges, patch, pair);
+        if patch_edges.iter().next().is_none() {
+            for (u, e) in s.edges.iter() {
+                if e.kind != EdgeKind::Pseudo {
+                    patch_edges.insert(patch(&e.patch), (node(u), node(&e.dest)));
+                }
+            }
+        }
+        Ok(GraggleData {
+            nodes: s.nodes.iter().map(node).collect(),
+            deleted_nodes: s.deleted_nodes.iter().map(node).collect(),
+            edges: map_mmap(&s.edges, node, edge),
+            back_edges: map_mmap(&s.back_edges, node, edge),
+            deleted_partition: s.deleted_partition.map(|u| node(&u)),
+            pseudo_edge_reasons: map_mmap(&s.pseudo_edge_reasons, pair, node),
+            reason_pseudo_edges: map_mmap(&s.reason_pseudo_edges, node, pair),
+            dirty_reps: s.dirty_reps.iter().map(node).collect(),
+            patch_edges,
+            replacements: s
+                .replacements
+                .iter()
+                .map(|(u, ps)| (node(u), ps.iter().map(patch).collect()))
+                .collect(),
+            deleters: s
+                .deleters
+                .iter()
+                .map(|(u, ps)| (node(u), ps.iter().map(patch).collect()))
+                .collect(),
+            table,
+        })
+    }
+}
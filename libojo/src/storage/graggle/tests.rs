@@ -11,9 +11,8 @@
 
 use super::*;
 use crate::patch::Change;
-use crate::{NodeId, PatchId};
+use crate::{Disorder, NodeId, PatchId};
 
-use byteorder::{LittleEndian, WriteBytesExt};
 use proptest::collection::hash_set;
 use proptest::prelude::*;
 use proptest::sample::subsequence;
@@ -64,7 +63,7 @@ macro_rules! changes {
                     Change::DeleteNode { id: NodeId::cur($delete_node) },
                 )*)*
                 $($(
-                    Change::NewNode { id: NodeId::cur($add_node), contents: vec![] },
+                    Change::NewNode { id: NodeId::cur($add_node), contents: vec![], line: None, path: None },
                 )*)*
                 $($(
                     Change::NewEdge { src: NodeId::cur($src), dest: NodeId::cur($dest) },
@@ -99,8 +98,8 @@ trait GraggleExt {
 
 impl GraggleExt for GraggleData {
     fn has_pseudoedge(&self, i: u64, j: u64) -> bool {
-        let src = NodeId::cur(i);
-        let edge = Edge::new_pseudo(NodeId::cur(j));
+        let src = self.local(&NodeId::cur(i));
+        let edge = LocalEdge::new_pseudo(self.local(&NodeId::cur(j)));
         self.edges.contains(&src, &edge)
     }
 
@@ -108,7 +107,7 @@ impl GraggleExt for GraggleData {
         self.edges
             .iter()
             .filter(|(_, e)| e.kind == EdgeKind::Pseudo)
-            .map(|(src, e)| (src.node, e.dest.node))
+            .map(|(src, e)| (u64::from(src.node), u64::from(e.dest.node)))
             .collect::<HashSet<_>>()
     }
 }
@@ -211,6 +210,238 @@ fn two_reasons() {
     assert_pseudoedges!(d; );
 }
 
+#[test]
+fn compact() {
+    assert_eq!(std::mem::size_of::<LocalId>(), 8);
+    assert!(std::mem::size_of::<LocalEdge>() <= 16);
+
+    // The serialized form uses full ids, so it survives a round-trip even though the patch table
+    // gets rebuilt.
+    let mut d = graggle!(
+        live: 0, 3
+        deleted: 1, 2
+        edges: 0-1, 1-3, 0-2, 2-3
+    );
+    let other = PatchId::synthetic(1);
+    d.add_node(NodeId {
+        patch: other,
+        node: 0,
+    });
+    d.add_edge(
+        NodeId::cur(3),
+        NodeId {
+            patch: other,
+            node: 0,
+        },
+        other,
+    );
+    d.resolve_pseudo_edges();
+    let yaml = serde_yaml::to_string(&d).unwrap();
+    let d2: GraggleData = serde_yaml::from_str(&yaml).unwrap();
+    d2.assert_consistent();
+    assert_eq!(d, d2);
+}
+
+#[test]
+fn compact_corrupted() {
+    // Node indices that don't fit in 32 bits are an error, not a panic.
+    let d = graggle!(live: 0, 7 edges: 0-7);
+    let yaml = serde_yaml::to_string(&d).unwrap();
+    for bad in &[
+        yaml.replace("node: 7", "node: 4294967296"),
+        // Just the destination of one edge.
+        yaml.replacen("    node: 7", "    node: 4294967296", 1),
+    ] {
+        assert_ne!(bad, &yaml);
+        assert!(serde_yaml::from_str::<GraggleData>(bad).is_err());
+    }
+
+    // The same goes for the parts of the graggle that are only there to speed things up.
+    let mut d = graggle!(live: 0, 2 deleted: 1 edges: 0-1, 1-2);
+    d.resolve_pseudo_edges();
+    let yaml = serde_yaml::to_string(&d).unwrap();
+    // Replaces `from` by `to`, but only in the given top-level field.
+    let corrupt = |field: &str, from: &str, to: &str| {
+        let mut ret = String::new();
+        let mut inside = false;
+        for line in yaml.lines() {
+            if !line.starts_with(' ') && !line.starts_with('-') {
+                inside = line == format!("{}:", field);
+            }
+            if inside {
+                ret.push_str(&line.replace(from, to));
+            } else {
+                ret.push_str(line);
+            }
+            ret.push('\n');
+        }
+        assert_ne!(ret, yaml);
+        ret
+    };
+    let bad = corrupt("reason_pseudo_edges", "node: 2", "node: 4294967296");
+    assert!(serde_yaml::from_str::<GraggleData>(&bad).is_err());
+
+    // A patch that is only mentioned in the back edges (or a node that is only mentioned in the
+    // pseudo-edge reasons) doesn't make any sense, but at least it doesn't cause a panic.
+    let cur = PatchId::cur().to_string();
+    let other = PatchId::synthetic(1).to_string();
+    for field in &["back_edges", "reason_pseudo_edges"] {
+        let _ = serde_yaml::from_str::<GraggleData>(&corrupt(field, &cur, &other));
+    }
+}
+
+#[test]
+fn neighbor_order() {
+    // The order of the neighbors doesn't depend on the order in which their patches were seen.
+    let node = |i| NodeId {
+        patch: PatchId::synthetic(i),
+        node: 0,
+    };
+    let with_order = |order: &[u64]| {
+        let mut d = graggle!(live: 0);
+        for &i in order {
+            d.add_node(node(i));
+        }
+        for &i in order {
+            d.add_edge(NodeId::cur(0), node(i), PatchId::synthetic(i));
+        }
+        d
+    };
+    let d1 = with_order(&[1, 2, 3]);
+    let d2 = with_order(&[3, 1, 2]);
+    let neighbors = |d: &GraggleData| {
+        d.as_graggle()
+            .out_neighbors(&NodeId::cur(0))
+            .collect::<Vec<_>>()
+    };
+    let mut expected = vec![node(1), node(2), node(3)];
+    expected.sort();
+    assert_eq!(neighbors(&d1), expected);
+    assert_eq!(neighbors(&d2), expected);
+    assert_eq!(
+        d1.as_graggle().nodes().collect::<Vec<_>>(),
+        d2.as_graggle().nodes().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn check_consistency() {
+    let mut d = graggle!(
+        live: 0, 3, 4
+        deleted: 1, 2
+        edges: 0-1, 1-2, 2-3, 3-4
+    );
+    d.resolve_pseudo_edges();
+    assert_eq!(d.check_consistency(true), Ok(()));
+
+    // A wrong pseudo-edge is only caught by the thorough checks.
+    let n = |i| d.local(&NodeId::cur(i));
+    let (n0, n1, n4) = (n(0), n(1), n(4));
+    let reason = d.deleted_partition.representative(n1);
+    d.edges.insert(n0, LocalEdge::new_pseudo(n4));
+    d.back_edges.insert(n4, LocalEdge::new_pseudo(n0));
+    d.pseudo_edge_reasons.insert((n0, n4), reason);
+    assert!(d.check_consistency(false).is_ok());
+    assert!(d.check_consistency(true).is_err());
+
+    d.edges.insert(n0, LocalEdge::new_pseudo(n0));
+    assert_eq!(
+        d.check_consistency(false),
+        Err(format!("self-loop at {:?}", n0))
+    );
+}
+
+#[test]
+fn rebuild_pseudo_edges() {
+    let mut d = graggle!(
+        live: 0, 3, 4
+        deleted: 1, 2
+        edges: 0-1, 1-2, 2-3, 3-4
+    );
+    assert_pseudoedges!(d; 0-3);
+    let orig = d.clone();
+    assert!(!d.rebuild_pseudo_edges());
+    d.assert_consistent();
+    assert_eq!(d, orig);
+
+    // Mess up the pseudo-edges: remove a correct one and add an incorrect one.
+    let n = |i| d.local(&NodeId::cur(i));
+    let (n0, n3, n4) = (n(0), n(3), n(4));
+    d.internal_delete_edge(&n0, &LocalEdge::new_pseudo(n3));
+    d.edges.insert(n0, LocalEdge::new_pseudo(n4));
+    d.pseudo_edge_reasons = MMap::new();
+    assert!(d.rebuild_pseudo_edges());
+    d.assert_consistent();
+    assert_eq!(d, orig);
+    assert_eq!(d.pseudoedges(), [(0, 3)].iter().cloned().collect());
+}
+
+#[test]
+fn rebuild_pseudo_edge_reasons() {
+    let mut orig = graggle!(
+        live: 0, 4
+        deleted: 1, 2, 3
+        edges: 0-1, 1-2, 2-3, 3-4
+    );
+    orig.resolve_pseudo_edges();
+    assert_pseudoedges!(orig; 0-4);
+    let n = |i| orig.local(&NodeId::cur(i));
+    let (n0, n1, n2, n3, n4) = (n(0), n(1), n(2), n(3), n(4));
+    let rep = orig.deleted_partition.representative(n1);
+    let other = if rep == n1 { n2 } else { n1 };
+
+    // The pseudo-edges themselves are right, but the reasons for them are wrong.
+    let mut d = orig.clone();
+    d.pseudo_edge_reasons.remove(&(n0, n4), &rep);
+    d.pseudo_edge_reasons.insert((n0, n4), other);
+    assert!(d.rebuild_pseudo_edges());
+    assert_eq!(d, orig);
+
+    let mut d = orig.clone();
+    d.reason_pseudo_edges = MMap::new();
+    assert!(d.rebuild_pseudo_edges());
+    assert_eq!(d, orig);
+
+    // The partition is missing one of the deleted nodes.
+    let mut d = orig.clone();
+    d.deleted_partition.remove_part(rep);
+    for &u in &[n1, n2, n3] {
+        d.deleted_partition.insert(u);
+    }
+    d.deleted_partition.merge(n1, n2);
+    assert!(d.rebuild_pseudo_edges());
+    assert_eq!(d, orig);
+    assert!(!d.rebuild_pseudo_edges());
+}
+
+#[test]
+fn neighbor_queries() {
+    let mut d = graggle!(
+        live: 0, 2, 3
+        deleted: 1
+        edges: 0-1, 1-2, 0-3, 3-2
+    );
+    d.resolve_pseudo_edges();
+    let g = d.as_graggle();
+    let n = |i| NodeId::cur(i);
+
+    let live_out = g.live_out_neighbors(&n(0)).collect::<HashSet<_>>();
+    let all_out = g.out_neighbors(&n(0)).collect::<HashSet<_>>();
+    assert_eq!(live_out, [n(3)].iter().cloned().collect());
+    assert_eq!(all_out, [n(2), n(3)].iter().cloned().collect());
+    assert_eq!(g.live_in_neighbors(&n(2)).collect::<Vec<_>>(), vec![n(3)]);
+
+    assert_eq!(g.deleted_neighbors(&n(0)).collect::<Vec<_>>(), vec![n(1)]);
+    assert_eq!(g.deleted_neighbors(&n(2)).collect::<Vec<_>>(), vec![n(1)]);
+    assert_eq!(g.deleted_neighbors(&n(3)).count(), 0);
+
+    // 0 -> 3 and the pseudo-edge 0 -> 2.
+    assert_eq!(g.degree(&n(0)), 2);
+    assert_eq!(g.degree(&n(3)), 2);
+
+    assert_eq!(g.edges_introduced_by(&PatchId::cur()).count(), 4);
+}
+
 // It's legal for two different patches to add the same edge.
 #[test]
 fn duplicate_edge() {
@@ -226,8 +457,22 @@ fn duplicate_edge() {
 
     // The changes! macro defaults to setting all ids to PatchId::cur, which isn't really correct
     // but it doesn't cause an error unless there are (like here) duplicate edges.
-    ch1.id.data[0] = 1;
-    ch2.id.data[0] = 2;
+    ch1.id = PatchId::synthetic(1);
+    ch2.id = PatchId::synthetic(2);
+
+    let mut applied = d.clone();
+    apply_changes(&mut applied, &ch1);
+    apply_changes(&mut applied, &ch2);
+    let g = applied.as_graggle();
+    let edge = (NodeId::cur(0), NodeId::cur(1));
+    assert_eq!(
+        g.edges_introduced_by(&ch1.id).collect::<Vec<_>>(),
+        vec![edge]
+    );
+    assert_eq!(
+        g.edges_introduced_by(&ch2.id).collect::<Vec<_>>(),
+        vec![edge]
+    );
 
     check_graggle_and_changes(d, &[ch1, ch2]);
 }
@@ -237,14 +482,6 @@ fn duplicate_edge() {
 // n*MAX_AVG_DEGREE.
 const MAX_AVG_DEGREE: usize = 5;
 
-fn fake_patch_id(id: usize) -> PatchId {
-    let mut ret = PatchId::cur();
-    (&mut ret.data[..])
-        .write_u64::<LittleEndian>(id as u64)
-        .unwrap();
-    ret
-}
-
 // Make a graggle like 0 -> 1 -> 2, and delete node 1.
 #[test]
 fn append_and_delete() {
@@ -446,14 +683,11 @@ prop_compose! {
     {
         let mut ret = GraggleData::new();
         for i in 0..num_nodes {
-            ret.nodes.insert(NodeId::cur(i as u64));
+            ret.add_node(NodeId::cur(i as u64));
         }
         for (u, v) in edges {
             if u != v {
-                let u = NodeId::cur(u as u64);
-                let v = NodeId::cur(v as u64);
-                ret.edges.insert(u, Edge::new_live(v, PatchId::cur()));
-                ret.back_edges.insert(v, Edge::new_live(u, PatchId::cur()));
+                ret.add_edge(NodeId::cur(u as u64), NodeId::cur(v as u64), PatchId::cur());
             }
         }
         ret
@@ -478,7 +712,7 @@ fn arb_changes<'a>(graggle: &'a GraggleData, size: usize) -> BoxedStrategy<Chang
         old_new_edges: HashSet<(usize, usize)>,
     ) -> ChangesWithId {
         let patch_id_int = CUR_ID.fetch_add(1, Ordering::SeqCst);
-        let patch_id = fake_patch_id(patch_id_int);
+        let patch_id = PatchId::synthetic(patch_id_int as u64);
 
         let new_ids = (0..num_to_add)
             .map(|i| NodeId {
@@ -494,6 +728,8 @@ fn arb_changes<'a>(graggle: &'a GraggleData, size: usize) -> BoxedStrategy<Chang
         let insertions = new_ids.iter().map(|u| Change::NewNode {
             id: *u,
             contents: vec![],
+            line: None,
+            path: None,
         });
 
         let edges = new_new_edges
@@ -519,7 +755,7 @@ fn arb_changes<'a>(graggle: &'a GraggleData, size: usize) -> BoxedStrategy<Chang
         }
     }
 
-    let old_ids = graggle.nodes.iter().cloned().collect::<Vec<_>>();
+    let old_ids = graggle.as_graggle().nodes().collect::<Vec<_>>();
     let num_to_add = 1..size;
 
     // Strategy returning a tuple
@@ -566,9 +802,11 @@ fn apply_changes(graggle: &mut GraggleData, changes: &ChangesWithId) {
         match *ch {
             Change::NewNode { ref id, .. } => graggle.add_node(id.clone()),
             Change::DeleteNode { ref id } => graggle.delete_node(&id),
+            Change::ReplaceContents { ref id, .. } => graggle.replace_contents(id, changes.id),
             Change::NewEdge { ref src, ref dest } => {
                 graggle.add_edge(src.clone(), dest.clone(), changes.id)
             }
+            Change::CreateFile => {}
         }
     }
 }
@@ -577,8 +815,9 @@ fn unapply_changes(graggle: &mut GraggleData, changes: &ChangesWithId) {
     for ch in &changes.changes {
         match *ch {
             Change::DeleteNode { ref id } => graggle.undelete_node(id),
+            Change::ReplaceContents { ref id, .. } => graggle.unreplace_contents(id, changes.id),
             Change::NewEdge { ref src, ref dest } => graggle.unadd_edge(src, dest, changes.id),
-            Change::NewNode { .. } => {}
+            Change::NewNode { .. } | Change::CreateFile => {}
         }
     }
     for ch in &changes.changes {
@@ -600,11 +839,30 @@ proptest! {
         d.resolve_pseudo_edges();
         d.assert_consistent();
 
+        // Unapplying the edges using the patch index should be the same as unapplying them
+        // one-by-one.
+        let mut indexed = d.clone();
+
         unapply_changes(&mut d, ch);
         d.assert_consistent();
 
         d.resolve_pseudo_edges();
         d.assert_consistent();
+
+        for c in &ch.changes {
+            if let Change::DeleteNode { ref id } = *c {
+                indexed.undelete_node(id);
+            }
+        }
+        indexed.unadd_patch_edges(ch.id);
+        for c in &ch.changes {
+            if let Change::NewNode { ref id, .. } = *c {
+                indexed.unadd_node(id);
+            }
+        }
+        indexed.resolve_pseudo_edges();
+        indexed.assert_consistent();
+        assert_eq!(d, indexed);
     }
 }
 
@@ -681,3 +939,22 @@ proptest! {
         assert_eq!(cur, all_at_once);
     }
 }
+
+#[test]
+fn disorder() {
+    // An extra edge that skips over a node doesn't make the file any less ordered.
+    let d = graggle!(live: 0, 1, 2 edges: 0-1, 1-2, 0-2);
+    assert_eq!(d.as_graggle().disorder(), Disorder::default());
+
+    let d = graggle!(live: 0, 1, 2, 3 edges: 0-1, 0-2, 1-3, 2-3);
+    let disorder = d.as_graggle().disorder();
+    assert_eq!((disorder.cycles, disorder.unordered), (0, 1));
+
+    let d = graggle!(live: 0, 1, 2, 3 edges: 0-1, 1-2, 2-1, 2-3);
+    let disorder = d.as_graggle().disorder();
+    assert_eq!((disorder.cycles, disorder.unordered), (1, 0));
+
+    // Deleted nodes don't count.
+    let d = graggle!(live: 0, 2 deleted: 1 edges: 0-1, 0-2);
+    assert_eq!(d.as_graggle().disorder(), Disorder::default());
+}
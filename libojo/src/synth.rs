@@ -0,0 +1,117 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use crate::{Changes, Error, PatchId, Repo};
+
+/// The kinds of synthetic histories that [`Repo::synthesize`] can create.
+///
+/// These are mainly useful for testing and benchmarking.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SynthPreset {
+    /// A single patch that creates a file with `size` lines.
+    Linear,
+    /// `size` patches, each of which appends one line to the file.
+    Appends,
+    /// A patch that creates a file with `size` lines, followed by a patch that deletes all of them
+    /// except the first and the last. This leaves a large cluster of deleted lines, which have to
+    /// be bridged by pseudo-edges.
+    Tombstones,
+}
+
+// The contents of the first `n` lines of a synthetic file.
+fn lines(n: usize) -> Vec<u8> {
+    (0..n)
+        .flat_map(|i| format!("Line {}\n", i).into_bytes())
+        .collect()
+}
+
+impl Repo {
+    /// Creates a synthetic history (as described by `preset`) and applies it to `branch`.
+    ///
+    /// Returns the ids of all the patches that were created, in the order that they were applied.
+    pub fn synthesize(
+        &mut self,
+        branch: &str,
+        preset: SynthPreset,
+        size: usize,
+    ) -> Result<Vec<PatchId>, Error> {
+        match preset {
+            SynthPreset::Linear => Ok(vec![self.synthesize_one(branch, &lines(size))?]),
+            SynthPreset::Appends => (1..=size)
+                .map(|i| self.synthesize_one(branch, &lines(i)))
+                .collect(),
+            SynthPreset::Tombstones => {
+                let first = self.synthesize_one(branch, &lines(size))?;
+                let mut contents = lines(1);
+                if size > 1 {
+                    contents.extend_from_slice(format!("Line {}\n", size - 1).as_bytes());
+                }
+                let second = self.synthesize_one(branch, &contents)?;
+                Ok(vec![first, second])
+            }
+        }
+    }
+
+    // Creates a patch that changes `branch` to have the given contents, and applies it.
+    fn synthesize_one(&mut self, branch: &str, contents: &[u8]) -> Result<PatchId, Error> {
+        let diff = self.diff(branch, contents)?;
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        let id = self.create_patch("Anonymous bot", "Synthesized", changes)?;
+        self.apply_patch(branch, &id)?;
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ojo_graph::Graph;
+
+    #[test]
+    fn presets() {
+        let mut repo = Repo::init_tmp();
+        assert_eq!(
+            repo.synthesize("master", SynthPreset::Linear, 3)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            repo.file("master").unwrap().as_bytes(),
+            b"Line 0\nLine 1\nLine 2\n"
+        );
+
+        let mut repo = Repo::init_tmp();
+        assert_eq!(
+            repo.synthesize("master", SynthPreset::Appends, 3)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            repo.file("master").unwrap().as_bytes(),
+            b"Line 0\nLine 1\nLine 2\n"
+        );
+
+        let mut repo = Repo::init_tmp();
+        repo.synthesize("master", SynthPreset::Tombstones, 5)
+            .unwrap();
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"Line 0\nLine 4\n");
+        assert_eq!(
+            repo.graggle("master")
+                .unwrap()
+                .as_full_graph()
+                .nodes()
+                .count(),
+            5
+        );
+    }
+}
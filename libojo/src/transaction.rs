@@ -0,0 +1,232 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{Error, HookEvent, PatchId, Repo};
+
+// Keeps track of the transactions that are currently running (see `Repo::transaction`).
+#[derive(Debug, Default)]
+pub(crate) struct Transactions {
+    // How many transactions are running (they can be nested).
+    depth: usize,
+    // The post-apply hooks that are waiting for the outermost transaction to be committed, as
+    // pairs of (branch, applied patches).
+    pending_post_apply: Vec<(String, Vec<PatchId>)>,
+}
+
+impl Repo {
+    /// Runs `f` on this repository, making its modifications all at once or not at all.
+    ///
+    /// If `f` returns an error then the repository goes back to the state it was in before `f`
+    /// ran: all of the patches, branches and journal entries that `f` created are forgotten, and
+    /// the current branch is restored. This means that a multi-step operation that fails half-way
+    /// can't leave the repository in a half-modified state (which could end up on disk after a
+    /// call to [`Repo::write`]).
+    ///
+    /// Hooks that run after something has happened (like [`HookKind::PostApply`]) are only run
+    /// once the transaction has succeeded. Transactions can be nested, in which case the hooks
+    /// wait for the outermost one.
+    ///
+    /// Since a transaction might still be rolled back, [`Repo::write`] fails with
+    /// [`Error::InTransaction`] while it is running. If `f` panics, the repository is rolled back
+    /// before the panic continues.
+    ///
+    /// [`HookKind::PostApply`]: crate::HookKind::PostApply
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Repo) -> Result<T, Error>,
+    {
+        let saved_storage = self.storage.clone();
+        let saved_branch = self.current_branch.clone();
+        let saved_journal_len = self.journal.len();
+        let saved_pending_len = self.transactions.pending_post_apply.len();
+        let rollback = move |repo: &mut Repo| {
+            repo.storage = saved_storage;
+            repo.current_branch = saved_branch;
+            repo.journal.truncate(saved_journal_len);
+            repo.transactions
+                .pending_post_apply
+                .truncate(saved_pending_len);
+        };
+
+        self.transactions.depth += 1;
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        self.transactions.depth -= 1;
+        let ret = match ret {
+            Ok(ret) => ret,
+            Err(payload) => {
+                rollback(self);
+                panic::resume_unwind(payload);
+            }
+        };
+
+        match ret {
+            Ok(val) => {
+                if self.transactions.depth == 0 {
+                    let pending = std::mem::take(&mut self.transactions.pending_post_apply);
+                    for (branch, patches) in pending {
+                        self.hooks.run_post(&HookEvent::PostApply {
+                            branch: &branch,
+                            patches: &patches,
+                        });
+                    }
+                }
+                Ok(val)
+            }
+            Err(e) => {
+                rollback(self);
+                Err(e)
+            }
+        }
+    }
+
+    // Is there a transaction running?
+    pub(crate) fn in_transaction(&self) -> bool {
+        self.transactions.depth > 0
+    }
+
+    // Runs the post-apply hooks, or saves them for later if we're in a transaction.
+    pub(crate) fn run_post_apply_hooks(&mut self, branch: &str, patches: &[PatchId]) {
+        if self.in_transaction() {
+            self.transactions
+                .pending_post_apply
+                .push((branch.to_owned(), patches.to_vec()));
+        } else {
+            self.hooks
+                .run_post(&HookEvent::PostApply { branch, patches });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use crate::{Changes, Disorder, Error, HookEvent, HookKind, Repo};
+
+    #[test]
+    fn rollback() {
+        let mut repo = Repo::init_tmp();
+        let ran = Rc::new(RefCell::new(0));
+        let ran_clone = Rc::clone(&ran);
+        repo.register_hook(HookKind::PostApply, move |_: &HookEvent<'_>| {
+            *ran_clone.borrow_mut() += 1;
+            Ok(())
+        });
+
+        let ret: Result<(), Error> = repo.transaction(|repo| {
+            let diff = repo.diff("master", b"a\n").unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes)?;
+            repo.apply_patch("master", &id)?;
+            repo.create_branch("other")?;
+            repo.switch_branch("other")?;
+            Err(Error::NotOrdered(Disorder::default()))
+        });
+        assert!(matches!(ret, Err(Error::NotOrdered(_))));
+        assert_eq!(repo.all_patches().count(), 0);
+        assert_eq!(repo.branches().collect::<Vec<_>>(), vec!["master"]);
+        assert_eq!(repo.current_branch, "master");
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"");
+        assert_eq!(repo.journal.len(), 0);
+        assert_eq!(*ran.borrow(), 0);
+
+        let id = repo
+            .transaction(|repo| {
+                let diff = repo.diff("master", b"a\n").unwrap();
+                let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+                let id = repo.create_patch("Author", "Msg", changes)?;
+                repo.apply_patch("master", &id)?;
+
+                // A failing nested transaction only undoes its own changes.
+                let _ = repo.transaction(|repo| -> Result<(), Error> {
+                    repo.unapply_patch("master", &id)?;
+                    Err(Error::NotOrdered(Disorder::default()))
+                });
+                // The hooks wait for the outermost transaction.
+                assert_eq!(*ran.borrow(), 0);
+                Ok(id)
+            })
+            .unwrap();
+        assert!(repo.is_applied("master", &id));
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\n");
+        assert_eq!(*ran.borrow(), 1);
+    }
+
+    #[test]
+    fn write_and_panic() {
+        let mut repo = Repo::init_tmp();
+        let ret = repo.transaction(|repo| {
+            repo.create_branch("other")?;
+            repo.write()
+        });
+        assert!(matches!(ret, Err(Error::InTransaction)));
+        assert_eq!(repo.branches().collect::<Vec<_>>(), vec!["master"]);
+
+        // A panicking transaction is rolled back, and the repository isn't left in a transaction.
+        let ret = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = repo.transaction(|repo| -> Result<(), Error> {
+                repo.create_branch("other")?;
+                panic!("oops");
+            });
+        }));
+        assert!(ret.is_err());
+        assert!(!repo.in_transaction());
+        assert_eq!(repo.branches().collect::<Vec<_>>(), vec!["master"]);
+        assert_eq!(repo.journal.len(), 0);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut repo = Repo::init_tmp();
+        let patch = |repo: &mut Repo, contents: &[u8]| {
+            let diff = repo.diff("master", contents).unwrap();
+            let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+            let id = repo.create_patch("Author", "Msg", changes).unwrap();
+            repo.apply_patch("master", &id).unwrap();
+            id
+        };
+        let p1 = patch(&mut repo, b"a\nb\nc\nd\ne\n");
+        // Deleting two separate lines makes two components of deleted nodes.
+        let p2 = patch(&mut repo, b"a\nc\ne\n");
+        repo.unapply_patch("master", &p1).unwrap();
+
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let cancel_now = Rc::new(Cell::new(true));
+        let token = repo.cancel_token();
+        let (progress_clone, cancel_clone) = (Rc::clone(&progress), Rc::clone(&cancel_now));
+        repo.register_hook(HookKind::CacheProgress, move |event| {
+            if let HookEvent::CacheProgress { done, total, .. } = *event {
+                progress_clone.borrow_mut().push((done, total));
+                if cancel_clone.get() {
+                    token.cancel();
+                }
+            }
+            Ok(())
+        });
+
+        let ret = repo.apply_patches("master", &[p2]);
+        assert!(matches!(ret, Err(Error::Cancelled)));
+        assert_eq!(*progress.borrow(), vec![(0, 2)]);
+        assert_eq!(repo.patches("master").count(), 0);
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"");
+
+        // Trying again works, as long as nobody cancels it.
+        progress.borrow_mut().clear();
+        cancel_now.set(false);
+        repo.apply_patches("master", &[p2]).unwrap();
+        assert_eq!(*progress.borrow(), vec![(0, 2), (1, 2), (2, 2)]);
+        assert_eq!(repo.file("master").unwrap().as_bytes(), b"a\nc\ne\n");
+        repo.check_branch("master").unwrap();
+    }
+}
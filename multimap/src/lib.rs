@@ -16,7 +16,7 @@ use serde::de::{SeqAccess, Visitor};
 use serde::ser::SerializeSeq;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Borrow;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{btree_set, BTreeMap, BTreeSet};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct MMap<K: Ord, V: Ord> {
@@ -40,27 +40,26 @@ impl<K: Ord, V: Ord> MMap<K, V> {
     }
 
     /// Returns an iterator over all the values associated with this key.
-    // FIXME: I don't understand why the one with the Box works, but the one without gives lifetime
-    // errors downstream.
-    //pub fn get<Q>(&'_ self, key: &Q) -> impl Iterator<Item = &'_ V> + '_
-    pub fn get<Q>(&'_ self, key: &Q) -> Box<dyn Iterator<Item = &'_ V> + '_>
+    // This returns a concrete type instead of `impl Iterator`, because an `impl Iterator` would
+    // capture `Q` (and therefore the lifetime of `key`), which gives lifetime errors downstream.
+    pub fn get<Q>(&'_ self, key: &Q) -> btree_set::Iter<'_, V>
     where
         K: Borrow<Q>,
         Q: Ord + ?Sized,
     {
-        Box::new(self.map.get(key).unwrap_or(&self.empty_set).iter())
+        self.map.get(key).unwrap_or(&self.empty_set).iter()
     }
 
     /// Returns an iterator over all the values associated with this key and that are greater than
     /// or equal to `val`.
-    pub fn get_from<Q, R>(&'_ self, key: &Q, val: &R) -> Box<dyn Iterator<Item = &'_ V> + '_>
+    pub fn get_from<Q, R>(&'_ self, key: &Q, val: &R) -> btree_set::Range<'_, V>
     where
         K: Borrow<Q>,
         Q: Ord + ?Sized,
         V: Borrow<R>,
         R: Ord, // I'm not sure why R has to be Sized here...
     {
-        Box::new(self.map.get(key).unwrap_or(&self.empty_set).range(val..))
+        self.map.get(key).unwrap_or(&self.empty_set).range(val..)
     }
 
     pub fn insert(&mut self, key: K, val: V) {
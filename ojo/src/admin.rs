@@ -0,0 +1,72 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{Error, ResultExt};
+use libojo::LineagePolicy;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    match m.subcommand_name() {
+        Some("coarse-diff") => coarse_diff_run(m.subcommand_matches("coarse-diff").unwrap()),
+        Some("lineage") => lineage_run(m.subcommand_matches("lineage").unwrap()),
+        Some("reattribute") => reattribute_run(m.subcommand_matches("reattribute").unwrap()),
+        _ => panic!("Unknown subcommand"),
+    }
+}
+
+fn reattribute_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    // Each of these requires the other, so they are either both present or both absent.
+    if let (Some(from), Some(to)) = (m.value_of("from"), m.value_of("to")) {
+        repo.reattribute(from, to);
+        repo.write()?;
+        eprintln!("{}", Msg::Reattributed { from, to });
+    } else {
+        for (from, to) in repo.reattributions() {
+            println!("{} -> {}", from, to);
+        }
+    }
+    Ok(())
+}
+
+fn coarse_diff_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    if let Some(n) = m.value_of("lines") {
+        let lines = n.parse::<usize>().with_context(|_| {
+            Msg::InvalidCount {
+                arg: "lines",
+                value: n,
+            }
+            .to_string()
+        })?;
+        repo.set_coarse_diff_lines(lines);
+        repo.write()?;
+        eprintln!("{}", Msg::CoarseDiffChanged(lines));
+    } else {
+        println!("{}", repo.coarse_diff_lines());
+    }
+    Ok(())
+}
+
+fn lineage_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let policy = m.value_of("policy").map(|p| {
+        // The unwrap is ok because clap only allows the names of the policies.
+        *LineagePolicy::ALL.iter().find(|q| q.name() == p).unwrap()
+    });
+    let identity = m.value_of("identity");
+    if policy.is_some() || identity.is_some() {
+        if let Some(policy) = policy {
+            repo.set_lineage_policy(policy);
+        }
+        if identity.is_some() {
+            repo.set_identity(identity);
+        }
+        repo.write()?;
+        eprintln!("{}", Msg::LineageChanged);
+    } else {
+        let none = Msg::NoIdentity.to_string();
+        let identity = repo.identity().unwrap_or(&none);
+        println!("{}", Msg::LineageIdentity(identity));
+        println!("{}", Msg::LineagePolicy(repo.lineage_policy().name()));
+    }
+    Ok(())
+}
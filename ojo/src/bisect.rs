@@ -0,0 +1,140 @@
+use crate::messages::Msg;
+use crate::repl::OpenRepo;
+use clap::ArgMatches;
+use failure::{Error, ResultExt};
+use libojo::{BisectStep, Repo, BISECT_BRANCH};
+use std::process::Command;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    match m.subcommand_name() {
+        Some("bad") => mark_run(m.subcommand_matches("bad").unwrap(), false),
+        Some("good") => mark_run(m.subcommand_matches("good").unwrap(), true),
+        Some("reset") => reset_run(m.subcommand_matches("reset").unwrap()),
+        Some("run") => run_run(m.subcommand_matches("run").unwrap()),
+        Some("start") => start_run(m.subcommand_matches("start").unwrap()),
+        _ => panic!("Unknown subcommand"),
+    }
+}
+
+// The branch that is being bisected.
+fn bisected_branch(repo: &Repo) -> Result<String, Error> {
+    match repo.bisect() {
+        Some(bisect) => Ok(bisect.branch.clone()),
+        None => Err(libojo::Error::NoBisect.into()),
+    }
+}
+
+fn start_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+    let path = crate::file_path(m);
+
+    // Every step overwrites the working file, so make sure that doesn't lose anything.
+    if repo.root_dir.join(&path).exists() {
+        let diff = crate::diff::diff(&repo, &branch, &path, false)?;
+        if crate::diff::has_changes(&diff) {
+            bail!("{}", Msg::BisectUncommitted(&path));
+        }
+    }
+
+    let step = repo.bisect_start(&branch)?;
+    show_step(&mut repo, step, &branch, &path)?;
+    Ok(())
+}
+
+fn mark_run(m: &ArgMatches<'_>, good: bool) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let branch = bisected_branch(&repo)?;
+    let step = repo.bisect_mark(good)?;
+    show_step(&mut repo, step, &branch, &crate::file_path(m))?;
+    Ok(())
+}
+
+fn reset_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let branch = bisected_branch(&repo)?;
+    repo.bisect_reset()?;
+    repo.write()?;
+    write_branch(&repo, &branch, &crate::file_path(m))?;
+    eprintln!("{}", Msg::BisectReset(&branch));
+    Ok(())
+}
+
+fn run_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwrap is ok because this is a required argument.
+    let script = m.value_of("SCRIPT").unwrap();
+    let path = crate::file_path(m);
+    let mut repo = crate::open_repo()?;
+    let branch = bisected_branch(&repo)?;
+
+    loop {
+        // Like the editor for patch descriptions, the script might come with arguments.
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", script))
+            .arg(script)
+            .arg(&path)
+            .current_dir(&repo.root_dir)
+            .status()
+            .with_context(|_| Msg::BisectScriptFailed(script).to_string())?;
+        let good = match status.code() {
+            Some(code) => code == 0,
+            None => bail!(
+                "{}",
+                Msg::BisectScriptKilled {
+                    script,
+                    status: &status
+                }
+            ),
+        };
+        let step = repo.bisect_mark(good)?;
+        if show_step(&mut repo, step, &branch, &path)? {
+            return Ok(());
+        }
+    }
+}
+
+// Writes the contents of `branch` to the working file.
+fn write_branch(repo: &Repo, branch: &str, path: &str) -> Result<(), Error> {
+    let file = repo.file(branch)?;
+    std::fs::write(repo.root_dir.join(path), file.as_bytes())
+        .with_context(|_| Msg::WriteFailed(path).to_string())?;
+    Ok(())
+}
+
+// Saves the progress of the bisection, and writes out the file that needs testing next (or, if
+// the search is over, the branch that was searched). Returns true if the search is over.
+fn show_step(
+    repo: &mut OpenRepo,
+    step: BisectStep,
+    branch: &str,
+    path: &str,
+) -> Result<bool, Error> {
+    repo.write()?;
+    match step {
+        BisectStep::Test { patch, remaining } => {
+            write_branch(repo, BISECT_BRANCH, path)?;
+            eprintln!(
+                "{}",
+                Msg::BisectTesting {
+                    patch: &repo.abbreviate_patch_id(&patch),
+                    remaining,
+                    path,
+                }
+            );
+            Ok(false)
+        }
+        BisectStep::Found(patch) => {
+            write_branch(repo, branch, path)?;
+            let description = repo.patch_header(&patch)?.description;
+            println!(
+                "{}",
+                Msg::BisectFound {
+                    patch: &patch.to_string(),
+                    description: description.lines().next().unwrap_or(""),
+                }
+            );
+            Ok(true)
+        }
+    }
+}
@@ -1,17 +1,52 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::Error;
+use std::collections::HashSet;
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     match m.subcommand_name() {
+        Some("archive") => archive_run(m.subcommand_matches("archive").unwrap(), true),
         Some("clone") => clone_run(m.subcommand_matches("clone").unwrap()),
         Some("delete") => delete_run(m.subcommand_matches("delete").unwrap()),
+        Some("fork") => fork_run(m.subcommand_matches("fork").unwrap()),
         Some("list") => list_run(m.subcommand_matches("list").unwrap()),
         Some("new") => new_run(m.subcommand_matches("new").unwrap()),
+        Some("protect") => protect_run(m.subcommand_matches("protect").unwrap(), true),
         Some("switch") => switch_run(m.subcommand_matches("switch").unwrap()),
+        Some("unarchive") => archive_run(m.subcommand_matches("unarchive").unwrap(), false),
+        Some("unprotect") => protect_run(m.subcommand_matches("unprotect").unwrap(), false),
         _ => panic!("Unknown subcommand"),
     }
 }
 
+fn archive_run(m: &ArgMatches<'_>, archived: bool) -> Result<(), Error> {
+    // The unwrap is ok, because NAME is a required argument.
+    let name = m.value_of("NAME").unwrap();
+    let mut repo = crate::open_repo()?;
+    repo.set_archived(name, archived)?;
+    repo.write()?;
+    if archived {
+        eprintln!("{}", Msg::BranchArchived(name));
+    } else {
+        eprintln!("{}", Msg::BranchUnarchived(name));
+    }
+    Ok(())
+}
+
+fn protect_run(m: &ArgMatches<'_>, protected: bool) -> Result<(), Error> {
+    // The unwrap is ok, because NAME is a required argument.
+    let name = m.value_of("NAME").unwrap();
+    let mut repo = crate::open_repo()?;
+    repo.set_protected(name, protected)?;
+    repo.write()?;
+    if protected {
+        eprintln!("{}", Msg::BranchProtected(name));
+    } else {
+        eprintln!("{}", Msg::BranchUnprotected(name));
+    }
+    Ok(())
+}
+
 fn clone_run(m: &ArgMatches<'_>) -> Result<(), Error> {
     // The unwrap is ok, because NAME is a required argument.
     let name = m.value_of("NAME").unwrap();
@@ -19,7 +54,13 @@ fn clone_run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let cur_branch = repo.current_branch.clone();
     repo.clone_branch(&cur_branch, name)?;
     repo.write()?;
-    eprintln!("Cloned branch \"{}\" to branch \"{}\"", cur_branch, name);
+    eprintln!(
+        "{}",
+        Msg::BranchCloned {
+            from: &cur_branch,
+            to: name
+        }
+    );
     Ok(())
 }
 
@@ -27,21 +68,71 @@ fn delete_run(m: &ArgMatches<'_>) -> Result<(), Error> {
     // The unwrap is ok, because NAME is a required argument.
     let name = m.value_of("NAME").unwrap();
     let mut repo = crate::open_repo()?;
-    repo.delete_branch(name)?;
+    crate::with_force(&mut repo, name, m, |repo| repo.delete_branch(name))?;
+    repo.write()?;
+    eprintln!("{}", Msg::BranchDeleted(name));
+    Ok(())
+}
+
+fn fork_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwraps are ok, because NAME and without are required arguments.
+    let name = m.value_of("NAME").unwrap();
+    let mut repo = crate::open_repo()?;
+    let cur_branch = repo.current_branch.clone();
+
+    // Leave out the requested patches, and everything that depends on them.
+    let mut without = m
+        .values_of("without")
+        .unwrap()
+        .map(|p| repo.resolve_patch(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    for p in &without {
+        if !repo.is_applied(&cur_branch, p) {
+            return Err(libojo::Error::NotApplied(*p, cur_branch).into());
+        }
+    }
+    let mut excluded = HashSet::new();
+    while let Some(p) = without.pop() {
+        if excluded.insert(p) {
+            without.extend(repo.patch_rev_deps(&p).cloned());
+        }
+    }
+    let keep = repo
+        .patches(&cur_branch)
+        .filter(|p| !excluded.contains(p))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    repo.fork_branch_at(&cur_branch, name, &keep)?;
     repo.write()?;
-    eprintln!("Deleted branch \"{}\"", name);
+    eprintln!(
+        "{}",
+        Msg::BranchForked {
+            from: &cur_branch,
+            to: name,
+            left_out: repo.patches(&cur_branch).count() - keep.len(),
+        }
+    );
     Ok(())
 }
 
-fn list_run(_m: &ArgMatches<'_>) -> Result<(), Error> {
+fn list_run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let repo = crate::open_repo()?;
-    let mut branches = repo.branches().collect::<Vec<_>>();
+    let mut branches = match m.value_of("PATTERN") {
+        Some(pattern) => repo.branches_matching(pattern).collect::<Vec<_>>(),
+        None => repo.branches().collect::<Vec<_>>(),
+    };
+    // Archived branches are hidden unless asked for, but the current branch is always shown.
+    if !m.is_present("all") {
+        branches.retain(|b| !repo.is_archived(b) || **b == repo.current_branch);
+    }
     branches.sort();
     for b in branches {
-        if b == repo.current_branch {
-            println!("* {}", b);
+        let marker = if *b == repo.current_branch { "*" } else { " " };
+        if repo.is_archived(b) {
+            println!("{} {}", marker, Msg::ArchivedBranch(b));
         } else {
-            println!("  {}", b);
+            println!("{} {}", marker, b);
         }
     }
     Ok(())
@@ -53,7 +144,7 @@ fn new_run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let mut repo = crate::open_repo()?;
     repo.create_branch(name)?;
     repo.write()?;
-    eprintln!("Created empty branch \"{}\"", name);
+    eprintln!("{}", Msg::BranchCreated(name));
     Ok(())
 }
 
@@ -63,6 +154,6 @@ fn switch_run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let mut repo = crate::open_repo()?;
     repo.switch_branch(name)?;
     repo.write()?;
-    eprintln!("Current branch is \"{}\"", name);
+    eprintln!("{}", Msg::BranchSwitched(name));
     Ok(())
 }
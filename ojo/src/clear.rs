@@ -1,10 +1,40 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::Error;
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let mut repo = super::open_repo()?;
     let branch = super::branch(&repo, m);
-    repo.clear(&branch)?;
-    repo.write()?;
+
+    if m.is_present("dry-run") {
+        let summary = repo.preview_clear(&branch)?;
+        println!(
+            "{}",
+            Msg::ClearPreview {
+                branch: &branch,
+                summary: &summary,
+            }
+        );
+        return Ok(());
+    }
+
+    if m.is_present("keep-contents") {
+        // The unwrap is ok because --keep-contents requires --author.
+        let author = m.value_of("author").unwrap();
+        let baseline = super::with_force(&mut repo, &branch, m, |repo| {
+            repo.clear_keeping_contents(&branch, author, "Baseline")
+        })?;
+        repo.write()?;
+        eprintln!(
+            "{}",
+            Msg::ClearedKeepingContents {
+                branch: &branch,
+                baseline: &baseline,
+            }
+        );
+    } else {
+        super::with_force(&mut repo, &branch, m, |repo| repo.clear(&branch))?;
+        repo.write()?;
+    }
     Ok(())
 }
@@ -1,9 +1,11 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use colored::*;
 use failure::{Error, Fail};
-use libojo::Repo;
+use libojo::{PatchId, Repo};
 use ojo_diff::LineDiff;
 use std::fmt;
+use std::io::Read;
 
 pub struct DiffDisplay(pub libojo::Diff);
 
@@ -28,32 +30,103 @@ impl fmt::Display for DiffDisplay {
     }
 }
 
-pub fn diff(repo: &Repo, branch: &str, file_name: &str) -> Result<libojo::Diff, Error> {
-    let mut path = repo.root_dir.clone();
-    path.push(file_name);
-    let fs_file_contents = std::fs::read(&path)
-        .map_err(|e| e.context(format!("Could not read the file {}", file_name)))?;
-
-    let ret = repo.diff(branch, &fs_file_contents[..]).map_err(|e| {
-        if let libojo::Error::NotOrdered = e {
-            e.context(format!(
-                "Cannot create a diff because the repo's contents aren't ordered"
-            ))
-            .into()
+// Diffs a branch against a file, or against stdin if `file_name` is "-".
+//
+// Unless `precise` is set, the diff of a very large file may be coarse (see
+// `Repo::coarse_diff_lines`), in which case we print a warning.
+pub fn diff(
+    repo: &Repo,
+    branch: &str,
+    file_name: &str,
+    precise: bool,
+) -> Result<libojo::Diff, Error> {
+    let ret = if file_name == "-" {
+        if precise {
+            let mut contents = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut contents)?;
+            repo.diff(branch, &contents[..])
+        } else {
+            repo.diff_reader(branch, std::io::stdin().lock())
+        }
+    } else {
+        let mut path = repo.root_dir.clone();
+        path.push(file_name);
+        let fs_file_contents = std::fs::read(&path)
+            .map_err(|e| e.context(Msg::DiffReadFailed(file_name).to_string()))?;
+        if precise {
+            repo.diff(branch, &fs_file_contents[..])
+        } else {
+            repo.diff_incremental(branch, &fs_file_contents[..])
+        }
+    };
+
+    let ret = ret.map_err(|e| {
+        if let libojo::Error::NotOrdered(_) = e {
+            e.context(Msg::DiffNotOrdered.to_string()).into()
         } else {
             Error::from(e)
         }
     });
-    Ok(ret?)
+    let ret = ret?;
+    if ret.coarse {
+        eprintln!("{}", Msg::CoarseDiff(repo.coarse_diff_lines()));
+    }
+    Ok(ret)
+}
+
+// Diffs the branch against what it would look like with some patches applied or unapplied.
+fn preview_diff(
+    repo: &Repo,
+    branch: &str,
+    apply: &[PatchId],
+    unapply: &[PatchId],
+) -> Result<libojo::Diff, Error> {
+    let file_a = repo.file(branch)?;
+    let file_b = repo
+        .preview_with_patches(branch, apply, unapply)
+        .map_err(|e| {
+            if let libojo::Error::NotOrdered(_) = e {
+                e.context(Msg::PreviewNotOrdered.to_string()).into()
+            } else {
+                Error::from(e)
+            }
+        })?;
+    Ok(libojo::Diff::new(file_a, file_b))
 }
 
-pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+// Computes the diff that `ojo diff` asked for, without printing anything.
+pub fn compute(m: &ArgMatches<'_>) -> Result<libojo::Diff, Error> {
     let repo = super::open_repo()?;
     let branch = super::branch(&repo, m);
-    let file_name = super::file_path(m);
+    let patches = |arg| -> Result<Vec<PatchId>, Error> {
+        m.values_of(arg)
+            .into_iter()
+            .flatten()
+            .map(|p| Ok(repo.resolve_patch(p)?))
+            .collect()
+    };
+    let apply = patches("with")?;
+    let unapply = patches("unapplied")?;
 
-    let diff = diff(&repo, &branch, &file_name)?;
-    print!("{}", DiffDisplay(diff));
+    if apply.is_empty() && unapply.is_empty() {
+        let file_name = super::file_path(m);
+        diff(&repo, &branch, &file_name, m.is_present("precise"))
+    } else {
+        preview_diff(&repo, &branch, &apply, &unapply)
+    }
+}
 
-    Ok(())
+// Returns true if the diff has any changes in it.
+pub fn has_changes(diff: &libojo::Diff) -> bool {
+    diff.diff.iter().any(|ch| !matches!(ch, LineDiff::Keep(..)))
+}
+
+// Runs `ojo diff`, returning true if there were any changes.
+pub fn run(m: &ArgMatches<'_>) -> Result<bool, Error> {
+    let diff = compute(m)?;
+    let changed = has_changes(&diff);
+    if !m.is_present("quiet") {
+        print!("{}", DiffDisplay(diff));
+    }
+    Ok(changed)
 }
@@ -0,0 +1,55 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{err_msg, Error};
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = super::open_repo()?;
+    let repair = m.is_present("repair");
+    let branches = repo.branches().map(|b| b.to_owned()).collect::<Vec<_>>();
+
+    let mut found_problems = false;
+    // Some problems can't be fixed by `--repair`.
+    let mut unrepairable = false;
+    for branch in &branches {
+        if let Err(missing) = repo.verify_branch_closure(branch) {
+            let missing = missing
+                .iter()
+                .map(|p| repo.abbreviate_patch_id(p))
+                .collect::<Vec<_>>()
+                .join(", ");
+            eprintln!(
+                "{}",
+                Msg::MissingDeps {
+                    branch,
+                    patches: &missing
+                }
+            );
+            unrepairable = true;
+        }
+
+        if repair {
+            if repo.rebuild_pseudo_edges(branch)? {
+                eprintln!("{}", Msg::PseudoEdgesRepaired(branch));
+                found_problems = true;
+            }
+        } else if repo.stale_pseudo_edges(branch)? {
+            eprintln!("{}", Msg::PseudoEdgesStale(branch));
+            found_problems = true;
+        }
+    }
+
+    if !found_problems && !unrepairable {
+        eprintln!("{}", Msg::NoProblems);
+        return Ok(());
+    }
+    if found_problems && repair {
+        repo.write()?;
+    }
+    if unrepairable {
+        Err(err_msg(Msg::UnrepairableProblems.to_string()))
+    } else if !repair {
+        Err(err_msg(Msg::ProblemsFound.to_string()))
+    } else {
+        Ok(())
+    }
+}
@@ -1,96 +1,79 @@
-use askama_escape::escape;
+use crate::messages::Msg;
 use clap::ArgMatches;
-use failure::Error;
-use libojo::ChainGraggle;
-use libojo::{NodeId, Repo};
+use failure::{Error, ResultExt};
+use libojo::{DotOptions, NodeId, Repo};
 use ojo_graph::Graph;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::prelude::*;
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let output = m.value_of("out").unwrap_or("out.dot");
     let repo = super::open_repo()?;
-    let graggle = repo.graggle("master")?;
-    // TODO: allow retrieving only the live graph
-    let graggle_decomp = ChainGraggle::from_graph(graggle.as_full_graph());
 
-    let mut output = File::create(output)?;
-    writeln!(output, "digraph {{")?;
-    for idx in graggle_decomp.nodes() {
-        let chain = graggle_decomp.chain(idx);
-        if chain.len() == 1 {
-            write_single_node(&mut output, &repo, graggle, &chain[0], idx)?;
-        } else {
-            write_chain_node(&mut output, &repo, graggle, chain, idx)?;
-        }
-
-        for nbr_idx in graggle_decomp.out_neighbors(&idx) {
-            writeln!(output, "\"{}\" -> \"{}\";", idx, nbr_idx)?;
-        }
-    }
-    writeln!(output, "}}")?;
+    let patch = m
+        .value_of("patch")
+        .map(|p| repo.resolve_patch(p))
+        .transpose()?;
+    let nodes = m
+        .value_of("around")
+        .map(|n| neighborhood(&repo, m, n))
+        .transpose()?;
+    let opts = DotOptions {
+        patch,
+        nodes,
+        live_only: m.is_present("live-only"),
+        deleted_only: m.is_present("deleted-only"),
+        condense_chains: m.is_present("condense-chains"),
+    };
 
+    let mut output = File::create(output)?;
+    repo.write_graph_dot("master", &mut output, &opts)?;
     Ok(())
 }
 
-fn node_id(n: &NodeId) -> String {
-    format!("{}/{:04}", escape(&n.patch.to_base64()[0..4]), n.node)
-}
-
-fn single_node_label(repo: &Repo, graggle: libojo::Graggle, id: &NodeId) -> String {
-    let contents = String::from_utf8_lossy(repo.contents(&id)).to_string();
+// Returns all the nodes that are within distance `--radius` of the node at line `line` (counting
+// from 1) of the rendered file. Edges are counted in both directions, and the path can go through
+// deleted nodes.
+fn neighborhood(repo: &Repo, m: &ArgMatches<'_>, line: &str) -> Result<HashSet<NodeId>, Error> {
+    let line = line
+        .parse::<usize>()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| failure::format_err!("{}", Msg::InvalidLineNumber(line)))?;
+    let radius = m.value_of("radius").unwrap_or("3");
+    let radius = radius
+        .parse::<usize>()
+        .with_context(|_| Msg::InvalidRadius(radius).to_string())?;
 
-    if graggle.is_live(id) {
-        format!(
-            "<font color=\"gray\">{}:</font> {}",
-            node_id(id),
-            escape(contents.trim_end())
-        )
-    } else {
-        format!(
-            "<s><font color=\"gray\">{}:</font> {}</s>",
-            node_id(id),
-            escape(contents.trim_end())
-        )
+    let file = repo
+        .file("master")
+        .context(Msg::AroundNotOrdered.to_string())?;
+    if line > file.num_nodes() {
+        return Err(failure::format_err!(
+            "{}",
+            Msg::LineOutOfRange {
+                line,
+                lines: file.num_nodes()
+            }
+        ));
     }
-}
-
-fn write_single_node<W: std::io::Write>(
-    mut write: W,
-    repo: &Repo,
-    graggle: libojo::Graggle,
-    id: &NodeId,
-    idx: usize,
-) -> Result<(), Error> {
-    writeln!(
-        write,
-        "\"{}\" [shape=box, style=rounded, label=<{}>]",
-        idx,
-        single_node_label(repo, graggle, id)
-    )?;
-    Ok(())
-}
-
-fn write_chain_node<W: std::io::Write>(
-    mut write: W,
-    repo: &Repo,
-    graggle: libojo::Graggle,
-    ids: &[NodeId],
-    idx: usize,
-) -> Result<(), Error> {
-    let mut label = ids
-        .iter()
-        .map(|id| single_node_label(repo, graggle, id))
-        .collect::<Vec<String>>()
-        .join("<br align=\"left\"/>");
-    // Graphviz defaults to centering the text. To left-align it all, we put <br align="left"/> at
-    // the end of every line (including the last one).
-    label.push_str("<br align=\"left\"/>");
 
-    writeln!(
-        write,
-        "\"{}\" [shape=box, style=rounded, label=<{}>]",
-        idx, label
-    )?;
-    Ok(())
+    let graggle = repo.graggle("master")?;
+    let full = graggle.as_full_graph();
+    let doubled = full.doubled();
+    let mut seen = HashSet::new();
+    let mut frontier = vec![*file.node_id(line - 1)];
+    seen.insert(frontier[0]);
+    for _ in 0..radius {
+        let mut next = Vec::new();
+        for u in frontier {
+            for v in doubled.out_neighbors(&u) {
+                if seen.insert(v) {
+                    next.push(v);
+                }
+            }
+        }
+        frontier = next;
+    }
+    Ok(seen)
 }
@@ -0,0 +1,88 @@
+use libojo::{HookEvent, HookKind, Repo};
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// Registers, as hooks in `repo`, all the executable scripts in the `hooks` subdirectory of the ojo
+// directory. Each script is named after the kind of hook that it implements (for example,
+// `pre-create-patch`), and it receives a description of the event as JSON on its standard input.
+pub fn register_scripts(repo: &mut Repo) {
+    let hooks_dir = repo.repo_dir.join("hooks");
+    for &kind in &HookKind::ALL {
+        let script = hooks_dir.join(kind.name());
+        if is_executable(&script) {
+            let root_dir = repo.root_dir.clone();
+            repo.register_hook(kind, move |event| run_script(&script, &root_dir, event));
+        }
+    }
+}
+
+// Scripts that aren't executable are ignored, so that a hook can be turned off by `chmod -x`.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn event_json(event: &HookEvent<'_>) -> serde_json::Value {
+    match event {
+        HookEvent::PreCreatePatch {
+            author,
+            description,
+            changes,
+        } => json!({
+            "hook": event.kind().name(),
+            "author": author,
+            "description": description,
+            "changes": changes,
+        }),
+        HookEvent::PostApply { branch, patches } => json!({
+            "hook": event.kind().name(),
+            "branch": branch,
+            "patches": patches.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+        }),
+        HookEvent::PostWrite => json!({
+            "hook": event.kind().name(),
+        }),
+        HookEvent::CacheProgress {
+            branch,
+            done,
+            total,
+        } => json!({
+            "hook": event.kind().name(),
+            "branch": branch,
+            "done": done,
+            "total": total,
+        }),
+    }
+}
+
+fn run_script(script: &Path, root_dir: &Path, event: &HookEvent<'_>) -> Result<(), String> {
+    let input = event_json(event).to_string();
+    let mut child = Command::new(script)
+        .current_dir(root_dir)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", script.display(), e))?;
+
+    // The unwrap is ok because we asked for stdin to be piped. We ignore errors while writing,
+    // because the script is allowed to exit without reading its input.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let status = child
+        .wait()
+        .map_err(|e| format!("failed to run {}: {}", script.display(), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", script.display(), status))
+    }
+}
@@ -0,0 +1,92 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{Error, ResultExt};
+use libojo::Changes;
+use std::process::Command;
+
+// A commit that touched the file that we're importing.
+struct Commit {
+    hash: String,
+    author: String,
+    message: String,
+}
+
+// Runs git in the repository `dir`, returning its output.
+fn git(dir: &str, args: &[&str]) -> Result<Vec<u8>, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .with_context(|e| Msg::GitFailed(&e.to_string()).to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", Msg::GitFailed(stderr.trim_end()));
+    }
+    Ok(output.stdout)
+}
+
+// Lists the commits that touched `file`, oldest first. We only follow first parents, because
+// there's no sensible way to import a merge as a single patch.
+fn commits(dir: &str, file: &str) -> Result<Vec<Commit>, Error> {
+    // With `-z`, each commit ends with a NUL byte. The fields are also separated by NUL bytes,
+    // which can't appear in any of them, so each commit is exactly three NUL-terminated fields.
+    let log = git(
+        dir,
+        &[
+            "log",
+            "-z",
+            "--reverse",
+            "--first-parent",
+            "--format=%H%x00%an <%ae>%x00%B",
+            "--",
+            file,
+        ],
+    )?;
+    let log = String::from_utf8_lossy(&log);
+    let fields = log.split_terminator('\0').collect::<Vec<_>>();
+    let ret = fields
+        .chunks_exact(3)
+        .map(|commit| Commit {
+            hash: commit[0].to_owned(),
+            author: commit[1].to_owned(),
+            message: commit[2].trim_end().to_owned(),
+        })
+        .collect();
+    Ok(ret)
+}
+
+// Returns the contents of `file` as of the commit `hash`, or `None` if the file doesn't exist
+// there (because the commit deleted it).
+fn contents_at(dir: &str, hash: &str, file: &str) -> Result<Option<Vec<u8>>, Error> {
+    let listed = git(dir, &["ls-tree", "-z", "--name-only", hash, "--", file])?;
+    if listed.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(git(dir, &["show", &format!("{}:{}", hash, file)])?))
+}
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwraps are ok because these are required arguments.
+    let dir = m.value_of("GIT_DIR").unwrap();
+    let file = m.value_of("FILE").unwrap();
+    let mut repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+
+    let mut count = 0;
+    for commit in commits(dir, file)? {
+        // If the commit deleted the file, we treat that as the file being empty.
+        let contents = contents_at(dir, &commit.hash, file)?.unwrap_or_default();
+        let diff = repo.diff(&branch, &contents[..])?;
+        let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+        if changes.changes.is_empty() {
+            continue;
+        }
+        let id = repo.create_patch_on_branch(&branch, &commit.author, &commit.message, changes)?;
+        repo.apply_patch(&branch, &id)?;
+        count += 1;
+    }
+    repo.write()?;
+    eprintln!("{}", Msg::GitImported(count));
+    Ok(())
+}
@@ -1,12 +1,20 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::{Error, ResultExt};
 use libojo::Repo;
 
-pub fn run(_m: &ArgMatches<'_>) -> Result<(), Error> {
-    let dir = std::env::current_dir().context("Couldn't open the current directory.")?;
-    let repo = Repo::init(&dir)?;
-    repo.write()
-        .context("Failed to write repository to disk.")?;
-    eprintln!("Created empty ojo repository.");
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let dir = crate::working_dir()?;
+    let repo = if let Some(ojo_dir) = m.value_of("separate-ojo-dir") {
+        let repo = Repo::init_with_repo_dir(&dir, ojo_dir)?;
+        repo.link_repo_dir().context(Msg::LinkFailed.to_string())?;
+        repo
+    } else if let Some(ojo_dir) = super::ojo_dir_from_env() {
+        Repo::init_with_repo_dir(&dir, ojo_dir)?
+    } else {
+        Repo::init(&dir)?
+    };
+    repo.write().context(Msg::RepoWriteFailed.to_string())?;
+    eprintln!("{}", Msg::RepoCreated);
     Ok(())
 }
@@ -0,0 +1,34 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{Error, ResultExt};
+use libojo::Limit;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = super::open_repo()?;
+    let mut limits = repo.limits();
+    let mut changed = false;
+    for &limit in &Limit::ALL {
+        if let Some(value) = m.value_of(limit.name()) {
+            let value = value
+                .parse::<usize>()
+                .context(Msg::InvalidLimit(value).to_string())?;
+            match limit {
+                Limit::Nodes => limits.max_nodes = value,
+                Limit::PatchSize => limits.max_patch_size = value,
+                Limit::ContentsSize => limits.max_contents_size = value,
+            }
+            changed = true;
+        }
+    }
+
+    if changed {
+        repo.set_limits(limits);
+        repo.write()?;
+        eprintln!("{}", Msg::LimitsChanged);
+    } else {
+        for &limit in &Limit::ALL {
+            println!("{}: {}", limit, limits.get(limit));
+        }
+    }
+    Ok(())
+}
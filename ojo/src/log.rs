@@ -1,19 +1,90 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
-use failure::Error;
+use failure::{Error, ResultExt};
+use libojo::PatchId;
+
+mod interactive;
+
+// Parses an optional numeric argument.
+fn count_arg(m: &ArgMatches<'_>, name: &str) -> Result<Option<usize>, Error> {
+    match m.value_of(name) {
+        Some(n) => Ok(Some(n.parse::<usize>().with_context(|_| {
+            Msg::InvalidCount {
+                arg: name,
+                value: n,
+            }
+            .to_string()
+        })?)),
+        None => Ok(None),
+    }
+}
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
-    let repo = super::open_repo()?;
+    let mut repo = super::open_repo()?;
     let branch = super::branch(&repo, m);
+    if m.is_present("interactive") {
+        if interactive::run(&mut repo, &branch)? {
+            repo.write()?;
+        }
+        return Ok(());
+    }
+
+    let limit = count_arg(m, "limit")?.unwrap_or(usize::MAX);
+    let skip = count_arg(m, "skip")?.unwrap_or(0);
+
+    let reverse = m.is_present("reverse");
 
-    for patch_id in repo.patches(&branch) {
-        let patch = repo.open_patch(&patch_id)?;
-        println!("patch {}", patch_id.to_base64());
-        println!("Author: {}", patch.header().author);
+    let unapplied;
+    let patches: Box<dyn Iterator<Item = &PatchId>> = if m.is_present("unapplied") {
+        unapplied = repo.unapplied_patches(&branch);
+        if reverse {
+            Box::new(unapplied.iter().rev())
+        } else {
+            Box::new(unapplied.iter())
+        }
+    } else if reverse {
+        Box::new(repo.patches(&branch))
+    } else {
+        Box::new(repo.patches(&branch).rev())
+    };
+    let superseded = repo.superseded_patches();
+    let show_id = |id: &PatchId| {
+        if m.is_present("full-ids") {
+            id.to_string()
+        } else {
+            repo.abbreviate_patch_id(id)
+        }
+    };
+    for patch_id in patches.skip(skip).take(limit) {
+        let header = repo.patch_header(patch_id)?;
+        let id = show_id(patch_id);
+        let aliases = repo.patch_aliases(patch_id).collect::<Vec<_>>();
+        if aliases.is_empty() {
+            println!("patch {}", id);
+        } else {
+            println!("patch {} ({})", id, aliases.join(", "));
+        }
+        println!("{}", Msg::Author(repo.author(&header.author)));
+        for old in header.supersedes() {
+            println!("{}", Msg::Supersedes(&show_id(&old)));
+        }
+        for new in superseded.get(patch_id).into_iter().flatten() {
+            println!("{}", Msg::SupersededBy(&show_id(new)));
+        }
         println!();
         // TODO: dates and sorting.
-        // TODO: better display for multi-line description.
-        println!("\t{}", patch.header().description);
+        for line in header.description.lines() {
+            if line.is_empty() {
+                println!();
+            } else {
+                println!("\t{}", line);
+            }
+        }
         println!();
+        if m.is_present("stat") {
+            println!(" {}", Msg::Stats(&repo.patch_stats(patch_id)?));
+            println!();
+        }
     }
     Ok(())
 }
@@ -0,0 +1,330 @@
+use failure::{Error, ResultExt};
+use libojo::{PatchId, Repo};
+use std::io::Write;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, style};
+
+use crate::messages::Msg;
+use crate::resolve::{draw_keybindings, write_truncated, Input, Screen};
+
+/// Lets the user browse the patches in `branch`, and apply or unapply them.
+///
+/// Returns `true` if the repository was modified (and so needs to be written).
+pub fn run(repo: &mut Repo, branch: &str) -> Result<bool, Error> {
+    let modified = {
+        // As in `ojo resolve`, nothing that we draw here will be visible after this scope ends.
+        let screen: Screen = Box::new(
+            AlternateScreen::from(std::io::stdout())
+                .into_raw_mode()
+                .with_context(|_| Msg::RawModeFailed.to_string())?,
+        );
+        write!(std::io::stdout(), "{}", cursor::Hide)?;
+        let browser = Browser::new(repo, branch, screen, std::io::stdin().keys());
+        browser.run()?
+    };
+    write!(std::io::stdout(), "{}", cursor::Show)?;
+    std::io::stdout().flush()?;
+    Ok(modified)
+}
+
+// An apply or unapply that is waiting for the user to confirm it. The patches are the ones that
+// would be affected, in the order that they would be applied or unapplied.
+enum Pending {
+    Apply(Vec<PatchId>),
+    Unapply(Vec<PatchId>),
+}
+
+struct Browser<'a> {
+    repo: &'a mut Repo,
+    branch: &'a str,
+    screen: Screen,
+    input: Input,
+
+    // The patches that we show: the applied ones (most recently applied first), followed by the
+    // unapplied ones.
+    patches: Vec<PatchId>,
+    // The index (in `patches`) of the selected patch, and of the first one on the screen.
+    selected: usize,
+    shown_first: usize,
+
+    // If this is set, the detail pane shows a preview of this instead of the selected patch.
+    pending: Option<Pending>,
+    // A message to show at the bottom of the screen, like the result of the last command.
+    status: String,
+    modified: bool,
+
+    // Dimensions of the screen.
+    width: u16,
+    height: u16,
+}
+
+impl<'a> Browser<'a> {
+    fn new(repo: &'a mut Repo, branch: &'a str, screen: Screen, input: Input) -> Browser<'a> {
+        let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+        let mut ret = Browser {
+            repo,
+            branch,
+            screen,
+            input,
+            patches: Vec::new(),
+            selected: 0,
+            shown_first: 0,
+            pending: None,
+            status: String::new(),
+            modified: false,
+            width,
+            height,
+        };
+        ret.reload();
+        ret
+    }
+
+    fn reload(&mut self) {
+        let selected = self.patches.get(self.selected).cloned();
+        self.patches = self.repo.patches(self.branch).rev().cloned().collect();
+        self.patches
+            .extend(self.repo.unapplied_patches(self.branch));
+        // Try to keep the same patch selected.
+        self.selected = selected
+            .and_then(|s| self.patches.iter().position(|p| *p == s))
+            .unwrap_or(0);
+    }
+
+    // The number of rows in the patch list. The detail pane gets the rest of the screen, apart from
+    // the divider and the status line.
+    fn list_height(&self) -> usize {
+        (self.height as usize / 2).max(1)
+    }
+
+    fn run(mut self) -> Result<bool, Error> {
+        loop {
+            self.redraw()?;
+            let key = self
+                .input
+                .next()
+                .ok_or_else(|| failure::err_msg(Msg::UnexpectedEndOfInput.to_string()))??;
+
+            if let Some(pending) = self.pending.take() {
+                if key == Key::Char('y') {
+                    self.perform(pending);
+                } else {
+                    self.status.clear();
+                }
+                continue;
+            }
+
+            self.status.clear();
+            match key {
+                Key::Char('j') | Key::Down => {
+                    if self.selected + 1 < self.patches.len() {
+                        self.selected += 1;
+                    }
+                }
+                Key::Char('k') | Key::Up => {
+                    self.selected = self.selected.saturating_sub(1);
+                }
+                Key::Char('a') => self.prepare(true),
+                Key::Char('u') => self.prepare(false),
+                Key::Char('q') | Key::Esc => {
+                    return Ok(self.modified);
+                }
+                _ => {
+                    debug!("unknown key");
+                }
+            }
+        }
+    }
+
+    // Works out what applying (or unapplying) the selected patch would do, and asks the user to
+    // confirm it.
+    fn prepare(&mut self, apply: bool) {
+        let patch = match self.patches.get(self.selected) {
+            Some(p) => *p,
+            None => return,
+        };
+        let abbrev = self.repo.abbreviate_patch_id(&patch);
+        let applied = self.repo.is_applied(self.branch, &patch);
+        let pending = if apply && applied {
+            Err(Msg::PatchAlreadyApplied {
+                patch: &abbrev,
+                branch: self.branch,
+            }
+            .to_string())
+        } else if !apply && !applied {
+            Err(Msg::PatchNotApplied {
+                patch: &abbrev,
+                branch: self.branch,
+            }
+            .to_string())
+        } else if apply {
+            self.repo
+                .patches_to_apply(self.branch, &[patch])
+                .map(Pending::Apply)
+                .map_err(|e| e.to_string())
+        } else {
+            self.repo
+                .patches_to_unapply(self.branch, &[patch])
+                .map(Pending::Unapply)
+                .map_err(|e| e.to_string())
+        };
+        match pending {
+            Ok(pending) => {
+                self.status = Msg::LogConfirm.to_string();
+                self.pending = Some(pending);
+            }
+            Err(msg) => self.status = msg,
+        }
+    }
+
+    fn perform(&mut self, pending: Pending) {
+        let result = match pending {
+            Pending::Apply(patches) => self
+                .repo
+                .apply_patches(self.branch, &patches)
+                .map(|p| Msg::LogApplied(p.len()).to_string()),
+            Pending::Unapply(patches) => self
+                .repo
+                .unapply_patches(self.branch, &patches)
+                .map(|p| Msg::LogUnapplied(p.len()).to_string()),
+        };
+        match result {
+            Ok(msg) => {
+                self.status = msg;
+                self.modified = true;
+                self.reload();
+            }
+            Err(e) => self.status = e.to_string(),
+        }
+    }
+
+    fn redraw(&mut self) -> Result<(), Error> {
+        write!(self.screen, "{}", clear::All)?;
+
+        let list_height = self.list_height();
+        if self.selected < self.shown_first {
+            self.shown_first = self.selected;
+        } else if self.selected >= self.shown_first + list_height {
+            self.shown_first = self.selected + 1 - list_height;
+        }
+
+        // Leave room for the keybindings on the right.
+        let list_width = self.width.saturating_sub(21).max(4);
+        let end = self.patches.len().min(self.shown_first + list_height);
+        for (i, p) in self.patches[self.shown_first..end].iter().enumerate() {
+            let row = 1 + i as u16;
+            let marker = if self.repo.is_applied(self.branch, p) {
+                '*'
+            } else {
+                ' '
+            };
+            let summary = self
+                .repo
+                .patch_header(p)
+                .map(|header| header.description.lines().next().unwrap_or("").to_owned())
+                .unwrap_or_default();
+            let line = format!(
+                "{} {} {}",
+                marker,
+                self.repo.abbreviate_patch_id(p),
+                summary
+            );
+            if self.shown_first + i == self.selected {
+                write!(self.screen, "{}", style::Invert)?;
+                write_truncated(&mut self.screen, line.as_bytes(), 1, row, list_width)?;
+                write!(self.screen, "{}", style::Reset)?;
+            } else {
+                write_truncated(&mut self.screen, line.as_bytes(), 1, row, list_width)?;
+            }
+        }
+
+        let divider_row = list_height as u16 + 1;
+        write!(
+            self.screen,
+            "{goto}{line}",
+            goto = cursor::Goto(1, divider_row),
+            line = "═".repeat(self.width as usize)
+        )?;
+
+        let details = match self.pending {
+            Some(ref pending) => self.preview(pending),
+            None => self.details()?,
+        };
+        let max_rows = self.height.saturating_sub(divider_row + 2) as usize;
+        for (i, line) in details.iter().take(max_rows).enumerate() {
+            let row = divider_row + 1 + i as u16;
+            write_truncated(&mut self.screen, line.as_bytes(), 1, row, self.width)?;
+        }
+
+        write_truncated(
+            &mut self.screen,
+            self.status.as_bytes(),
+            1,
+            self.height,
+            self.width,
+        )?;
+
+        let keybindings = if self.pending.is_some() {
+            vec![("y", Msg::KeyConfirm), ("n", Msg::KeyCancel)]
+        } else {
+            vec![
+                ("k", Msg::KeySelectPrevious),
+                ("j", Msg::KeySelectNext),
+                ("a", Msg::KeyApply),
+                ("u", Msg::KeyUnapply),
+                ("ESC", Msg::KeyQuit),
+            ]
+        };
+        draw_keybindings(&mut self.screen, keybindings, self.width)?;
+
+        self.screen.flush()?;
+        Ok(())
+    }
+
+    // The lines describing the selected patch, in the same format as `ojo patch show`.
+    fn details(&self) -> Result<Vec<String>, Error> {
+        let id = match self.patches.get(self.selected) {
+            Some(id) => id,
+            None => return Ok(vec![Msg::LogNoPatches(self.branch).to_string()]),
+        };
+        let header = self.repo.patch_header(id)?;
+        let mut ret = vec![
+            format!("patch {}", id),
+            Msg::Author(self.repo.author(&header.author)).to_string(),
+            Msg::Date(&header.timestamp).to_string(),
+        ];
+        for dep in self.repo.patch_deps(id) {
+            ret.push(Msg::DependsOn(&self.repo.abbreviate_patch_id(dep)).to_string());
+        }
+        ret.push(format!(" {}", Msg::Stats(&self.repo.patch_stats(id)?)));
+        ret.push(String::new());
+        ret.extend(header.description.lines().map(|l| format!("    {}", l)));
+        Ok(ret)
+    }
+
+    // The lines describing what would happen if `pending` were confirmed.
+    fn preview(&self, pending: &Pending) -> Vec<String> {
+        let (patches, result) = match pending {
+            Pending::Apply(ps) => (ps, self.repo.preview_with_patches(self.branch, ps, &[])),
+            Pending::Unapply(ps) => (ps, self.repo.preview_with_patches(self.branch, &[], ps)),
+        };
+        let mut ret = vec![match pending {
+            Pending::Apply(_) => Msg::LogWouldApply(patches.len()).to_string(),
+            Pending::Unapply(_) => Msg::LogWouldUnapply(patches.len()).to_string(),
+        }];
+        ret.extend(
+            patches
+                .iter()
+                .map(|p| format!("    {}", self.repo.abbreviate_patch_id(p))),
+        );
+        ret.push(String::new());
+        ret.push(match result {
+            Ok(file) => Msg::LogWouldHaveLines(file.num_nodes()).to_string(),
+            Err(libojo::Error::NotOrdered(_)) => Msg::LogWouldNotBeOrdered.to_string(),
+            Err(e) => e.to_string(),
+        });
+        ret
+    }
+}
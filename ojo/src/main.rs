@@ -7,21 +7,43 @@ extern crate failure;
 #[macro_use]
 extern crate log as other_log;
 
+use crate::messages::Msg;
 use clap::{App, ArgMatches};
 use failure::{Error, ResultExt};
 use flexi_logger::Logger;
 use libojo::Repo;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 
+mod admin;
+mod bisect;
 mod branch;
 mod clear;
 mod diff;
+mod fsck;
 mod graph;
+mod hooks;
+mod import_git;
 mod init;
+mod limits;
 mod log;
+mod messages;
+mod pack;
 pub mod patch;
 mod render;
+mod repl;
 mod resolve;
+mod restore;
+mod stash;
 mod synthesize;
+mod watch;
+
+// Set by the `--paranoid` flag, and applied to every repository that we open.
+static PARANOID: AtomicBool = AtomicBool::new(false);
+
+// Set by the `--repo` flag. If it's unset, commands operate on the current directory.
+static REPO_DIR: OnceLock<PathBuf> = OnceLock::new();
 
 fn main() {
     let yml = load_yaml!("main.yaml");
@@ -32,41 +54,99 @@ fn main() {
         .start()
         .unwrap_or_else(|e| panic!("Logger initialization failed with {}", e));
 
+    PARANOID.store(m.is_present("paranoid"), Ordering::Relaxed);
+    if let Some(dir) = m.value_of_os("repo") {
+        REPO_DIR.set(PathBuf::from(dir)).unwrap();
+    }
+
     let result = match m.subcommand_name() {
+        // Like `diff`, `ojo diff` exits with 1 if there are changes, and 2 if there was an error.
+        Some("diff") => match diff::run(m.subcommand_matches("diff").unwrap()) {
+            Ok(changed) => std::process::exit(changed as i32),
+            Err(e) => {
+                report_error(&e);
+                std::process::exit(2);
+            }
+        },
+        Some("repl") => repl::run(m.subcommand_matches("repl").unwrap()),
+        _ => run(&m),
+    };
+
+    if let Err(e) = result {
+        report_error(&e);
+        std::process::exit(1);
+    }
+}
+
+// Runs the subcommand in `m` (which can't be `repl`).
+fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    match m.subcommand_name() {
+        Some("admin") => admin::run(m.subcommand_matches("admin").unwrap()),
+        Some("bisect") => bisect::run(m.subcommand_matches("bisect").unwrap()),
         Some("branch") => branch::run(m.subcommand_matches("branch").unwrap()),
         Some("clear") => clear::run(m.subcommand_matches("clear").unwrap()),
-        Some("diff") => diff::run(m.subcommand_matches("diff").unwrap()),
+        Some("diff") => diff::run(m.subcommand_matches("diff").unwrap()).map(|_| ()),
+        Some("fsck") => fsck::run(m.subcommand_matches("fsck").unwrap()),
         Some("graph") => graph::run(m.subcommand_matches("graph").unwrap()),
+        Some("import-git") => import_git::run(m.subcommand_matches("import-git").unwrap()),
         Some("init") => init::run(m.subcommand_matches("init").unwrap()),
+        Some("limits") => limits::run(m.subcommand_matches("limits").unwrap()),
         Some("log") => log::run(m.subcommand_matches("log").unwrap()),
+        Some("pack") => pack::run_pack(m.subcommand_matches("pack").unwrap()),
         Some("patch") => patch::run(m.subcommand_matches("patch").unwrap()),
         Some("render") => render::run(m.subcommand_matches("render").unwrap()),
         Some("resolve") => resolve::run(m.subcommand_matches("resolve").unwrap()),
+        Some("restore") => restore::run(m.subcommand_matches("restore").unwrap()),
+        Some("stash") => stash::run(m.subcommand_matches("stash").unwrap()),
         Some("synthesize") => synthesize::run(m.subcommand_matches("synthesize").unwrap()),
+        Some("unpack") => pack::run_unpack(m.subcommand_matches("unpack").unwrap()),
+        Some("watch") => watch::run(m.subcommand_matches("watch").unwrap()),
         _ => panic!("Unknown subcommand"),
-    };
+    }
+}
 
-    if let Err(e) = result {
-        println!("Error: {}", e);
-        for cause in e.iter_causes() {
-            println!("\tcaused by: {}", cause);
-        }
-        std::process::exit(1);
+fn report_error(e: &Error) {
+    println!("{}", Msg::Error(e));
+    for cause in e.iter_causes() {
+        println!("{}", Msg::CausedBy(&cause));
     }
 }
 
-fn open_repo() -> Result<libojo::Repo, Error> {
-    let mut dir = std::env::current_dir().context("Could not open the current directory")?;
-    loop {
-        let mut ojo_dir = dir.clone();
-        ojo_dir.push(".ojo");
-        if ojo_dir.is_dir() {
-            return Ok(libojo::Repo::open(dir).context("Failed to open the ojo repository")?);
-        }
-        if !dir.pop() {
-            bail!("Failed to find a ojo repository");
-        }
+// If the OJO_DIR environment variable is set, it overrides the location of the directory where
+// ojo's data is stored. Relative paths are relative to the repository's root directory.
+fn ojo_dir_from_env() -> Option<PathBuf> {
+    std::env::var_os("OJO_DIR").map(PathBuf::from)
+}
+
+// The directory that commands operate on: the one given by `--repo`, or else the current one.
+fn working_dir() -> Result<PathBuf, Error> {
+    let cur_dir = std::env::current_dir().context(Msg::NoCurrentDir.to_string())?;
+    // libojo wants absolute paths, so a relative `--repo` is relative to the current directory.
+    Ok(match REPO_DIR.get() {
+        Some(dir) => cur_dir.join(dir),
+        None => cur_dir,
+    })
+}
+
+// Opens the repository that commands operate on. Under `ojo repl`, this is the repl's repository
+// instead of a fresh copy from disk.
+fn open_repo() -> Result<repl::OpenRepo, Error> {
+    if let Some(repo) = repl::OpenRepo::lent() {
+        return Ok(repo);
     }
+    let dir = working_dir()?;
+    let repo = if let Some(ojo_dir) = ojo_dir_from_env() {
+        libojo::Repo::open_with_repo_dir(dir, ojo_dir)
+    } else {
+        libojo::Repo::discover(dir)
+    };
+    let mut repo = match repo {
+        Err(libojo::Error::RepoNotFound(_)) => bail!("{}", Msg::RepoNotFound),
+        repo => repo.context(Msg::RepoOpenFailed.to_string())?,
+    };
+    hooks::register_scripts(&mut repo);
+    repo.set_paranoid(PARANOID.load(Ordering::Relaxed));
+    Ok(repl::OpenRepo::owned(repo))
 }
 
 fn branch(repo: &Repo, m: &ArgMatches<'_>) -> String {
@@ -75,6 +155,29 @@ fn branch(repo: &Repo, m: &ArgMatches<'_>) -> String {
         .to_owned()
 }
 
+// Runs `f`, which modifies `branch`. If the `--force` flag was given, `branch` is allowed to be
+// protected (see `ojo branch protect`): the protection is lifted while `f` runs.
+fn with_force<T, F>(repo: &mut Repo, branch: &str, m: &ArgMatches<'_>, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut Repo) -> Result<T, libojo::Error>,
+{
+    let lifted = m.is_present("force") && repo.is_protected(branch);
+    if lifted {
+        repo.set_protected(branch, false)?;
+    }
+    let ret = f(repo);
+    // The branch might not exist any more, if `f` deleted it.
+    if lifted && repo.branches().any(|b| b == branch) {
+        repo.set_protected(branch, true)?;
+    }
+    Ok(ret?)
+}
+
+// The path of the working file. For commands that accept it, this may be "-", meaning stdin.
 fn file_path(m: &ArgMatches<'_>) -> String {
-    m.value_of("path").unwrap_or("ojo_file.txt").to_owned()
+    if m.is_present("from-stdin") {
+        "-".to_owned()
+    } else {
+        m.value_of("path").unwrap_or("ojo_file.txt").to_owned()
+    }
 }
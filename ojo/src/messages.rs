@@ -0,0 +1,782 @@
+// Copyright 2018-2019 Joe Neeman.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+//
+// See the LICENSE-APACHE or LICENSE-MIT files at the top-level directory
+// of this distribution.
+
+//! The user-facing text of the command line interface.
+//!
+//! Every message that we show to the user is a variant of [`Msg`], and the actual text of the
+//! messages comes from a [`Catalog`]. To translate the interface, implement `Catalog` for the new
+//! language and add it to [`catalog`].
+//!
+//! The catalog is chosen by the `OJO_LOCALE` environment variable. Besides English (the default),
+//! there is a "pseudo" locale that decorates the English text, which makes it easy to spot text
+//! that doesn't go through the catalog.
+
+use libojo::{ClearSummary, PatchStats};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// A message that can be shown to the user.
+///
+/// Messages are displayed using the current [`catalog`].
+#[derive(Clone, Copy)]
+pub enum Msg<'a> {
+    // General messages.
+    Error(&'a dyn fmt::Display),
+    CausedBy(&'a dyn fmt::Display),
+    NoCurrentDir,
+    RepoNotFound,
+    RepoOpenFailed,
+    RepoWriteFailed,
+    ReadFailed(&'a str),
+    WriteFailed(&'a str),
+    CreateFailed(&'a str),
+    WroteFile(&'a str),
+    NoChanges,
+    PatchCreated(&'a dyn fmt::Display),
+    PatchCreatedAndApplied(&'a dyn fmt::Display),
+    Stashed(&'a str),
+
+    // admin
+    CoarseDiffChanged(usize),
+    Reattributed {
+        from: &'a str,
+        to: &'a str,
+    },
+    LineageChanged,
+    LineageIdentity(&'a str),
+    LineagePolicy(&'a str),
+    NoIdentity,
+
+    // bisect
+    BisectUncommitted(&'a str),
+    BisectTesting {
+        patch: &'a str,
+        remaining: usize,
+        path: &'a str,
+    },
+    BisectFound {
+        patch: &'a str,
+        description: &'a str,
+    },
+    BisectReset(&'a str),
+    BisectScriptFailed(&'a str),
+    BisectScriptKilled {
+        script: &'a str,
+        status: &'a dyn fmt::Display,
+    },
+
+    // branch
+    BranchArchived(&'a str),
+    BranchUnarchived(&'a str),
+    BranchProtected(&'a str),
+    BranchUnprotected(&'a str),
+    BranchCloned {
+        from: &'a str,
+        to: &'a str,
+    },
+    BranchDeleted(&'a str),
+    BranchForked {
+        from: &'a str,
+        to: &'a str,
+        left_out: usize,
+    },
+    BranchCreated(&'a str),
+    BranchSwitched(&'a str),
+    ArchivedBranch(&'a str),
+
+    // clear
+    ClearPreview {
+        branch: &'a str,
+        summary: &'a ClearSummary,
+    },
+    ClearedKeepingContents {
+        branch: &'a str,
+        baseline: &'a dyn fmt::Display,
+    },
+
+    // diff
+    DiffReadFailed(&'a str),
+    DiffNotOrdered,
+    CoarseDiff(usize),
+    PreviewNotOrdered,
+
+    // fsck
+    PseudoEdgesRepaired(&'a str),
+    PseudoEdgesStale(&'a str),
+    MissingDeps {
+        branch: &'a str,
+        patches: &'a str,
+    },
+    NoProblems,
+    ProblemsFound,
+    UnrepairableProblems,
+
+    // import-git
+    GitFailed(&'a str),
+    GitImported(usize),
+
+    // graph
+    InvalidLineNumber(&'a str),
+    InvalidRadius(&'a str),
+    AroundNotOrdered,
+    LineOutOfRange {
+        line: usize,
+        lines: usize,
+    },
+
+    // init
+    LinkFailed,
+    RepoCreated,
+
+    // limits
+    InvalidLimit(&'a str),
+    LimitsChanged,
+
+    // log
+    InvalidCount {
+        arg: &'a str,
+        value: &'a str,
+    },
+    Stats(&'a PatchStats),
+    LogNoPatches(&'a str),
+    LogConfirm,
+    LogApplied(usize),
+    LogUnapplied(usize),
+    LogWouldApply(usize),
+    LogWouldUnapply(usize),
+    LogWouldHaveLines(usize),
+    LogWouldNotBeOrdered,
+    KeySelectPrevious,
+    KeySelectNext,
+    KeyApply,
+    KeyUnapply,
+    KeyConfirm,
+    KeyCancel,
+
+    // pack
+    BaselineFailed(&'a str),
+    Unpacked,
+
+    // patch
+    PatchNotApplied {
+        patch: &'a str,
+        branch: &'a str,
+    },
+    PatchAlreadyApplied {
+        patch: &'a str,
+        branch: &'a str,
+    },
+    AppliedPatches,
+    UnappliedPatches,
+    NothingToApply,
+    NothingToUnapply,
+    PatchDependsOn {
+        dep: &'a str,
+        patch: &'a str,
+    },
+    PatchAliased {
+        patch: &'a str,
+        name: &'a str,
+    },
+    PatchImported(&'a dyn fmt::Display),
+    DownloadFailed(&'a str),
+    EquivalentPatch(&'a str),
+    ForeignPatch {
+        patch: &'a str,
+        repository: &'a str,
+    },
+    NoEditor,
+    DescriptionTemplate,
+    TemplateWriteFailed,
+    EditorFailed(&'a str),
+    EditorExited {
+        editor: &'a str,
+        status: &'a dyn fmt::Display,
+    },
+    DescriptionReadFailed,
+    EmptyDescription,
+    CreatesFile,
+    Author(&'a str),
+    Date(&'a dyn fmt::Display),
+    Branch(&'a str),
+    FormatVersion(u32),
+    DependsOn(&'a str),
+    Repository(&'a str),
+    Supersedes(&'a str),
+    SupersededBy(&'a str),
+    InvalidLineRanges(&'a str),
+    AmendNeededBy {
+        patch: &'a str,
+        dependent: &'a str,
+    },
+    AmendAppliedElsewhere {
+        patch: &'a str,
+        branch: &'a str,
+    },
+    AmendNothingLeft,
+    PatchAmended {
+        old: &'a str,
+        new: &'a dyn fmt::Display,
+    },
+
+    // render
+    RenderNotOrdered,
+    RenderConflict(&'a str, &'a str),
+    RenderDisorder {
+        cycles: usize,
+        unordered: usize,
+        edits: usize,
+    },
+    RenderNear(&'a str),
+
+    // repl
+    ReplGlobalOption(&'a str),
+    ReplNested,
+    ReplUnterminatedQuote,
+    ReplWatch,
+
+    // resolve
+    RawModeFailed,
+    UnexpectedEndOfInput,
+    NoPatchCreated,
+    ScriptInvalidStep {
+        line: usize,
+        text: &'a str,
+    },
+    ScriptNoSuchChoice(usize),
+    ScriptExpectedCycle(usize),
+    ScriptExpectedOrder(usize),
+    ScriptExpectedVersion(usize),
+    ScriptUnexpectedVersion(usize),
+    ScriptEnded,
+    ScriptTooLong(usize),
+    ResolveChooseVersion,
+    KeyChooseLine,
+    KeyChooseVersion,
+    KeyShowPrevious,
+    KeyShowNext,
+    KeyQuit,
+    KeyTakeOne,
+    KeyDeleteOne,
+    KeyTakeAll,
+    KeyDeleteAll,
+    KeyTakeLeft,
+    KeyTakeRight,
+    KeyDeleteLeft,
+    KeyDeleteRight,
+    KeyTakeLine,
+    KeyDeleteLine,
+    KeyTakeLines,
+    KeyDeleteLines,
+
+    // restore
+    RestoreNotOrdered(&'a str),
+    Restored {
+        path: &'a str,
+        patch: &'a str,
+    },
+
+    // stash
+    StashEntry {
+        index: usize,
+        branch: &'a str,
+        description: &'a str,
+    },
+    InvalidStashIndex(&'a str),
+    StashPopNotOrdered,
+    StashPopMismatch(&'a str),
+    StashPopped(usize),
+    NothingToStash,
+
+    // synthesize
+    InvalidSize(&'a str),
+    StdinNotUtf8,
+    EdgeParseFailed(&'a str),
+    EmptyInput,
+    Synthesized,
+
+    // watch
+    InvalidInterval(&'a str),
+    WatchStartFailed,
+    NoParentDir(&'a str),
+    WatchFailed(&'a str),
+    Watching(&'a str),
+    WatchEventFailed,
+    WatcherStopped,
+}
+
+impl<'a> fmt::Display for Msg<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        catalog().write(self, f)
+    }
+}
+
+/// A collection of translations for all the messages in [`Msg`].
+pub trait Catalog: Sync {
+    /// Writes out the text of `msg`.
+    fn write(&self, msg: &Msg<'_>, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+/// Returns the catalog for the locale in the `OJO_LOCALE` environment variable.
+///
+/// Unknown locales fall back to English.
+pub fn catalog() -> &'static dyn Catalog {
+    static CATALOG: OnceLock<&'static dyn Catalog> = OnceLock::new();
+    *CATALOG.get_or_init(|| match std::env::var("OJO_LOCALE").as_deref() {
+        Ok("pseudo") => &Pseudo,
+        _ => &English,
+    })
+}
+
+/// The default catalog.
+pub struct English;
+
+// Writes `n` followed by `noun`, pluralizing the noun if necessary.
+fn count(f: &mut fmt::Formatter<'_>, n: usize, noun: &str) -> fmt::Result {
+    if n == 1 {
+        write!(f, "{} {}", n, noun)
+    } else {
+        write!(f, "{} {}s", n, noun)
+    }
+}
+
+fn patches(n: usize) -> String {
+    if n == 1 {
+        "1 patch".to_owned()
+    } else {
+        format!("{} patches", n)
+    }
+}
+
+impl Catalog for English {
+    fn write(&self, msg: &Msg<'_>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Msg::*;
+
+        match *msg {
+            Error(e) => write!(f, "Error: {}", e),
+            CausedBy(e) => write!(f, "\tcaused by: {}", e),
+            NoCurrentDir => write!(f, "Couldn't open the current directory."),
+            RepoNotFound => write!(f, "Failed to find a ojo repository"),
+            RepoOpenFailed => write!(f, "Failed to open the ojo repository"),
+            RepoWriteFailed => write!(f, "Failed to write repository to disk."),
+            ReadFailed(path) => write!(f, "Failed to read file '{}'", path),
+            WriteFailed(path) => write!(f, "Failed to write the file '{}'", path),
+            CreateFailed(path) => write!(f, "Couldn't create file '{}'", path),
+            WroteFile(path) => write!(f, "Successfully wrote the file '{}'", path),
+            NoChanges => write!(f, "Not creating a patch because there were no changes."),
+            PatchCreated(id) => write!(f, "Created patch {}", id),
+            PatchCreatedAndApplied(id) => write!(f, "Created and applied patch {}", id),
+            Stashed(path) => write!(f, "Stashed the changes to '{}'", path),
+
+            CoarseDiffChanged(lines) => write!(
+                f,
+                "Files with more than {} lines will get coarse diffs.",
+                lines
+            ),
+            Reattributed { from, to } => {
+                write!(
+                    f,
+                    "Patches by '{}' will be shown as being by '{}'.",
+                    from, to
+                )
+            }
+            LineageChanged => write!(f, "Changed the repository's lineage settings."),
+            LineageIdentity(identity) => write!(f, "identity: {}", identity),
+            LineagePolicy(policy) => write!(f, "policy: {}", policy),
+            NoIdentity => write!(f, "(none)"),
+
+            BisectUncommitted(path) => write!(
+                f,
+                "The file '{}' has changes that aren't in a patch; create a patch or stash them \
+                 before bisecting",
+                path
+            ),
+            BisectTesting {
+                patch,
+                remaining,
+                path,
+            } => write!(
+                f,
+                "Wrote '{}' with only {} and its dependencies applied ({} patches left to check). \
+                 Test it, then run `ojo bisect good` or `ojo bisect bad`.",
+                path, patch, remaining
+            ),
+            BisectFound { patch, description } => {
+                write!(f, "The first bad patch is {}: {}", patch, description)
+            }
+            BisectReset(branch) => write!(f, "Stopped bisecting branch \"{}\"", branch),
+            BisectScriptFailed(script) => write!(f, "Failed to run '{}'", script),
+            BisectScriptKilled { script, status } => {
+                write!(f, "'{}' didn't finish normally ({})", script, status)
+            }
+
+            BranchArchived(name) => write!(f, "Archived branch \"{}\"", name),
+            BranchUnarchived(name) => write!(f, "Unarchived branch \"{}\"", name),
+            BranchProtected(name) => write!(f, "Protected branch \"{}\"", name),
+            BranchUnprotected(name) => write!(f, "Removed the protection from branch \"{}\"", name),
+            BranchCloned { from, to } => {
+                write!(f, "Cloned branch \"{}\" to branch \"{}\"", from, to)
+            }
+            BranchDeleted(name) => write!(f, "Deleted branch \"{}\"", name),
+            BranchForked { from, to, left_out } => write!(
+                f,
+                "Forked branch \"{}\" to branch \"{}\", leaving out {} patch(es)",
+                from, to, left_out
+            ),
+            BranchCreated(name) => write!(f, "Created empty branch \"{}\"", name),
+            BranchSwitched(name) => write!(f, "Current branch is \"{}\"", name),
+            ArchivedBranch(name) => write!(f, "{} (archived)", name),
+
+            ClearPreview { branch, summary } => {
+                write!(f, "Clearing branch '{}' would remove ", branch)?;
+                match summary.patches {
+                    1 => write!(f, "1 patch, ")?,
+                    n => write!(f, "{} patches, ", n)?,
+                }
+                count(f, summary.live_nodes, "live node")?;
+                write!(f, " and ")?;
+                count(f, summary.deleted_nodes, "deleted node")?;
+                write!(f, ".")
+            }
+            ClearedKeepingContents { branch, baseline } => write!(
+                f,
+                "Cleared branch '{}', keeping its contents in the baseline patch {}",
+                branch, baseline
+            ),
+
+            DiffReadFailed(path) => write!(f, "Could not read the file {}", path),
+            DiffNotOrdered => write!(
+                f,
+                "Cannot create a diff because the repo's contents aren't ordered"
+            ),
+            CoarseDiff(lines) => write!(
+                f,
+                "Warning: the file has more than {} lines, so the diff was computed on blocks of \
+                 lines and may show bigger changes than necessary (use --precise for an exact diff)",
+                lines
+            ),
+            PreviewNotOrdered => write!(
+                f,
+                "Cannot create a diff because the result wouldn't be ordered"
+            ),
+
+            PseudoEdgesRepaired(branch) => {
+                write!(f, "Repaired the pseudo-edges in branch '{}'", branch)
+            }
+            PseudoEdgesStale(branch) => {
+                write!(f, "The pseudo-edges in branch '{}' are out of date", branch)
+            }
+            MissingDeps { branch, patches } => write!(
+                f,
+                "Branch '{}' is missing dependencies of its patches: {}",
+                branch, patches
+            ),
+            NoProblems => write!(f, "No problems found"),
+            ProblemsFound => write!(
+                f,
+                "Found some problems; run `ojo fsck --repair` to fix them"
+            ),
+            UnrepairableProblems => {
+                write!(f, "Found some problems that `ojo fsck --repair` can't fix")
+            }
+
+            GitFailed(msg) => write!(f, "Failed to run git: {}", msg),
+            GitImported(count) => write!(f, "Imported {} commit(s) from git", count),
+
+            InvalidLineNumber(line) => write!(f, "Invalid line number \"{}\"", line),
+            InvalidRadius(radius) => write!(f, "Invalid radius \"{}\"", radius),
+            AroundNotOrdered => write!(f, "--around requires the file to be totally ordered"),
+            LineOutOfRange { line, lines } => write!(
+                f,
+                "Line {} is out of range (the file has {} lines)",
+                line, lines
+            ),
+
+            LinkFailed => write!(f, "Failed to link to the ojo directory."),
+            RepoCreated => write!(f, "Created empty ojo repository."),
+
+            InvalidLimit(value) => write!(f, "Invalid limit \"{}\"", value),
+            LimitsChanged => write!(f, "Changed the limits"),
+
+            InvalidCount { arg, value } => write!(f, "Invalid value for --{}: {}", arg, value),
+            Stats(stats) => {
+                count(f, stats.nodes_added, "node")?;
+                write!(f, " added, ")?;
+                count(f, stats.nodes_deleted, "node")?;
+                write!(f, " deleted, ")?;
+                count(f, stats.edges_added, "edge")?;
+                write!(f, " added")
+            }
+            LogNoPatches(branch) => {
+                write!(f, "There are no patches to show for branch '{}'.", branch)
+            }
+            LogConfirm => write!(f, "Press 'y' to go ahead, or any other key to cancel."),
+            LogApplied(n) => write!(f, "Applied {}", patches(n)),
+            LogUnapplied(n) => write!(f, "Unapplied {}", patches(n)),
+            LogWouldApply(n) => write!(f, "This would apply {}:", patches(n)),
+            LogWouldUnapply(n) => write!(f, "This would unapply {}:", patches(n)),
+            LogWouldHaveLines(n) => {
+                write!(f, "Afterwards, the file would have ")?;
+                count(f, n, "line")
+            }
+            LogWouldNotBeOrdered => write!(
+                f,
+                "Afterwards, the file would not be totally ordered; use 'ojo resolve' to fix that."
+            ),
+            KeySelectPrevious => write!(f, "previous patch"),
+            KeySelectNext => write!(f, "next patch"),
+            KeyApply => write!(f, "apply"),
+            KeyUnapply => write!(f, "unapply"),
+            KeyConfirm => write!(f, "confirm"),
+            KeyCancel => write!(f, "cancel"),
+
+            BaselineFailed(branch) => {
+                write!(f, "Couldn't create a baseline for branch '{}'", branch)
+            }
+            Unpacked => write!(f, "Successfully unpacked the repository."),
+
+            PatchNotApplied { patch, branch } => {
+                write!(f, "Patch {} is not applied to branch {}.", patch, branch)
+            }
+            PatchAlreadyApplied { patch, branch } => write!(
+                f,
+                "Patch {} is already applied to branch {}.",
+                patch, branch
+            ),
+            AppliedPatches => write!(f, "Applied:"),
+            UnappliedPatches => write!(f, "Unapplied:"),
+            NothingToApply => write!(f, "No patches to apply."),
+            NothingToUnapply => write!(f, "No patches to unapply."),
+            PatchDependsOn { dep, patch } => write!(
+                f,
+                "Patch {} depends on patch {}; use --all-from to unapply both",
+                dep, patch
+            ),
+            PatchAliased { patch, name } => {
+                write!(f, "Patch {} is now also called {}", patch, name)
+            }
+            PatchImported(id) => write!(f, "Successfully imported a patch with id {}", id),
+            DownloadFailed(url) => write!(f, "Failed to download '{}'", url),
+            InvalidLineRanges(s) => write!(f, "Invalid line ranges \"{}\"", s),
+            AmendNeededBy { patch, dependent } => write!(
+                f,
+                "Patch {} can't be amended, because patch {} depends on it",
+                patch, dependent
+            ),
+            AmendAppliedElsewhere { patch, branch } => write!(
+                f,
+                "Patch {} can't be amended, because it is also applied to branch {}",
+                patch, branch
+            ),
+            AmendNothingLeft => write!(
+                f,
+                "Not amending the patch, because the new patch would have no changes."
+            ),
+            PatchAmended { old, new } => write!(f, "Replaced patch {} with patch {}", old, new),
+            EquivalentPatch(id) => write!(
+                f,
+                "Warning: patch {} makes the same changes; applying both will duplicate lines",
+                id
+            ),
+            ForeignPatch { patch, repository } => write!(
+                f,
+                "Warning: patch {} was created in a different repository ({})",
+                patch, repository
+            ),
+            NoEditor => write!(
+                f,
+                "No description given, and neither $VISUAL nor $EDITOR is set"
+            ),
+            DescriptionTemplate => write!(
+                f,
+                "
+# Please enter a description for this patch. Lines starting with '#' will be ignored, and an
+# empty description aborts the patch.
+#
+# Changes to be recorded:
+"
+            ),
+            TemplateWriteFailed => write!(f, "Failed to write the description template"),
+            EditorFailed(editor) => write!(f, "Failed to run the editor {}", editor),
+            EditorExited { editor, status } => {
+                write!(f, "The editor {} exited with {}", editor, status)
+            }
+            DescriptionReadFailed => write!(f, "Failed to read the description"),
+            EmptyDescription => write!(f, "Aborting the patch because the description is empty"),
+            CreatesFile => write!(f, "Creates the file"),
+            Author(author) => write!(f, "Author: {}", author),
+            Date(date) => write!(f, "Date: {}", date),
+            Branch(branch) => write!(f, "Branch: {}", branch),
+            FormatVersion(version) => write!(f, "Format version: {}", version),
+            DependsOn(patch) => write!(f, "Depends on: {}", patch),
+            Repository(identity) => write!(f, "Repository: {}", identity),
+            Supersedes(patch) => write!(f, "Supersedes: {}", patch),
+            SupersededBy(patch) => write!(f, "Superseded by: {}", patch),
+
+            RenderNotOrdered => write!(f, "Couldn't render a file, because the data isn't ordered"),
+            RenderDisorder {
+                cycles,
+                unordered,
+                edits: 0,
+            } => write!(
+                f,
+                "The file has {} cycle(s) and {} unordered region(s)",
+                cycles, unordered
+            ),
+            RenderDisorder {
+                cycles,
+                unordered,
+                edits,
+            } => write!(
+                f,
+                "The file has {} cycle(s) and {} unordered region(s), and {} line(s) with \
+                 conflicting edits",
+                cycles, unordered, edits
+            ),
+            RenderNear(line) => write!(f, "  near: {}", line),
+            RenderConflict(a, b) => write!(f, "Patch {} conflicts with patch {}", a, b),
+
+            ReplGlobalOption(name) => write!(
+                f,
+                "The --{} option can only be given to 'ojo repl' itself",
+                name
+            ),
+            ReplNested => write!(f, "Already in the repl"),
+            ReplUnterminatedQuote => write!(f, "Unterminated quote"),
+            ReplWatch => write!(f, "Can't run 'watch' in the repl"),
+
+            RawModeFailed => write!(f, "Failed to open the terminal in raw mode"),
+            UnexpectedEndOfInput => write!(f, "Unexpected end of input"),
+            NoPatchCreated => write!(f, "No patch created"),
+            ScriptInvalidStep { line, text } => {
+                write!(
+                    f,
+                    "Line {} of the script isn't a valid step: {}",
+                    line, text
+                )
+            }
+            ScriptNoSuchChoice(line) => write!(
+                f,
+                "Line {} of the script doesn't refer to one of the current choices",
+                line
+            ),
+            ScriptExpectedCycle(line) => write!(
+                f,
+                "Line {} of the script should resolve a cycle, because there are cycles left",
+                line
+            ),
+            ScriptExpectedOrder(line) => write!(
+                f,
+                "Line {} of the script resolves a cycle, but there are no cycles left",
+                line
+            ),
+            ScriptExpectedVersion(line) => write!(
+                f,
+                "Line {} of the script should choose a version of a line, because there are \
+                 conflicting edits left",
+                line
+            ),
+            ScriptUnexpectedVersion(line) => write!(
+                f,
+                "Line {} of the script chooses a version of a line, but there are no conflicting \
+                 edits left",
+                line
+            ),
+            ScriptEnded => write!(f, "The script ended before the resolution was finished"),
+            ScriptTooLong(line) => write!(
+                f,
+                "The resolution was finished before line {} of the script",
+                line
+            ),
+            ResolveChooseVersion => write!(
+                f,
+                "This line was edited in more than one way. Which version should be kept?"
+            ),
+            KeyChooseLine => write!(f, "choose line"),
+            KeyChooseVersion => write!(f, "choose version"),
+            KeyShowPrevious => write!(f, "show previous"),
+            KeyShowNext => write!(f, "show next"),
+            KeyQuit => write!(f, "quit"),
+            KeyTakeOne => write!(f, "take one"),
+            KeyDeleteOne => write!(f, "delete one"),
+            KeyTakeAll => write!(f, "take all"),
+            KeyDeleteAll => write!(f, "delete all"),
+            KeyTakeLeft => write!(f, "take left"),
+            KeyTakeRight => write!(f, "take right"),
+            KeyDeleteLeft => write!(f, "delete left"),
+            KeyDeleteRight => write!(f, "delete right"),
+            KeyTakeLine => write!(f, "take line"),
+            KeyDeleteLine => write!(f, "delete line"),
+            KeyTakeLines => write!(f, "take lines"),
+            KeyDeleteLines => write!(f, "delete lines"),
+
+            RestoreNotOrdered(patch) => write!(
+                f,
+                "Couldn't restore the file, because the data at {} isn't ordered",
+                patch
+            ),
+            Restored { path, patch } => {
+                write!(f, "Restored '{}' to how it was at patch {}", path, patch)
+            }
+
+            StashEntry {
+                index,
+                branch,
+                description,
+            } => write!(f, "{}: on {}: {}", index, branch, description),
+            InvalidStashIndex(index) => write!(f, "Invalid stash index '{}'", index),
+            StashPopNotOrdered => write!(
+                f,
+                "Couldn't pop the stash, because the result wouldn't be ordered"
+            ),
+            StashPopMismatch(branch) => write!(
+                f,
+                "Couldn't pop the stash, because it doesn't match branch '{}'",
+                branch
+            ),
+            StashPopped(index) => write!(f, "Popped stash {}", index),
+            NothingToStash => write!(f, "Not stashing because there were no changes."),
+
+            InvalidSize(size) => write!(f, "Invalid size \"{}\"", size),
+            StdinNotUtf8 => write!(f, "Expected stdin to be UTF-8, but it wasn't."),
+            EdgeParseFailed(s) => write!(f, "Failed to parse '{}'.", s),
+            EmptyInput => write!(f, "Input was empty."),
+            Synthesized => write!(f, "Synthesized a ojo repository."),
+
+            InvalidInterval(s) => write!(f, "Invalid interval '{}'", s),
+            WatchStartFailed => write!(f, "Failed to start watching"),
+            NoParentDir(path) => {
+                write!(f, "Failed to find the directory containing '{}'", path)
+            }
+            WatchFailed(path) => write!(f, "Failed to watch '{}'", path),
+            Watching(path) => write!(f, "Watching '{}' for changes", path),
+            WatchEventFailed => write!(f, "Failed to watch for changes"),
+            WatcherStopped => write!(f, "The file watcher stopped unexpectedly"),
+        }
+    }
+}
+
+/// A pseudo-locale for testing, which marks the English text with brackets.
+///
+/// Text that doesn't have the brackets didn't come from the catalog.
+pub struct Pseudo;
+
+impl Catalog for Pseudo {
+    fn write(&self, msg: &Msg<'_>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The template gets parsed after the user edits it, so it needs to stay as it is.
+        if let Msg::DescriptionTemplate = msg {
+            return English.write(msg, f);
+        }
+        write!(f, "[")?;
+        English.write(msg, f)?;
+        write!(f, "]")
+    }
+}
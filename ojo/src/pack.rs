@@ -0,0 +1,41 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{Error, ResultExt};
+use libojo::{Pack, Repo};
+
+pub fn run_pack(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let out = m.value_of("output").unwrap_or("ojo.pack");
+
+    let repo = crate::open_repo()?;
+    let pack = if m.is_present("shallow") {
+        // The unwrap is ok because --shallow requires --author.
+        let author = m.value_of("author").unwrap();
+        let branch = crate::branch(&repo, m);
+        repo.export_shallow(&branch, author, "Baseline")
+            .with_context(|_| Msg::BaselineFailed(&branch).to_string())?
+    } else {
+        match m.value_of("branch") {
+            Some(branch) => repo.export_branch(branch)?,
+            None => repo.pack(),
+        }
+    };
+    let file = std::fs::File::create(out).with_context(|_| Msg::CreateFailed(out).to_string())?;
+    pack.write_out(file)?;
+
+    eprintln!("{}", Msg::WroteFile(out));
+    Ok(())
+}
+
+pub fn run_unpack(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwrap is ok because this is a required argument.
+    let path = m.value_of("PATH").unwrap();
+
+    let dir = crate::working_dir()?;
+    let file = std::fs::File::open(path).with_context(|_| Msg::ReadFailed(path).to_string())?;
+    let pack = Pack::from_reader(file)?;
+    let repo = Repo::unpack(&dir, &pack)?;
+    repo.write().context(Msg::RepoWriteFailed.to_string())?;
+
+    eprintln!("{}", Msg::Unpacked);
+    Ok(())
+}
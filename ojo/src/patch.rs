@@ -1,17 +1,25 @@
 use clap::ArgMatches;
 use failure::Error;
 
+mod alias;
+mod amend;
 mod apply;
 pub mod create;
 mod export;
 mod import;
+mod show;
+mod unapply;
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     match m.subcommand_name() {
+        Some("alias") => alias::run(m.subcommand_matches("alias").unwrap()),
+        Some("amend") => amend::run(m.subcommand_matches("amend").unwrap()),
         Some("apply") => apply::run(m.subcommand_matches("apply").unwrap()),
         Some("create") => create::run(m.subcommand_matches("create").unwrap()),
         Some("export") => export::run(m.subcommand_matches("export").unwrap()),
         Some("import") => import::run(m.subcommand_matches("import").unwrap()),
+        Some("show") => show::run(m.subcommand_matches("show").unwrap()),
+        Some("unapply") => unapply::run(m.subcommand_matches("unapply").unwrap()),
         _ => panic!("Unknown subcommand"),
     }
 }
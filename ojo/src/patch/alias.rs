@@ -0,0 +1,23 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::Error;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwraps are ok because these are required arguments.
+    let patch = m.value_of("PATCH").unwrap();
+    let name = m.value_of("NAME").unwrap();
+
+    let mut repo = crate::open_repo()?;
+    let id = repo.resolve_patch(patch)?;
+    repo.set_patch_alias(name, &id)?;
+    repo.write()?;
+
+    eprintln!(
+        "{}",
+        Msg::PatchAliased {
+            patch: &repo.abbreviate_patch_id(&id),
+            name
+        }
+    );
+    Ok(())
+}
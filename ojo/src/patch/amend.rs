@@ -0,0 +1,83 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::Error;
+use libojo::Changes;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwrap is ok because this is a required argument.
+    let patch = m.value_of("PATCH").unwrap();
+
+    let mut repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+    let path = crate::file_path(m);
+    let id = repo.resolve_patch(patch)?;
+    let abbrev = repo.abbreviate_patch_id(&id);
+
+    // Replacing the patch is only safe if nothing else could have seen it: no other patch may
+    // depend on it, and it must only be applied to this branch.
+    if !repo.is_applied(&branch, &id) {
+        return Err(libojo::Error::NotApplied(id, branch).into());
+    }
+    if let Some(dependent) = repo.patch_rev_deps(&id).next() {
+        bail!(
+            "{}",
+            Msg::AmendNeededBy {
+                patch: &abbrev,
+                dependent: &repo.abbreviate_patch_id(dependent),
+            }
+        );
+    }
+    if let Some(other) = repo
+        .branches()
+        .find(|b| *b != branch && repo.is_applied(b, &id))
+    {
+        bail!(
+            "{}",
+            Msg::AmendAppliedElsewhere {
+                patch: &abbrev,
+                branch: other,
+            }
+        );
+    }
+
+    let old = repo.open_patch(&id)?;
+    let header = old.header();
+    let author = m.value_of("author").unwrap_or(&header.author).to_owned();
+    let msg = m
+        .value_of("description")
+        .unwrap_or(&header.description)
+        .to_owned();
+    let aliases = repo
+        .patch_aliases(&id)
+        .map(|a| a.to_owned())
+        .collect::<Vec<_>>();
+
+    // Once the old patch is gone, the working file differs from the branch by both the old
+    // patch's changes and the ones made since. Nothing gets written unless we succeed.
+    repo.unapply_patch(&branch, &id)?;
+    repo.unregister_patch(&id)?;
+    let diff = crate::diff::diff(&repo, &branch, &path, m.is_present("precise"))?;
+    if !crate::diff::has_changes(&diff) {
+        bail!("{}", Msg::AmendNothingLeft);
+    }
+    let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+    let new_id = repo.create_patch_superseding(&branch, &author, &msg, changes, &[id])?;
+    repo.apply_patch(&branch, &new_id)?;
+    for alias in &aliases {
+        repo.set_patch_alias(alias, &new_id)?;
+    }
+    repo.write()?;
+
+    if m.is_present("output-hash") {
+        println!("{}", new_id);
+    } else {
+        eprintln!(
+            "{}",
+            Msg::PatchAmended {
+                old: &abbrev,
+                new: &new_id,
+            }
+        );
+    }
+    Ok(())
+}
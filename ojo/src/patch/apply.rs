@@ -1,37 +1,116 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
-use failure::Error;
-use libojo::PatchId;
+use failure::{Error, ResultExt};
+use libojo::{Patch, PatchId, Repo};
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
-    // The unwrap is ok because this is a required argument.
-    let patch_id = m.value_of("PATCH").unwrap();
-    let patch_id = PatchId::from_base64(patch_id)?;
-
     let mut repo = crate::open_repo()?;
+    // The unwrap is ok because this is a required argument.
+    let patches = m.values_of("PATCH").unwrap();
+    let patch_ids = if m.is_present("from-url") {
+        let ids = fetch(&mut repo, patches)?;
+        if m.is_present("no-apply") {
+            repo.write()?;
+            return Ok(());
+        }
+        ids
+    } else {
+        patches
+            .map(|p| repo.resolve_patch(p))
+            .collect::<Result<Vec<_>, _>>()?
+    };
     let branch = crate::branch(&repo, m);
 
     if m.is_present("revert") {
-        let unapplied = repo.unapply_patch(&branch, &patch_id)?;
-        if unapplied.is_empty() {
-            eprintln!("No patches to unapply.");
-        } else {
-            eprintln!("Unapplied:");
-            for u in unapplied {
-                eprintln!("  {}", u.to_base64());
+        let mut to_unapply = Vec::new();
+        for p in patch_ids {
+            if repo.is_applied(&branch, &p) {
+                to_unapply.push(p);
+            } else {
+                eprintln!(
+                    "{}",
+                    Msg::PatchNotApplied {
+                        patch: &repo.abbreviate_patch_id(&p),
+                        branch: &branch,
+                    }
+                );
             }
         }
+        if to_unapply.is_empty() {
+            return Ok(());
+        }
+        let unapplied = crate::with_force(&mut repo, &branch, m, |repo| {
+            repo.unapply_patches(&branch, &to_unapply)
+        })?;
+        print_patches(
+            &repo,
+            Msg::UnappliedPatches,
+            Msg::NothingToUnapply,
+            &unapplied,
+        );
     } else {
-        let applied = repo.apply_patch(&branch, &patch_id)?;
-        if applied.is_empty() {
-            eprintln!("No patches to apply.");
-        } else {
-            eprintln!("Applied:");
-            for a in applied {
-                eprintln!("  {}", a.to_base64());
+        let mut to_apply = Vec::new();
+        for p in patch_ids {
+            if repo.is_applied(&branch, &p) {
+                eprintln!(
+                    "{}",
+                    Msg::PatchAlreadyApplied {
+                        patch: &repo.abbreviate_patch_id(&p),
+                        branch: &branch,
+                    }
+                );
+            } else {
+                to_apply.push(p);
             }
         }
+        if to_apply.is_empty() {
+            return Ok(());
+        }
+        let applied = repo.apply_patches(&branch, &to_apply)?;
+        print_patches(&repo, Msg::AppliedPatches, Msg::NothingToApply, &applied);
     }
 
     repo.write()?;
     Ok(())
 }
+
+pub fn print_patches(repo: &Repo, header: Msg<'_>, if_empty: Msg<'_>, patches: &[PatchId]) {
+    if patches.is_empty() {
+        eprintln!("{}", if_empty);
+    } else {
+        eprintln!("{}", header);
+        for p in patches {
+            eprintln!("  {}", repo.abbreviate_patch_id(p));
+        }
+    }
+}
+
+// Downloads the patches at the given URLs, and registers them with the repository.
+fn fetch<'a>(repo: &mut Repo, urls: impl Iterator<Item = &'a str>) -> Result<Vec<PatchId>, Error> {
+    // There's no point downloading anything bigger than the repository would accept.
+    let max_size = repo.limits().max_patch_size as u64;
+    let mut patches = Vec::new();
+    for url in urls {
+        let data = ureq::get(url)
+            .call()
+            .and_then(|mut r| r.body_mut().with_config().limit(max_size).read_to_vec())
+            .with_context(|_| Msg::DownloadFailed(url).to_string())?;
+        let patch = Patch::from_reader(&data[..])?;
+        super::import::warn_if_foreign(repo, &patch);
+        patches.push((*patch.id(), patch.deps().to_owned(), data));
+    }
+    let ids = patches.iter().map(|p| p.0).collect::<Vec<_>>();
+
+    // The patches might depend on one another, and a patch can only be registered after its
+    // dependencies. If some dependency is missing altogether, registering fails with an error.
+    while !patches.is_empty() {
+        let next = patches
+            .iter()
+            .position(|(_, deps, _)| deps.iter().all(|d| repo.open_patch_data(d).is_ok()))
+            .unwrap_or(0);
+        let (_, _, data) = patches.remove(next);
+        let id = repo.register_patch(&data)?;
+        eprintln!("{}", Msg::PatchImported(&id));
+    }
+    Ok(ids)
+}
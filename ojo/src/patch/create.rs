@@ -1,42 +1,187 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
-use failure::Error;
-use libojo::Changes;
+use failure::{Error, ResultExt};
+use libojo::{Changes, Diff, LineDiff, Repo};
+use std::fmt::Write;
+use std::process::Command;
+
+// Appends the changed lines of `diff` to `out`, as comments.
+fn write_diff_comments(out: &mut String, diff: &Diff) {
+    let mut line = |prefix: &str, bytes: &[u8]| {
+        let _ = write!(out, "# {} {}", prefix, String::from_utf8_lossy(bytes));
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+    };
+    for &d in &diff.diff {
+        match d {
+            LineDiff::New(i) => line("+", diff.file_b.node(i)),
+            LineDiff::Delete(i) => line("-", diff.file_a.node(i)),
+            LineDiff::Keep(..) => {}
+        }
+    }
+}
+
+// Finds the user's preferred editor.
+fn editor() -> Result<String, Error> {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .map_err(|_| format_err!("{}", Msg::NoEditor))
+}
+
+// Asks for a description of the patch by opening `editor` on a template.
+fn edit_description(repo: &Repo, diff: &Diff, editor: &str) -> Result<String, Error> {
+    let mut template = Msg::DescriptionTemplate.to_string();
+    write_diff_comments(&mut template, diff);
+    let path = repo.repo_dir.join("PATCH_DESCRIPTION");
+    std::fs::write(&path, template).context(Msg::TemplateWriteFailed.to_string())?;
+
+    // The editor might come with arguments, so let the shell split it up.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(&path)
+        .status()
+        .with_context(|_| Msg::EditorFailed(editor).to_string())?;
+    if !status.success() {
+        bail!(
+            "{}",
+            Msg::EditorExited {
+                editor,
+                status: &status
+            }
+        );
+    }
+
+    let contents =
+        std::fs::read_to_string(&path).context(Msg::DescriptionReadFailed.to_string())?;
+    let _ = std::fs::remove_file(&path);
+    let description = contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let description = description.trim();
+    if description.is_empty() {
+        bail!("{}", Msg::EmptyDescription);
+    }
+    Ok(description.to_owned())
+}
+
+// Parses a list of line ranges like "3-5,8", where the lines are numbered from 1 and the ranges
+// are inclusive.
+fn parse_ranges(s: &str) -> Result<Vec<(usize, usize)>, Error> {
+    let bad = || format_err!("{}", Msg::InvalidLineRanges(s));
+    let mut ret = Vec::new();
+    for range in s.split(',') {
+        let (a, b) = match range.find('-') {
+            Some(idx) => (&range[..idx], &range[(idx + 1)..]),
+            None => (range, range),
+        };
+        let a: usize = a.trim().parse().map_err(|_| bad())?;
+        let b: usize = b.trim().parse().map_err(|_| bad())?;
+        if a == 0 || a > b {
+            return Err(bad());
+        }
+        ret.push((a, b));
+    }
+    Ok(ret)
+}
+
+// Restricts a diff to the changes that overlap the given (1-based, inclusive) ranges of lines in
+// the new file; the other changes are turned into `Keep`s, so that `Changes::from_diff` leaves
+// them out.
+//
+// A deleted line doesn't appear in the new file, so it counts as overlapping a range if either of
+// the new lines next to it does. Deletions that we leave out become `Keep`s whose second line
+// number is only approximate, but `Changes::from_diff` doesn't look at it.
+fn select_lines(diff: &[LineDiff], ranges: &[(usize, usize)]) -> Vec<LineDiff> {
+    let selected = |first: usize, last: usize| ranges.iter().any(|&(a, b)| a <= last && first <= b);
+    // The number of lines of the new file that come before the current position.
+    let mut pos = 0;
+    let mut ret = Vec::with_capacity(diff.len());
+    for &d in diff {
+        match d {
+            LineDiff::New(i) => {
+                pos = i + 1;
+                if selected(pos, pos) {
+                    ret.push(d);
+                }
+            }
+            LineDiff::Delete(i) => {
+                if selected(pos, pos + 1) {
+                    ret.push(d);
+                } else {
+                    ret.push(LineDiff::Keep(i, pos));
+                }
+            }
+            LineDiff::Keep(_, j) => {
+                pos = j + 1;
+                ret.push(d);
+            }
+        }
+    }
+    ret
+}
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
-    // The unwraps are ok because these are required arguments.
-    let msg = m.value_of("description").unwrap();
+    // The unwrap is ok because this is a required argument.
     let author = m.value_of("author").unwrap();
+    let ranges = m.value_of("lines").map(parse_ranges).transpose()?;
+    // If there's no description, make sure we can ask for one before doing anything else.
+    let editor = match m.value_of("description") {
+        Some(_) => None,
+        None => Some(editor()?),
+    };
 
     let mut repo = crate::open_repo()?;
     let branch = crate::branch(&repo, m);
     let path = crate::file_path(m);
-    let diff = crate::diff::diff(&repo, &branch, &path)?;
+    let mut diff = crate::diff::diff(&repo, &branch, &path, m.is_present("precise"))?;
+    if let Some(ranges) = ranges {
+        diff.diff = select_lines(&diff.diff, &ranges);
+    }
     let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
     let output_hash = m.is_present("output-hash");
 
-    if changes.changes.is_empty() {
+    // (We can't just check whether `changes` is empty: if `--lines` left out every line of a new
+    // file, it still has a `CreateFile`.)
+    if !crate::diff::has_changes(&diff) {
         if !output_hash {
-            eprintln!("Not creating a patch because there were no changes.");
+            eprintln!("{}", Msg::NoChanges);
         }
         return Ok(());
     }
 
-    let id = repo.create_patch(author, msg, changes)?;
+    if let Some(other) = repo.find_equivalent_patch(&changes)? {
+        eprintln!(
+            "{}",
+            Msg::EquivalentPatch(&repo.abbreviate_patch_id(&other))
+        );
+    }
+
+    let msg = match editor {
+        Some(editor) => edit_description(&repo, &diff, &editor)?,
+        // The unwrap is ok because we only skip the editor if there is a description.
+        None => m.value_of("description").unwrap().to_owned(),
+    };
+    let id = repo.create_patch_on_branch(&branch, author, &msg, changes)?;
     if m.is_present("then-apply") {
         repo.apply_patch(&branch, &id)?;
         repo.write()?;
         if !output_hash {
-            eprintln!("Created and applied patch {}", id.to_base64());
+            eprintln!("{}", Msg::PatchCreatedAndApplied(&id));
         }
     } else {
         repo.write()?;
         if !output_hash {
-            eprintln!("Created patch {}", id.to_base64());
+            eprintln!("{}", Msg::PatchCreated(&id));
         }
     }
 
     if output_hash {
-        println!("{}", id.to_base64());
+        println!("{}", id);
     }
     Ok(())
 }
@@ -1,17 +1,18 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::{Error, ResultExt};
-use libojo::PatchId;
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     // The unwrap is ok because this is a required argument.
     let hash = m.value_of("PATCH").unwrap();
-    let out = m.value_of("output").unwrap_or(hash);
 
     let repo = crate::open_repo()?;
-    let id = PatchId::from_base64(hash)?;
+    let id = repo.resolve_patch(hash)?;
+    let full_hash = id.to_string();
+    let out = m.value_of("output").unwrap_or(&full_hash);
     let patch_data = repo.open_patch_data(&id)?;
-    std::fs::write(out, patch_data).with_context(|_| format!("Couldn't create file '{}'", out))?;
+    std::fs::write(out, patch_data).with_context(|_| Msg::CreateFailed(out).to_string())?;
 
-    eprintln!("Successfully wrote the file '{}'", out);
+    eprintln!("{}", Msg::WroteFile(out));
     Ok(())
 }
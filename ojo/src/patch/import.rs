@@ -1,16 +1,44 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::{Error, ResultExt};
+use libojo::{LineagePolicy, Patch, Repo};
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     // The unwrap is ok because this is a required argument.
     let path = m.value_of("PATH").unwrap();
 
     let mut repo = crate::open_repo()?;
-    let contents =
-        std::fs::read(path).with_context(|_| format!("Failed to read file '{}'", path))?;
+    let contents = std::fs::read(path).with_context(|_| Msg::ReadFailed(path).to_string())?;
+    let patch = Patch::from_reader(&contents[..])?;
+    // Re-importing a patch that we already have is harmless, but importing another copy of it
+    // isn't.
+    if repo.open_patch(patch.id()).is_err() {
+        if let Some(other) = repo.find_equivalent_patch(&patch.unidentified_changes())? {
+            eprintln!(
+                "{}",
+                Msg::EquivalentPatch(&repo.abbreviate_patch_id(&other))
+            );
+        }
+    }
+    warn_if_foreign(&repo, &patch);
     let id = repo.register_patch(&contents)?;
     repo.write()?;
 
-    eprintln!("Successfully imported a patch with id {}", id.to_base64());
+    eprintln!("{}", Msg::PatchImported(&id));
     Ok(())
 }
+
+/// Prints a warning if `patch` was created in a repository of a different lineage, and the
+/// repository is configured to warn about that. (If it's configured to refuse, registering the
+/// patch will fail anyway.)
+pub fn warn_if_foreign(repo: &Repo, patch: &Patch) {
+    if repo.lineage_policy() == LineagePolicy::Warn && repo.is_foreign(patch) {
+        eprintln!(
+            "{}",
+            Msg::ForeignPatch {
+                patch: &repo.abbreviate_patch_id(patch.id()),
+                repository: patch.header().repository().unwrap_or_default(),
+            }
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::Error;
+use libojo::PatchHeader;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwrap is ok because this is a required argument.
+    let patch = m.value_of("PATCH").unwrap();
+
+    let repo = crate::open_repo()?;
+    let id = repo.resolve_patch(patch)?;
+    let patch = repo.open_patch(&id)?;
+    let header = patch.header();
+
+    println!("patch {}", id);
+    println!("{}", Msg::Author(repo.author(&header.author)));
+    println!("{}", Msg::Date(&header.timestamp));
+    if let Some(branch) = header.branch() {
+        println!("{}", Msg::Branch(branch));
+    }
+    if let Some(version) = header.format_version() {
+        println!("{}", Msg::FormatVersion(version));
+    }
+    if let Some(repository) = header.repository() {
+        println!("{}", Msg::Repository(repository));
+    }
+    // Any metadata that we don't know about is printed as it is.
+    let known = [
+        PatchHeader::BRANCH,
+        PatchHeader::FORMAT_VERSION,
+        PatchHeader::REPOSITORY,
+        PatchHeader::SUPERSEDES,
+    ];
+    for (key, value) in &header.metadata {
+        if !known.contains(&key.as_str()) {
+            println!("{}: {}", key, value);
+        }
+    }
+    for dep in patch.deps() {
+        println!("{}", Msg::DependsOn(&repo.abbreviate_patch_id(dep)));
+    }
+    // This includes the patches that were superseded by the ones that this patch superseded, and
+    // so on, even though they aren't in its header.
+    for old in repo.supersession_trail(&id) {
+        println!("{}", Msg::Supersedes(&repo.abbreviate_patch_id(&old)));
+    }
+    for new in repo.superseded_patches().remove(&id).unwrap_or_default() {
+        println!("{}", Msg::SupersededBy(&repo.abbreviate_patch_id(&new)));
+    }
+    if patch.changes().creates_file() {
+        println!("{}", Msg::CreatesFile);
+    }
+    println!();
+    for line in header.description.lines() {
+        if line.is_empty() {
+            println!();
+        } else {
+            println!("\t{}", line);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::Error;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+
+    let patch_ids = if let Some(p) = m.value_of("all-from") {
+        // Unapplying a patch also unapplies everything that depends on it, so there's nothing
+        // more to check.
+        vec![repo.resolve_patch(p)?]
+    } else {
+        // The unwrap is ok because clap requires PATCH unless all-from is given.
+        let patch_ids = m
+            .values_of("PATCH")
+            .unwrap()
+            .map(|p| repo.resolve_patch(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Only unapply exactly the patches that were asked for: if something else depends on
+        // one of them, the user needs to ask for it too (or to use --all-from).
+        for p in &patch_ids {
+            if !repo.is_applied(&branch, p) {
+                return Err(libojo::Error::NotApplied(*p, branch).into());
+            }
+            if let Some(dep) = repo
+                .patch_rev_deps(p)
+                .find(|d| repo.is_applied(&branch, d) && !patch_ids.contains(d))
+            {
+                return Err(format_err!(
+                    "{}",
+                    Msg::PatchDependsOn {
+                        dep: &repo.abbreviate_patch_id(dep),
+                        patch: &repo.abbreviate_patch_id(p),
+                    }
+                ));
+            }
+        }
+        patch_ids
+    };
+
+    let unapplied = crate::with_force(&mut repo, &branch, m, |repo| {
+        repo.unapply_patches(&branch, &patch_ids)
+    })?;
+    super::apply::print_patches(
+        &repo,
+        Msg::UnappliedPatches,
+        Msg::NothingToUnapply,
+        &unapplied,
+    );
+    repo.write()?;
+    Ok(())
+}
@@ -1,19 +1,76 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::{err_msg, Error};
+use libojo::Disorder;
+use std::io::Write;
 
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let path = crate::file_path(m);
     let repo = crate::open_repo()?;
     let branch = crate::branch(&repo, m);
-    let file = repo.file(&branch).map_err(|e| match e {
-        libojo::Error::NotOrdered => {
-            err_msg("Couldn't render a file, because the data isn't ordered")
-        }
+    let show_deleted = m.is_present("show-deleted");
+    let file = if show_deleted {
+        repo.file_with_deleted(&branch)
+    } else {
+        repo.file(&branch)
+    };
+    let file = file.map_err(|e| match e {
+        libojo::Error::NotOrdered(d) => not_ordered(&repo, &branch, &d),
         other => other.into(),
     })?;
 
-    std::fs::write(&path, file.as_bytes())?;
-    eprintln!("Successfully wrote file '{}'", path);
+    if show_deleted {
+        // Mark the lines in the same way as `ojo diff` does.
+        let graggle = repo.graggle(&branch)?;
+        let mut output = Vec::new();
+        for i in 0..file.num_nodes() {
+            if graggle.is_live(file.node_id(i)) {
+                output.extend_from_slice(b"  ");
+            } else {
+                output.extend_from_slice(b"- ");
+            }
+            output.extend_from_slice(file.node(i));
+        }
+        // This isn't the working file, so we only overwrite a file if we were asked to.
+        if m.is_present("path") {
+            std::fs::write(repo.root_dir.join(&path), output)?;
+        } else {
+            std::io::stdout().write_all(&output)?;
+            return Ok(());
+        }
+    } else {
+        std::fs::write(repo.root_dir.join(&path), file.as_bytes())?;
+    }
+    eprintln!("{}", Msg::WroteFile(&path));
 
     Ok(())
 }
+
+// Explains a failure to render: roughly where the problems are, and which patches are probably to
+// blame.
+fn not_ordered(repo: &libojo::Repo, branch: &str, disorder: &Disorder) -> Error {
+    let mut msg = Msg::RenderNotOrdered.to_string();
+    msg.push('\n');
+    msg.push_str(
+        &Msg::RenderDisorder {
+            cycles: disorder.cycles,
+            unordered: disorder.unordered,
+            edits: disorder.edits,
+        }
+        .to_string(),
+    );
+    if let Ok(graggle) = repo.graggle(branch) {
+        for id in &disorder.nodes {
+            let contents = String::from_utf8_lossy(repo.contents_in(graggle, id));
+            msg.push('\n');
+            msg.push_str(&Msg::RenderNear(contents.trim_end()).to_string());
+        }
+    }
+    for (a, b) in repo.conflicting_patches(branch).unwrap_or_default() {
+        let a = repo.abbreviate_patch_id(&a);
+        let b = repo.abbreviate_patch_id(&b);
+        msg.push('\n');
+        msg.push_str(&Msg::RenderConflict(&a, &b).to_string());
+    }
+    err_msg(msg)
+}
@@ -0,0 +1,210 @@
+use crate::messages::Msg;
+use clap::{App, ArgMatches};
+use failure::Error;
+use libojo::Repo;
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::ops::{Deref, DerefMut};
+
+// The repository that `ojo repl` keeps open, while one of its commands is running. `wrote` is set
+// when the command asks for the repository to be written.
+#[derive(Default)]
+struct Session {
+    repo: Option<Repo>,
+    wrote: bool,
+}
+
+thread_local! {
+    static SESSION: RefCell<Session> = RefCell::new(Session::default());
+}
+
+/// A repository returned by `crate::open_repo`.
+///
+/// Usually this just owns a repository that was read from disk. But while `ojo repl` is running a
+/// command, it is lent the repl's repository instead, and gives it back when it is dropped.
+pub struct OpenRepo {
+    repo: Option<Repo>,
+    lent: bool,
+}
+
+impl OpenRepo {
+    pub fn owned(repo: Repo) -> OpenRepo {
+        OpenRepo {
+            repo: Some(repo),
+            lent: false,
+        }
+    }
+
+    // Borrows the repl's repository, if there's a command running in the repl.
+    pub fn lent() -> Option<OpenRepo> {
+        SESSION
+            .with(|s| s.borrow_mut().repo.take())
+            .map(|repo| OpenRepo {
+                repo: Some(repo),
+                lent: true,
+            })
+    }
+
+    /// Writes the repository to disk, or (if it belongs to the repl) arranges for it to be
+    /// written when the repl exits.
+    pub fn write(&mut self) -> Result<(), libojo::Error> {
+        if self.lent {
+            SESSION.with(|s| s.borrow_mut().wrote = true);
+            Ok(())
+        } else {
+            self.deref_mut().write()
+        }
+    }
+}
+
+impl Deref for OpenRepo {
+    type Target = Repo;
+
+    fn deref(&self) -> &Repo {
+        // The repository is only taken out when this is dropped.
+        self.repo.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for OpenRepo {
+    fn deref_mut(&mut self) -> &mut Repo {
+        self.repo.as_mut().unwrap()
+    }
+}
+
+impl Drop for OpenRepo {
+    fn drop(&mut self) {
+        if self.lent {
+            let repo = self.repo.take();
+            SESSION.with(|s| s.borrow_mut().repo = repo);
+        }
+    }
+}
+
+pub fn run(_m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let interactive = termion::is_tty(&std::io::stdin());
+    // Whether any command has changed the repository since it was last written.
+    let mut modified = false;
+
+    let yml = load_yaml!("main.yaml");
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("ojo> ");
+            std::io::stdout().flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                crate::report_error(&e);
+                continue;
+            }
+        };
+        match words.first().map(|w| w.as_str()) {
+            None => continue,
+            Some("exit") | Some("quit") => break,
+            _ => {}
+        }
+
+        let args = std::iter::once("ojo".to_owned()).chain(words);
+        let m = match App::from_yaml(yml).get_matches_from_safe(args) {
+            Ok(m) => m,
+            // This includes the output of `help` and `--version`.
+            Err(e) => {
+                println!("{}", e.message);
+                continue;
+            }
+        };
+        match run_command(&mut repo, &m) {
+            Ok(changed) => modified |= changed,
+            Err(e) => crate::report_error(&e),
+        }
+    }
+
+    if modified {
+        repo.write()?;
+    }
+    Ok(())
+}
+
+// Runs the command described by `m`, using `repo` instead of the repository on disk. Like a
+// command run from the shell, its changes are only kept if it succeeds and writes the repository.
+// Returns true if it did.
+fn run_command(repo: &mut Repo, m: &ArgMatches<'_>) -> Result<bool, Error> {
+    // The global options were already applied when the repl started.
+    for &name in &["paranoid", "repo"] {
+        if m.is_present(name) {
+            bail!("{}", Msg::ReplGlobalOption(name));
+        }
+    }
+    let mut result = None;
+    let kept = repo.transaction(|repo| {
+        // The command gets the repository from `crate::open_repo`, so it needs to be in the
+        // session while the command runs. The temporary repository is a placeholder.
+        let session = Session {
+            repo: Some(std::mem::replace(repo, Repo::init_tmp())),
+            wrote: false,
+        };
+        SESSION.with(|s| *s.borrow_mut() = session);
+        let ret = match m.subcommand_name() {
+            Some("repl") => Err(format_err!("{}", Msg::ReplNested)),
+            // Watching only stops when it's interrupted, which would also kill the repl (and lose
+            // its changes).
+            Some("watch") => Err(format_err!("{}", Msg::ReplWatch)),
+            _ => crate::run(m),
+        };
+        let session = SESSION.with(|s| s.replace(Session::default()));
+        // The command gave back the repository when it was done with it.
+        *repo = session.repo.unwrap();
+
+        let keep = ret.is_ok() && session.wrote;
+        result = Some(ret);
+        // Anything else is rolled back. The error is only a signal to `Repo::transaction`: it
+        // never gets shown.
+        if keep {
+            Ok(())
+        } else {
+            Err(libojo::Error::Cancelled)
+        }
+    });
+    // The unwrap is ok because the closure always runs.
+    result.unwrap().map(|()| kept.is_ok())
+}
+
+// Splits a line into words, roughly like a shell would: words are separated by whitespace, except
+// inside single or double quotes, and a backslash (outside single quotes) escapes the next
+// character.
+fn split_words(line: &str) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') => {
+                let escaped = chars.next().unwrap_or('\\');
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        bail!("{}", Msg::ReplUnterminatedQuote);
+    }
+    words.extend(word);
+    Ok(words)
+}
@@ -1,14 +1,19 @@
 use clap::ArgMatches;
 use failure::{Error, ResultExt};
-use libojo::resolver::{CandidateChain, CycleResolver, OrderResolver};
-use libojo::{Changes, Graggle, NodeId, Repo};
-use std::io::Write;
+use libojo::resolver::{CandidateChain, CycleResolver, OrderResolver, Ranking, Resolution};
+use libojo::{Changes, Graggle, NodeId, PatchId, Repo};
+use std::collections::HashSet;
+use std::io::{Read, Write};
 use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
 use termion::{clear, cursor, style};
 
+use crate::messages::Msg;
+
+mod script;
+
 pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     // The unwrap is ok because this is a required argument.
     let author = m.value_of("author").unwrap();
@@ -16,28 +21,66 @@ pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     let mut repo = super::open_repo()?;
     let branch = super::branch(&repo, m);
     let graggle = repo.graggle(&branch)?;
-    let testing = m.is_present("testing");
+    let edits = repo.edit_conflicts(&branch)?;
+    let ranking = match m.value_of("rank") {
+        Some("time") => Ranking::Timestamps(repo.patch_timestamps(&branch)?),
+        Some("none") => Ranking::Unranked,
+        _ => Ranking::Hints(repo.ordering_hints(&branch)?),
+    };
 
-    let changes = {
-        // Here we use the alternate screen, so nothing we print in this scope will be visible
-        // after the scope ends.
-        let stdout = std::io::stdout();
-        let screen: Screen = if !testing {
-            Box::new(
-                AlternateScreen::from(stdout)
-                    .into_raw_mode()
-                    .with_context(|_| "Failed to open the terminal in raw mode")?,
-            )
+    let resolved = if let Some(path) = m.value_of("script") {
+        let script = if path == "-" {
+            let mut script = String::new();
+            std::io::stdin().read_to_string(&mut script)?;
+            script
         } else {
-            // In testing mode, we just ignore all the output (because into_raw_mode fails with
-            // piped stdin).
-            Box::new(std::io::sink())
+            std::fs::read_to_string(path).with_context(|_| Msg::ReadFailed(path).to_string())?
         };
+        Some(script::run(&repo, graggle, ranking, &edits, &script)?)
+    } else {
+        resolve_interactively(&repo, graggle, ranking, edits)?
+    };
+
+    if let Some((changes, resolution)) = resolved {
+        let id = repo.create_resolution_patch(
+            &branch,
+            author,
+            "Resolve to a file",
+            changes,
+            resolution,
+        )?;
+        repo.write()?;
+        eprintln!("{}", Msg::PatchCreated(&id));
+    } else {
+        eprintln!("{}", Msg::NoPatchCreated);
+    }
+
+    Ok(())
+}
+
+// The lines that were edited concurrently, and the patches that edited them (as returned by
+// `Repo::edit_conflicts`).
+pub(crate) type Edits = Vec<(NodeId, Vec<PatchId>)>;
+
+fn resolve_interactively(
+    repo: &Repo,
+    graggle: Graggle<'_>,
+    ranking: Ranking,
+    edits: Edits,
+) -> Result<Option<(Changes, Resolution)>, Error> {
+    let resolved = {
+        // Here we use the alternate screen, so nothing we print in this scope will be visible
+        // after the scope ends.
+        let screen: Screen = Box::new(
+            AlternateScreen::from(std::io::stdout())
+                .into_raw_mode()
+                .with_context(|_| Msg::RawModeFailed.to_string())?,
+        );
         let stdin = std::io::stdin();
 
         // TODO: check if the terminal is big enough.
         write!(std::io::stdout(), "{}", cursor::Hide)?;
-        let cycle = CycleResolverState::new(&repo, screen, stdin.keys(), graggle)?;
+        let cycle = CycleResolverState::new(repo, screen, stdin.keys(), graggle, ranking, edits)?;
         if let Some(order) = cycle.run()? {
             order.run()?
         } else {
@@ -48,16 +91,7 @@ pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
     // TODO: the flush is currently necessary for the eprintln to work; see
     // https://gitlab.redox-os.org/redox-os/termion/issues/158
     std::io::stdout().flush()?;
-
-    if let Some(changes) = changes {
-        let id = repo.create_patch(author, "Resolve to a file", changes)?;
-        repo.write()?;
-        eprintln!("Created patch {}", id.to_base64());
-    } else {
-        eprintln!("No patch created");
-    }
-
-    Ok(())
+    Ok(resolved)
 }
 
 const NUMBERS: &[u8] = b"1234567890";
@@ -65,14 +99,19 @@ const NUMBERS_UPPER: &[u8] = b"!@#$%^&*()";
 const QWERTY: &[u8] = b"qwertyuiop";
 const QWERTY_UPPER: &[u8] = b"QWERTYUIOP";
 
-type Screen = Box<dyn std::io::Write>;
-type Input = termion::input::Keys<std::io::Stdin>;
+pub(crate) type Screen = Box<dyn std::io::Write>;
+pub(crate) type Input = termion::input::Keys<std::io::Stdin>;
 
 struct CycleResolverState<'a> {
     repo: &'a Repo,
+    graggle: Graggle<'a>,
     screen: Screen,
     input: Input,
     resolver: CycleResolver<'a>,
+    // How to rank the candidates, once we get to the order-resolving stage.
+    ranking: Ranking,
+    // The lines whose versions need to be chosen, once all the lines are in order.
+    edits: Edits,
 
     // Dimensions of the screen.
     width: u16,
@@ -84,21 +123,28 @@ impl<'a> CycleResolverState<'a> {
         screen: Screen,
         input: Input,
         graggle: Graggle<'a>,
+        ranking: Ranking,
+        edits: Edits,
     ) -> Result<CycleResolverState<'a>, Error> {
         let (width, _) = termion::terminal_size().unwrap_or((80, 24));
 
         Ok(CycleResolverState {
             repo,
+            graggle,
             screen,
             input,
             resolver: CycleResolver::new(graggle),
+            ranking,
+            edits,
             width,
         })
     }
 
     fn run(mut self) -> Result<Option<OrderResolverState<'a>>, Error> {
         while let Some(component) = self.resolver.next_component() {
-            let component = component.iter().cloned().collect::<Vec<_>>();
+            // The order matters for `ojo resolve --script`, which can refer to lines by position.
+            let mut component = component.iter().cloned().collect::<Vec<_>>();
+            component.sort();
 
             // We show at most 10 lines on a page; this is the index of the first shown line.
             let mut offset = 0;
@@ -110,7 +156,7 @@ impl<'a> CycleResolverState<'a> {
                 let key = self
                     .input
                     .next()
-                    .ok_or_else(|| failure::err_msg("Unexpected end of input"))??;
+                    .ok_or_else(|| failure::err_msg(Msg::UnexpectedEndOfInput.to_string()))??;
                 match key {
                     Key::Char(c) => {
                         if let Some(x) = NUMBERS.iter().position(|&a| a == c as u8) {
@@ -133,8 +179,16 @@ impl<'a> CycleResolverState<'a> {
                 }
             }
         }
-        let resolver = self.resolver.into_order_resolver();
-        OrderResolverState::new(self.repo, self.screen, self.input, resolver).map(Some)
+        let resolver = self.resolver.into_order_resolver_with(self.ranking);
+        OrderResolverState::new(
+            self.repo,
+            self.graggle,
+            self.screen,
+            self.input,
+            resolver,
+            self.edits,
+        )
+        .map(Some)
     }
 
     fn redraw(&mut self, lines: &[NodeId]) -> Result<(), Error> {
@@ -144,7 +198,7 @@ impl<'a> CycleResolverState<'a> {
                 "{goto}{key} {line}",
                 key = NUMBERS[i],
                 goto = cursor::Goto(1, 1 + (i as u16)),
-                line = String::from_utf8_lossy(self.repo.contents(u)),
+                line = String::from_utf8_lossy(self.repo.contents_in(self.graggle, u)),
             )?;
         }
 
@@ -152,10 +206,10 @@ impl<'a> CycleResolverState<'a> {
         draw_keybindings(
             &mut self.screen,
             vec![
-                (&keys[..], "choose line"),
-                ("k", "show previous"),
-                ("j", "show next"),
-                ("ESC", "quit"),
+                (&keys[..], Msg::KeyChooseLine),
+                ("k", Msg::KeyShowPrevious),
+                ("j", Msg::KeyShowNext),
+                ("ESC", Msg::KeyQuit),
             ],
             self.width,
         )?;
@@ -167,9 +221,11 @@ impl<'a> CycleResolverState<'a> {
 
 struct OrderResolverState<'a> {
     repo: &'a Repo,
+    graggle: Graggle<'a>,
     screen: Screen,
     input: Input,
     resolver: OrderResolver<'a>,
+    edits: Edits,
 
     // Dimensions of the screen.
     width: u16,
@@ -183,9 +239,11 @@ struct OrderResolverState<'a> {
 impl<'a> OrderResolverState<'a> {
     fn new(
         repo: &'a Repo,
+        graggle: Graggle<'a>,
         screen: Screen,
         input: Input,
         resolver: OrderResolver<'a>,
+        edits: Edits,
     ) -> Result<OrderResolverState<'a>, Error> {
         // If we fail to get a real width and height, try to keep going anyway. It probably just
         // means that stdin and stdout are pipes (which is probably because we're running some
@@ -194,20 +252,24 @@ impl<'a> OrderResolverState<'a> {
 
         Ok(OrderResolverState {
             repo,
+            graggle,
             screen,
             input,
             resolver,
+            edits,
             width,
             height,
             shown_first: 0,
         })
     }
 
-    fn run(mut self) -> Result<Option<Changes>, Error> {
+    fn run(mut self) -> Result<Option<(Changes, Resolution)>, Error> {
         loop {
+            // Don't bother asking about anything that doesn't need a decision.
+            self.resolver.auto_advance();
             let candidates = self.resolver.candidates().collect::<Vec<_>>();
             if candidates.is_empty() {
-                return Ok(Some(self.resolver.changes()));
+                return self.choose_versions();
             }
 
             self.shown_first = 0;
@@ -217,7 +279,7 @@ impl<'a> OrderResolverState<'a> {
             let key = self
                 .input
                 .next()
-                .ok_or_else(|| failure::err_msg("Unexpected end of input"))??;
+                .ok_or_else(|| failure::err_msg(Msg::UnexpectedEndOfInput.to_string()))??;
             match key {
                 Key::Char(c) => {
                     let chosen = |x: usize| {
@@ -269,6 +331,80 @@ impl<'a> OrderResolverState<'a> {
         }
     }
 
+    // Once all the lines are in order, asks which version to keep of each line that was edited
+    // concurrently (unless it was deleted).
+    fn choose_versions(mut self) -> Result<Option<(Changes, Resolution)>, Error> {
+        let kept = self
+            .resolver
+            .ordered_nodes()
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let edits = std::mem::take(&mut self.edits);
+        for (u, patches) in edits.iter().filter(|(u, _)| kept.contains(u)) {
+            // We can only offer as many versions as there are keys.
+            let versions = patches
+                .iter()
+                .take(NUMBERS.len())
+                .map(|p| self.repo.replacement(u, p))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.redraw_versions(&versions)?;
+            loop {
+                let key = self
+                    .input
+                    .next()
+                    .ok_or_else(|| failure::err_msg(Msg::UnexpectedEndOfInput.to_string()))??;
+                match key {
+                    Key::Char(c) => {
+                        if let Some(x) = NUMBERS.iter().position(|&a| a == c as u8) {
+                            if x < versions.len() {
+                                self.resolver.replace_contents(u, versions[x].to_vec());
+                                break;
+                            }
+                        }
+                    }
+                    Key::Esc => {
+                        return Ok(None);
+                    }
+                    _ => {
+                        debug!("unknown key");
+                    }
+                }
+            }
+        }
+        Ok(Some((self.resolver.changes(), self.resolver.resolution())))
+    }
+
+    fn redraw_versions(&mut self, versions: &[&[u8]]) -> Result<(), Error> {
+        write!(
+            self.screen,
+            "{clear}{goto}{msg}",
+            clear = clear::All,
+            goto = cursor::Goto(1, 1),
+            msg = Msg::ResolveChooseVersion,
+        )?;
+        for (i, data) in versions.iter().enumerate() {
+            let row = 3 + i as u16;
+            write!(
+                self.screen,
+                "{goto}{bold}{key}{unbold}",
+                goto = cursor::Goto(1, row),
+                bold = style::Bold,
+                key = NUMBERS[i] as char,
+                unbold = style::NoBold,
+            )?;
+            write_truncated(&mut self.screen, data, 3, row, self.width - 24)?;
+        }
+
+        let keys = format!("1-{}", NUMBERS[versions.len() - 1] as char);
+        self.draw_keybindings(vec![
+            (&keys[..], Msg::KeyChooseVersion),
+            ("ESC", Msg::KeyQuit),
+        ])?;
+        self.screen.flush()?;
+        Ok(())
+    }
+
     fn redraw(&mut self) -> Result<(), Error> {
         let divider_row = self.height - 5;
         write!(
@@ -287,7 +423,13 @@ impl<'a> OrderResolverState<'a> {
         let mut row = divider_row;
         for u in done.iter().rev().take(divider_row as usize - 1) {
             row -= 1;
-            write_truncated(&mut self.screen, self.repo.contents(u), 1, row, self.width)?;
+            write_truncated(
+                &mut self.screen,
+                self.repo.contents_in(self.graggle, u),
+                1,
+                row,
+                self.width,
+            )?;
         }
 
         let candidates = self.resolver.candidates().collect::<Vec<_>>();
@@ -309,10 +451,10 @@ impl<'a> OrderResolverState<'a> {
     fn redraw_one_choice(&mut self, candidate: &CandidateChain) -> Result<(), Error> {
         self.write_candidate_chain(candidate, 1, self.width)?;
         self.draw_keybindings(vec![
-            ("1", "take one"),
-            ("q", "delete one"),
-            ("!", "take all"),
-            ("Q", "delete all"),
+            ("1", Msg::KeyTakeOne),
+            ("q", Msg::KeyDeleteOne),
+            ("!", Msg::KeyTakeAll),
+            ("Q", Msg::KeyDeleteAll),
         ])?;
         Ok(())
     }
@@ -346,11 +488,11 @@ impl<'a> OrderResolverState<'a> {
         )?;
 
         self.draw_keybindings(vec![
-            ("1", "take left"),
-            ("2", "take right"),
-            ("q", "delete left"),
-            ("w", "delete right"),
-            ("ESC", "quit"),
+            ("1", Msg::KeyTakeLeft),
+            ("2", Msg::KeyTakeRight),
+            ("q", Msg::KeyDeleteLeft),
+            ("w", Msg::KeyDeleteRight),
+            ("ESC", Msg::KeyQuit),
         ])
     }
 
@@ -375,7 +517,7 @@ impl<'a> OrderResolverState<'a> {
             let u = candidates[cand_idx].first();
             write_truncated(
                 &mut self.screen,
-                self.repo.contents(&u),
+                self.repo.contents_in(self.graggle, &u),
                 3,
                 row,
                 self.width - 2,
@@ -392,33 +534,36 @@ impl<'a> OrderResolverState<'a> {
         delete_all_range[2] = QWERTY_UPPER[num_candidates - 1];
 
         let mut keybindings = vec![
-            (std::str::from_utf8(&choose_range[..]).unwrap(), "take line"),
+            (
+                std::str::from_utf8(&choose_range[..]).unwrap(),
+                Msg::KeyTakeLine,
+            ),
             (
                 std::str::from_utf8(&delete_range[..]).unwrap(),
-                "delete line",
+                Msg::KeyDeleteLine,
             ),
             (
                 std::str::from_utf8(&choose_all_range[..]).unwrap(),
-                "take lines",
+                Msg::KeyTakeLines,
             ),
             (
                 std::str::from_utf8(&delete_all_range[..]).unwrap(),
-                "delete lines",
+                Msg::KeyDeleteLines,
             ),
         ];
 
         if self.shown_first > 0 {
-            keybindings.push(("k", "show previous"));
+            keybindings.push(("k", Msg::KeyShowPrevious));
         }
         if self.shown_first + 5 < candidates.len() {
-            keybindings.push(("j", "show next"));
+            keybindings.push(("j", Msg::KeyShowNext));
         }
-        keybindings.push(("ESC", "quit"));
+        keybindings.push(("ESC", Msg::KeyQuit));
         self.draw_keybindings(keybindings)?;
         Ok(())
     }
 
-    fn draw_keybindings(&mut self, bindings: Vec<(&str, &str)>) -> Result<(), Error> {
+    fn draw_keybindings(&mut self, bindings: Vec<(&str, Msg<'_>)>) -> Result<(), Error> {
         draw_keybindings(&mut self.screen, bindings, self.width)
     }
 
@@ -431,16 +576,16 @@ impl<'a> OrderResolverState<'a> {
         let mut row = self.height - 5;
         for u in chain.iter().take(5) {
             row += 1;
-            let data = self.repo.contents(&u);
+            let data = self.repo.contents_in(self.graggle, &u);
             write_truncated(&mut self.screen, data, col, row, max_width)?;
         }
         Ok(())
     }
 }
 
-fn draw_keybindings(
+pub(crate) fn draw_keybindings(
     screen: &mut Screen,
-    bindings: Vec<(&str, &str)>,
+    bindings: Vec<(&str, Msg<'_>)>,
     width: u16,
 ) -> Result<(), Error> {
     let mut row = 1;
@@ -466,7 +611,7 @@ fn draw_keybindings(
     Ok(())
 }
 
-fn write_truncated(
+pub(crate) fn write_truncated(
     screen: &mut Screen,
     data: &[u8],
     col: u16,
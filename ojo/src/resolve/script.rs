@@ -0,0 +1,210 @@
+// Replays a resolution from a script, instead of asking the user.
+//
+// A script is a text file with one step per line. Empty lines and lines starting with '#' are
+// ignored. Each step is an action followed by a line, where the line is either its position
+// (counting from 1) among the current choices or its node id, written as `<patch>/<index>` (the
+// patch can be abbreviated, or given by an alias). The actions are:
+//
+// - `cycle`: resolve the current cycle by keeping this line and deleting the rest of the cycle.
+//   The lines in a cycle are ordered by their node ids.
+// - `choose`, `delete`: take the first line of this candidate next, or delete it.
+// - `choose-all`, `delete-all`: take (or delete) the whole chain of lines starting here.
+// - `version`: for a line that was edited concurrently, keep this version of it. Instead of a line,
+//   this takes either the position of the version (counting from 1) among the versions, which are
+//   ordered by the ids of the patches that made them, or the patch that made it.
+//
+// Steps are matched with the decisions in the order that `ojo resolve` asks for them: first all the
+// cycles, then the ordering, and then the versions of the lines that were edited concurrently (and
+// that weren't deleted), ordered by the lines' ids. Decisions that `ojo resolve` doesn't ask about (because there's
+// only one sensible choice) don't get steps. Any mismatch between the script and the decisions
+// that need to be made is an error.
+
+use failure::Error;
+use libojo::resolver::{CycleResolver, Ranking, Resolution};
+use libojo::{Changes, Graggle, NodeId, PatchId, Repo};
+use std::collections::HashSet;
+
+use super::Edits;
+use crate::messages::Msg;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    Cycle,
+    Choose,
+    Delete,
+    ChooseAll,
+    DeleteAll,
+    Version,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum NodeRef {
+    // A position among the current choices, counting from zero.
+    Index(usize),
+    Id(NodeId),
+    // A version of a line, given by the patch that made it.
+    Patch(PatchId),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    // The line of the script that this step came from, counting from 1.
+    line: usize,
+    action: Action,
+    node: NodeRef,
+}
+
+impl Step {
+    // Finds the position of this step's node in `choices`.
+    fn find(&self, choices: &[NodeId]) -> Result<usize, Error> {
+        let idx = match self.node {
+            NodeRef::Index(i) if i < choices.len() => Some(i),
+            NodeRef::Index(_) => None,
+            NodeRef::Id(u) => choices.iter().position(|c| *c == u),
+            NodeRef::Patch(_) => None,
+        };
+        idx.ok_or_else(|| format_err!("{}", Msg::ScriptNoSuchChoice(self.line)))
+    }
+
+    // Finds the position of this step's version in `patches`.
+    fn find_version(&self, patches: &[PatchId]) -> Result<usize, Error> {
+        let idx = match self.node {
+            NodeRef::Index(i) if i < patches.len() => Some(i),
+            NodeRef::Patch(p) => patches.iter().position(|q| *q == p),
+            _ => None,
+        };
+        idx.ok_or_else(|| format_err!("{}", Msg::ScriptNoSuchChoice(self.line)))
+    }
+}
+
+fn parse_node(repo: &Repo, s: &str) -> Result<Option<NodeRef>, Error> {
+    if let Some(slash) = s.find('/') {
+        let patch = repo.resolve_patch(&s[..slash])?;
+        Ok(s[(slash + 1)..]
+            .parse::<u64>()
+            .ok()
+            .map(|node| NodeRef::Id(NodeId { patch, node })))
+    } else {
+        Ok(s.parse::<usize>()
+            .ok()
+            .filter(|&i| i > 0)
+            .map(|i| NodeRef::Index(i - 1)))
+    }
+}
+
+fn parse(repo: &Repo, script: &str) -> Result<Vec<Step>, Error> {
+    let mut ret = Vec::new();
+    for (i, text) in script.lines().enumerate() {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let action = match words[0] {
+            "cycle" => Some(Action::Cycle),
+            "choose" => Some(Action::Choose),
+            "delete" => Some(Action::Delete),
+            "choose-all" => Some(Action::ChooseAll),
+            "delete-all" => Some(Action::DeleteAll),
+            "version" => Some(Action::Version),
+            _ => None,
+        };
+        let node = match words[..] {
+            [_, word] if action == Some(Action::Version) => match word.parse::<usize>() {
+                Ok(i) => Some(i).filter(|&i| i > 0).map(|i| NodeRef::Index(i - 1)),
+                Err(_) => Some(NodeRef::Patch(repo.resolve_patch(word)?)),
+            },
+            [_, word] => parse_node(repo, word)?,
+            _ => None,
+        };
+        match (action, node) {
+            (Some(action), Some(node)) => ret.push(Step {
+                line: i + 1,
+                action,
+                node,
+            }),
+            _ => bail!("{}", Msg::ScriptInvalidStep { line: i + 1, text }),
+        }
+    }
+    Ok(ret)
+}
+
+/// Resolves `graggle` according to `script`.
+pub fn run(
+    repo: &Repo,
+    graggle: Graggle<'_>,
+    ranking: Ranking,
+    edits: &Edits,
+    script: &str,
+) -> Result<(Changes, Resolution), Error> {
+    let mut steps = parse(repo, script)?.into_iter();
+    let mut next_step = || {
+        steps
+            .next()
+            .ok_or_else(|| format_err!("{}", Msg::ScriptEnded))
+    };
+
+    let mut cycles = CycleResolver::new(graggle);
+    while let Some(component) = cycles.next_component() {
+        let mut component = component.iter().cloned().collect::<Vec<_>>();
+        component.sort();
+        let step = next_step()?;
+        if step.action != Action::Cycle {
+            bail!("{}", Msg::ScriptExpectedCycle(step.line));
+        }
+        cycles.resolve_component(component[step.find(&component)?]);
+    }
+
+    let mut order = cycles.into_order_resolver_with(ranking);
+    loop {
+        order.auto_advance();
+        let candidates = order.candidates().collect::<Vec<_>>();
+        if candidates.is_empty() {
+            break;
+        }
+
+        let step = next_step()?;
+        match step.action {
+            Action::Cycle => bail!("{}", Msg::ScriptExpectedOrder(step.line)),
+            Action::Version => bail!("{}", Msg::ScriptUnexpectedVersion(step.line)),
+            _ => {}
+        }
+        let firsts = candidates.iter().map(|c| c.first()).collect::<Vec<_>>();
+        let chosen = &candidates[step.find(&firsts)?];
+        match step.action {
+            Action::Cycle | Action::Version => unreachable!(),
+            Action::Choose => order.choose(&chosen.first()),
+            Action::Delete => order.delete(&chosen.first()),
+            Action::ChooseAll => {
+                for u in chosen.iter() {
+                    order.choose(&u);
+                }
+            }
+            Action::DeleteAll => {
+                for u in chosen.iter() {
+                    order.delete(&u);
+                }
+            }
+        }
+    }
+
+    let kept = order
+        .ordered_nodes()
+        .iter()
+        .cloned()
+        .collect::<HashSet<_>>();
+    for (u, patches) in edits.iter().filter(|(u, _)| kept.contains(u)) {
+        let step = next_step()?;
+        if step.action != Action::Version {
+            bail!("{}", Msg::ScriptExpectedVersion(step.line));
+        }
+        let patch = &patches[step.find_version(patches)?];
+        order.replace_contents(u, repo.replacement(u, patch)?.to_vec());
+    }
+
+    if let Some(step) = steps.next() {
+        bail!("{}", Msg::ScriptTooLong(step.line));
+    }
+    Ok((order.changes(), order.resolution()))
+}
@@ -0,0 +1,33 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{err_msg, Error, ResultExt};
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // The unwrap is ok because this is a required argument.
+    let patch_id = m.value_of("to").unwrap();
+
+    let repo = crate::open_repo()?;
+    let patch_id = repo.resolve_patch(patch_id)?;
+    let branch = crate::branch(&repo, m);
+    let file_name = crate::file_path(m);
+
+    let file = repo
+        .preview_at_patch(&branch, &patch_id)
+        .map_err(|e| match e {
+            libojo::Error::NotOrdered(_) => {
+                err_msg(Msg::RestoreNotOrdered(&repo.abbreviate_patch_id(&patch_id)).to_string())
+            }
+            other => other.into(),
+        })?;
+    let path = repo.root_dir.join(&file_name);
+    std::fs::write(&path, file.as_bytes())
+        .with_context(|_| Msg::WriteFailed(&file_name).to_string())?;
+    eprintln!(
+        "{}",
+        Msg::Restored {
+            path: &file_name,
+            patch: &repo.abbreviate_patch_id(&patch_id),
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{Error, Fail, ResultExt};
+use libojo::Changes;
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    match m.subcommand_name() {
+        Some("list") => list_run(m.subcommand_matches("list").unwrap()),
+        Some("pop") => pop_run(m.subcommand_matches("pop").unwrap()),
+        Some("push") => push_run(m.subcommand_matches("push").unwrap()),
+        _ => panic!("Unknown subcommand"),
+    }
+}
+
+fn list_run(_m: &ArgMatches<'_>) -> Result<(), Error> {
+    let repo = crate::open_repo()?;
+    for (i, stash) in repo.stashes().iter().enumerate() {
+        println!(
+            "{}",
+            Msg::StashEntry {
+                index: i,
+                branch: &stash.branch,
+                description: &stash.description,
+            }
+        );
+    }
+    Ok(())
+}
+
+fn pop_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let index = match m.value_of("INDEX") {
+        Some(i) => i
+            .parse::<usize>()
+            .with_context(|_| Msg::InvalidStashIndex(i).to_string())?,
+        None => 0,
+    };
+    let mut repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+    let path = repo.root_dir.join(crate::file_path(m));
+
+    let file = repo.stash_pop(&branch, index).map_err(|e| match e {
+        libojo::Error::NotOrdered(_) => e.context(Msg::StashPopNotOrdered.to_string()).into(),
+        libojo::Error::UnknownNode(_) => {
+            e.context(Msg::StashPopMismatch(&branch).to_string()).into()
+        }
+        other => Error::from(other),
+    })?;
+    std::fs::write(&path, file.as_bytes())
+        .with_context(|_| Msg::WriteFailed(&path.to_string_lossy()).to_string())?;
+    repo.write()?;
+    eprintln!("{}", Msg::StashPopped(index));
+    Ok(())
+}
+
+fn push_run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+    let file_name = crate::file_path(m);
+    let diff = crate::diff::diff(&repo, &branch, &file_name, false)?;
+    let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+    if changes.changes.is_empty() {
+        eprintln!("{}", Msg::NothingToStash);
+        return Ok(());
+    }
+
+    let description = m.value_of("description").unwrap_or("");
+    repo.stash_push(&branch, description, changes);
+    std::fs::write(repo.root_dir.join(&file_name), diff.file_a.as_bytes())
+        .with_context(|_| Msg::WriteFailed(&file_name).to_string())?;
+    repo.write()?;
+    eprintln!("{}", Msg::Stashed(&file_name));
+    Ok(())
+}
@@ -1,6 +1,7 @@
+use crate::messages::Msg;
 use clap::ArgMatches;
 use failure::{err_msg, Error, ResultExt};
-use libojo::{Change, Changes, NodeId, Repo};
+use libojo::{Change, Changes, NodeId, Repo, SynthPreset};
 use std::io::{stdin, Read};
 
 fn parse_edge(s: &str) -> Option<(usize, usize)> {
@@ -10,29 +11,49 @@ fn parse_edge(s: &str) -> Option<(usize, usize)> {
     Some((u, v))
 }
 
-pub fn run(_m: &ArgMatches<'_>) -> Result<(), Error> {
-    let dir = std::env::current_dir().context("Couldn't open the current directory.")?;
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    let dir = crate::working_dir()?;
     let mut repo = Repo::init(&dir)?;
     // We need to write the repo before creating the patch, so that the directories all exist.
-    repo.write()
-        .context("Failed to write repository to disk.")?;
+    repo.write().context(Msg::RepoWriteFailed.to_string())?;
+
+    if let Some(preset) = m.value_of("preset") {
+        let preset = match preset {
+            "linear" => SynthPreset::Linear,
+            "appends" => SynthPreset::Appends,
+            "tombstones" => SynthPreset::Tombstones,
+            // clap checks that the value is one of the ones above.
+            _ => unreachable!(),
+        };
+        // The unwrap is ok because size is a required argument when preset is present.
+        let size = m.value_of("size").unwrap();
+        let size = size
+            .parse::<usize>()
+            .with_context(|_| Msg::InvalidSize(size).to_string())?;
+        repo.synthesize("master", preset, size)?;
+        repo.write().context(Msg::RepoWriteFailed.to_string())?;
+        eprintln!("{}", Msg::Synthesized);
+        return Ok(());
+    }
 
     let mut buf = Vec::new();
     stdin().read_to_end(&mut buf)?;
-    let buf = String::from_utf8(buf).context("Expected stdin to be UTF-8, but it wasn't.")?;
+    let buf = String::from_utf8(buf).context(Msg::StdinNotUtf8.to_string())?;
     let edges = buf
         .split_whitespace()
-        .map(|s| parse_edge(s).ok_or_else(|| format_err!("Failed to parse '{}'.", s)))
+        .map(|s| parse_edge(s).ok_or_else(|| format_err!("{}", Msg::EdgeParseFailed(s))))
         .collect::<Result<Vec<_>, _>>()?;
 
     let max_node = edges
         .iter()
         .map(|&(x, y)| x.max(y))
         .max()
-        .ok_or_else(|| err_msg("Input was empty."))?;
+        .ok_or_else(|| err_msg(Msg::EmptyInput.to_string()))?;
     let new_nodes = (0..=max_node).map(|i| Change::NewNode {
         id: NodeId::cur(i as u64),
         contents: format!("Line {}\n", i).into_bytes(),
+        line: None,
+        path: None,
     });
     let new_edges = edges.into_iter().map(|(i, j)| Change::NewEdge {
         src: NodeId::cur(i as u64),
@@ -43,9 +64,8 @@ pub fn run(_m: &ArgMatches<'_>) -> Result<(), Error> {
     };
     let id = repo.create_patch("Anonymous bot", "Synthesized", changes)?;
     repo.apply_patch("master", &id)?;
-    repo.write()
-        .context("Failed to write repository to disk.")?;
+    repo.write().context(Msg::RepoWriteFailed.to_string())?;
 
-    eprintln!("Synthesized a ojo repository.");
+    eprintln!("{}", Msg::Synthesized);
     Ok(())
 }
@@ -0,0 +1,107 @@
+use crate::messages::Msg;
+use clap::ArgMatches;
+use failure::{err_msg, Error, ResultExt};
+use libojo::Changes;
+use notify::{Event, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+const DESCRIPTION: &str = "Automatic snapshot";
+
+pub fn run(m: &ArgMatches<'_>) -> Result<(), Error> {
+    // If there's no author, we're stashing (clap makes sure of that).
+    let author = m.value_of("author");
+    let interval = match m.value_of("interval") {
+        Some(s) => s
+            .parse::<u64>()
+            .with_context(|_| Msg::InvalidInterval(s).to_string())?,
+        None => 1,
+    };
+    let interval = Duration::from_secs(interval);
+
+    let repo = crate::open_repo()?;
+    let branch = crate::branch(&repo, m);
+    let file_name = crate::file_path(m);
+    let path = repo.root_dir.join(&file_name);
+    // We reopen the repository every time we record something, so that we don't hold on to stale
+    // data while other commands modify it.
+    drop(repo);
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx).context(Msg::WatchStartFailed.to_string())?;
+    // Many editors save files by writing a new file and moving it into place, so we watch the
+    // whole directory instead of just the file.
+    let dir = path
+        .parent()
+        .ok_or_else(|| format_err!("{}", Msg::NoParentDir(&file_name)))?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|_| Msg::WatchFailed(&file_name).to_string())?;
+    eprintln!("{}", Msg::Watching(&file_name));
+
+    loop {
+        wait_for_change(&rx, &path, None)?;
+        // Editors sometimes save a file in several steps, so wait until it stops changing.
+        while wait_for_change(&rx, &path, Some(interval))? {}
+
+        // Failing to record one snapshot (for example, because the file is temporarily missing)
+        // shouldn't stop us from recording the next one.
+        if let Err(e) = record(&branch, &file_name, author) {
+            eprintln!("{}", Msg::Error(&e));
+        }
+    }
+}
+
+// Waits until there's an event that modifies `path`, returning `false` if `timeout` passes first.
+fn wait_for_change(
+    rx: &Receiver<notify::Result<Event>>,
+    path: &Path,
+    timeout: Option<Duration>,
+) -> Result<bool, Error> {
+    loop {
+        let event = match timeout {
+            Some(t) => match rx.recv_timeout(t) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => return Err(watcher_stopped()),
+            },
+            None => rx.recv().map_err(|_| watcher_stopped())?,
+        };
+        let event = event.context(Msg::WatchEventFailed.to_string())?;
+        let touches_path = event
+            .paths
+            .iter()
+            .any(|p| p.file_name() == path.file_name());
+        if touches_path && !event.kind.is_access() {
+            return Ok(true);
+        }
+    }
+}
+
+fn watcher_stopped() -> Error {
+    err_msg(Msg::WatcherStopped.to_string())
+}
+
+// Records the current contents of the file, either as a patch (if there's an author) or as a
+// stash.
+fn record(branch: &str, file_name: &str, author: Option<&str>) -> Result<(), Error> {
+    let mut repo = crate::open_repo()?;
+    let diff = crate::diff::diff(&repo, branch, file_name, false)?;
+    let changes = Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
+    if changes.changes.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(author) = author {
+        let id = repo.create_patch_on_branch(branch, author, DESCRIPTION, changes)?;
+        repo.apply_patch(branch, &id)?;
+        repo.write()?;
+        eprintln!("{}", Msg::PatchCreatedAndApplied(&id));
+    } else {
+        repo.stash_push(branch, DESCRIPTION, changes);
+        repo.write()?;
+        eprintln!("{}", Msg::Stashed(file_name));
+    }
+    Ok(())
+}
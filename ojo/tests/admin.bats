@@ -0,0 +1,37 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "admin reattribute" {
+    $OJO init
+    echo "Content" > ojo_file.txt
+    HASH=`$OJO patch create -a "Old Name" -m Msg --output-hash --then-apply`
+
+    run $OJO admin reattribute
+    assert_success
+    assert_output ""
+
+    run $OJO admin reattribute --from "Old Name" --to "New Name"
+    assert_success
+    assert_output "Patches by 'Old Name' will be shown as being by 'New Name'."
+
+    run $OJO log
+    assert_line "Author: New Name"
+    run $OJO patch show $HASH
+    assert_line "Author: New Name"
+
+    # The patch itself is unchanged.
+    run $OJO log --full-ids
+    assert_line "patch $HASH"
+
+    run $OJO admin reattribute
+    assert_output "Old Name -> New Name"
+
+    run $OJO admin reattribute --from "Old Name"
+    assert_failure
+
+    # Reattributing an author to itself undoes the reattribution.
+    $OJO admin reattribute --from "Old Name" --to "Old Name"
+    run $OJO log
+    assert_line "Author: Old Name"
+}
@@ -0,0 +1,116 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "patch amend" {
+    $OJO init
+    printf "a\nb\n" > ojo_file.txt
+    HASH=`$OJO patch create -a me -m Msg --output-hash --then-apply`
+    $OJO patch alias $HASH first
+
+    printf "a\nb\nc\n" > ojo_file.txt
+    run $OJO patch amend first
+    assert_success
+    assert_output --partial "Replaced patch"
+
+    run $OJO patch show first
+    assert_success
+    assert_line "Author: me"
+    assert_line "	Msg"
+    refute_line "patch $HASH"
+
+    run $OJO patch show $HASH
+    assert_failure
+
+    run $OJO render
+    run cat ojo_file.txt
+    assert_output "a
+b
+c"
+
+    run $OJO log
+    assert_line "	Msg"
+    refute_output --partial "No patches"
+
+    # The new patch remembers the one that it replaced.
+    run $OJO log --full-ids
+    assert_line "Supersedes: $HASH"
+}
+
+@test "patch amend: twice" {
+    $OJO init
+    printf "a\n" > ojo_file.txt
+    FIRST=`$OJO patch create -a me -m Msg --output-hash --then-apply`
+    printf "b\n" > ojo_file.txt
+    SECOND=`$OJO patch amend $FIRST --output-hash`
+    printf "c\n" > ojo_file.txt
+    THIRD=`$OJO patch amend $SECOND --output-hash`
+
+    # The trail leads all the way back to the original patch.
+    run $OJO patch show $THIRD
+    assert_success
+    assert_line "Supersedes: ${SECOND:0:8}"
+    assert_line "Supersedes: ${FIRST:0:8}"
+}
+
+@test "patch amend: new description" {
+    $OJO init
+    printf "a\n" > ojo_file.txt
+    HASH=`$OJO patch create -a me -m Msg --output-hash --then-apply`
+    printf "b\n" > ojo_file.txt
+    NEW=`$OJO patch amend $HASH -m Other -a you --output-hash`
+
+    run $OJO patch show $NEW
+    assert_line "Author: you"
+    assert_line "	Other"
+}
+
+@test "patch amend: needed by another patch" {
+    $OJO init
+    printf "a\n" > ojo_file.txt
+    HASH=`$OJO patch create -a me -m Msg --output-hash --then-apply`
+    printf "a\nb\n" > ojo_file.txt
+    $OJO patch create -a me -m Msg2 --then-apply
+
+    run $OJO patch amend $HASH
+    assert_failure
+    assert_output --partial "can't be amended, because patch"
+
+    # Nothing changed.
+    run $OJO patch show $HASH
+    assert_success
+}
+
+@test "patch amend: applied to another branch" {
+    $OJO init
+    printf "a\n" > ojo_file.txt
+    HASH=`$OJO patch create -a me -m Msg --output-hash --then-apply`
+    $OJO branch clone other
+
+    run $OJO patch amend $HASH
+    assert_failure
+    assert_output --partial "it is also applied to branch other"
+}
+
+@test "patch amend: not applied" {
+    $OJO init
+    printf "a\n" > ojo_file.txt
+    HASH=`$OJO patch create -a me -m Msg --output-hash`
+
+    run $OJO patch amend $HASH
+    assert_failure
+}
+
+@test "patch amend: nothing left" {
+    $OJO init
+    printf "a\n" > ojo_file.txt
+    HASH=`$OJO patch create -a me -m Msg --output-hash --then-apply`
+    : > ojo_file.txt
+
+    run $OJO patch amend $HASH
+    assert_failure
+    assert_output --partial "would have no changes"
+
+    run $OJO patch show $HASH
+    assert_success
+}
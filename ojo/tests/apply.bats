@@ -54,7 +54,13 @@ EOF
     run $OJO render
     cat ojo_file.txt
     assert_failure
-    assert_output "Error: Couldn't render a file, because the data isn't ordered"
+    assert_line --index 0 "Error: Couldn't render a file, because the data isn't ordered"
+    assert_line --index 1 "The file has 0 cycle(s) and 1 unordered region(s)"
+    assert_line "  near: Second"
+    assert_line "  near: Middle"
+    assert_line --index 4 --partial "${HASH_A:0:8}"
+    assert_line --index 4 --partial "${HASH_B:0:8}"
+    assert_equal "${#lines[@]}" 5
 }
 
 @test "delete and undelete" {
@@ -83,3 +89,104 @@ EOF
     $OJO patch apply "$HASH"
 }
 
+
+@test "apply: abbreviated hash" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH=`$OJO patch create -a Author -m Msg --output-hash`
+    run $OJO patch apply "${HASH:0:10}"
+    assert_success
+    assert_output --partial "${HASH:0:8}"
+
+    run $OJO log
+    assert_line --index 0 --regexp "^patch ${HASH:0:8}"
+    refute_output --partial "$HASH"
+
+    run $OJO patch apply "Pnonexistent"
+    assert_failure
+    assert_output --partial "There is no patch whose hash starts with"
+}
+
+@test "apply: alias" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH=`$OJO patch create -a Author -m Msg --output-hash`
+    run $OJO patch alias "$HASH" the-first-patch
+    assert_success
+
+    run $OJO patch alias "$HASH" the-first-patch
+    assert_failure
+    assert_output --partial "already exists"
+
+    run $OJO patch apply the-first-patch
+    assert_success
+    run $OJO log
+    assert_line --index 0 --partial "(the-first-patch)"
+}
+
+@test "apply: already applied" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH=`$OJO patch create -a Author -m Msg --output-hash`
+    run $OJO patch apply "$HASH"
+    assert_success
+    assert_line --index 0 "Applied:"
+
+    run $OJO patch apply "$HASH"
+    assert_success
+    assert_output --partial "is already applied to branch master"
+
+    run $OJO patch apply -R "$HASH"
+    assert_success
+    assert_line --index 0 "Unapplied:"
+
+    run $OJO patch apply -R "$HASH"
+    assert_success
+    assert_output --partial "is not applied to branch master"
+}
+
+@test "apply and unapply several patches" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    FIRST=`$OJO patch create -a Author -m Msg --output-hash`
+    $OJO patch apply "$FIRST"
+    echo "Second" >> ojo_file.txt
+    SECOND=`$OJO patch create -a Author -m Msg --output-hash`
+    $OJO patch apply "$SECOND"
+    echo "Third" >> ojo_file.txt
+    THIRD=`$OJO patch create -a Author -m Msg --output-hash`
+    $OJO patch apply -R "$SECOND"
+
+    run $OJO patch apply "$FIRST" "$SECOND" "$THIRD"
+    assert_success
+    assert_line --partial "is already applied"
+    assert_line "Applied:"
+
+    run $OJO patch unapply "$SECOND"
+    assert_failure
+    assert_output --partial "use --all-from to unapply both"
+
+    run $OJO patch unapply "$SECOND" "$THIRD"
+    assert_success
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First"
+
+    $OJO patch apply "$THIRD"
+    run $OJO patch unapply --all-from "$FIRST"
+    assert_success
+    $OJO render
+    run cat ojo_file.txt
+    assert_output ""
+}
+
+@test "applying several patches is all or nothing" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    FIRST=`$OJO patch create -a Author -m Msg --output-hash`
+
+    run $OJO patch apply "$FIRST" not-a-patch
+    assert_failure
+    run $OJO log
+    assert_output ""
+}
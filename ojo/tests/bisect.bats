@@ -0,0 +1,63 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+# Creates a branch with five patches, each appending a line. The third one adds "bug".
+make_history() {
+    $OJO init
+    for line in one two bug three four; do
+        echo $line >> ojo_file.txt
+        $OJO patch create -a me -m "add $line" --then-apply
+    done
+}
+
+@test "bisect: by hand" {
+    make_history
+    run $OJO bisect start
+    assert_success
+    assert_output -p "5 patches left"
+
+    while [ -z "$(echo "$output" | grep "first bad patch")" ]; do
+        if grep -q bug ojo_file.txt; then
+            run $OJO bisect bad
+        else
+            run $OJO bisect good
+        fi
+        assert_success
+    done
+    assert_output -p "The first bad patch is"
+    assert_output -p "add bug"
+    run $OJO branch list
+    refute_output -p "bisect"
+    run $OJO diff
+    assert_success
+}
+
+@test "bisect: run" {
+    make_history
+    $OJO bisect start
+    run $OJO bisect run "! grep -q bug"
+    assert_success
+    assert_output -p "add bug"
+}
+
+@test "bisect: reset" {
+    make_history
+    $OJO bisect start
+    run $OJO bisect reset
+    assert_success
+    assert_output 'Stopped bisecting branch "master"'
+    run $OJO diff
+    assert_success
+    run $OJO bisect good
+    assert_failure
+    assert_output "Error: There is no bisection in progress"
+}
+
+@test "bisect: uncommitted changes" {
+    make_history
+    echo extra >> ojo_file.txt
+    run $OJO bisect start
+    assert_failure
+    assert_output -p "has changes that aren't in a patch"
+}
@@ -99,3 +99,125 @@ load 'libs/setup'
 }
 
 
+
+@test "branch: invalid names" {
+    $OJO init
+    run $OJO branch new "has space"
+    assert_failure
+    assert_output --partial "not a valid branch name"
+    run $OJO branch clone "bad/../name"
+    assert_failure
+    assert_output --partial "not a valid branch name"
+}
+
+@test "branch: list with a pattern" {
+    $OJO init
+    $OJO branch new me/one
+    $OJO branch new me/two
+    $OJO branch new me/two/old
+    $OJO branch new you/one
+
+    run $OJO branch list "me/*"
+    assert_success
+    assert_output "  me/one
+  me/two"
+
+    run $OJO branch list "*/one"
+    assert_output "  me/one
+  you/one"
+}
+
+@test "branch fork: leaves out patches and their dependents" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    FIRST=$($OJO patch create -a Author -m Msg --then-apply 2>&1 | awk '{print $NF}')
+    echo "Second" >> ojo_file.txt
+    SECOND=$($OJO patch create -a Author -m Msg --then-apply 2>&1 | awk '{print $NF}')
+    echo "Third" >> ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    run $OJO branch fork fork --without $SECOND
+    assert_success
+    assert_output --partial "leaving out 2 patch(es)"
+
+    $OJO branch switch fork
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First"
+
+    $OJO branch switch master
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Second
+Third"
+
+    run $OJO branch fork other --without not-a-patch
+    assert_failure
+}
+
+@test "branch archive: hides branches from the list" {
+    $OJO init
+    $OJO branch new old
+    $OJO branch new zebra
+
+    run $OJO branch archive old
+    assert_success
+    assert_output "Archived branch \"old\""
+
+    run $OJO branch list
+    assert_output "* master
+  zebra"
+
+    run $OJO branch list --all
+    assert_output "* master
+  old (archived)
+  zebra"
+
+    $OJO branch unarchive old
+    run $OJO branch list
+    assert_output "* master
+  old
+  zebra"
+
+    run $OJO branch archive master
+    assert_failure
+    assert_output "Error: \"master\" is the current branch"
+}
+
+@test "branch protect: refuses to unapply, clear or delete without --force" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH=`$OJO patch create -a Author -m Msg --then-apply --output-hash`
+    $OJO branch clone other
+
+    run $OJO branch protect master
+    assert_success
+    assert_output "Protected branch \"master\""
+    $OJO branch protect other
+
+    run $OJO patch unapply "$HASH"
+    assert_failure
+    assert_output "Error: The branch \"master\" is protected"
+    run $OJO patch apply -R "$HASH"
+    assert_failure
+    run $OJO clear
+    assert_failure
+    run $OJO branch delete other
+    assert_failure
+    assert_output "Error: The branch \"other\" is protected"
+
+    run $OJO patch unapply --force "$HASH"
+    assert_success
+    # The branch is still protected afterwards.
+    run $OJO patch apply "$HASH"
+    assert_success
+    run $OJO clear
+    assert_failure
+
+    run $OJO branch delete --force other
+    assert_success
+    $OJO branch unprotect master
+    run $OJO clear
+    assert_success
+}
@@ -0,0 +1,63 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+create_history() {
+    $OJO init
+    printf "a\nb\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    printf "c\nb\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+}
+
+@test "clear: dry run" {
+    create_history
+    run $OJO clear --dry-run
+    assert_success
+    assert_output "Clearing branch 'master' would remove 2 patches, 2 live nodes and 1 deleted node."
+
+    rm ojo_file.txt
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "c
+b"
+}
+
+@test "clear: keep contents" {
+    create_history
+    run $OJO clear --keep-contents
+    assert_failure
+
+    run $OJO clear --keep-contents -a Someone
+    assert_success
+    assert_output -p "Cleared branch 'master', keeping its contents in the baseline patch"
+
+    run $OJO log
+    assert_output -p "Someone"
+    refute_output -p "Author: Author"
+
+    rm ojo_file.txt
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "c
+b"
+
+    run $OJO clear --dry-run
+    assert_output "Clearing branch 'master' would remove 1 patch, 2 live nodes and 0 deleted nodes."
+}
+
+@test "clear: keep contents of a protected branch" {
+    create_history
+    $OJO branch protect master
+    run $OJO clear --keep-contents -a Someone
+    assert_failure
+    assert_output -p "protected"
+
+    run $OJO clear --dry-run
+    assert_output -p "2 patches"
+
+    run $OJO clear --keep-contents -a Someone --force
+    assert_success
+    run $OJO clear --dry-run
+    assert_output -p "1 patch,"
+}
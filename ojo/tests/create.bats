@@ -60,5 +60,104 @@ load 'libs/setup'
     echo contents > ojo_file.txt
     run $OJO patch create -a Author -m Msg --output-hash
     assert_success
-    assert_output --regexp "^P[-=_a-zA-Z0-9]{44}$"
+    assert_output --regexp "^P[-_a-zA-Z0-9]{43}$"
+}
+
+@test "patch create: pre-create-patch hook" {
+    $OJO init
+    echo "line" > ojo_file.txt
+    mkdir .ojo/hooks
+    printf '#!/bin/sh\ngrep -q forbidden && exit 1\nexit 0\n' > .ojo/hooks/pre-create-patch
+    chmod +x .ojo/hooks/pre-create-patch
+
+    run $OJO patch create -a me -m forbidden
+    assert_failure
+    assert_output --partial "The pre-create-patch hook failed"
+
+    run $OJO patch create -a me -m allowed
+    assert_success
+
+    # Hooks that aren't executable are ignored.
+    chmod -x .ojo/hooks/pre-create-patch
+    echo "more" >> ojo_file.txt
+    run $OJO patch create -a me -m forbidden
+    assert_success
+}
+
+@test "patch create: description from editor" {
+    $OJO init
+    echo "line" > ojo_file.txt
+    printf '#!/bin/sh\ngrep -q "^# + line" "$1" || exit 1\nprintf "Title\\n\\nBody\\n# comment\\n" > "$1"\n' > editor.sh
+    chmod +x editor.sh
+
+    EDITOR=./editor.sh run $OJO patch create -a me --then-apply
+    assert_success
+    run $OJO log
+    assert_line --index 2 "	Title"
+    assert_line --index 3 "	Body"
+    refute_output --partial "comment"
+}
+
+@test "create patch from stdin" {
+    $OJO init
+    echo "On disk" > ojo_file.txt
+
+    run bash -c "printf 'From stdin\n' | $OJO patch create -a Author -m Msg --then-apply --from-stdin"
+    assert_success
+    run bash -c "printf 'From stdin\nMore\n' | $OJO diff --path -"
+    assert_failure 1
+    assert_line --index 0 "  From stdin"
+    assert_line --index 1 --partial "+ More"
+
+    run $OJO render
+    run cat ojo_file.txt
+    assert_output "From stdin"
+}
+
+@test "create warns about equivalent patches" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH=`$OJO patch create -a Author -m Msg --output-hash`
+
+    run $OJO patch create -a Someone -m Other
+    assert_success
+    assert_line --index 0 "Warning: patch ${HASH:0:8} makes the same changes; applying both will duplicate lines"
+
+    echo "Second" > ojo_file.txt
+    run $OJO patch create -a Author -m Msg
+    assert_success
+    refute_output --partial "Warning"
+}
+
+@test "patch create: only selected lines" {
+    $OJO init
+    printf "a\nb\nc\nd\ne\n" > ojo_file.txt
+    $OJO patch create -a me -m initial --then-apply
+    printf "a\nB1\nB2\nc\nd\nE\n" > ojo_file.txt
+
+    $OJO patch create -a me -m first --then-apply --lines 2-3
+    run $OJO diff
+    assert_output -p "- e"
+    assert_output -p "+ E"
+    refute_output -p "+ B1"
+
+    $OJO patch create -a me -m second --then-apply --lines 6
+    run $OJO diff
+    assert_success
+}
+
+@test "patch create: selected lines outside the changes" {
+    $OJO init
+    printf "a\nb\n" > ojo_file.txt
+    run $OJO patch create -a me -m msg --lines 5-6
+    assert_success
+    assert_output "Not creating a patch because there were no changes."
+}
+
+@test "patch create: bad line ranges" {
+    $OJO init
+    touch ojo_file.txt
+    run $OJO patch create -a me -m msg --lines 3-1
+    assert_failure
+    assert_output 'Error: Invalid line ranges "3-1"'
 }
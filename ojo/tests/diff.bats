@@ -0,0 +1,83 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "diff: with and unapplied patches" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    echo "Second" >> ojo_file.txt
+    SECOND=$($OJO patch create -a Author -m Msg --then-apply 2>&1 | awk '{print $NF}')
+    echo "Third" >> ojo_file.txt
+    THIRD=$($OJO patch create -a Author -m Msg 2>&1 | awk '{print $NF}')
+
+    run $OJO diff --unapplied $SECOND
+    assert_failure 1
+    assert_line --index 0 "  First"
+    assert_line --index 1 --partial "- Second"
+
+    run $OJO diff --with $THIRD
+    assert_failure 1
+    assert_line --index 1 "  Second"
+    assert_line --index 2 --partial "+ Third"
+
+    # Nothing was actually applied or unapplied.
+    run $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Second"
+}
+
+@test "diff: exit status and --quiet" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    run $OJO diff
+    assert_success
+    assert_output "  First"
+
+    run $OJO diff --quiet
+    assert_success
+    assert_output ""
+
+    echo "Second" >> ojo_file.txt
+    run $OJO diff -q
+    assert_failure 1
+    assert_output ""
+
+    run $OJO diff --path missing.txt
+    assert_failure 2
+    assert_line --index 0 "Error: Could not read the file missing.txt"
+}
+
+@test "diff: coarse diffs of large files" {
+    $OJO init
+    run $OJO admin coarse-diff
+    assert_output "200000"
+    run $OJO admin coarse-diff --lines 10
+    assert_success
+    run $OJO admin coarse-diff
+    assert_output "10"
+
+    seq 30 > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    sed -i -e 's/^5$/five/' -e 's/^25$/twenty-five/' ojo_file.txt
+
+    run $OJO diff
+    assert_failure 1
+    assert_output --partial "Warning: the file has more than 10 lines"
+    assert_output --partial "+ five"
+
+    run $OJO diff --precise
+    assert_failure 1
+    refute_output --partial "Warning"
+    assert_line --index 4 --partial "- 5"
+    assert_line --index 5 --partial "+ five"
+
+    # Either way, the patch has the right contents.
+    $OJO patch create -a Author -m Msg --then-apply
+    $OJO render
+    run $OJO diff --precise
+    assert_success
+}
@@ -0,0 +1,40 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "fsck: healthy repository" {
+    $OJO init
+    printf "First\nSecond\nThird\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    printf "First\nThird\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    run $OJO fsck
+    assert_success
+    assert_output "No problems found"
+
+    run $OJO fsck --repair
+    assert_success
+    assert_output "No problems found"
+
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Third"
+}
+
+@test "paranoid checks" {
+    $OJO --paranoid init
+    printf "First\nSecond\nThird\n" > ojo_file.txt
+    run $OJO --paranoid patch create -a Author -m Msg --then-apply
+    assert_success
+    printf "First\nThird\n" > ojo_file.txt
+    run $OJO patch create -a Author -m Msg --then-apply --paranoid
+    assert_success
+
+    run $OJO render --paranoid
+    assert_success
+    run cat ojo_file.txt
+    assert_output "First
+Third"
+}
@@ -0,0 +1,61 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "graph: filters" {
+    $OJO init
+    printf "1\n2\n3\n4\n5\n6\n7\n8\n" > ojo_file.txt
+    FIRST=$($OJO patch create -a Author -m Msg --then-apply 2>&1 | awk '{print $NF}')
+    printf "1\n2\n3\n4\n5\n6\n8\n9\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    $OJO graph
+    run grep -c -- "->" out.dot
+    assert_output 5
+
+    $OJO graph --around 2 --radius 1
+    run grep -o "</font> [0-9]" out.dot
+    assert_output "</font> 1
+</font> 2
+</font> 3"
+
+    $OJO graph --deleted-only
+    run grep -o "</font> [0-9]" out.dot
+    assert_output "</font> 7"
+
+    $OJO graph --patch $FIRST --live-only
+    run grep -o "</font> [0-9]" out.dot
+    assert_output --partial "</font> 8"
+    refute_output --partial "</font> 7"
+    refute_output --partial "</font> 9"
+
+    run $OJO graph --around 10
+    assert_failure
+    run $OJO graph --radius 2
+    assert_failure
+}
+
+@test "graph: condense chains" {
+    $OJO init
+    printf "1\n2\n3\n4\n5\n6\n7\n8\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    printf "1\n2\n3\n4\n5\n6\n8\n9\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    $OJO graph
+    run sh -c 'grep -o "</font> [0-9]" out.dot | wc -l'
+    assert_output 9
+
+    $OJO graph --condense-chains
+    run grep -c -- "->" out.dot
+    assert_output 5
+    run sh -c 'grep -o "</font> [0-9]" out.dot | sort'
+    assert_output "</font> 1
+</font> 5
+</font> 6
+</font> 7
+</font> 8
+</font> 9"
+    run grep -o "[0-9]* more lines" out.dot
+    assert_output "3 more lines"
+}
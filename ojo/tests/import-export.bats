@@ -71,6 +71,42 @@ load 'libs/setup'
     assert_output Content
 }
 
+@test "import: patch from a different repository" {
+    $OJO init
+    echo Content > ojo_file.txt
+    HASH=`$OJO patch create -a Me -m Msg --output-hash`
+    $OJO patch export -o patch.txt $HASH
+    IDENTITY=`$OJO admin lineage | sed -n 's/^identity: //p'`
+
+    mkdir other
+    cd other
+    $OJO init
+    run $OJO admin lineage
+    assert_line "policy: warn"
+    refute_line "identity: $IDENTITY"
+    run env OJO_LOCALE=pseudo $OJO admin lineage
+    assert_line "[policy: warn]"
+
+    $OJO admin lineage --policy refuse
+    run $OJO patch import ../patch.txt
+    assert_failure
+    assert_output --partial "was created in a different repository ($IDENTITY)"
+
+    $OJO admin lineage --policy warn
+    run $OJO patch import ../patch.txt
+    assert_success
+    assert_line "Warning: patch ${HASH:0:8} was created in a different repository ($IDENTITY)"
+
+    # Joining the first repository's lineage makes its patches welcome.
+    mkdir ../third
+    cd ../third
+    $OJO init
+    $OJO admin lineage --identity $IDENTITY
+    run $OJO patch import ../patch.txt
+    assert_success
+    refute_output --partial "Warning"
+}
+
 @test "import: bad file" {
     $OJO init
     run $OJO patch import no_such_file.txt
@@ -78,3 +114,77 @@ load 'libs/setup'
     assert_line --index 0 "Error: Failed to read file 'no_such_file.txt'"
     assert_line --index 1 --partial "No such file"
 }
+
+# Serves the current directory over HTTP in the background, and sets PORT and SERVER.
+serve() {
+    command -v python3 > /dev/null || skip "python3 is needed to serve the patches"
+    PORT=`python3 -c 'import socket; s = socket.socket(); s.bind(("127.0.0.1", 0)); print(s.getsockname()[1])'`
+    # Don't leave the server running for too long if the test fails.
+    timeout 60 python3 -m http.server --bind 127.0.0.1 $PORT > /dev/null 2>&1 3>&- &
+    SERVER=$!
+    for i in `seq 50`; do
+        (echo > /dev/tcp/127.0.0.1/$PORT) 2> /dev/null && break
+        sleep 0.1
+    done
+}
+
+@test "apply --from-url" {
+    mkdir remote
+    cd remote
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH1=`$OJO patch create -a Me -m Msg --output-hash --then-apply`
+    echo "Second" >> ojo_file.txt
+    HASH2=`$OJO patch create -a Me -m Msg --output-hash --then-apply`
+    $OJO patch export $HASH1
+    $OJO patch export $HASH2
+    serve
+    cd ..
+
+    $OJO init
+    # The dependent patch comes first, but the patches get imported in the right order anyway.
+    run $OJO patch apply --from-url http://127.0.0.1:$PORT/$HASH2 http://127.0.0.1:$PORT/$HASH1
+    assert_success
+    assert_line "Successfully imported a patch with id $HASH1"
+    assert_line "Successfully imported a patch with id $HASH2"
+    run $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Second"
+
+    run $OJO patch apply --from-url http://127.0.0.1:$PORT/nonexistent
+    assert_failure
+    assert_line --index 0 "Error: Failed to download 'http://127.0.0.1:$PORT/nonexistent'"
+
+    kill $SERVER
+}
+
+@test "apply --from-url --no-apply" {
+    mkdir remote
+    cd remote
+    $OJO init
+    echo "First" > ojo_file.txt
+    HASH=`$OJO patch create -a Me -m Msg --output-hash`
+    $OJO patch export $HASH
+    IDENTITY=`$OJO admin lineage | sed -n 's/^identity: //p'`
+    serve
+    cd ..
+
+    # Sharing the remote's lineage means that there's no warning about where the patch came from.
+    $OJO init
+    $OJO admin lineage --identity $IDENTITY
+    # The download is refused if the patch is too big for the repository.
+    $OJO limits --max-patch-size 10
+    run $OJO patch apply --from-url --no-apply http://127.0.0.1:$PORT/$HASH
+    assert_failure
+    assert_line --index 0 "Error: Failed to download 'http://127.0.0.1:$PORT/$HASH'"
+
+    $OJO limits --max-patch-size 10000
+    run $OJO patch apply --from-url --no-apply http://127.0.0.1:$PORT/$HASH
+    assert_success
+    assert_output "Successfully imported a patch with id $HASH"
+    run $OJO log --unapplied
+    assert_line --index 0 --partial "patch ${HASH:0:8}"
+
+    kill $SERVER
+}
@@ -0,0 +1,72 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "import-git: one patch per commit" {
+    export GIT_AUTHOR_NAME="Git Author" GIT_AUTHOR_EMAIL="author@example.com"
+    export GIT_COMMITTER_NAME="Git Author" GIT_COMMITTER_EMAIL="author@example.com"
+    mkdir git
+    git -C git init -q
+    printf "First\nLast\n" > git/file.txt
+    git -C git add file.txt
+    git -C git commit -q -m "Add the file"
+    echo "Unrelated" > git/other.txt
+    git -C git add other.txt
+    git -C git commit -q -m "Add another file"
+    printf "First\nMiddle\nLast\n" > git/file.txt
+    git -C git commit -q -a -m "Add a line"
+
+    $OJO init
+    run $OJO import-git git file.txt
+    assert_success
+    assert_output "Imported 2 commit(s) from git"
+
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Middle
+Last"
+
+    run $OJO log
+    assert_line --index 1 "Author: Git Author <author@example.com>"
+    assert_line --index 2 --partial "Add a line"
+    assert_line --index 5 --partial "Add the file"
+
+    run $OJO import-git git missing.txt
+    assert_success
+    assert_output "Imported 0 commit(s) from git"
+
+    run $OJO import-git not-a-repo file.txt
+    assert_failure
+    assert_line --index 0 --partial "Error: Failed to run git:"
+}
+
+@test "import-git: deleted files" {
+    export GIT_AUTHOR_NAME="Git Author" GIT_AUTHOR_EMAIL="author@example.com"
+    export GIT_COMMITTER_NAME="Git Author" GIT_COMMITTER_EMAIL="author@example.com"
+    mkdir git
+    git -C git init -q
+    printf "First\nLast\n" > git/file.txt
+    git -C git add file.txt
+    git -C git commit -q -m "Add the file"
+    git -C git rm -q file.txt
+    git -C git commit -q -m "Delete the file"
+    echo "Back" > git/file.txt
+    git -C git add file.txt
+    git -C git commit -q -m "Restore the file
+
+With a record separator  in the middle of the message."
+
+    $OJO init
+    run $OJO import-git git file.txt
+    assert_success
+    assert_output "Imported 3 commit(s) from git"
+
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "Back"
+    run $OJO log
+    assert_line --index 2 --partial "Restore the file"
+    assert_line --index 3 --partial "in the middle of the message"
+    assert_line --index 6 --partial "Delete the file"
+}
@@ -14,3 +14,67 @@ load 'libs/setup'
     assert_failure
     assert_output --partial "There is already a repository"
 }
+
+@test "init with separate ojo dir" {
+    mkdir work
+    cd work
+    $OJO init --separate-ojo-dir ../data
+    assert [ -f .ojo ]
+    assert [ -e ../data/db ]
+    run $OJO log
+    assert_success
+}
+
+@test "init with OJO_DIR" {
+    OJO_DIR=data $OJO init
+    assert [ ! -e .ojo ]
+    assert [ -e data/db ]
+    OJO_DIR=data run $OJO log
+    assert_success
+    run $OJO log
+    assert_failure
+}
+
+@test "init in a non-ASCII directory" {
+    mkdir "répertoire-日本"
+    cd "répertoire-日本"
+    run $OJO init --separate-ojo-dir "../données-ojo"
+    assert_success
+    echo "First" > ojo_file.txt
+    run $OJO patch create -a Author -m Msg --then-apply
+    assert_success
+    run $OJO log
+    assert_success
+    assert_output --partial "Msg"
+}
+
+@test "init with pseudo locale" {
+    run env OJO_LOCALE=pseudo $OJO init
+    assert_success
+    assert_output "[Created empty ojo repository.]"
+
+    run env OJO_LOCALE=pseudo $OJO init
+    assert_failure
+    assert_output --partial "[Error: "
+}
+
+@test "operate on another directory with --repo" {
+    mkdir elsewhere
+    $OJO --repo elsewhere init
+    assert [ -e elsewhere/.ojo/db ]
+    assert [ ! -e .ojo ]
+
+    echo "First" > elsewhere/ojo_file.txt
+    $OJO --repo elsewhere patch create -a Me -m Msg --then-apply
+    rm elsewhere/ojo_file.txt
+    $OJO render --repo elsewhere
+    assert [ ! -e ojo_file.txt ]
+    run cat elsewhere/ojo_file.txt
+    assert_output "First"
+
+    run $OJO log
+    assert_failure
+    run $OJO log --repo elsewhere
+    assert_success
+    assert_output -p "Msg"
+}
@@ -5,6 +5,9 @@ setup() {
     export TOPLEVEL="$BATS_TEST_DIRNAME/../.."
     export OJO="$TOPLEVEL/target/debug/ojo"
 
+    # Make sure that nothing tries to open an editor, unless a test asks for it.
+    unset VISUAL EDITOR
+
     # Ensure that the build is up-to-date.
     cargo build --all
 
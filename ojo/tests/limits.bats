@@ -0,0 +1,37 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "limits: show and change" {
+    $OJO init
+    run $OJO limits
+    assert_success
+    assert_output "max-nodes: 4000000
+max-patch-size: 1073741824
+max-contents-size: 268435456"
+
+    run $OJO limits --max-nodes 1 --max-contents-size 100
+    assert_success
+    assert_output "Changed the limits"
+
+    run $OJO limits
+    assert_line --index 0 "max-nodes: 1"
+    assert_line --index 2 "max-contents-size: 100"
+
+    run $OJO limits --max-nodes lots
+    assert_failure
+    assert_line --index 0 "Error: Invalid limit \"lots\""
+}
+
+@test "limits: reject large patches" {
+    $OJO init
+    $OJO limits --max-nodes 1
+    printf "First\nSecond\n" > ojo_file.txt
+    run $OJO patch create -a Author -m Msg
+    assert_failure
+    assert_output --partial "it has 2 new nodes, but the limit is 1"
+
+    printf "First\n" > ojo_file.txt
+    run $OJO patch create -a Author -m Msg
+    assert_success
+}
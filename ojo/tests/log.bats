@@ -7,3 +7,83 @@ load 'libs/setup'
     assert_failure
     assert_output "Error: Failed to find a ojo repository"
 }
+
+@test "log from a subdirectory" {
+    $OJO init
+    mkdir -p sub/dir
+    cd sub/dir
+    run $OJO log
+    assert_success
+}
+
+@test "log: limit, skip and reverse" {
+    $OJO init
+    for i in 1 2 3; do
+        echo $i >> ojo_file.txt
+        $OJO patch create -a me -m "patch $i" --then-apply
+    done
+
+    run $OJO log
+    assert_line --index 2 "	patch 3"
+    assert_line --index 8 "	patch 1"
+
+    run $OJO log --limit 1 --skip 1
+    assert_output --partial "patch 2"
+    refute_output --partial "patch 1"
+    refute_output --partial "patch 3"
+
+    run $OJO log --reverse -n 1
+    assert_output --partial "patch 1"
+    refute_output --partial "patch 2"
+}
+
+@test "log --stat" {
+    $OJO init
+    printf "a\nb\n" > ojo_file.txt
+    $OJO patch create -a me -m "add" --then-apply
+    printf "a\n" > ojo_file.txt
+    $OJO patch create -a me -m "delete" --then-apply
+
+    run $OJO log --stat
+    assert_success
+    assert_output --partial " 0 nodes added, 1 node deleted, 0 edges added"
+    assert_output --partial " 2 nodes added, 0 nodes deleted, 1 edge added"
+
+    run $OJO log
+    refute_output --partial "added"
+}
+
+@test "log --unapplied" {
+    $OJO init
+    for i in 1 2 3; do
+        echo $i >> ojo_file.txt
+        $OJO patch create -a me -m "patch $i" --then-apply
+    done
+    $OJO branch new other
+
+    run $OJO log --unapplied
+    assert_success
+    assert_output ""
+
+    run $OJO log --unapplied --branch other
+    assert_success
+    assert_line --index 2 "	patch 1"
+    assert_line --index 5 "	patch 2"
+    assert_line --index 8 "	patch 3"
+
+    run $OJO log --unapplied --reverse --branch other
+    assert_line --index 2 "	patch 3"
+}
+
+@test "log --interactive needs a terminal" {
+    $OJO init
+    echo 1 > ojo_file.txt
+    $OJO patch create -a me -m "patch 1" --then-apply
+
+    run $OJO log --interactive --stat
+    assert_failure
+
+    run $OJO log --interactive < /dev/null
+    assert_failure
+    assert_output -p "Failed to open the terminal in raw mode"
+}
@@ -0,0 +1,89 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "pack and unpack" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    echo "Second" >> ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    run $OJO pack -o backup.pack
+    assert_success
+
+    mkdir restored
+    cd restored
+    run $OJO unpack ../backup.pack
+    assert_success
+    $OJO render
+    run cat ojo_file.txt
+    assert_line --index 0 "First"
+    assert_line --index 1 "Second"
+}
+
+@test "unpack: existing repo" {
+    $OJO init
+    $OJO pack
+    run $OJO unpack ojo.pack
+    assert_failure
+    assert_output --partial "There is already a repository"
+}
+
+@test "pack a single branch" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    $OJO branch clone feature
+    echo "Abandoned" >> ojo_file.txt
+    $OJO patch create -a Author -m Msg
+
+    run $OJO pack --branch feature -o feature.pack
+    assert_success
+
+    mkdir restored
+    cd restored
+    run $OJO unpack ../feature.pack
+    assert_success
+    run $OJO branch list
+    assert_output --partial "feature"
+    run $OJO log --branch feature
+    assert_line --index 0 --partial "patch"
+}
+
+@test "pack a shallow copy" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    echo "Second" >> ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    run $OJO pack --shallow
+    assert_failure
+
+    touch -d 2000-01-01 .ojo/db
+    run $OJO pack --shallow -a Author -o shallow.pack
+    assert_success
+    # The source repository isn't modified.
+    run find .ojo/db -newermt 2001-01-01
+    assert_output ""
+
+    mkdir restored
+    cd restored
+    run $OJO unpack ../shallow.pack
+    assert_success
+    run $OJO render
+    assert_success
+    run cat ojo_file.txt
+    assert_output "First
+Second"
+
+    run $OJO log
+    assert_line --index 0 --partial "patch"
+    refute_output --partial "Msg"
+
+    BASELINE=$($OJO log --full-ids | head -n 1 | cut -d ' ' -f 2)
+    run $OJO patch apply --revert $BASELINE
+    assert_failure
+    assert_output --partial "baseline"
+}
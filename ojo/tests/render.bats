@@ -0,0 +1,36 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "render: show deleted lines" {
+    $OJO init
+    printf "First\nSecond\nThird\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    printf "First\nThird\nFourth\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    $OJO render --show-deleted --path annotated.txt
+    run cat annotated.txt
+    assert_output "  First
+- Second
+  Third
+  Fourth"
+
+    # Without a path, it goes to stdout and the working file is left alone.
+    echo "Working" > ojo_file.txt
+    run $OJO render --show-deleted
+    assert_success
+    assert_output "  First
+- Second
+  Third
+  Fourth"
+    run cat ojo_file.txt
+    assert_output "Working"
+
+    # The working file is rendered as usual.
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Third
+Fourth"
+}
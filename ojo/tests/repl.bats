@@ -0,0 +1,90 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "repl: run several commands" {
+    $OJO init
+    echo First > ojo_file.txt
+    run $OJO repl <<'END'
+patch create -a 'Me Myself' -m "First patch" --then-apply
+
+branch new other
+log
+exit
+END
+    assert_success
+    assert_line --partial "Created and applied patch"
+    assert_line 'Created empty branch "other"'
+    assert_line "Author: Me Myself"
+    assert_line "	First patch"
+
+    # Everything got written at the end.
+    run $OJO branch list
+    assert_line "  other"
+    run $OJO log
+    assert_line "	First patch"
+}
+
+@test "repl: failed commands don't change anything" {
+    $OJO init
+    echo First > ojo_file.txt
+    HASH=`$OJO patch create -a Me -m Msg --then-apply --output-hash`
+
+    # Amending fails after unapplying the patch, because there's nothing left to amend.
+    : > ojo_file.txt
+    run $OJO repl <<END
+patch amend $HASH
+log
+branch new other
+branch delete nonexistent
+patch show "unterminated
+repl
+watch -a Me
+--repo elsewhere branch new elsewhere
+branch new other --paranoid
+not-a-command
+branch new other
+END
+    assert_success
+    assert_line --index 0 "Error: Not amending the patch, because the new patch would have no changes."
+    assert_line --index 1 "patch ${HASH:0:8}"
+    assert_line 'Created empty branch "other"'
+    assert_line 'Error: There is no branch named "nonexistent"'
+    assert_line "Error: Unterminated quote"
+    assert_line "Error: Already in the repl"
+    assert_line "Error: Can't run 'watch' in the repl"
+    assert_line "Error: The --repo option can only be given to 'ojo repl' itself"
+    assert_line "Error: The --paranoid option can only be given to 'ojo repl' itself"
+    assert_line --partial "'not-a-command'"
+    assert_line 'Error: The branch "other" already exists'
+
+    run $OJO branch list
+    assert_output "* master
+  other"
+}
+
+@test "repl: nothing is written without changes" {
+    $OJO init
+    touch -d 2000-01-01 .ojo/db
+    echo log | $OJO repl
+    run find .ojo/db -newermt 2001-01-01
+    assert_output ""
+}
+
+@test "repl: bisect" {
+    $OJO init
+    for line in one two bug three; do
+        echo $line >> ojo_file.txt
+        $OJO patch create -a me -m "add $line" --then-apply
+    done
+    run $OJO repl <<'END'
+bisect start
+bisect run "! grep -q bug"
+END
+    assert_success
+    assert_output -p "4 patches left"
+    assert_output -p "The first bad patch is"
+    assert_output -p "add bug"
+    run $OJO branch list
+    refute_output -p "bisect"
+}
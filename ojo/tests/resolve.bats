@@ -7,8 +7,132 @@ load 'libs/setup'
     run $OJO resolve
     assert_failure
 
-    HASH=`echo "1111" | $OJO resolve --author me --testing 2>&1 | cut -d " " -f 3`
+    HASH=`printf "choose 1\nchoose 1\n" | $OJO resolve --author me --script - 2>&1 | cut -d " " -f 3`
     $OJO patch apply $HASH
     $OJO render
 }
 
+@test "resolve: script with node ids" {
+    echo "0-1 0-2 1-3 2-3" | $OJO synthesize
+    P=`$OJO log --full-ids | head -n 1 | cut -d " " -f 2`
+    printf "# Put line 2 first.\nchoose $P/2\n\nchoose ${P:0:8}/1\n" > script.txt
+
+    run $OJO resolve --author me --script script.txt
+    assert_success
+    HASH=`echo "$output" | cut -d " " -f 3`
+    $OJO patch apply $HASH
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "Line 0
+Line 2
+Line 1
+Line 3"
+}
+
+@test "resolve: script with a cycle" {
+    echo "0-1 1-2 2-1 2-3" | $OJO synthesize
+    HASH=`printf "cycle 2\nchoose 1\n" | $OJO resolve --author me --script - 2>&1 | cut -d " " -f 3`
+    $OJO patch apply $HASH
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "Line 0
+Line 2
+Line 3"
+}
+
+@test "resolve: script mismatch" {
+    echo "0-1 0-2 1-3 2-3" | $OJO synthesize
+
+    run $OJO resolve --author me --script nonexistent.txt
+    assert_failure
+    assert_output --partial "Failed to read file 'nonexistent.txt'"
+
+    run sh -c "echo 'choose 1' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: The script ended before the resolution was finished"
+
+    run sh -c "printf 'choose 1\nchoose 1\ndelete 1\n' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: The resolution was finished before line 3 of the script"
+
+    run sh -c "echo 'cycle 1' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: Line 1 of the script resolves a cycle, but there are no cycles left"
+
+    run sh -c "echo 'choose 3' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: Line 1 of the script doesn't refer to one of the current choices"
+
+    run sh -c "echo 'pick 1' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: Line 1 of the script isn't a valid step: pick 1"
+}
+
+@test "resolve: rank by time" {
+    $OJO init
+    printf "First\nLast\n" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    # By position, "Newer" would go first.
+    printf "Zero\nFirst\nOlder\nLast\n" > ojo_file.txt
+    HASH_A=`$OJO patch create -a Author -m Msg --output-hash`
+    printf "First\nNewer\nLast\n" > ojo_file.txt
+    HASH_B=`$OJO patch create -a Author -m Msg --output-hash`
+    $OJO patch apply "$HASH_B"
+    $OJO patch apply "$HASH_A"
+
+    HASH=`printf "choose 1\nchoose 1\n" | $OJO resolve --author me --script - 2>&1 | cut -d " " -f 3`
+    $OJO patch apply $HASH
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "Zero
+First
+Newer
+Older
+Last"
+    $OJO patch apply -R $HASH
+
+    HASH=`printf "choose 1\nchoose 1\n" | $OJO resolve --author me --rank time --script - 2>&1 | cut -d " " -f 3`
+    $OJO patch apply $HASH
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "Zero
+First
+Older
+Newer
+Last"
+
+    run $OJO resolve --author me --rank sideways --script -
+    assert_failure
+}
+
+@test "resolve: concurrent edits of a line" {
+    $OJO init
+    printf "a\nsecond line\n" > ojo_file.txt
+    $OJO patch create -a me -m Msg --then-apply
+    printf "a\nsecond lime\n" > ojo_file.txt
+    HASH_A=`$OJO patch create -a me -m Msg --output-hash`
+    printf "a\nsecond line!\n" > ojo_file.txt
+    HASH_B=`$OJO patch create -a me -m Msg --output-hash`
+    $OJO patch apply $HASH_A
+    $OJO patch apply $HASH_B
+
+    # Neither edit wins.
+    run $OJO render
+    assert_failure
+    assert_output --partial "The file has 0 cycle(s) and 0 unordered region(s), and 1 line(s) with conflicting edits"
+    assert_output --partial "conflicts with patch"
+
+    run sh -c "echo 'choose 1' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: Line 1 of the script should choose a version of a line, because there are conflicting edits left"
+    run sh -c "echo 'version 3' | $OJO resolve --author me --script -"
+    assert_failure
+    assert_output "Error: Line 1 of the script doesn't refer to one of the current choices"
+
+    HASH=`echo "version $HASH_A" | $OJO resolve --author me --script - 2>&1 | cut -d " " -f 3`
+    $OJO patch apply $HASH
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "a
+second lime"
+}
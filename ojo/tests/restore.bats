@@ -0,0 +1,31 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "restore: go back to an earlier patch" {
+    $OJO init
+    echo First > ojo_file.txt
+    HASH_A=$($OJO patch create -a Author -m Msg --then-apply 2>&1 | awk '{print $NF}')
+    echo Last >> ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    run $OJO restore --to "$HASH_A"
+    assert_success
+    run cat ojo_file.txt
+    assert_output "First"
+
+    # The branch itself didn't change.
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "First
+Last"
+}
+
+@test "restore: unapplied patch" {
+    $OJO init
+    echo First > ojo_file.txt
+    HASH_A=`$OJO patch create -a Author -m Msg 2>&1 | cut -d " " -f 3`
+
+    run $OJO restore --to "$HASH_A"
+    assert_failure
+}
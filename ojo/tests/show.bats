@@ -0,0 +1,58 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "patch show" {
+    $OJO init
+    $OJO branch new other
+    echo First > ojo_file.txt
+    HASH=`$OJO patch create -a Author -m "Title
+
+Body" --branch other --output-hash`
+
+    run $OJO patch show $HASH
+    assert_success
+    assert_line --index 0 "patch $HASH"
+    assert_line --index 1 "Author: Author"
+    assert_line --index 3 "Branch: other"
+    assert_line --index 4 "Format version: 1"
+    assert_line --index 5 --partial "Repository: "
+    assert_line --index 6 "Creates the file"
+    assert_line --index 7 "	Title"
+    assert_line --index 8 "	Body"
+
+    # The labels come from the message catalog.
+    run env OJO_LOCALE=pseudo $OJO patch show $HASH
+    assert_success
+    assert_line --index 1 "[Author: Author]"
+    assert_line --index 2 --regexp '^\[Date: .*\]$'
+    assert_line --index 3 "[Branch: other]"
+    assert_line --index 4 "[Format version: 1]"
+    assert_line --index 5 --regexp '^\[Repository: .*\]$'
+}
+
+@test "patch show: dependencies" {
+    $OJO init
+    echo First > ojo_file.txt
+    FIRST=`$OJO patch create -a Author -m Msg --then-apply --output-hash`
+    echo Second >> ojo_file.txt
+    SECOND=`$OJO patch create -a Author -m Msg --output-hash`
+
+    run $OJO patch show $SECOND
+    assert_success
+    assert_line "Depends on: ${FIRST:0:8}"
+    refute_line "Creates the file"
+}
+
+@test "patch show: supersedes" {
+    $OJO init
+    echo First > ojo_file.txt
+    OLD=`$OJO patch create -a Author -m Msg --then-apply --output-hash`
+    echo Second >> ojo_file.txt
+    NEW=`$OJO patch amend $OLD --output-hash`
+
+    run $OJO patch show $NEW
+    assert_success
+    assert_line "Supersedes: ${OLD:0:8}"
+    refute_output --partial "supersedes:"
+}
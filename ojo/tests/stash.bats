@@ -0,0 +1,51 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "stash: push, list and pop" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+
+    echo "Work in progress" >> ojo_file.txt
+    run $OJO stash push -m "half done"
+    assert_success
+    run cat ojo_file.txt
+    assert_output "First"
+
+    run $OJO stash list
+    assert_output "0: on master: half done"
+
+    run $OJO stash pop
+    assert_success
+    run cat ojo_file.txt
+    assert_output "First
+Work in progress"
+    run $OJO stash list
+    assert_output ""
+}
+
+@test "stash: nothing to stash" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    run $OJO stash push
+    assert_success
+    assert_output --partial "no changes"
+}
+
+@test "stash: pop onto a branch that doesn't match" {
+    $OJO init
+    echo "First" > ojo_file.txt
+    $OJO patch create -a Author -m Msg --then-apply
+    echo "Second" > ojo_file.txt
+    $OJO stash push
+    $OJO branch new other
+    $OJO branch switch other
+
+    run $OJO stash pop
+    assert_failure
+    assert_output --partial "doesn't match branch 'other'"
+    run $OJO stash list
+    assert_output "0: on master: "
+}
@@ -0,0 +1,14 @@
+#!./libs/bats-core/bin/bats
+
+load 'libs/setup'
+
+@test "synthesize: presets" {
+    $OJO synthesize --preset tombstones --size 5
+    $OJO render
+    run cat ojo_file.txt
+    assert_output "Line 0
+Line 4"
+
+    run $OJO synthesize --preset linear
+    assert_failure
+}
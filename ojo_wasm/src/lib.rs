@@ -8,7 +8,7 @@ use wasm_bindgen::prelude::*;
 
 use libojo::{EdgeKind, NodeId, PatchId};
 use ojo_graph::Graph;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 #[wasm_bindgen]
 pub struct Repo {
@@ -25,13 +25,39 @@ impl Repo {
         Repo { inner }
     }
 
+    // All of the operations act on the current branch.
+    fn branch(&self) -> String {
+        self.inner.current_branch.clone()
+    }
+
+    /// Returns the name of the current branch.
+    pub fn current_branch(&self) -> String {
+        self.branch()
+    }
+
+    /// Returns an array containing the names of all the branches.
+    pub fn branches(&self) -> JsValue {
+        JsValue::from_serde(&self.inner.branches().collect::<Vec<_>>()).unwrap()
+    }
+
+    /// Creates a new branch, with the same patches as the current one.
+    pub fn create_branch(&mut self, name: &str) -> Result<(), JsValue> {
+        let branch = self.branch();
+        self.inner.clone_branch(&branch, name).map_err(js_error)
+    }
+
+    pub fn switch_branch(&mut self, name: &str) -> Result<(), JsValue> {
+        self.inner.switch_branch(name).map_err(js_error)
+    }
+
     pub fn commit(&mut self, new_input: &str) {
-        match self.inner.diff("master", new_input.as_bytes()) {
+        let branch = self.branch();
+        match self.inner.diff(&branch, new_input.as_bytes()) {
             Ok(diff) => {
                 let changes = libojo::Changes::from_diff(&diff.file_a, &diff.file_b, &diff.diff);
                 if !changes.changes.is_empty() {
                     let id = self.inner.create_patch("You", "Msg", changes).unwrap();
-                    self.inner.apply_patch("master", &id).unwrap();
+                    self.inner.apply_patch(&branch, &id).unwrap();
                 }
             }
             Err(_) => {
@@ -40,14 +66,89 @@ impl Repo {
         }
     }
 
-    pub fn apply_patch(&mut self, patch_id: &str) {
-        let patch_id = PatchId::from_base64(patch_id).unwrap();
-        self.inner.apply_patch("master", &patch_id).unwrap();
+    pub fn apply_patch(&mut self, patch_id: &str) -> Result<(), JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        let branch = self.branch();
+        self.inner
+            .apply_patch(&branch, &patch_id)
+            .map_err(js_error)?;
+        Ok(())
+    }
+
+    pub fn unapply_patch(&mut self, patch_id: &str) -> Result<(), JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        let branch = self.branch();
+        self.inner
+            .unapply_patch(&branch, &patch_id)
+            .map_err(js_error)?;
+        Ok(())
+    }
+
+    /// Returns an array containing the ids of the patches that `apply_patch` would apply, in the
+    /// order that it would apply them. Nothing is actually applied.
+    pub fn patches_to_apply(&self, patch_id: &str) -> Result<JsValue, JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        let patches = self
+            .inner
+            .patches_to_apply(&self.inner.current_branch, &[patch_id])
+            .map_err(js_error)?;
+        Ok(patch_ids(&patches))
+    }
+
+    /// Returns an array containing the ids of the patches that `unapply_patch` would unapply, in
+    /// the order that it would unapply them. Nothing is actually unapplied.
+    pub fn patches_to_unapply(&self, patch_id: &str) -> Result<JsValue, JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        let patches = self
+            .inner
+            .patches_to_unapply(&self.inner.current_branch, &[patch_id])
+            .map_err(js_error)?;
+        Ok(patch_ids(&patches))
+    }
+
+    /// Returns an array containing the ids of the patches that are known but not applied to the
+    /// current branch, ordered so that every patch comes after its dependencies.
+    pub fn unapplied_patches(&self) -> JsValue {
+        patch_ids(&self.inner.unapplied_patches(&self.inner.current_branch))
+    }
+
+    /// Returns an array of `[patch_id, patch_id]` pairs, one for each pair of patches on the
+    /// current branch that are likely to be in conflict.
+    pub fn conflicting_patches(&self) -> Result<JsValue, JsValue> {
+        let pairs = self
+            .inner
+            .conflicting_patches(&self.inner.current_branch)
+            .map_err(js_error)?
+            .into_iter()
+            .map(|(a, b)| [a.to_string(), b.to_string()])
+            .collect::<Vec<_>>();
+        Ok(JsValue::from_serde(&pairs).unwrap())
+    }
+
+    /// Returns an array containing the ids of all the patches that the given patch depends on,
+    /// directly or indirectly.
+    pub fn transitive_deps(&self, patch_id: &str) -> Result<JsValue, JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        Ok(patch_ids(&self.inner.transitive_deps(&patch_id)))
+    }
+
+    /// Returns an array containing the ids of all the patches that depend on the given patch,
+    /// directly or indirectly.
+    pub fn transitive_rev_deps(&self, patch_id: &str) -> Result<JsValue, JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        Ok(patch_ids(&self.inner.transitive_rev_deps(&patch_id)))
     }
 
-    pub fn unapply_patch(&mut self, patch_id: &str) {
-        let patch_id = PatchId::from_base64(patch_id).unwrap();
-        self.inner.unapply_patch("master", &patch_id).unwrap();
+    /// Forgets about a patch. This fails if the patch is applied to any branch, or if any other
+    /// patch depends on it.
+    pub fn unregister_patch(&mut self, patch_id: &str) -> Result<(), JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        self.inner.unregister_patch(&patch_id).map_err(js_error)
+    }
+
+    pub fn is_applied(&self, patch_id: &str) -> Result<bool, JsValue> {
+        let patch_id = parse_patch_id(patch_id)?;
+        Ok(self.inner.is_applied(&self.inner.current_branch, &patch_id))
     }
 
     pub fn apply_changes(&mut self, changes: &Changes) {
@@ -55,21 +156,17 @@ impl Repo {
             .inner
             .create_patch("You", "Msg", changes.to_ojo_changes())
             .unwrap();
-        self.inner.apply_patch("master", &id).unwrap();
+        let branch = self.branch();
+        self.inner.apply_patch(&branch, &id).unwrap();
     }
 
     pub fn file(&self) -> Option<String> {
-        let data = self.inner.file("master").ok()?;
+        let data = self.inner.file(&self.inner.current_branch).ok()?;
         String::from_utf8(data.as_bytes().to_owned()).ok()
     }
 
     pub fn patches(&self) -> Patches {
         let ids = self.inner.all_patches().cloned().collect::<Vec<_>>();
-        let applied_ids = self
-            .inner
-            .patches("master")
-            .cloned()
-            .collect::<HashSet<_>>();
         let id_idx = ids
             .iter()
             .cloned()
@@ -82,8 +179,8 @@ impl Repo {
 
         for p in &ids {
             patches.push(Patch {
-                id: p.to_base64(),
-                applied: applied_ids.contains(&p),
+                id: p.to_string(),
+                applied: self.inner.is_applied(&self.inner.current_branch, p),
             });
             for q in self.inner.patch_deps(p) {
                 deps.push((id_idx[p], id_idx[q]));
@@ -93,8 +190,34 @@ impl Repo {
         Patches { patches, deps }
     }
 
+    /// Returns the contents of the node with the given id (in the same format as
+    /// [`GraggleNode::id`]), or `undefined` if there is no such node.
+    pub fn try_contents(&self, node_id: &str) -> Option<String> {
+        let id = parse_node_id(node_id)?;
+        let contents = self.inner.contents(&id).ok()?;
+        Some(String::from_utf8_lossy(contents).into_owned())
+    }
+
+    /// Returns an array of the live nodes in the current branch (as `GraggleNode`s), in no
+    /// particular order.
+    ///
+    /// This is much cheaper than calling `try_contents` on every node of `graggle`.
+    pub fn live_nodes(&self) -> JsValue {
+        let nodes = self
+            .inner
+            .nodes(&self.inner.current_branch)
+            .unwrap()
+            .map(|(u, contents)| GraggleNode {
+                id: format!("{}/{}", u.patch, u.node),
+                live: true,
+                text: String::from_utf8_lossy(contents).into_owned(),
+            })
+            .collect::<Vec<_>>();
+        JsValue::from_serde(&nodes).unwrap()
+    }
+
     pub fn graggle(&self) -> Graggle {
-        let d = self.inner.graggle("master").unwrap();
+        let d = self.inner.graggle(&self.inner.current_branch).unwrap();
         let id_idx = d
             .as_full_graph()
             .nodes()
@@ -107,9 +230,9 @@ impl Repo {
 
         for u in d.as_full_graph().nodes() {
             nodes.push(GraggleNode {
-                id: format!("{}/{}", u.patch.to_base64(), u.node),
+                id: format!("{}/{}", u.patch, u.node),
                 live: d.is_live(&u),
-                text: String::from_utf8(self.inner.contents(&u).to_owned()).unwrap(),
+                text: String::from_utf8(self.inner.contents_in(d, &u).to_owned()).unwrap(),
             });
 
             for edge in d.all_out_edges(&u) {
@@ -123,6 +246,27 @@ impl Repo {
 
         Graggle { nodes, edges }
     }
+
+    /// Summarizes the size and shape of the current branch's graggle, without serializing it.
+    ///
+    /// This is cheap compared to `graggle`, so it can be used to decide whether the full graggle
+    /// is small enough to draw.
+    pub fn graggle_summary(&self) -> GraggleSummary {
+        let d = self.inner.graggle(&self.inner.current_branch).unwrap();
+        let full = d.as_full_graph();
+        let nodes = full.nodes().count();
+        let edges = full.nodes().map(|u| d.all_out_edges(&u).count()).sum();
+
+        let sccs = d.as_live_graph().tarjan().num_components();
+        let disorder = d.disorder();
+
+        GraggleSummary {
+            nodes,
+            edges,
+            sccs,
+            conflict_regions: disorder.cycles + disorder.unordered,
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -200,6 +344,20 @@ impl Graggle {
     }
 }
 
+#[wasm_bindgen]
+pub struct GraggleSummary {
+    /// The number of nodes, including deleted ones.
+    pub nodes: usize,
+    /// The number of edges, including pseudo-edges and edges between deleted nodes.
+    pub edges: usize,
+    /// The number of strongly connected components among the live nodes.
+    pub sccs: usize,
+    /// The number of places where the live nodes fail to be totally ordered: cycles, and regions
+    /// of lines that are unordered relative to one another (see `libojo::Disorder`). This is zero
+    /// if and only if the current branch is a file.
+    pub conflict_regions: usize,
+}
+
 #[wasm_bindgen]
 #[derive(Deserialize)]
 pub struct Changes {
@@ -227,11 +385,7 @@ impl Changes {
     // Converts this into an libojo::Changes.
     fn to_ojo_changes(&self) -> libojo::Changes {
         fn node_id(s: &str) -> NodeId {
-            let i = s.find('/').unwrap();
-            NodeId {
-                patch: PatchId::from_base64(&s[..i]).unwrap(),
-                node: s[(i + 1)..].parse().unwrap(),
-            }
+            parse_node_id(s).unwrap()
         }
         let nodes = self
             .deleted_nodes
@@ -250,3 +404,25 @@ impl Changes {
         }
     }
 }
+
+fn js_error<E: std::fmt::Display>(e: E) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+fn parse_patch_id(s: &str) -> Result<PatchId, JsValue> {
+    s.parse::<PatchId>().map_err(js_error)
+}
+
+// Converts a list of patch ids into an array of strings.
+fn patch_ids(ids: &[PatchId]) -> JsValue {
+    JsValue::from_serde(&ids.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+}
+
+// Parses a node id in the format "<patch id>/<node index>".
+fn parse_node_id(s: &str) -> Option<NodeId> {
+    let i = s.find('/').filter(|&i| i > 0)?;
+    Some(NodeId {
+        patch: s[..i].parse().ok()?,
+        node: s[(i + 1)..].parse().ok()?,
+    })
+}
@@ -128,6 +128,26 @@ impl<T: Copy + Ord> Partition<T> {
         }
     }
 
+    /// Returns a partition with the same parts (and the same representatives) as this one, but
+    /// with every element replaced by its image under `f`.
+    ///
+    /// `f` must be one-to-one, because otherwise the result isn't a partition.
+    pub fn map<U: Copy + Ord, F: FnMut(T) -> U>(&self, mut f: F) -> Partition<U> {
+        let mut child_map = MMap::new();
+        for (parent, child) in self.child_map.iter() {
+            child_map.insert(f(*parent), f(*child));
+        }
+        Partition {
+            ranks: self.ranks.iter().map(|(e, r)| (f(*e), *r)).collect(),
+            parent_map: self
+                .parent_map
+                .iter()
+                .map(|(e, parent)| (f(*e), f(*parent)))
+                .collect(),
+            child_map,
+        }
+    }
+
     pub fn iter_part<'a>(&'a self, elt: T) -> impl Iterator<Item = T> + 'a {
         PartIter::new(self, self.representative(elt))
     }
@@ -248,4 +268,26 @@ mod tests {
         assert_eq!(partition.iter_parts().count(), 1);
         assert_vec_eq(partition.iter_part(3).collect(), vec![3]);
     }
+
+    #[test]
+    fn map() {
+        let mut partition = Partition::new();
+        for i in 0..5u32 {
+            partition.insert(i);
+        }
+        partition.merge(0, 4);
+        partition.merge(1, 2);
+        partition.merge(2, 4);
+
+        let mapped = partition.map(|i| i + 10);
+        assert_eq!(mapped.iter_parts().count(), 2);
+        assert!(mapped.same_part(10, 14));
+        assert!(!mapped.same_part(10, 13));
+        for i in 0..5 {
+            assert_eq!(
+                mapped.representative(i + 10),
+                partition.representative(i) + 10
+            );
+        }
+    }
 }